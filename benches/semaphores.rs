@@ -0,0 +1,210 @@
+// Compares `Semaphore` with other counting semaphores, run with `cargo bench`.
+//
+// Every benchmark measures an acquire followed by a release. Uncontended, a single thread takes
+// the only permit and gives it back. Contended, N threads share N / 2 permits, so half of them are
+// waiting at any time, and the time reported is per acquire/release pair across all threads.
+//
+// The baselines are tokio's async semaphore driven by a current-thread runtime per thread, a
+// counter guarded by a `parking_lot` mutex and condition variable, and, on Linux, a raw POSIX
+// `sem_t`.
+
+extern crate criterion;
+extern crate libc;
+extern crate parking_lot;
+extern crate sema;
+extern crate tokio;
+
+use std::sync::{
+    Arc,
+    Barrier,
+};
+use std::thread;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use criterion::{
+    criterion_group,
+    criterion_main,
+    BenchmarkId,
+    Criterion,
+};
+
+trait Permits: Send + Sync + 'static {
+    const NAME: &'static str;
+    fn new(permits: u32) -> Self;
+    fn acquire(&self);
+    fn release(&self);
+}
+
+impl Permits for sema::Semaphore {
+    const NAME: &'static str = "sema";
+
+    fn new(permits: u32) -> Self {
+        sema::Semaphore::new(permits as _)
+    }
+
+    fn acquire(&self) {
+        self.wait().unwrap();
+    }
+
+    fn release(&self) {
+        self.post();
+    }
+}
+
+thread_local! {
+    static RUNTIME: tokio::runtime::Runtime =
+        tokio::runtime::Builder::new_current_thread().build().unwrap();
+}
+
+impl Permits for tokio::sync::Semaphore {
+    const NAME: &'static str = "tokio";
+
+    fn new(permits: u32) -> Self {
+        tokio::sync::Semaphore::new(permits as usize)
+    }
+
+    fn acquire(&self) {
+        RUNTIME.with(|rt| rt.block_on(tokio::sync::Semaphore::acquire(self)).unwrap().forget());
+    }
+
+    fn release(&self) {
+        self.add_permits(1);
+    }
+}
+
+// The textbook semaphore: a count under a mutex, with a condition variable to wait for it.
+struct ParkingLotSemaphore {
+    count: parking_lot::Mutex<u32>,
+    available: parking_lot::Condvar,
+}
+
+impl Permits for ParkingLotSemaphore {
+    const NAME: &'static str = "parking_lot";
+
+    fn new(permits: u32) -> Self {
+        ParkingLotSemaphore {
+            count: parking_lot::Mutex::new(permits),
+            available: parking_lot::Condvar::new(),
+        }
+    }
+
+    fn acquire(&self) {
+        let mut count = self.count.lock();
+        while *count == 0 {
+            self.available.wait(&mut count);
+        }
+        *count -= 1;
+    }
+
+    fn release(&self) {
+        *self.count.lock() += 1;
+        self.available.notify_one();
+    }
+}
+
+#[cfg(target_os = "linux")]
+struct PosixSemaphore {
+    inner: Box<std::cell::UnsafeCell<libc::sem_t>>,
+}
+
+#[cfg(target_os = "linux")]
+unsafe impl Send for PosixSemaphore {}
+#[cfg(target_os = "linux")]
+unsafe impl Sync for PosixSemaphore {}
+
+#[cfg(target_os = "linux")]
+impl Permits for PosixSemaphore {
+    const NAME: &'static str = "sem_t";
+
+    fn new(permits: u32) -> Self {
+        let sem = PosixSemaphore {
+            inner: Box::new(std::cell::UnsafeCell::new(unsafe { std::mem::zeroed() })),
+        };
+        assert_eq!(unsafe { libc::sem_init(sem.inner.get(), 0, permits) }, 0);
+        sem
+    }
+
+    fn acquire(&self) {
+        while unsafe { libc::sem_wait(self.inner.get()) } != 0 {}
+    }
+
+    fn release(&self) {
+        unsafe {
+            libc::sem_post(self.inner.get());
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for PosixSemaphore {
+    fn drop(&mut self) {
+        unsafe {
+            libc::sem_destroy(self.inner.get());
+        }
+    }
+}
+
+fn uncontended<S: Permits>(c: &mut Criterion) {
+    let sem = S::new(1);
+    c.benchmark_group("uncontended").bench_function(S::NAME, |b| {
+        b.iter(|| {
+            sem.acquire();
+            sem.release();
+        })
+    });
+}
+
+// Runs `iters` acquire/release pairs spread over `threads` threads sharing `threads / 2` permits.
+fn contended_run<S: Permits>(threads: u32, iters: u64) -> Duration {
+    let sem = Arc::new(S::new(threads / 2));
+    let start = Arc::new(Barrier::new(threads as usize + 1));
+    let workers: Vec<_> = (0..threads as u64).map(|i| {
+        let sem = sem.clone();
+        let start = start.clone();
+        // Hand out the remainder one iteration at a time.
+        let n = iters / threads as u64 + (i < iters % threads as u64) as u64;
+        thread::spawn(move || {
+            start.wait();
+            for _ in 0..n {
+                sem.acquire();
+                sem.release();
+            }
+        })
+    }).collect();
+    start.wait();
+    let begin = Instant::now();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    begin.elapsed()
+}
+
+fn contended<S: Permits>(c: &mut Criterion) {
+    let mut group = c.benchmark_group("contended");
+    for &threads in &[2, 4, 8] {
+        group.bench_with_input(BenchmarkId::new(S::NAME, threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| contended_run::<S>(threads, iters))
+        });
+    }
+    group.finish();
+}
+
+fn benches(c: &mut Criterion) {
+    uncontended::<sema::Semaphore>(c);
+    uncontended::<tokio::sync::Semaphore>(c);
+    uncontended::<ParkingLotSemaphore>(c);
+    #[cfg(target_os = "linux")]
+    uncontended::<PosixSemaphore>(c);
+
+    contended::<sema::Semaphore>(c);
+    contended::<tokio::sync::Semaphore>(c);
+    contended::<ParkingLotSemaphore>(c);
+    #[cfg(target_os = "linux")]
+    contended::<PosixSemaphore>(c);
+}
+
+criterion_group!(semaphores, benches);
+criterion_main!(semaphores);
@@ -0,0 +1,146 @@
+// Adaptive concurrency limits.
+//
+// An `AdaptiveLimiter` is a `ConcurrencyLimiter` whose limit is steered by a `LimitControl`: each
+// time a closure finishes, the control is shown a `LimitSample` (the current limit and load, and
+// how long the closure took) and picks the next limit, which is clamped to the configured bounds.
+// Like the rate limiters there is no background thread, the limit only moves as work completes.
+//
+// `Aimd` is the classic policy: raise the limit by one while closures finish within a target
+// latency, and cut it by a factor when one doesn't.
+use std::sync::Mutex;
+use std::time::Instant;
+use std::io::Error;
+
+use time::Duration;
+
+use limiter::ConcurrencyLimiter;
+
+// What a `LimitControl` gets to see after each closure.
+#[derive(Clone, Copy, Debug)]
+pub struct LimitSample {
+    pub limit: usize,
+    pub in_flight: usize,
+    pub queued: usize,
+    // How long the closure ran, not counting the wait for a permit.
+    pub latency: Duration,
+}
+
+// Decides the limit from samples of completed work.
+pub trait LimitControl {
+    fn adjust(&mut self, sample: &LimitSample) -> usize;
+}
+
+impl<F: FnMut(&LimitSample) -> usize> LimitControl for F {
+    fn adjust(&mut self, sample: &LimitSample) -> usize {
+        self(sample)
+    }
+}
+
+// Additive increase, multiplicative decrease.
+#[derive(Clone, Copy, Debug)]
+pub struct Aimd {
+    target: Duration,
+    backoff: f64,
+}
+
+pub struct AdaptiveLimiter<C> {
+    limiter: ConcurrencyLimiter,
+    control: Mutex<C>,
+    min: usize,
+    max: usize,
+}
+
+impl Aimd {
+    // Grows the limit by one per closure finishing within `target`, and multiplies it by
+    // `backoff` when one takes longer.
+    //
+    // # Panics
+    //
+    // Panics unless `0 < backoff < 1`.
+    pub fn new(target: Duration, backoff: f64) -> Aimd {
+        assert!(backoff > 0.0 && backoff < 1.0, "backoff must be between 0 and 1");
+        Aimd {
+            target,
+            backoff,
+        }
+    }
+}
+
+impl LimitControl for Aimd {
+    fn adjust(&mut self, sample: &LimitSample) -> usize {
+        if sample.latency <= self.target {
+            sample.limit + 1
+        } else {
+            (sample.limit as f64 * self.backoff) as usize
+        }
+    }
+}
+
+impl<C: LimitControl> AdaptiveLimiter<C> {
+    // Creates a limiter starting at `initial`, whose limit `control` moves within `min..=max`.
+    //
+    // # Panics
+    //
+    // Panics if `min` is zero or `initial` is outside the bounds.
+    pub fn new(initial: usize, min: usize, max: usize, control: C) -> AdaptiveLimiter<C> {
+        assert!(min > 0, "min must be positive");
+        assert!(min <= initial && initial <= max, "initial limit out of bounds");
+        AdaptiveLimiter {
+            limiter: ConcurrencyLimiter::new(initial),
+            control: Mutex::new(control),
+            min,
+            max,
+        }
+    }
+
+    pub fn run<F, R>(&self, f: F) -> Result<R, Error>
+        where F: FnOnce() -> R
+    {
+        self.limiter.run(|| self.observe(f))
+    }
+
+    pub fn try_run<F, R>(&self, f: F) -> Result<R, Error>
+        where F: FnOnce() -> R
+    {
+        self.limiter.try_run(|| self.observe(f))
+    }
+
+    pub fn run_timeout<F, R>(&self, timeout: Duration, f: F) -> Result<R, Error>
+        where F: FnOnce() -> R
+    {
+        self.limiter.run_timeout(timeout, || self.observe(f))
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limiter.limit()
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.limiter.in_flight()
+    }
+
+    pub fn queued(&self) -> usize {
+        self.limiter.queued()
+    }
+
+    // Runs `f` and feeds its latency to the control. A panicking closure is not sampled.
+    fn observe<F, R>(&self, f: F) -> R
+        where F: FnOnce() -> R
+    {
+        let start = Instant::now();
+        let res = f();
+        let latency = Duration::from_std(start.elapsed()).unwrap_or(Duration::max_value());
+        let mut control = self.control.lock().unwrap_or_else(|e| e.into_inner());
+        let sample = LimitSample {
+            limit: self.limiter.limit(),
+            in_flight: self.limiter.in_flight(),
+            queued: self.limiter.queued(),
+            latency,
+        };
+        let limit = control.adjust(&sample).clamp(self.min, self.max);
+        if limit != sample.limit {
+            self.limiter.set_limit(limit);
+        }
+        res
+    }
+}
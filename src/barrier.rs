@@ -0,0 +1,135 @@
+// Reusable barriers.
+//
+// A `Barrier` releases its threads once `parties` of them have called `wait()`, then starts over
+// for the next round. Arrivals and the round's generation share one futex word, the generation in
+// the upper half, so that the last thread can close a round with a single compare-and-swap while
+// a thread timing out can withdraw its arrival, and each sees whether the other got there first.
+use std::ops::ControlFlow;
+use std::ptr;
+use std::sync::atomic::{
+    Ordering,
+    AtomicU32,
+};
+use std::io::Error;
+
+use libc;
+use time::Duration;
+
+use sys::{
+    futex_wait_until,
+    futex_wake_bitset,
+    monotonic_deadline,
+    FutexMode,
+    InterruptPolicy,
+    FUTEX_BITSET_MATCH_ANY,
+};
+
+// The number of arrivals is stored in the lower half of the state.
+const ARRIVED_MASK: u32 = 0xffff;
+const GENERATION_SHIFT: u32 = 16;
+
+#[repr(C)]
+pub struct Barrier {
+    state: AtomicU32,
+    parties: u32,
+    mode: FutexMode,
+}
+
+impl Barrier {
+    // Creates a barrier for `parties` threads. A barrier for zero parties behaves like one for a
+    // single party.
+    //
+    // # Panics
+    //
+    // Panics if `parties` exceeds 65535.
+    pub fn new(parties: u32) -> Barrier {
+        Barrier::with_futex_mode(parties, FutexMode::Private)
+    }
+
+    // Barriers placed in memory shared with other processes must use `FutexMode::Shared`.
+    pub fn with_futex_mode(parties: u32, mode: FutexMode) -> Barrier {
+        assert!(parties <= ARRIVED_MASK, "too many parties for a Barrier");
+        Barrier {
+            state: AtomicU32::new(0),
+            parties: parties.max(1),
+            mode,
+        }
+    }
+
+    // Waits until all parties have arrived. Returns `true` in exactly one thread per round, the
+    // last one to arrive.
+    pub fn wait(&self) -> Result<bool, Error> {
+        self.wait_until(ptr::null())
+    }
+
+    // Like `wait()`, but gives up with `ErrorKind::TimedOut` once `timeout` has passed. A thread
+    // which gives up no longer counts as arrived.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<bool, Error> {
+        let deadline = monotonic_deadline(timeout);
+        self.wait_until(&deadline)
+    }
+
+    pub fn parties(&self) -> u32 {
+        self.parties
+    }
+
+    pub fn futex_mode(&self) -> FutexMode {
+        self.mode
+    }
+
+    fn wait_until(&self, deadline: *const libc::timespec) -> Result<bool, Error> {
+        let mut s = self.state.load(Ordering::Relaxed);
+        let generation = loop {
+            let generation = s >> GENERATION_SHIFT;
+            if (s & ARRIVED_MASK) + 1 == self.parties {
+                // Last to arrive: start the next round and release everybody.
+                let next = generation.wrapping_add(1) << GENERATION_SHIFT;
+                match self.state.compare_exchange_weak(s, next, Ordering::AcqRel,
+                                                       Ordering::Relaxed) {
+                    Ok(_) => {
+                        futex_wake_bitset(self.state.as_ptr(), i32::MAX as u32,
+                                          FUTEX_BITSET_MATCH_ANY, self.mode).unwrap();
+                        return Ok(true);
+                    }
+                    Err(prev) => s = prev,
+                }
+            } else {
+                match self.state.compare_exchange_weak(s, s + 1, Ordering::AcqRel,
+                                                       Ordering::Relaxed) {
+                    Ok(_) => break generation,
+                    Err(prev) => s = prev,
+                }
+            }
+        };
+
+        let res = futex_wait_until(&self.state, deadline, self.mode, InterruptPolicy::Retry, || {
+            let s = self.state.load(Ordering::Acquire);
+            if s >> GENERATION_SHIFT != generation {
+                ControlFlow::Break(false)
+            } else {
+                ControlFlow::Continue(s)
+            }
+        });
+        // Only reaching the deadline ends the wait early.
+        res.or_else(|e| self.withdraw(generation, e))
+    }
+
+    // Takes back an arrival in round `generation`, failing with `err` unless the round completed
+    // in the meantime.
+    fn withdraw(&self, generation: u32, err: Error) -> Result<bool, Error> {
+        let mut s = self.state.load(Ordering::Acquire);
+        loop {
+            if s >> GENERATION_SHIFT != generation {
+                return Ok(false);
+            }
+            match self.state.compare_exchange_weak(s, s - 1, Ordering::Acquire,
+                                                   Ordering::Acquire) {
+                Ok(_) => return Err(err),
+                Err(prev) => s = prev,
+            }
+        }
+    }
+}
+
+unsafe impl Send for Barrier {}
+unsafe impl Sync for Barrier {}
@@ -0,0 +1,125 @@
+// Binary semaphores.
+//
+// A `BinarySemaphore` holds at most one token: posting an already available semaphore does
+// nothing, so it records that something happened at least once since the last wait, rather than
+// how many times.
+//
+// `state` is the futex word, 1 while the token is available. Blocked threads register in
+// `nwaiters`, so that a post only makes a syscall if somebody may be sleeping.
+use std::ops::ControlFlow;
+use std::ptr;
+use std::sync::atomic::{
+    Ordering,
+    AtomicU32,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc;
+use time::Duration;
+
+use sys::{
+    futex_wait_until,
+    futex_wake_bitset,
+    monotonic_deadline,
+    FutexMode,
+    InterruptPolicy,
+    FUTEX_BITSET_MATCH_ANY,
+};
+
+#[repr(C)]
+pub struct BinarySemaphore {
+    state: AtomicU32,
+    nwaiters: AtomicU32,
+    mode: FutexMode,
+}
+
+pub struct BinarySemaphoreGuard<'a> {
+    sem: &'a BinarySemaphore,
+}
+
+impl BinarySemaphore {
+    pub fn new(available: bool) -> BinarySemaphore {
+        BinarySemaphore::with_futex_mode(available, FutexMode::Private)
+    }
+
+    // Semaphores placed in memory shared with other processes must use `FutexMode::Shared`.
+    pub fn with_futex_mode(available: bool, mode: FutexMode) -> BinarySemaphore {
+        BinarySemaphore {
+            state: AtomicU32::new(available as u32),
+            nwaiters: AtomicU32::new(0),
+            mode,
+        }
+    }
+
+    // Makes the token available, waking a waiter. Does nothing if it already is.
+    pub fn post(&self) {
+        // SeqCst orders it before the load of `nwaiters`, pairing with `wait_until()` which
+        // registers before looking at the state.
+        if self.state.swap(1, Ordering::SeqCst) == 0 && self.nwaiters.load(Ordering::SeqCst) > 0 {
+            futex_wake_bitset(self.state.as_ptr(), 1, FUTEX_BITSET_MATCH_ANY, self.mode).unwrap();
+        }
+    }
+
+    // Takes the token away if it is available, without waiting.
+    pub fn reset(&self) {
+        self.state.store(0, Ordering::Relaxed);
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.state.load(Ordering::Relaxed) == 1
+    }
+
+    pub fn wait(&self) -> Result<(), Error> {
+        self.wait_until(ptr::null())
+    }
+
+    pub fn try_wait(&self) -> Result<(), Error> {
+        match self.state.compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error::new(ErrorKind::WouldBlock, "wait would block")),
+        }
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        let deadline = monotonic_deadline(timeout);
+        self.wait_until(&deadline)
+    }
+
+    pub fn take(&self) -> Result<BinarySemaphoreGuard<'_>, Error> {
+        self.wait()?;
+        Ok(BinarySemaphoreGuard {
+            sem: self,
+        })
+    }
+
+    pub fn futex_mode(&self) -> FutexMode {
+        self.mode
+    }
+
+    fn wait_until(&self, deadline: *const libc::timespec) -> Result<(), Error> {
+        if self.try_wait().is_ok() {
+            return Ok(());
+        }
+        self.nwaiters.fetch_add(1, Ordering::SeqCst);
+        let res = futex_wait_until(&self.state, deadline, self.mode, InterruptPolicy::Surface, || {
+            match self.state.compare_exchange(1, 0, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => ControlFlow::Break(()),
+                Err(_) => ControlFlow::Continue(0),
+            }
+        });
+        self.nwaiters.fetch_sub(1, Ordering::Relaxed);
+        res
+    }
+}
+
+unsafe impl Send for BinarySemaphore {}
+unsafe impl Sync for BinarySemaphore {}
+
+impl<'a> Drop for BinarySemaphoreGuard<'a> {
+    fn drop(&mut self) {
+        self.sem.post();
+    }
+}
@@ -0,0 +1,85 @@
+// Memory budgets.
+//
+// A `MemoryBudget` bounds the number of bytes in flight, e.g. the total size of messages being
+// processed, with a `WeightedSemaphore` underneath: callers acquire the size of their data and the
+// returned `MemoryPermit` releases it when dropped. Large requests are queued in arrival order
+// like any other, so they aren't starved by a stream of small ones.
+use std::io::Error;
+
+use time::Duration;
+
+use weighted::WeightedSemaphore;
+
+pub struct MemoryBudget {
+    sem: WeightedSemaphore,
+}
+
+pub struct MemoryPermit<'a> {
+    budget: &'a MemoryBudget,
+    bytes: usize,
+}
+
+impl MemoryBudget {
+    pub fn new(bytes: usize) -> MemoryBudget {
+        MemoryBudget {
+            sem: WeightedSemaphore::new(bytes as u64),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.sem.size() as usize
+    }
+
+    // Returns the number of bytes not currently acquired.
+    pub fn available(&self) -> usize {
+        self.sem.available() as usize
+    }
+
+    // Waits until `bytes` are available. Fails with `ErrorKind::InvalidInput` if `bytes` exceeds
+    // the capacity of the budget.
+    pub fn acquire(&self, bytes: usize) -> Result<MemoryPermit<'_>, Error> {
+        self.sem.acquire(bytes as u64)?;
+        Ok(self.permit(bytes))
+    }
+
+    pub fn try_acquire(&self, bytes: usize) -> Result<MemoryPermit<'_>, Error> {
+        self.sem.try_acquire(bytes as u64)?;
+        Ok(self.permit(bytes))
+    }
+
+    pub fn acquire_timeout(&self, bytes: usize, timeout: Duration)
+                           -> Result<MemoryPermit<'_>, Error> {
+        self.sem.acquire_timeout(bytes as u64, timeout)?;
+        Ok(self.permit(bytes))
+    }
+
+    fn permit(&self, bytes: usize) -> MemoryPermit<'_> {
+        MemoryPermit {
+            budget: self,
+            bytes,
+        }
+    }
+}
+
+impl<'a> MemoryPermit<'a> {
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    // Releases all but `bytes` of the permit early, e.g. once a buffer has been compressed.
+    //
+    // # Panics
+    //
+    // Panics if `bytes` is more than the permit holds.
+    pub fn shrink(&mut self, bytes: usize) {
+        assert!(bytes <= self.bytes, "cannot grow a memory permit");
+        self.budget.sem.release((self.bytes - bytes) as u64);
+        self.bytes = bytes;
+    }
+}
+
+impl<'a> Drop for MemoryPermit<'a> {
+    fn drop(&mut self) {
+        self.budget.sem.release(self.bytes as u64);
+    }
+}
@@ -0,0 +1,100 @@
+// Per-thread permit caches.
+//
+// A semaphore used as a limiter with a large count is mostly taken and posted without anyone
+// blocking, yet every operation is an atomic read-modify-write of the same word. A `PermitCache`
+// belongs to one thread and takes tokens from the semaphore in batches, handing them out and
+// taking them back locally, so that the shared word is only touched about once per batch.
+//
+// Tokens sitting in a cache are unavailable to other threads. A cache therefore returns its tokens
+// as soon as it notices a blocked waiter, whenever it holds more than a batch, and when it is
+// flushed or dropped. A thread which stops using its cache without dropping it should `flush()` it.
+use std::cell::Cell;
+use std::io::Error;
+
+use sys::Semaphore;
+
+pub struct PermitCache<'a> {
+    sem: &'a Semaphore,
+    batch: u32,
+    cached: Cell<u32>,
+}
+
+pub struct PermitCacheGuard<'a, 'b: 'a> {
+    cache: &'a PermitCache<'b>,
+}
+
+impl Semaphore {
+    // Returns a cache taking up to `batch` tokens at a time from the semaphore, for use by the
+    // calling thread.
+    pub fn permit_cache(&self, batch: u32) -> PermitCache<'_> {
+        PermitCache {
+            sem: self,
+            batch: batch.max(1),
+            cached: Cell::new(0),
+        }
+    }
+}
+
+impl<'a> PermitCache<'a> {
+    pub fn wait(&self) -> Result<(), Error> {
+        if self.try_wait().is_ok() {
+            return Ok(());
+        }
+        self.sem.wait()
+    }
+
+    pub fn try_wait(&self) -> Result<(), Error> {
+        let cached = self.cached.get();
+        if cached > 0 {
+            self.cached.set(cached - 1);
+            return Ok(());
+        }
+        match self.sem.try_wait_many(self.batch) {
+            0 => self.sem.try_wait(),
+            n => {
+                self.cached.set(n - 1);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn post(&self) {
+        let cached = self.cached.get() + 1;
+        if cached > self.batch || self.sem.has_waiters() {
+            self.cached.set(0);
+            self.sem.post_many(cached);
+        } else {
+            self.cached.set(cached);
+        }
+    }
+
+    pub fn take(&self) -> Result<PermitCacheGuard<'_, 'a>, Error> {
+        self.wait()?;
+        Ok(PermitCacheGuard {
+            cache: self,
+        })
+    }
+
+    // Returns every cached token to the semaphore.
+    pub fn flush(&self) {
+        let cached = self.cached.replace(0);
+        self.sem.post_many(cached);
+    }
+
+    // Returns the number of tokens currently held by the cache.
+    pub fn cached(&self) -> u32 {
+        self.cached.get()
+    }
+}
+
+impl<'a> Drop for PermitCache<'a> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+impl<'a, 'b> Drop for PermitCacheGuard<'a, 'b> {
+    fn drop(&mut self) {
+        self.cache.post();
+    }
+}
@@ -0,0 +1,131 @@
+// Futex-based condition variables.
+//
+// A `Condvar` pairs with a `FutexMutex`. Waiters sleep on `seq`, which every notification bumps, so
+// a notification racing with a thread between unlocking the mutex and going to sleep makes the
+// sleep fail instead of being lost.
+//
+// `notify_all()` wakes a single waiter and requeues the others onto the mutex word with
+// `FUTEX_CMP_REQUEUE`. They then get the mutex one at a time as it is unlocked, instead of all
+// waking up at once only to block on the mutex again. Requeued threads relock the mutex marked
+// as contended, so that each unlock passes it on to the next of them.
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{
+    Ordering,
+    AtomicPtr,
+    AtomicU32,
+};
+use std::io::ErrorKind;
+
+use libc;
+use time::Duration;
+
+use mutex::{
+    FutexMutex,
+    FutexMutexGuard,
+};
+use sys::{
+    futex_cmp_requeue,
+    futex_wait_bitset,
+    Clock,
+    futex_wake_bitset,
+    monotonic_deadline,
+    FutexMode,
+    FUTEX_BITSET_MATCH_ANY,
+};
+
+pub struct Condvar {
+    seq: AtomicU32,
+    // Word of the mutex used with the condition variable, known once somebody waited.
+    mutex: AtomicPtr<AtomicU32>,
+}
+
+impl Condvar {
+    pub const fn new() -> Condvar {
+        Condvar {
+            seq: AtomicU32::new(0),
+            mutex: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    // Unlocks the mutex and sleeps until notified, then locks the mutex again. Wakeups may be
+    // spurious, so the caller should check its condition in a loop, or use `wait_while()`.
+    //
+    // # Panics
+    //
+    // Panics if the condition variable is used with more than one mutex.
+    pub fn wait<'a, T: ?Sized>(&self, guard: FutexMutexGuard<'a, T>) -> FutexMutexGuard<'a, T> {
+        self.wait_until(guard, ptr::null()).0
+    }
+
+    // Waits until `condition` returns false.
+    pub fn wait_while<'a, T: ?Sized, F>(&self, mut guard: FutexMutexGuard<'a, T>, mut condition: F)
+                                        -> FutexMutexGuard<'a, T>
+        where F: FnMut(&mut T) -> bool
+    {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    // Like `wait()`, but gives up after `timeout`. Returns whether the wait timed out.
+    pub fn wait_timeout<'a, T: ?Sized>(&self, guard: FutexMutexGuard<'a, T>, timeout: Duration)
+                                       -> (FutexMutexGuard<'a, T>, bool) {
+        let deadline = monotonic_deadline(timeout);
+        self.wait_until(guard, &deadline)
+    }
+
+    // Wakes one waiting thread.
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::SeqCst);
+        futex_wake_bitset(self.seq.as_ptr(), 1, FUTEX_BITSET_MATCH_ANY, FutexMode::Private)
+            .unwrap();
+    }
+
+    // Wakes all waiting threads, moving all but one of them straight onto the mutex.
+    pub fn notify_all(&self) {
+        let mutex = self.mutex.load(Ordering::Relaxed);
+        if mutex.is_null() {
+            // Nobody ever waited.
+            return;
+        }
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst).wrapping_add(1);
+        let res = futex_cmp_requeue(self.seq.as_ptr(), 1, i32::MAX as u32,
+                                    unsafe { (*mutex).as_ptr() }, seq, FutexMode::Private);
+        if res.is_err() {
+            // Another notification changed `seq` in the meantime, wake everybody instead.
+            futex_wake_bitset(self.seq.as_ptr(), i32::MAX as u32, FUTEX_BITSET_MATCH_ANY,
+                              FutexMode::Private).unwrap();
+        }
+    }
+
+    fn wait_until<'a, T: ?Sized>(&self, guard: FutexMutexGuard<'a, T>,
+                                 deadline: *const libc::timespec)
+                                 -> (FutexMutexGuard<'a, T>, bool) {
+        let mutex: &'a FutexMutex<T> = guard.mutex;
+        let word = mutex.state() as *const AtomicU32 as *mut AtomicU32;
+        let prev = self.mutex.swap(word, Ordering::Relaxed);
+        assert!(prev.is_null() || prev == word, "Condvar used with more than one mutex");
+
+        let seq = self.seq.load(Ordering::SeqCst);
+        mem::forget(guard);
+        mutex.unlock();
+        let res = futex_wait_bitset(self.seq.as_ptr(), seq, deadline, Clock::Monotonic,
+                                    FUTEX_BITSET_MATCH_ANY, FutexMode::Private);
+        mutex.lock_as_contended();
+        let timed_out = match res {
+            Err(ref e) => e.kind() == ErrorKind::TimedOut,
+            Ok(_) => false,
+        };
+        (FutexMutexGuard {
+            mutex,
+        }, timed_out)
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Condvar {
+        Condvar::new()
+    }
+}
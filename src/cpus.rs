@@ -0,0 +1,46 @@
+// Semaphores sized to the machine.
+//
+// `Semaphore::per_cpu()` starts with one permit per CPU the process may run on, as reported by
+// `std::thread::available_parallelism()`. On Linux that already honours the affinity mask and a
+// CPU quota set through cgroups, which is how container runtimes limit CPU time: a container
+// limited to 1.5 CPUs on a 64 core host gets 2 permits rather than 64.
+//
+// Detection never fails, a platform which can't tell falls back to a single CPU.
+use std::convert::TryFrom;
+use std::thread;
+
+use sys::Semaphore;
+
+impl Semaphore {
+    // Creates a semaphore with one permit per available CPU.
+    pub fn per_cpu() -> Semaphore {
+        Semaphore::new(cpu_count())
+    }
+
+    // Creates a semaphore with `factor` permits per available CPU, rounded to the nearest
+    // integer but at least one, e.g. `per_cpu_scaled(2.0)` for I/O-bound work.
+    //
+    // Panics if `factor` is not positive.
+    pub fn per_cpu_scaled(factor: f64) -> Semaphore {
+        assert!(factor > 0.0, "factor must be positive");
+        Semaphore::new(scaled(cpu_count(), factor))
+    }
+
+    // Creates a semaphore with one permit per CPU the process' cgroup CPU quota allows, rounded
+    // up, or per available CPU if that is fewer. The same as `per_cpu()`, since
+    // `available_parallelism()` applies the quota itself.
+    #[cfg(target_os = "linux")]
+    pub fn per_cpu_quota() -> Semaphore {
+        Semaphore::per_cpu()
+    }
+}
+
+fn cpu_count() -> u32 {
+    let cpus = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    u32::try_from(cpus).unwrap_or(u32::MAX)
+}
+
+fn scaled(cpus: u32, factor: f64) -> u32 {
+    let permits = (f64::from(cpus) * factor).round().max(1.0) as u64;
+    u32::try_from(permits).unwrap_or(u32::MAX)
+}
@@ -0,0 +1,161 @@
+// Events, as known from Windows.
+//
+// An `AutoResetEvent` is a `BinarySemaphore` by another name: `set()` releases exactly one waiter,
+// or the next thread to wait if nobody is waiting yet, and the event clears itself as that thread
+// passes.
+//
+// A `ManualResetEvent` stays set until it is reset, releasing every thread that waits meanwhile.
+// Its `state` word is the futex word, 1 while set, and `set()` wakes all registered waiters.
+use std::ops::ControlFlow;
+use std::ptr;
+use std::sync::atomic::{
+    Ordering,
+    AtomicU32,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc;
+use time::Duration;
+
+use binary::BinarySemaphore;
+use sys::{
+    futex_wait_until,
+    futex_wake_bitset,
+    monotonic_deadline,
+    FutexMode,
+    InterruptPolicy,
+    FUTEX_BITSET_MATCH_ANY,
+};
+
+#[repr(C)]
+pub struct AutoResetEvent {
+    sem: BinarySemaphore,
+}
+
+#[repr(C)]
+pub struct ManualResetEvent {
+    state: AtomicU32,
+    nwaiters: AtomicU32,
+    mode: FutexMode,
+}
+
+impl AutoResetEvent {
+    pub fn new(set: bool) -> AutoResetEvent {
+        AutoResetEvent::with_futex_mode(set, FutexMode::Private)
+    }
+
+    // Events placed in memory shared with other processes must use `FutexMode::Shared`.
+    pub fn with_futex_mode(set: bool, mode: FutexMode) -> AutoResetEvent {
+        AutoResetEvent {
+            sem: BinarySemaphore::with_futex_mode(set, mode),
+        }
+    }
+
+    // Sets the event, releasing one waiting thread. Setting an event which is already set does
+    // nothing.
+    pub fn set(&self) {
+        self.sem.post();
+    }
+
+    pub fn reset(&self) {
+        self.sem.reset();
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.sem.is_available()
+    }
+
+    // Waits until the event is set, clearing it again.
+    pub fn wait(&self) -> Result<(), Error> {
+        self.sem.wait()
+    }
+
+    pub fn try_wait(&self) -> Result<(), Error> {
+        self.sem.try_wait()
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        self.sem.wait_timeout(timeout)
+    }
+
+    pub fn futex_mode(&self) -> FutexMode {
+        self.sem.futex_mode()
+    }
+}
+
+impl ManualResetEvent {
+    pub fn new(set: bool) -> ManualResetEvent {
+        ManualResetEvent::with_futex_mode(set, FutexMode::Private)
+    }
+
+    // Events placed in memory shared with other processes must use `FutexMode::Shared`.
+    pub fn with_futex_mode(set: bool, mode: FutexMode) -> ManualResetEvent {
+        ManualResetEvent {
+            state: AtomicU32::new(set as u32),
+            nwaiters: AtomicU32::new(0),
+            mode,
+        }
+    }
+
+    // Sets the event, releasing every waiting thread and any that wait until it is reset.
+    pub fn set(&self) {
+        // SeqCst orders it before the load of `nwaiters`, pairing with `wait_until()` which
+        // registers before looking at the state.
+        if self.state.swap(1, Ordering::SeqCst) == 0 && self.nwaiters.load(Ordering::SeqCst) > 0 {
+            futex_wake_bitset(self.state.as_ptr(), i32::MAX as u32, FUTEX_BITSET_MATCH_ANY,
+                              self.mode).unwrap();
+        }
+    }
+
+    pub fn reset(&self) {
+        self.state.store(0, Ordering::Relaxed);
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.state.load(Ordering::Acquire) == 1
+    }
+
+    // Waits until the event is set. The event stays set.
+    pub fn wait(&self) -> Result<(), Error> {
+        self.wait_until(ptr::null())
+    }
+
+    pub fn try_wait(&self) -> Result<(), Error> {
+        if self.is_set() {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::WouldBlock, "wait would block"))
+        }
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        let deadline = monotonic_deadline(timeout);
+        self.wait_until(&deadline)
+    }
+
+    pub fn futex_mode(&self) -> FutexMode {
+        self.mode
+    }
+
+    fn wait_until(&self, deadline: *const libc::timespec) -> Result<(), Error> {
+        if self.is_set() {
+            return Ok(());
+        }
+        self.nwaiters.fetch_add(1, Ordering::SeqCst);
+        let res = futex_wait_until(&self.state, deadline, self.mode, InterruptPolicy::Surface, || {
+            if self.state.load(Ordering::SeqCst) == 1 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(0)
+            }
+        });
+        self.nwaiters.fetch_sub(1, Ordering::Relaxed);
+        res
+    }
+}
+
+unsafe impl Send for ManualResetEvent {}
+unsafe impl Sync for ManualResetEvent {}
@@ -0,0 +1,164 @@
+// Event counts.
+//
+// An `EventCount` adds blocking to a lock-free data structure. A consumer which finds nothing to
+// do announces that it is about to wait with `prepare_wait()`, checks its condition once more, and
+// then either sleeps with `commit_wait()` or gives up on waiting with `cancel_wait()`. A producer
+// calls `notify()` after making its change. Since the waiter registers before its last check,
+// either it sees the change or the notification sees it, so no wakeup is lost.
+//
+// Waiters sleep on `epoch`, which notifications bump, but only if somebody is registered in
+// `nwaiters`: notifying without waiters costs a single load.
+use std::cmp;
+use std::ops::ControlFlow;
+use std::ptr;
+use std::sync::atomic::{
+    Ordering,
+    AtomicU32,
+};
+use std::io::Error;
+
+use libc;
+use time::Duration;
+
+use sys::{
+    futex_wait_until,
+    futex_wake_bitset,
+    monotonic_deadline,
+    FutexMode,
+    InterruptPolicy,
+    FUTEX_BITSET_MATCH_ANY,
+};
+
+/// Blocking for lock-free data structures.
+///
+/// ```
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use sema::EventCount;
+///
+/// let ready = AtomicBool::new(false);
+/// let event = EventCount::new();
+///
+/// // Producer.
+/// ready.store(true, Ordering::SeqCst);
+/// event.notify();
+///
+/// // Consumer.
+/// loop {
+///     if ready.load(Ordering::SeqCst) {
+///         break;
+///     }
+///     let key = event.prepare_wait();
+///     if ready.load(Ordering::SeqCst) {
+///         event.cancel_wait(key);
+///         break;
+///     }
+///     event.commit_wait(key);
+/// }
+/// ```
+#[repr(C)]
+pub struct EventCount {
+    epoch: AtomicU32,
+    nwaiters: AtomicU32,
+    mode: FutexMode,
+}
+
+// A registration to wait on an `EventCount`, returned by `EventCount::prepare_wait()`.
+//
+// It must be passed to `commit_wait()` or `cancel_wait()` of the same `EventCount`.
+#[must_use]
+pub struct WaitKey {
+    epoch: u32,
+}
+
+impl EventCount {
+    pub fn new() -> EventCount {
+        EventCount::with_futex_mode(FutexMode::Private)
+    }
+
+    // Event counts placed in memory shared with other processes must use `FutexMode::Shared`.
+    pub fn with_futex_mode(mode: FutexMode) -> EventCount {
+        EventCount {
+            epoch: AtomicU32::new(0),
+            nwaiters: AtomicU32::new(0),
+            mode,
+        }
+    }
+
+    // Registers the calling thread as about to wait. Changes made after this returns and followed
+    // by a `notify()` cause the matching `commit_wait()` to return.
+    pub fn prepare_wait(&self) -> WaitKey {
+        // SeqCst orders the registration before the caller's last check of its condition, pairing
+        // with `notify()`.
+        self.nwaiters.fetch_add(1, Ordering::SeqCst);
+        WaitKey {
+            epoch: self.epoch.load(Ordering::SeqCst),
+        }
+    }
+
+    // Gives up on waiting, e.g. because the condition turned out to hold after all.
+    pub fn cancel_wait(&self, _key: WaitKey) {
+        self.nwaiters.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    // Sleeps until a notification made after `prepare_wait()`. Wakeups may be spurious, so the
+    // caller should check its condition again afterwards.
+    pub fn commit_wait(&self, key: WaitKey) {
+        let _ = self.commit_wait_until(key, ptr::null());
+    }
+
+    // Like `commit_wait()`, but fails with `ErrorKind::TimedOut` once `timeout` has passed.
+    pub fn commit_wait_timeout(&self, key: WaitKey, timeout: Duration) -> Result<(), Error> {
+        let deadline = monotonic_deadline(timeout);
+        self.commit_wait_until(key, &deadline)
+    }
+
+    // Wakes one waiting thread.
+    pub fn notify(&self) {
+        self.notify_many(1);
+    }
+
+    // Wakes every waiting thread.
+    pub fn notify_all(&self) {
+        self.notify_many(i32::MAX as u32);
+    }
+
+    // Wakes up to `n` waiting threads. The caller's change must be visible before this is
+    // called, e.g. by making it with `Ordering::SeqCst`.
+    pub fn notify_many(&self, n: u32) {
+        let waiters = self.nwaiters.load(Ordering::SeqCst);
+        if waiters > 0 && n > 0 {
+            self.epoch.fetch_add(1, Ordering::SeqCst);
+            let wake = cmp::min(cmp::min(n, waiters), i32::MAX as u32);
+            futex_wake_bitset(self.epoch.as_ptr(), wake, FUTEX_BITSET_MATCH_ANY, self.mode)
+                .unwrap();
+        }
+    }
+
+    pub fn futex_mode(&self) -> FutexMode {
+        self.mode
+    }
+
+    // Sleeps until a notification or the absolute `CLOCK_MONOTONIC` time `deadline`, if non-null.
+    pub(crate) fn commit_wait_until(&self, key: WaitKey, deadline: *const libc::timespec)
+                                    -> Result<(), Error> {
+        // Interruptions are retried, checking the epoch again like any other wakeup.
+        let res = futex_wait_until(&self.epoch, deadline, self.mode, InterruptPolicy::Retry, || {
+            if self.epoch.load(Ordering::Acquire) != key.epoch {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(key.epoch)
+            }
+        });
+        self.nwaiters.fetch_sub(1, Ordering::Relaxed);
+        res
+    }
+}
+
+impl Default for EventCount {
+    fn default() -> EventCount {
+        EventCount::new()
+    }
+}
+
+unsafe impl Send for EventCount {}
+unsafe impl Sync for EventCount {}
@@ -0,0 +1,343 @@
+// Eventfd semaphores.
+//
+// An `EventFdSemaphore` is an `eventfd` in semaphore mode: every read takes one permit and every
+// write of 1 posts one. Since the semaphore is just a file descriptor it can be inherited by a
+// child or passed to an unrelated process over a Unix socket, which gives unnamed cross-process
+// semaphores without any shared memory.
+//
+// The descriptor is non-blocking, waits block in `poll()` and retry the read if another process
+// took the permit first.
+//
+// `wait_with_sigmask()` blocks in `ppoll()` instead, which installs the given signal mask only for
+// the duration of the sleep, atomically, as `pselect()` does. That can't be done for a futex wait:
+// changing the mask around `futex()` leaves a window in which a signal is handled before the
+// thread sleeps, and the wakeup it was meant to cause is lost.
+use std::mem;
+use std::ptr;
+use std::os::unix::io::{
+    AsRawFd,
+    FromRawFd,
+    IntoRawFd,
+    RawFd,
+};
+use std::os::unix::net::UnixStream;
+use std::time::Instant;
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc::{
+    self,
+    c_int,
+    c_void,
+    EFD_CLOEXEC,
+    EFD_NONBLOCK,
+    EFD_SEMAPHORE,
+};
+use sys;
+use time::Duration;
+
+pub struct EventFdSemaphore {
+    fd: RawFd,
+}
+
+pub struct EventFdSemaphoreGuard<'a> {
+    sem: &'a EventFdSemaphore,
+}
+
+impl EventFdSemaphore {
+    pub fn new(value: u32) -> Result<EventFdSemaphore, Error> {
+        let fd = unsafe {
+            libc::eventfd(value, EFD_SEMAPHORE | EFD_NONBLOCK | EFD_CLOEXEC)
+        };
+        if fd == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(EventFdSemaphore {
+                fd,
+            })
+        }
+    }
+
+    pub fn wait(&self) -> Result<(), Error> {
+        loop {
+            match self.try_wait() {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                res => return res,
+            }
+            self.poll(-1)?;
+        }
+    }
+
+    pub fn try_wait(&self) -> Result<(), Error> {
+        let mut buf: u64 = 0;
+        let res = unsafe {
+            libc::read(self.fd, &mut buf as *mut u64 as *mut c_void, mem::size_of::<u64>())
+        };
+        if res == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        // Negative durations are treated as an already expired timeout.
+        let deadline = Instant::now() + timeout.to_std().unwrap_or_default();
+        loop {
+            match self.try_wait() {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                res => return res,
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::new(ErrorKind::TimedOut, "wait timed out"));
+            }
+            // Round up so that we don't spin on sub-millisecond remainders.
+            let left = deadline - now;
+            let millis = left.as_millis() + !left.subsec_nanos().is_multiple_of(1_000_000) as u128;
+            self.poll(millis.min(c_int::MAX as u128) as c_int)?;
+        }
+    }
+
+    // Waits like `wait()` with the signal mask replaced by `mask` while the thread is blocked,
+    // restoring it afterwards, so that only the signals `mask` leaves unblocked can interrupt
+    // the wait.
+    //
+    // The mask is switched atomically with going to sleep, so a signal that is blocked in the
+    // caller's mask and pending or arriving during the call is guaranteed to interrupt it with
+    // `ErrorKind::Interrupted`, after its handler ran.
+    pub fn wait_with_sigmask(&self, mask: &libc::sigset_t) -> Result<(), Error> {
+        self.wait_masked(ptr::null(), mask)
+    }
+
+    pub fn wait_timeout_with_sigmask(&self, timeout: Duration, mask: &libc::sigset_t)
+                                     -> Result<(), Error> {
+        // Negative durations are treated as an already expired timeout.
+        let deadline = Instant::now() + timeout.to_std().unwrap_or_default();
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let ts = libc::timespec {
+                tv_sec: left.as_secs() as libc::time_t,
+                tv_nsec: left.subsec_nanos() as libc::c_long,
+            };
+            match self.wait_masked(&ts, mask) {
+                Err(ref e) if e.kind() == ErrorKind::TimedOut && Instant::now() < deadline => {}
+                res => return res,
+            }
+        }
+    }
+
+    // Panics if the write fails, see `try_post()`.
+    pub fn post(&self) {
+        if let Err(err) = self.try_post() {
+            panic!("failed to post semaphore: {}", err);
+        }
+    }
+
+    // Fails if `write()` does, e.g. with `WouldBlock` once the counter is at its maximum of
+    // `u64::MAX - 1`, since the descriptor is non-blocking.
+    pub fn try_post(&self) -> Result<(), Error> {
+        let buf: u64 = 1;
+        let res = unsafe {
+            libc::write(self.fd, &buf as *const u64 as *const c_void, mem::size_of::<u64>())
+        };
+        if res == -1 {
+            return Err(Error::last_os_error());
+        }
+        debug_assert_eq!(res, mem::size_of::<u64>() as isize);
+        Ok(())
+    }
+
+    pub fn take(&self) -> Result<EventFdSemaphoreGuard<'_>, Error> {
+        self.wait()?;
+        Ok(EventFdSemaphoreGuard {
+            sem: self,
+        })
+    }
+
+    /// Sends a duplicate of the semaphore's descriptor over a Unix socket with `SCM_RIGHTS`.
+    ///
+    /// The peer receives it with `EventFdSemaphore::recv_from()`; both ends then refer to the same
+    /// semaphore.
+    ///
+    /// ```
+    /// # extern crate sema;
+    /// # extern crate time;
+    /// use std::os::unix::net::UnixStream;
+    /// use sema::EventFdSemaphore;
+    ///
+    /// # fn main() {
+    /// let (parent, child) = UnixStream::pair().unwrap();
+    /// let sem = EventFdSemaphore::new(0).unwrap();
+    /// sem.send_to(&parent).unwrap();
+    ///
+    /// // Usually in another process, e.g. after `fork()`.
+    /// let received = EventFdSemaphore::recv_from(&child).unwrap();
+    /// received.post();
+    /// sem.wait_timeout(time::Duration::seconds(1)).unwrap();
+    /// # }
+    /// ```
+    pub fn send_to(&self, sock: &UnixStream) -> Result<(), Error> {
+        // At least one byte of regular data has to accompany the descriptor.
+        let mut data = [0u8; 1];
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr() as *mut c_void,
+            iov_len: data.len(),
+        };
+        let mut cmsg_buf = [0u8; 64];
+        let cmsg_space = unsafe {
+            libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32)
+        } as usize;
+        debug_assert!(cmsg_space <= cmsg_buf.len());
+
+        let mut msg: libc::msghdr = unsafe {
+            mem::zeroed()
+        };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = cmsg_buf.as_mut_ptr() as *mut c_void;
+        msg.msg_controllen = cmsg_space as _;
+
+        let res = unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+            ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, self.fd);
+            libc::sendmsg(sock.as_raw_fd(), &msg, 0)
+        };
+        if res == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    // Receives a semaphore sent by `EventFdSemaphore::send_to()` on the other end of `sock`.
+    pub fn recv_from(sock: &UnixStream) -> Result<EventFdSemaphore, Error> {
+        let mut data = [0u8; 1];
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr() as *mut c_void,
+            iov_len: data.len(),
+        };
+        let mut cmsg_buf = [0u8; 64];
+
+        let mut msg: libc::msghdr = unsafe {
+            mem::zeroed()
+        };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = cmsg_buf.as_mut_ptr() as *mut c_void;
+        msg.msg_controllen = cmsg_buf.len() as _;
+
+        let res = unsafe {
+            libc::recvmsg(sock.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC)
+        };
+        if res == -1 {
+            return Err(Error::last_os_error());
+        }
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            if cmsg.is_null()
+                || (*cmsg).cmsg_level != libc::SOL_SOCKET
+                || (*cmsg).cmsg_type != libc::SCM_RIGHTS {
+                return Err(Error::new(ErrorKind::InvalidData, "no descriptor received"));
+            }
+            let fd = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const RawFd);
+            Ok(EventFdSemaphore::from_raw_fd(fd))
+        }
+    }
+
+    // Takes a permit, sleeping in `ppoll()` with `mask` installed for at most `timeout`, if not
+    // null. Fails with `ErrorKind::TimedOut` if the timeout passed without a permit.
+    fn wait_masked(&self, timeout: *const libc::timespec, mask: &libc::sigset_t)
+                   -> Result<(), Error> {
+        loop {
+            match self.try_wait() {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                res => return res,
+            }
+            let mut pfd = libc::pollfd {
+                fd: self.fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let res = unsafe {
+                libc::ppoll(&mut pfd, 1, timeout, mask)
+            };
+            match res {
+                -1 => return Err(Error::last_os_error()),
+                0 => {
+                    return self.try_wait().map_err(|_| {
+                        Error::new(ErrorKind::TimedOut, "wait timed out")
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Blocks until the descriptor is readable or `millis` milliseconds have passed.
+    fn poll(&self, millis: c_int) -> Result<(), Error> {
+        let mut pfd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let res = unsafe {
+            libc::poll(&mut pfd, 1, millis)
+        };
+        if res == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl AsRawFd for EventFdSemaphore {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl IntoRawFd for EventFdSemaphore {
+    fn into_raw_fd(self) -> RawFd {
+        let fd = self.fd;
+        mem::forget(self);
+        fd
+    }
+}
+
+impl FromRawFd for EventFdSemaphore {
+    // `fd` must be an eventfd created in semaphore mode. It is switched to non-blocking mode, which
+    // affects every descriptor sharing its file description.
+    unsafe fn from_raw_fd(fd: RawFd) -> EventFdSemaphore {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags != -1 && flags & libc::O_NONBLOCK == 0 {
+            libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+        }
+        EventFdSemaphore {
+            fd,
+        }
+    }
+}
+
+impl Drop for EventFdSemaphore {
+    fn drop(&mut self) {
+        let res = unsafe {
+            libc::close(self.fd)
+        };
+        debug_assert_eq!(res, 0);
+    }
+}
+
+impl<'a> Drop for EventFdSemaphoreGuard<'a> {
+    fn drop(&mut self) {
+        if let Err(err) = self.sem.try_post() {
+            sys::lost_post(err);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+// Exchangers.
+//
+// An `Exchanger<T>` is a rendezvous point where pairs of threads swap values: the first thread to
+// arrive leaves its value and waits, the second takes it, leaves its own, and returns right away.
+// Further threads wait until the first of the pair has collected its value.
+use std::mem;
+use std::time::Instant;
+
+use time::Duration;
+
+use condvar::Condvar;
+use mutex::{
+    FutexMutex,
+    FutexMutexGuard,
+};
+
+pub struct Exchanger<T> {
+    slot: FutexMutex<Slot<T>>,
+    cond: Condvar,
+}
+
+enum Slot<T> {
+    Empty,
+    // Offered by a thread waiting for its partner.
+    Offered(T),
+    // Left by the partner, for the waiting thread to collect.
+    Answered(T),
+}
+
+impl<T> Exchanger<T> {
+    pub fn new() -> Exchanger<T> {
+        Exchanger {
+            slot: FutexMutex::new(Slot::Empty),
+            cond: Condvar::new(),
+        }
+    }
+
+    // Waits for another thread to arrive, and returns its value in exchange for ours.
+    pub fn exchange(&self, value: T) -> T {
+        match self.exchange_until(value, None) {
+            Ok(value) => value,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    // Like `exchange()`, but gives up after `timeout`, handing our value back.
+    pub fn exchange_timeout(&self, value: T, timeout: Duration) -> Result<T, T> {
+        // Negative durations are treated as an already expired timeout.
+        let deadline = Instant::now() + timeout.to_std().unwrap_or_default();
+        self.exchange_until(value, Some(deadline))
+    }
+
+    fn exchange_until(&self, value: T, deadline: Option<Instant>) -> Result<T, T> {
+        let mut slot = self.slot.lock();
+        // Another pair is still completing its exchange.
+        while let Slot::Answered(_) = *slot {
+            let (guard, timed_out) = self.wait(slot, deadline);
+            slot = guard;
+            if timed_out {
+                return Err(value);
+            }
+        }
+
+        match mem::replace(&mut *slot, Slot::Empty) {
+            Slot::Offered(other) => {
+                *slot = Slot::Answered(value);
+                drop(slot);
+                self.cond.notify_all();
+                return Ok(other);
+            }
+            _ => *slot = Slot::Offered(value),
+        }
+
+        loop {
+            if let Slot::Answered(_) = *slot {
+                let other = match mem::replace(&mut *slot, Slot::Empty) {
+                    Slot::Answered(other) => other,
+                    _ => unreachable!(),
+                };
+                drop(slot);
+                // Let threads waiting for the slot in.
+                self.cond.notify_all();
+                return Ok(other);
+            }
+            let (guard, timed_out) = self.wait(slot, deadline);
+            slot = guard;
+            if timed_out {
+                if let Slot::Offered(_) = *slot {
+                    match mem::replace(&mut *slot, Slot::Empty) {
+                        Slot::Offered(value) => return Err(value),
+                        _ => unreachable!(),
+                    }
+                }
+                // Our partner arrived just in time, collect its value.
+            }
+        }
+    }
+
+    // Waits on the condition variable until `deadline`, if any. Returns whether it has passed.
+    fn wait<'a>(&self, slot: FutexMutexGuard<'a, Slot<T>>, deadline: Option<Instant>)
+                -> (FutexMutexGuard<'a, Slot<T>>, bool) {
+        match deadline {
+            None => (self.cond.wait(slot), false),
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return (slot, true);
+                }
+                let left = Duration::from_std(deadline - now).unwrap_or(Duration::max_value());
+                self.cond.wait_timeout(slot, left)
+            }
+        }
+    }
+}
+
+impl<T> Default for Exchanger<T> {
+    fn default() -> Exchanger<T> {
+        Exchanger::new()
+    }
+}
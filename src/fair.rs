@@ -0,0 +1,250 @@
+// FIFO semaphores.
+//
+// A `FairSemaphore` hands out permits strictly in the order waiters arrived, including waiters in
+// other processes when it is placed in shared memory. Every waiter draws a ticket from `next`,
+// and ticket `t` may proceed once `granted` has passed it; `granted` starts at the initial value
+// and is advanced by every post. Both counters live in the semaphore itself, so the ordering holds
+// across process boundaries, and there is no fast path that lets a newcomer barge past a sleeping
+// waiter.
+//
+// Waiters sleep on `granted` with a futex bitset derived from their ticket, so a post only wakes
+// the waiters whose ticket may just have been granted.
+//
+// A waiter which gives up (timeout or signal) cannot leave the queue, since the tickets behind it
+// are already numbered. Instead it records its ticket in `abandoned`, and the post which grants
+// that ticket passes the permit on to the next one. Up to `ABANDONED_SLOTS` such tickets can be
+// pending, beyond that a waiter giving up keeps waiting until an earlier abandoned ticket has been
+// passed over.
+//
+// Tickets are compared by their distance to `granted` as a signed 32-bit number, so at most
+// `FAIR_MAX_VALUE` permits can be outstanding: the initial value is limited to it, and a post past
+// it panics.
+use std::array;
+use std::ptr;
+use std::thread;
+use std::time::Duration as StdDuration;
+use std::sync::atomic::{
+    Ordering,
+    AtomicU32,
+    AtomicU64,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc;
+use time::Duration;
+
+use sys::{
+    futex_wait_bitset,
+    Clock,
+    futex_wake_bitset,
+    monotonic_deadline,
+    FutexMode,
+};
+
+// Largest number of permits a `FairSemaphore` can hold.
+pub const FAIR_MAX_VALUE: u32 = i32::MAX as u32;
+
+// Number of abandoned tickets that can be pending at the same time.
+const ABANDONED_SLOTS: usize = 64;
+
+// Marks an occupied slot in `abandoned`, the low half holds the ticket.
+const ABANDONED: u64 = 1 << 32;
+
+#[repr(C)]
+pub struct FairSemaphore {
+    // Tickets below this one may proceed.
+    granted: AtomicU32,
+    // Next ticket to hand out.
+    next: AtomicU32,
+    mode: FutexMode,
+    abandoned: [AtomicU64; ABANDONED_SLOTS],
+}
+
+pub struct FairSemaphoreGuard<'a> {
+    sem: &'a FairSemaphore,
+}
+
+// Returns whether ticket `t` has been granted, accounting for wrap-around.
+fn is_granted(t: u32, granted: u32) -> bool {
+    (granted.wrapping_sub(t) as i32) > 0
+}
+
+// Futex bitset shared by every ticket congruent to `t` modulo 32.
+fn ticket_bit(t: u32) -> u32 {
+    1 << (t % 32)
+}
+
+impl FairSemaphore {
+    pub fn new(value: u32) -> FairSemaphore {
+        FairSemaphore::with_futex_mode(value, FutexMode::Private)
+    }
+
+    // Panics if `value` exceeds `FAIR_MAX_VALUE`.
+    pub fn with_futex_mode(value: u32, mode: FutexMode) -> FairSemaphore {
+        assert!(value <= FAIR_MAX_VALUE, "too many permits for a FairSemaphore");
+        FairSemaphore {
+            granted: AtomicU32::new(value),
+            next: AtomicU32::new(0),
+            mode,
+            abandoned: array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+
+    /// Initializes a FIFO semaphore using shared futexes at `ptr`, which should point into memory
+    /// mapped into every participating process (e.g. `MAP_SHARED`).
+    ///
+    /// Fails if `ptr` is null or misaligned, or `value` exceeds `FAIR_MAX_VALUE`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writes of `mem::size_of::<FairSemaphore>()` bytes, must not hold a
+    /// semaphore that is in use, and must stay mapped for the lifetime `'a`.
+    pub unsafe fn init_at<'a>(ptr: *mut FairSemaphore, value: u32)
+                              -> Result<&'a FairSemaphore, Error> {
+        if ptr.is_null() {
+            return Err(Error::new(ErrorKind::InvalidInput, "null semaphore pointer"));
+        }
+        if !ptr.is_aligned() {
+            return Err(Error::new(ErrorKind::InvalidInput, "misaligned semaphore pointer"));
+        }
+        if value > FAIR_MAX_VALUE {
+            return Err(Error::new(ErrorKind::InvalidInput, "too many permits for a FairSemaphore"));
+        }
+        ptr::write(ptr, FairSemaphore::with_futex_mode(value, FutexMode::Shared));
+        Ok(&*ptr)
+    }
+
+    /// Attaches to a FIFO semaphore previously initialized with `FairSemaphore::init_at()`,
+    /// typically by another process.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to an initialized FIFO semaphore which stays mapped for the lifetime `'a`.
+    pub unsafe fn from_raw_ptr<'a>(ptr: *mut FairSemaphore) -> &'a FairSemaphore {
+        &*ptr
+    }
+
+    // Returns the number of permits available to a newly arriving waiter.
+    pub fn value(&self) -> u32 {
+        let granted = self.granted.load(Ordering::Relaxed);
+        let next = self.next.load(Ordering::Relaxed);
+        (granted.wrapping_sub(next) as i32).max(0) as u32
+    }
+
+    // Panics if `FAIR_MAX_VALUE` permits are already available.
+    pub fn post(&self) {
+        loop {
+            // Ticket `t` is granted by this increment. Waiters only ever move `next` forward, so a
+            // stale `next` overestimates the permits available, never the other way around.
+            let t = self.granted.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |granted| {
+                let available = granted.wrapping_sub(self.next.load(Ordering::SeqCst)) as i32;
+                if available < FAIR_MAX_VALUE as i32 {
+                    Some(granted.wrapping_add(1))
+                } else {
+                    None
+                }
+            }).unwrap_or_else(|_| panic!("semaphore count overflow"));
+            let slot = &self.abandoned[t as usize % ABANDONED_SLOTS];
+            if slot.compare_exchange(ABANDONED | t as u64, 0, Ordering::SeqCst, Ordering::Relaxed)
+                   .is_err() {
+                futex_wake_bitset(self.granted_ptr(), i32::MAX as u32, ticket_bit(t), self.mode)
+                    .unwrap();
+                return;
+            }
+            // Its waiter gave up, pass the permit on.
+        }
+    }
+
+    pub fn wait(&self) -> Result<(), Error> {
+        let t = self.next.fetch_add(1, Ordering::Relaxed);
+        self.wait_ticket(t, ptr::null())
+    }
+
+    // Succeeds only if a permit is available and nobody is queued for it.
+    pub fn try_wait(&self) -> Result<(), Error> {
+        let mut next = self.next.load(Ordering::Relaxed);
+        loop {
+            if !is_granted(next, self.granted.load(Ordering::Acquire)) {
+                return Err(Error::new(ErrorKind::WouldBlock, "wait would block"));
+            }
+            match self.next.compare_exchange_weak(next, next.wrapping_add(1), Ordering::Acquire,
+                                                  Ordering::Relaxed) {
+                Ok(_) => return Ok(()),
+                Err(prev) => next = prev,
+            }
+        }
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        let deadline = monotonic_deadline(timeout);
+        let t = self.next.fetch_add(1, Ordering::Relaxed);
+        self.wait_ticket(t, &deadline)
+    }
+
+    pub fn take(&self) -> Result<FairSemaphoreGuard<'_>, Error> {
+        self.wait()?;
+        Ok(FairSemaphoreGuard {
+            sem: self,
+        })
+    }
+
+    pub fn futex_mode(&self) -> FutexMode {
+        self.mode
+    }
+
+    fn granted_ptr(&self) -> *mut u32 {
+        self.granted.as_ptr()
+    }
+
+    fn wait_ticket(&self, t: u32, deadline: *const libc::timespec) -> Result<(), Error> {
+        loop {
+            let granted = self.granted.load(Ordering::Acquire);
+            if is_granted(t, granted) {
+                return Ok(());
+            }
+            let res = futex_wait_bitset(self.granted_ptr(), granted, deadline, Clock::Monotonic,
+                                        ticket_bit(t), self.mode);
+            if let Err(e) = res {
+                if e.kind() == ErrorKind::Interrupted || e.kind() == ErrorKind::TimedOut {
+                    return self.abandon(t, e);
+                }
+            }
+        }
+    }
+
+    // Gives up on ticket `t`, failing with `err` unless the ticket was granted in the meantime.
+    fn abandon(&self, t: u32, err: Error) -> Result<(), Error> {
+        let slot = &self.abandoned[t as usize % ABANDONED_SLOTS];
+        loop {
+            if is_granted(t, self.granted.load(Ordering::SeqCst)) {
+                return Ok(());
+            }
+            if slot.compare_exchange(0, ABANDONED | t as u64, Ordering::SeqCst, Ordering::Relaxed)
+                   .is_ok() {
+                break;
+            }
+            // The slot still holds an older abandoned ticket, which is ahead of ours in the queue.
+            thread::sleep(StdDuration::from_millis(1));
+        }
+        // A post may have granted the ticket before it was recorded as abandoned. Whoever clears the
+        // slot first gets the permit.
+        if is_granted(t, self.granted.load(Ordering::SeqCst))
+           && slot.compare_exchange(ABANDONED | t as u64, 0, Ordering::SeqCst, Ordering::Relaxed)
+                  .is_ok() {
+            return Ok(());
+        }
+        Err(err)
+    }
+}
+
+unsafe impl Send for FairSemaphore {}
+unsafe impl Sync for FairSemaphore {}
+
+impl<'a> Drop for FairSemaphoreGuard<'a> {
+    fn drop(&mut self) {
+        self.sem.post();
+    }
+}
@@ -0,0 +1,96 @@
+// Fault injection for tests.
+//
+// With the `fault-injection` feature, a test can make the futex-based primitives on its own thread
+// misbehave the way they may in production, without sending real signals or racing real
+// deadlines: skip the fast path of `Semaphore::wait()`, fail futex waits with `Interrupted` or
+// `TimedOut` before they sleep, and delay the wakes the thread issues. Waits see an injected
+// error exactly as they would see the kernel's, so this exercises the error handling of the crate
+// as well as that of its callers.
+//
+// Faults are per thread, so tests running in parallel don't disturb each other. `inject()`
+// installs them and the returned guard restores the previous ones when dropped.
+//
+// ```
+// use sema::Semaphore;
+// use sema::faults::{self, Faults};
+//
+// let sem = Semaphore::new(0);
+// let _faults = faults::inject(Faults { interrupts: 1, ..Faults::default() });
+// assert_eq!(sem.wait().unwrap_err().kind(), ErrorKind::Interrupted);
+// ```
+use std::cell::Cell;
+use std::io::Error;
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use libc;
+
+// The faults injected into the current thread's futex operations.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Faults {
+    // `Semaphore` waits skip the fast path and spinning and go straight to the slow path, which
+    // still takes an available token without sleeping.
+    pub force_slow_path: bool,
+    // The next this many futex waits fail with `ErrorKind::Interrupted` instead of sleeping.
+    pub interrupts: u32,
+    // Then the next this many fail with `ErrorKind::TimedOut`, whether they have a deadline or not.
+    pub timeouts: u32,
+    // Every futex wake waits this long before it is issued.
+    pub wake_delay: Option<StdDuration>,
+}
+
+// Restores the faults in effect before `inject()` when dropped.
+#[must_use = "the faults are removed again when the guard is dropped"]
+pub struct FaultGuard {
+    prev: Faults,
+}
+
+thread_local! {
+    static FAULTS: Cell<Faults> = Cell::new(Faults::default());
+}
+
+// Injects `faults` into the current thread's futex operations, replacing any injected before.
+pub fn inject(faults: Faults) -> FaultGuard {
+    FaultGuard {
+        prev: FAULTS.with(|f| f.replace(faults)),
+    }
+}
+
+// Returns the faults still to be injected into the current thread.
+pub fn current() -> Faults {
+    FAULTS.with(|f| f.get())
+}
+
+impl Drop for FaultGuard {
+    fn drop(&mut self) {
+        FAULTS.with(|f| f.set(self.prev));
+    }
+}
+
+pub(crate) fn force_slow_path() -> bool {
+    current().force_slow_path
+}
+
+// Consumes the next injected wait failure, if any.
+pub(crate) fn wait_fault() -> Option<Error> {
+    FAULTS.with(|f| {
+        let mut faults = f.get();
+        let errno = if faults.interrupts > 0 {
+            faults.interrupts -= 1;
+            libc::EINTR
+        } else if faults.timeouts > 0 {
+            faults.timeouts -= 1;
+            libc::ETIMEDOUT
+        } else {
+            return None;
+        };
+        f.set(faults);
+        Some(Error::from_raw_os_error(errno))
+    })
+}
+
+pub(crate) fn delay_wake() {
+    if let Some(delay) = current().wake_delay {
+        thread::sleep(delay);
+    }
+}
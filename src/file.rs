@@ -0,0 +1,189 @@
+// File-backed semaphores.
+//
+// A `FileSemaphore` keeps its count as decimal text in a regular file, and every operation updates
+// it under an exclusive `flock()`. Any process which can open the file can use the semaphore, so
+// unrelated processes which only share a directory, possibly on a network filesystem with working
+// locks, can coordinate through it.
+//
+// Waiters poll the file with an increasing backoff, which makes these far slower than the other
+// semaphores. The lock is taken per open file, so threads of a process sharing a `FileSemaphore`
+// are serialized by a mutex as well.
+use std::fs::{
+    File,
+    OpenOptions,
+};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{
+    Duration as StdDuration,
+    Instant,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+    Read,
+    Seek,
+    SeekFrom,
+    Write,
+};
+
+use libc;
+use time::Duration;
+
+// Bounds of the sleep between two attempts to acquire a permit.
+const MIN_BACKOFF: StdDuration = StdDuration::from_millis(1);
+const MAX_BACKOFF: StdDuration = StdDuration::from_millis(100);
+
+pub struct FileSemaphore {
+    file: Mutex<File>,
+}
+
+pub struct FileSemaphoreGuard<'a> {
+    sem: &'a FileSemaphore,
+}
+
+impl FileSemaphore {
+    // Creates a new semaphore file at `path`, failing with `ErrorKind::AlreadyExists` if the file
+    // exists.
+    pub fn create<P: AsRef<Path>>(path: P, value: u32) -> Result<FileSemaphore, Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?;
+        let sem = FileSemaphore {
+            file: Mutex::new(file),
+        };
+        // Another process may already have opened the file and posted to it.
+        sem.update(|v| Some(v.saturating_add(value)))?;
+        Ok(sem)
+    }
+
+    // Opens an existing semaphore file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FileSemaphore, Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)?;
+        Ok(FileSemaphore {
+            file: Mutex::new(file),
+        })
+    }
+
+    // Returns the number of available permits.
+    pub fn value(&self) -> Result<u32, Error> {
+        let mut value = 0;
+        self.update(|v| {
+            value = v;
+            None
+        })?;
+        Ok(value)
+    }
+
+    pub fn wait(&self) -> Result<(), Error> {
+        self.wait_until(None)
+    }
+
+    pub fn try_wait(&self) -> Result<(), Error> {
+        if self.update(|v| v.checked_sub(1))? {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::WouldBlock, "wait would block"))
+        }
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        // Negative durations are treated as an already expired timeout.
+        let timeout = timeout.to_std().unwrap_or_default();
+        self.wait_until(Some(Instant::now() + timeout))
+    }
+
+    // Posting fails if the file can't be updated, unlike with in-memory semaphores.
+    pub fn post(&self) -> Result<(), Error> {
+        self.update(|v| v.checked_add(1)).and_then(|updated| {
+            if updated {
+                Ok(())
+            } else {
+                Err(Error::other("semaphore count overflow"))
+            }
+        })
+    }
+
+    pub fn take(&self) -> Result<FileSemaphoreGuard<'_>, Error> {
+        self.wait()?;
+        Ok(FileSemaphoreGuard {
+            sem: self,
+        })
+    }
+
+    fn wait_until(&self, deadline: Option<Instant>) -> Result<(), Error> {
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            match self.try_wait() {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                res => return res,
+            }
+            let mut sleep = backoff;
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(Error::new(ErrorKind::TimedOut, "wait timed out"));
+                }
+                sleep = sleep.min(deadline - now);
+            }
+            thread::sleep(sleep);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    // Reads the count under the file lock and, if `f` returns a new count, writes it back.
+    // Returns whether the count was written.
+    fn update<F: FnOnce(u32) -> Option<u32>>(&self, f: F) -> Result<bool, Error> {
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        let fd = file.as_raw_fd();
+        if unsafe { libc::flock(fd, libc::LOCK_EX) } == -1 {
+            return Err(Error::last_os_error());
+        }
+        let res = read_write(&mut file, f);
+        unsafe {
+            libc::flock(fd, libc::LOCK_UN);
+        }
+        res
+    }
+}
+
+impl<'a> Drop for FileSemaphoreGuard<'a> {
+    fn drop(&mut self) {
+        let res = self.sem.post();
+        debug_assert!(res.is_ok());
+    }
+}
+
+fn read_write<F: FnOnce(u32) -> Option<u32>>(file: &mut File, f: F) -> Result<bool, Error> {
+    let mut buf = String::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_string(&mut buf)?;
+    // A freshly created file is empty until its initial value is written.
+    let value = if buf.is_empty() {
+        0
+    } else {
+        buf.trim().parse().map_err(|_| {
+            Error::new(ErrorKind::InvalidData, "corrupt semaphore file")
+        })?
+    };
+    match f(value) {
+        Some(new) => {
+            let text = format!("{}\n", new);
+            file.seek(SeekFrom::Start(0))?;
+            file.set_len(0)?;
+            file.write_all(text.as_bytes())?;
+            file.sync_data()?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
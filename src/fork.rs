@@ -0,0 +1,128 @@
+// Fork support.
+//
+// Only the thread calling `fork()` exists in the child, so any bookkeeping about waiting threads a
+// semaphore inherited from the parent refers to threads that are not there. Such a semaphore has
+// to be reinitialized in the child before it is used, either by hand with `reinit_in_child()` or
+// automatically by registering it with `reinit_on_fork()` (requires the `atfork` feature).
+//
+// Permits are preserved: the child starts with as many permits as the semaphore had when the
+// parent forked.
+use std::io::Error;
+
+use libc::{
+    self,
+    pid_t,
+};
+
+use sys::Semaphore;
+
+// Checks whether a process with the given pid exists.
+pub(crate) fn is_alive(pid: pid_t) -> bool {
+    let res = unsafe {
+        libc::kill(pid, 0)
+    };
+    // EPERM means the process exists but belongs to someone else.
+    res == 0 || Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+impl Semaphore {
+    /// Resets the state of the semaphore inherited from the parent after `fork()`, keeping its
+    /// current value.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called in a freshly forked child, before any other thread is started. The
+    /// semaphore must not be shared with another process, e.g. through `Semaphore::init_at()`,
+    /// since this would discard the waiters of the processes sharing it.
+    pub unsafe fn reinit_in_child(&self) {
+        self.reset_after_fork();
+    }
+}
+
+#[cfg(feature = "atfork")]
+mod atfork {
+    use std::cell::UnsafeCell;
+    use std::hint;
+    use std::sync::Once;
+    use std::sync::atomic::{
+        Ordering,
+        AtomicBool,
+        AtomicI32,
+    };
+    use std::io::Error;
+
+    use libc;
+
+    use sys::Semaphore;
+
+    // Semaphores to reinitialize in the child, guarded by `LOCK`.
+    //
+    // A spin lock is used rather than a `Mutex` since it is held across `fork()` by the prepare
+    // handler and released independently in the parent and the child.
+    struct Registry {
+        sems: UnsafeCell<Vec<&'static Semaphore>>,
+    }
+
+    unsafe impl Sync for Registry {}
+
+    static REGISTRY: Registry = Registry {
+        sems: UnsafeCell::new(Vec::new()),
+    };
+    static LOCK: AtomicBool = AtomicBool::new(false);
+    static INSTALL: Once = Once::new();
+    // Error returned by `pthread_atfork()`, or 0 if the handlers were installed.
+    static INSTALL_ERR: AtomicI32 = AtomicI32::new(0);
+
+    fn lock() {
+        while LOCK.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            hint::spin_loop();
+        }
+    }
+
+    fn unlock() {
+        LOCK.store(false, Ordering::Release);
+    }
+
+    extern "C" fn prepare() {
+        lock();
+    }
+
+    extern "C" fn parent() {
+        unlock();
+    }
+
+    extern "C" fn child() {
+        unsafe {
+            for sem in (*REGISTRY.sems.get()).iter() {
+                sem.reinit_in_child();
+            }
+        }
+        unlock();
+    }
+
+    impl Semaphore {
+        // Registers the semaphore to be reinitialized with `reinit_in_child()` in the child after
+        // every subsequent `fork()`.
+        //
+        // Fails if the fork handlers could not be installed.
+        pub fn reinit_on_fork(&'static self) -> Result<(), Error> {
+            INSTALL.call_once(|| {
+                let res = unsafe {
+                    libc::pthread_atfork(Some(prepare), Some(parent), Some(child))
+                };
+                INSTALL_ERR.store(res, Ordering::Relaxed);
+            });
+            let err = INSTALL_ERR.load(Ordering::Relaxed);
+            if err != 0 {
+                return Err(Error::from_raw_os_error(err));
+            }
+
+            lock();
+            unsafe {
+                (*REGISTRY.sems.get()).push(self);
+            }
+            unlock();
+            Ok(())
+        }
+    }
+}
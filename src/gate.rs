@@ -0,0 +1,61 @@
+// Gates.
+//
+// A `Gate` lets threads through while it is open and holds them back while it is closed, without
+// counting anything: opening it releases every waiting thread, and closing it only affects threads
+// arriving afterwards. It behaves like a `ManualResetEvent`, which it is built on, and is what
+// "pause the worker pool" features need.
+use std::io::Error;
+
+use time::Duration;
+
+use event::ManualResetEvent;
+use sys::FutexMode;
+
+#[repr(C)]
+pub struct Gate {
+    event: ManualResetEvent,
+}
+
+impl Gate {
+    pub fn new(open: bool) -> Gate {
+        Gate::with_futex_mode(open, FutexMode::Private)
+    }
+
+    // Gates placed in memory shared with other processes must use `FutexMode::Shared`.
+    pub fn with_futex_mode(open: bool, mode: FutexMode) -> Gate {
+        Gate {
+            event: ManualResetEvent::with_futex_mode(open, mode),
+        }
+    }
+
+    // Opens the gate, letting every waiting and arriving thread pass.
+    pub fn open(&self) {
+        self.event.set();
+    }
+
+    // Closes the gate, holding back threads which arrive afterwards.
+    pub fn close(&self) {
+        self.event.reset();
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.event.is_set()
+    }
+
+    // Waits until the gate is open.
+    pub fn wait(&self) -> Result<(), Error> {
+        self.event.wait()
+    }
+
+    pub fn try_wait(&self) -> Result<(), Error> {
+        self.event.try_wait()
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        self.event.wait_timeout(timeout)
+    }
+
+    pub fn futex_mode(&self) -> FutexMode {
+        self.event.futex_mode()
+    }
+}
@@ -0,0 +1,284 @@
+// GNU Make jobserver client and server.
+//
+// A jobserver is a pipe (or, since GNU Make 4.4, a named fifo) holding one byte per available job
+// slot. Taking a slot means reading a byte, releasing it means writing one back. Every process
+// also owns one implicit slot which is never represented in the pipe, so a process only needs to
+// `wait()` for the jobs it runs beyond the first.
+//
+// `Jobserver::from_env()` joins the jobserver advertised in `MAKEFLAGS` by a parent `make -jN`,
+// and `Jobserver::new()` creates a fresh one to hand down to child processes.
+//
+// Reads are done through a non-blocking descriptor of our own where possible (the fifo opened
+// again, or on Linux the pipe reopened through `/proc/self/fd`), since the inherited descriptors
+// are shared with `make` and must not have their flags changed. Without one, `try_wait()` and
+// `wait_timeout()` may block if another process takes the token between `poll()` and `read()`.
+use std::env;
+use std::ffi::CString;
+use std::os::unix::io::RawFd;
+use std::time::Instant;
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc::{
+    self,
+    c_int,
+    c_void,
+};
+use time::Duration;
+
+// Byte written back for every released token. GNU Make writes `+`.
+const TOKEN: u8 = b'+';
+
+// Where the jobserver lives.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Auth {
+    Pipe(RawFd, RawFd),
+    Fifo(String),
+}
+
+pub struct Jobserver {
+    auth: Auth,
+    read: RawFd,
+    write: RawFd,
+    // Whether `read` is non-blocking.
+    nonblocking: bool,
+    // Descriptors closed on drop.
+    owned: Vec<RawFd>,
+}
+
+pub struct JobserverGuard<'a> {
+    jobserver: &'a Jobserver,
+    // The byte the token was read as. Make may tell tokens apart, and expects each one back
+    // unchanged.
+    token: u8,
+}
+
+impl Jobserver {
+    // Creates a new jobserver holding `limit` tokens. Its pipe is inherited by child processes,
+    // pass `makeflags()` to them in `MAKEFLAGS` so they can find it.
+    pub fn new(limit: u32) -> Result<Jobserver, Error> {
+        let mut fds: [c_int; 2] = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } == -1 {
+            return Err(Error::last_os_error());
+        }
+        let mut js = Jobserver::from_auth(Auth::Pipe(fds[0], fds[1]))?;
+        js.owned.extend_from_slice(&fds);
+        for _ in 0..limit {
+            js.post();
+        }
+        Ok(js)
+    }
+
+    // Joins the jobserver advertised by a parent `make` in `MAKEFLAGS` (or `CARGO_MAKEFLAGS`).
+    //
+    // Fails with `ErrorKind::NotFound` if there is no jobserver or its descriptors were not
+    // inherited, e.g. because the recipe was not marked with `+`.
+    pub fn from_env() -> Result<Jobserver, Error> {
+        let flags = ["CARGO_MAKEFLAGS", "MAKEFLAGS", "MFLAGS"].iter()
+            .filter_map(|var| env::var(var).ok())
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no jobserver in environment"))?;
+        let auth = parse_makeflags(&flags)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no jobserver in environment"))?;
+        if let Auth::Pipe(read, write) = auth {
+            if !is_open(read) || !is_open(write) {
+                return Err(Error::new(ErrorKind::NotFound, "jobserver descriptors not inherited"));
+            }
+        }
+        Jobserver::from_auth(auth)
+    }
+
+    // Returns the `MAKEFLAGS` argument pointing children at this jobserver.
+    pub fn makeflags(&self) -> String {
+        match self.auth {
+            Auth::Pipe(read, write) => format!("--jobserver-auth={},{}", read, write),
+            Auth::Fifo(ref path) => format!("--jobserver-auth=fifo:{}", path),
+        }
+    }
+
+    pub fn wait(&self) -> Result<(), Error> {
+        self.read().map(|_| ())
+    }
+
+    pub fn try_wait(&self) -> Result<(), Error> {
+        self.try_read().map(|_| ())
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        // Negative durations are treated as an already expired timeout.
+        let deadline = Instant::now() + timeout.to_std().unwrap_or_default();
+        loop {
+            match self.try_wait() {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                res => return res,
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::new(ErrorKind::TimedOut, "wait timed out"));
+            }
+            let left = deadline - now;
+            let millis = left.as_millis() + !left.subsec_nanos().is_multiple_of(1_000_000) as u128;
+            self.poll(millis.min(c_int::MAX as u128) as c_int)?;
+        }
+    }
+
+    pub fn post(&self) {
+        self.write(TOKEN);
+    }
+
+    // Takes a token, which the guard writes back as the same byte it was read as.
+    pub fn take(&self) -> Result<JobserverGuard<'_>, Error> {
+        let token = self.read()?;
+        Ok(JobserverGuard {
+            jobserver: self,
+            token,
+        })
+    }
+
+    // Reads a token, blocking until one is available, and returns its byte.
+    fn read(&self) -> Result<u8, Error> {
+        loop {
+            match self.try_read() {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                res => return res,
+            }
+            self.poll(-1)?;
+        }
+    }
+
+    fn try_read(&self) -> Result<u8, Error> {
+        if !self.nonblocking && !self.poll(0)? {
+            return Err(Error::new(ErrorKind::WouldBlock, "wait would block"));
+        }
+        let mut buf = [0u8; 1];
+        let res = unsafe {
+            libc::read(self.read, buf.as_mut_ptr() as *mut c_void, 1)
+        };
+        match res {
+            1 => Ok(buf[0]),
+            0 => Err(Error::new(ErrorKind::UnexpectedEof, "jobserver closed")),
+            _ => Err(Error::last_os_error()),
+        }
+    }
+
+    fn write(&self, token: u8) {
+        let res = unsafe {
+            libc::write(self.write, &token as *const u8 as *const c_void, 1)
+        };
+        debug_assert_eq!(res, 1);
+    }
+
+    fn from_auth(auth: Auth) -> Result<Jobserver, Error> {
+        let mut js = match auth {
+            Auth::Pipe(read, write) => {
+                Jobserver {
+                    auth: auth.clone(),
+                    read,
+                    write,
+                    nonblocking: false,
+                    owned: Vec::new(),
+                }
+            }
+            Auth::Fifo(ref path) => {
+                let fd = open(path, libc::O_RDWR)?;
+                Jobserver {
+                    auth: auth.clone(),
+                    read: fd,
+                    write: fd,
+                    nonblocking: false,
+                    owned: vec![fd],
+                }
+            }
+        };
+        // A separate non-blocking reader, see above.
+        let path = match auth {
+            Auth::Pipe(read, _) => format!("/proc/self/fd/{}", read),
+            Auth::Fifo(ref path) => path.clone(),
+        };
+        if let Ok(fd) = open(&path, libc::O_RDONLY | libc::O_NONBLOCK) {
+            js.read = fd;
+            js.nonblocking = true;
+            js.owned.push(fd);
+        }
+        Ok(js)
+    }
+
+    // Waits until a token may be available, returning whether one is.
+    fn poll(&self, millis: c_int) -> Result<bool, Error> {
+        let mut pfd = libc::pollfd {
+            fd: self.read,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let res = unsafe {
+            libc::poll(&mut pfd, 1, millis)
+        };
+        if res == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(res > 0)
+        }
+    }
+}
+
+impl Drop for Jobserver {
+    fn drop(&mut self) {
+        for &fd in self.owned.iter() {
+            unsafe {
+                libc::close(fd);
+            }
+        }
+    }
+}
+
+impl<'a> Drop for JobserverGuard<'a> {
+    fn drop(&mut self) {
+        self.jobserver.write(self.token);
+    }
+}
+
+// Extracts the jobserver from `MAKEFLAGS`. The last occurrence wins, as in GNU Make.
+fn parse_makeflags(flags: &str) -> Option<Auth> {
+    flags.split_whitespace()
+        .rev()
+        .find_map(|arg| {
+            arg.strip_prefix("--jobserver-auth=")
+               .or_else(|| arg.strip_prefix("--jobserver-fds="))
+        })
+        .and_then(|auth| {
+            if let Some(path) = auth.strip_prefix("fifo:") {
+                return Some(Auth::Fifo(path.to_owned()));
+            }
+            let mut fds = auth.splitn(2, ',');
+            let read = fds.next()?.parse().ok()?;
+            let write = fds.next()?.parse().ok()?;
+            // Negative descriptors mean the jobserver is disabled.
+            if read < 0 || write < 0 {
+                None
+            } else {
+                Some(Auth::Pipe(read, write))
+            }
+        })
+}
+
+fn is_open(fd: RawFd) -> bool {
+    unsafe {
+        libc::fcntl(fd, libc::F_GETFD) != -1
+    }
+}
+
+fn open(path: &str, flags: c_int) -> Result<RawFd, Error> {
+    let c_path = CString::new(path).map_err(|_| {
+        Error::new(ErrorKind::InvalidInput, "jobserver path contains a nul byte")
+    })?;
+    let fd = unsafe {
+        libc::open(c_path.as_ptr(), flags | libc::O_CLOEXEC)
+    };
+    if fd == -1 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(fd)
+    }
+}
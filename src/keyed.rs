@@ -0,0 +1,127 @@
+// Per-key semaphores.
+//
+// A `KeyedSemaphore<K>` gives every key its own semaphore with `permits` permits, e.g. to allow at
+// most two concurrent requests per customer. Semaphores are created when a key is first used and
+// dropped again once nobody holds or waits for one of its permits, so the map only holds keys
+// which are in use. Each entry counts its users (holders and waiters) under the map lock, which
+// is what tells the last one out to remove it.
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{
+    Arc,
+    Mutex,
+    MutexGuard,
+};
+use std::io::Error;
+
+use time::Duration;
+
+use sys::Semaphore;
+
+struct Entry {
+    sem: Arc<Semaphore>,
+    users: usize,
+}
+
+pub struct KeyedSemaphore<K> {
+    entries: Mutex<HashMap<K, Entry>>,
+    permits: u32,
+}
+
+pub struct KeyedSemaphoreGuard<'a, K: 'a + Eq + Hash> {
+    keyed: &'a KeyedSemaphore<K>,
+    key: K,
+    sem: Arc<Semaphore>,
+}
+
+impl<K: Eq + Hash + Clone> KeyedSemaphore<K> {
+    // Creates a keyed semaphore allowing `permits` concurrent holders per key.
+    pub fn new(permits: u32) -> KeyedSemaphore<K> {
+        KeyedSemaphore {
+            entries: Mutex::new(HashMap::new()),
+            permits,
+        }
+    }
+
+    pub fn acquire(&self, key: K) -> Result<KeyedSemaphoreGuard<'_, K>, Error> {
+        self.acquire_with(key, |sem| sem.wait())
+    }
+
+    pub fn try_acquire(&self, key: K) -> Result<KeyedSemaphoreGuard<'_, K>, Error> {
+        self.acquire_with(key, |sem| sem.try_wait())
+    }
+
+    pub fn acquire_timeout(&self, key: K, timeout: Duration)
+                           -> Result<KeyedSemaphoreGuard<'_, K>, Error> {
+        self.acquire_with(key, |sem| sem.wait_timeout(timeout))
+    }
+
+    // Returns the number of keys with permits held or waited for.
+    pub fn active_keys(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn permits(&self) -> u32 {
+        self.permits
+    }
+
+    // Registers as a user of `key`'s semaphore and takes a permit from it with `wait`, which runs
+    // without the map lock held.
+    fn acquire_with<F>(&self, key: K, wait: F) -> Result<KeyedSemaphoreGuard<'_, K>, Error>
+        where F: FnOnce(&Semaphore) -> Result<(), Error>
+    {
+        let sem = {
+            let mut entries = self.lock();
+            let permits = self.permits;
+            let entry = entries.entry(key.clone()).or_insert_with(|| {
+                Entry {
+                    sem: Arc::new(Semaphore::new(permits as _)),
+                    users: 0,
+                }
+            });
+            entry.users += 1;
+            entry.sem.clone()
+        };
+        if let Err(e) = wait(&sem) {
+            self.release(&key);
+            return Err(e);
+        }
+        Ok(KeyedSemaphoreGuard {
+            keyed: self,
+            key,
+            sem,
+        })
+    }
+}
+
+impl<K: Eq + Hash> KeyedSemaphore<K> {
+    fn lock(&self) -> MutexGuard<'_, HashMap<K, Entry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Drops a user of `key`'s semaphore, removing the entry once it was the last.
+    fn release(&self, key: &K) {
+        let mut entries = self.lock();
+        let idle = {
+            let entry = entries.get_mut(key).expect("keyed semaphore entry missing");
+            entry.users -= 1;
+            entry.users == 0
+        };
+        if idle {
+            entries.remove(key);
+        }
+    }
+}
+
+impl<'a, K: Eq + Hash> KeyedSemaphoreGuard<'a, K> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+impl<'a, K: Eq + Hash> Drop for KeyedSemaphoreGuard<'a, K> {
+    fn drop(&mut self) {
+        self.sem.post();
+        self.keyed.release(&self.key);
+    }
+}
@@ -0,0 +1,103 @@
+// Process-wide registry of labelled semaphores.
+//
+// `Semaphore::with_label()` creates a semaphore and records it under a label, such as "db-pool",
+// in a registry shared by the whole process. When a service with dozens of limiters stalls,
+// `labels::dump()` lists every labelled semaphore with its permits and waiters (and its
+// contention counters with the `stats` feature), which usually points straight at the one that
+// ran dry.
+//
+// Labels need not be unique, e.g. every shard of a sharded pool may carry the same one. The
+// registry holds semaphores weakly, so it never keeps one alive, and forgets them once dropped.
+use std::fmt;
+use std::ptr;
+use std::sync::{
+    Arc,
+    Mutex,
+    MutexGuard,
+    Weak,
+};
+
+use sys::Semaphore;
+#[cfg(feature = "stats")]
+use sys::SemaphoreStats;
+
+static REGISTRY: Mutex<Vec<(String, Weak<Semaphore>)>> = Mutex::new(Vec::new());
+
+// A labelled semaphore's state, as listed by `snapshot()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LabelledSemaphore {
+    pub label: String,
+    pub permits: u32,
+    pub waiters: u32,
+    #[cfg(feature = "stats")]
+    pub stats: SemaphoreStats,
+}
+
+impl Semaphore {
+    // Creates a semaphore with the given value and records it in the process-wide registry
+    // under `label`, see `labels::dump()`.
+    pub fn with_label(label: &str, value: u32) -> Arc<Semaphore> {
+        let sem = Arc::new(Semaphore::new(value));
+        register(label, &sem);
+        sem
+    }
+
+    // Returns the label the semaphore was registered under, if any.
+    pub fn label(&self) -> Option<String> {
+        registry().iter()
+                  .find(|(_, s)| ptr::eq(s.as_ptr(), self))
+                  .map(|(label, _)| label.clone())
+    }
+}
+
+// Records an existing semaphore under `label`. Registering it again adds a second entry.
+pub fn register(label: &str, sem: &Arc<Semaphore>) {
+    let mut registry = registry();
+    registry.retain(|(_, s)| s.strong_count() > 0);
+    registry.push((label.to_owned(), Arc::downgrade(sem)));
+}
+
+// Removes every entry for `sem` from the registry, returning whether there was one.
+pub fn unregister(sem: &Semaphore) -> bool {
+    let mut registry = registry();
+    let len = registry.len();
+    registry.retain(|(_, s)| s.strong_count() > 0 && !ptr::eq(s.as_ptr(), sem));
+    registry.len() != len
+}
+
+// Returns the state of every labelled semaphore still alive, in the order they were registered.
+pub fn snapshot() -> Vec<LabelledSemaphore> {
+    let sems: Vec<(String, Arc<Semaphore>)> = {
+        let mut registry = registry();
+        registry.retain(|(_, s)| s.strong_count() > 0);
+        registry.iter().filter_map(|(l, s)| s.upgrade().map(|s| (l.clone(), s))).collect()
+    };
+    sems.into_iter()
+        .map(|(label, sem)| LabelledSemaphore {
+            label,
+            permits: sem.permits(),
+            waiters: sem.waiters(),
+            #[cfg(feature = "stats")]
+            stats: sem.stats(),
+        })
+        .collect()
+}
+
+// Renders `snapshot()` as text, one semaphore per line.
+pub fn dump() -> String {
+    snapshot().iter().map(|s| format!("{}\n", s)).collect()
+}
+
+fn registry() -> MutexGuard<'static, Vec<(String, Weak<Semaphore>)>> {
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl fmt::Display for LabelledSemaphore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} permits, {} waiters", self.label, self.permits, self.waiters)?;
+        #[cfg(feature = "stats")]
+        write!(f, " (peak {}), {} fast, {} slow, {} timeouts", self.stats.peak_waiters,
+               self.stats.fast_acquisitions, self.stats.slow_acquisitions, self.stats.timeouts)?;
+        Ok(())
+    }
+}
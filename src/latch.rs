@@ -0,0 +1,103 @@
+// Countdown latches.
+//
+// A `CountdownLatch` starts at a count which `count_down()` decrements, and releases every waiter
+// once it reaches zero. The count is the futex word: waiters sleep on the value they last saw, so
+// a decrement racing with a waiter going to sleep makes the sleep fail instead of being missed.
+use std::ops::ControlFlow;
+use std::ptr;
+use std::sync::atomic::{
+    Ordering,
+    AtomicU32,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc;
+use time::Duration;
+
+use sys::{
+    futex_wait_until,
+    futex_wake_bitset,
+    monotonic_deadline,
+    FutexMode,
+    InterruptPolicy,
+    FUTEX_BITSET_MATCH_ANY,
+};
+
+#[repr(C)]
+pub struct CountdownLatch {
+    count: AtomicU32,
+    mode: FutexMode,
+}
+
+impl CountdownLatch {
+    pub fn new(count: u32) -> CountdownLatch {
+        CountdownLatch::with_futex_mode(count, FutexMode::Private)
+    }
+
+    // Latches placed in memory shared with other processes must use `FutexMode::Shared`.
+    pub fn with_futex_mode(count: u32, mode: FutexMode) -> CountdownLatch {
+        CountdownLatch {
+            count: AtomicU32::new(count),
+            mode,
+        }
+    }
+
+    // Decrements the count, releasing the waiters when it reaches zero. Does nothing once the
+    // count is zero.
+    pub fn count_down(&self) {
+        let mut c = self.count.load(Ordering::Relaxed);
+        while c > 0 {
+            // Release, pairing with the acquire in `wait_until()`.
+            match self.count.compare_exchange_weak(c, c - 1, Ordering::Release, Ordering::Relaxed) {
+                Ok(1) => {
+                    futex_wake_bitset(self.count.as_ptr(), i32::MAX as u32,
+                                      FUTEX_BITSET_MATCH_ANY, self.mode).unwrap();
+                    return;
+                }
+                Ok(_) => return,
+                Err(prev) => c = prev,
+            }
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    // Waits until the count reaches zero.
+    pub fn wait(&self) -> Result<(), Error> {
+        self.wait_until(ptr::null())
+    }
+
+    pub fn try_wait(&self) -> Result<(), Error> {
+        if self.count.load(Ordering::Acquire) == 0 {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::WouldBlock, "wait would block"))
+        }
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        let deadline = monotonic_deadline(timeout);
+        self.wait_until(&deadline)
+    }
+
+    pub fn futex_mode(&self) -> FutexMode {
+        self.mode
+    }
+
+    fn wait_until(&self, deadline: *const libc::timespec) -> Result<(), Error> {
+        futex_wait_until(&self.count, deadline, self.mode, InterruptPolicy::Surface, || {
+            match self.count.load(Ordering::Acquire) {
+                0 => ControlFlow::Break(()),
+                c => ControlFlow::Continue(c),
+            }
+        })
+    }
+}
+
+unsafe impl Send for CountdownLatch {}
+unsafe impl Sync for CountdownLatch {}
@@ -0,0 +1,118 @@
+// Wait latency histograms.
+//
+// With the `metrics` feature a semaphore records how long every blocked wait took, in nanoseconds,
+// in a histogram of logarithmic buckets as HDR histograms use: each power of two is split into
+// `SUB_BUCKETS` linear buckets, so that every recorded latency is known to within 25% while the
+// whole range up to `u64::MAX` nanoseconds fits in a fixed array of counters.
+use std::fmt;
+use std::sync::atomic::{
+    Ordering,
+    AtomicU64,
+};
+
+use time::Duration;
+
+// Linear buckets per power of two, as a power of two itself.
+const SUB_BITS: u32 = 2;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+pub(crate) const LATENCY_BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_BUCKETS;
+
+// Returns the bucket holding `nanos`.
+pub(crate) fn bucket(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+    let exp = 63 - nanos.leading_zeros();
+    let sub = (nanos >> (exp - SUB_BITS)) as usize - SUB_BUCKETS;
+    (exp - SUB_BITS + 1) as usize * SUB_BUCKETS + sub
+}
+
+// Returns the largest value falling into bucket `i`.
+fn bucket_max(i: usize) -> u64 {
+    if i < SUB_BUCKETS {
+        return i as u64;
+    }
+    let shift = (i / SUB_BUCKETS - 1) as u32;
+    let low = ((i % SUB_BUCKETS + SUB_BUCKETS) as u64) << shift;
+    low + ((1u64 << shift) - 1)
+}
+
+pub(crate) fn record(buckets: &[AtomicU64; LATENCY_BUCKETS], latency: ::std::time::Duration) {
+    let nanos = latency.as_nanos().min(u64::MAX as u128) as u64;
+    buckets[bucket(nanos)].fetch_add(1, Ordering::Relaxed);
+}
+
+// A snapshot of the latency histogram of a semaphore's blocked waits, see `Semaphore::stats()`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct WaitLatency {
+    counts: [u64; LATENCY_BUCKETS],
+}
+
+impl WaitLatency {
+    pub(crate) fn load(buckets: &[AtomicU64; LATENCY_BUCKETS]) -> WaitLatency {
+        let mut counts = [0; LATENCY_BUCKETS];
+        for (count, bucket) in counts.iter_mut().zip(buckets.iter()) {
+            *count = bucket.load(Ordering::Relaxed);
+        }
+        WaitLatency {
+            counts,
+        }
+    }
+
+    // Returns the number of blocked waits recorded.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    // Returns the latency which `p` percent of the recorded waits didn't exceed, e.g. 99.0 for
+    // the 99th percentile, or `None` if nothing was recorded. The result is the upper end of the
+    // bucket the percentile falls into, so it errs on the slow side.
+    //
+    // # Panics
+    //
+    // Panics unless `0 <= p <= 100`.
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        assert!((0.0..=100.0).contains(&p), "percentile out of range");
+        let total = self.count();
+        if total == 0 {
+            return None;
+        }
+        let rank = ((p / 100.0 * total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(nanos(bucket_max(i)));
+            }
+        }
+        None
+    }
+
+    // Returns the longest recorded latency, to within its bucket.
+    pub fn max(&self) -> Option<Duration> {
+        self.counts.iter().rposition(|&c| c > 0).map(|i| nanos(bucket_max(i)))
+    }
+}
+
+fn nanos(n: u64) -> Duration {
+    Duration::nanoseconds(n.min(i64::MAX as u64) as i64)
+}
+
+impl Default for WaitLatency {
+    fn default() -> WaitLatency {
+        WaitLatency {
+            counts: [0; LATENCY_BUCKETS],
+        }
+    }
+}
+
+impl fmt::Debug for WaitLatency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WaitLatency")
+         .field("count", &self.count())
+         .field("p50", &self.percentile(50.0))
+         .field("p99", &self.percentile(99.0))
+         .field("max", &self.max())
+         .finish()
+    }
+}
@@ -0,0 +1,112 @@
+// Outstanding guard tracking.
+//
+// With the `holders` feature a process-private `Semaphore` remembers which thread took every live
+// `SemaphoreGuard`, and forgets it again when the guard is dropped, so `Semaphore::holders()` can
+// tell who is keeping a saturated semaphore busy.
+//
+// The `leak-check` feature additionally records where each guard was created, as a backtrace. A
+// guard that is never dropped (`mem::forget()`, a reference cycle, a guard stashed in a leaked
+// allocation) holds its permit forever, so when the semaphore is dropped the backtraces still on
+// record point at the culprits. Permits taken with `wait()` and never posted carry no guard, for
+// those the drop only reports that fewer permits are left than the semaphore started with.
+// Backtraces are captured regardless of `RUST_BACKTRACE`, which makes `take()` expensive. This is
+// meant for chasing a leak, not for production builds.
+//
+// The record lives on the heap of the process that created the semaphore, so semaphores using
+// shared futexes, which other processes may map, are not tracked.
+#[cfg(feature = "leak-check")]
+use std::backtrace::Backtrace;
+use std::collections::BTreeMap;
+#[cfg(feature = "leak-check")]
+use std::io::{
+    self,
+    Write,
+};
+use std::sync::Mutex;
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+use std::thread::{
+    self,
+    ThreadId,
+};
+
+// Ids handed to tracked guards, 0 means untracked.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+// A thread holding a `SemaphoreGuard`, see `Semaphore::holders()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Holder {
+    pub thread: ThreadId,
+    pub name: Option<String>,
+}
+
+struct Guard {
+    holder: Holder,
+    #[cfg(feature = "leak-check")]
+    trace: Backtrace,
+}
+
+pub(crate) struct GuardTracker {
+    #[cfg_attr(not(feature = "leak-check"), allow(dead_code))]
+    initial: u32,
+    // Keyed by id, which orders the guards by when they were taken.
+    guards: Mutex<BTreeMap<u64, Guard>>,
+}
+
+impl GuardTracker {
+    pub(crate) fn new(initial: u32) -> GuardTracker {
+        GuardTracker {
+            initial,
+            guards: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    // Records a new guard held by the current thread, returning its id.
+    pub(crate) fn track(&self) -> u64 {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let current = thread::current();
+        let guard = Guard {
+            holder: Holder {
+                thread: current.id(),
+                name: current.name().map(str::to_owned),
+            },
+            #[cfg(feature = "leak-check")]
+            trace: Backtrace::force_capture(),
+        };
+        self.guards.lock().unwrap_or_else(|e| e.into_inner()).insert(id, guard);
+        id
+    }
+
+    pub(crate) fn untrack(&self, id: u64) {
+        self.guards.lock().unwrap_or_else(|e| e.into_inner()).remove(&id);
+    }
+
+    pub(crate) fn outstanding(&self) -> usize {
+        self.guards.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub(crate) fn holders(&self) -> Vec<Holder> {
+        let guards = self.guards.lock().unwrap_or_else(|e| e.into_inner());
+        guards.values().map(|g| g.holder.clone()).collect()
+    }
+
+    // Writes a report to stderr if guards are outstanding or `value` is below the initial value.
+    #[cfg(feature = "leak-check")]
+    pub(crate) fn report(&self, sem: u64, value: u32) {
+        let guards = self.guards.lock().unwrap_or_else(|e| e.into_inner());
+        if guards.is_empty() && value >= self.initial {
+            return;
+        }
+        let stderr = io::stderr();
+        let mut out = stderr.lock();
+        let _ = writeln!(out, "sema: semaphore #{} dropped with {} of {} permits outstanding",
+                         sem, self.initial.saturating_sub(value), self.initial);
+        for guard in guards.values() {
+            let _ = writeln!(out, "sema: guard leaked by thread {} ({:?}), created at:\n{}",
+                             guard.holder.name.as_deref().unwrap_or("<unnamed>"),
+                             guard.holder.thread, guard.trace);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+// Leased permits.
+//
+// A permit taken from a `LeaseSemaphore` is a `Lease` which expires `ttl` after it was granted.
+// Expired leases are reclaimed, their permits returned to the semaphore, and dropping the stale
+// `Lease` later does nothing, so a hung worker can't keep a permit forever.
+//
+// As with the rate limiters, no timer thread is involved: leases are reclaimed whenever somebody
+// tries to acquire, and a waiter sleeps no longer than until the next lease expires.
+//
+// A holder which is still making progress can `renew()` its lease. Renewing and reclaiming both
+// happen under the `leases` lock and compare against the current time there, so a lease is
+// either extended or reclaimed, never both: renewing fails once the lease has run out, even if
+// nobody has reclaimed it yet.
+use std::collections::BTreeMap;
+use std::sync::{
+    Mutex,
+    MutexGuard,
+};
+use std::time::{
+    Duration as StdDuration,
+    Instant,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use time::Duration;
+
+use sys::Semaphore;
+
+pub struct LeaseSemaphore {
+    sem: Semaphore,
+    ttl: StdDuration,
+    leases: Mutex<Leases>,
+}
+
+struct Leases {
+    next: u64,
+    // Expiry of every outstanding lease, by id. There are at most as many as permits.
+    active: BTreeMap<u64, Instant>,
+}
+
+pub struct Lease<'a> {
+    sem: &'a LeaseSemaphore,
+    id: u64,
+    expires: Instant,
+}
+
+impl LeaseSemaphore {
+    // Creates a semaphore with `value` permits, each of which may be held for at most `ttl`.
+    //
+    // # Panics
+    //
+    // Panics if `ttl` is negative.
+    pub fn new(value: u32, ttl: Duration) -> LeaseSemaphore {
+        LeaseSemaphore {
+            sem: Semaphore::new(value as _),
+            ttl: ttl.to_std().expect("ttl must not be negative"),
+            leases: Mutex::new(Leases {
+                next: 0,
+                active: BTreeMap::new(),
+            }),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        Duration::from_std(self.ttl).unwrap_or(Duration::max_value())
+    }
+
+    // Returns the number of leases which are outstanding and not yet known to have expired.
+    pub fn active(&self) -> usize {
+        self.lock().active.len()
+    }
+
+    pub fn acquire(&self) -> Result<Lease<'_>, Error> {
+        self.acquire_until(None)
+    }
+
+    pub fn try_acquire(&self) -> Result<Lease<'_>, Error> {
+        self.reclaim();
+        self.sem.try_wait()?;
+        Ok(self.grant())
+    }
+
+    pub fn acquire_timeout(&self, timeout: Duration) -> Result<Lease<'_>, Error> {
+        // Negative durations are treated as an already expired timeout.
+        let timeout = timeout.to_std().unwrap_or_default();
+        self.acquire_until(Some(Instant::now() + timeout))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Leases> {
+        self.leases.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn acquire_until(&self, deadline: Option<Instant>) -> Result<Lease<'_>, Error> {
+        loop {
+            // Wake up when the next lease expires, to reclaim its permit.
+            let oldest = self.reclaim();
+            let until = match (oldest, deadline) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            let res = match until {
+                None => self.sem.wait(),
+                Some(until) => {
+                    let left = until.saturating_duration_since(Instant::now());
+                    let left = Duration::from_std(left).unwrap_or(Duration::max_value());
+                    self.sem.wait_timeout(left)
+                }
+            };
+            match res {
+                Ok(()) => return Ok(self.grant()),
+                // Only the caller's own deadline ends the wait.
+                Err(e) => {
+                    if e.kind() != ErrorKind::TimedOut
+                       || deadline.is_some_and(|d| Instant::now() >= d) {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+
+    // Returns the permits of expired leases, and the earliest expiry of the remaining ones.
+    fn reclaim(&self) -> Option<Instant> {
+        let now = Instant::now();
+        let mut leases = self.lock();
+        let before = leases.active.len();
+        leases.active.retain(|_, expires| *expires > now);
+        for _ in leases.active.len()..before {
+            self.sem.post();
+        }
+        leases.active.values().min().cloned()
+    }
+
+    // Records a new lease, given a permit from `sem`.
+    fn grant(&self) -> Lease<'_> {
+        let expires = Instant::now() + self.ttl;
+        let mut leases = self.lock();
+        let id = leases.next;
+        leases.next += 1;
+        leases.active.insert(id, expires);
+        Lease {
+            sem: self,
+            id,
+            expires,
+        }
+    }
+}
+
+impl<'a> Lease<'a> {
+    // Returns whether the lease has run out. Its permit may already be in use by someone else.
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires
+    }
+
+    // Extends the lease to expire `ttl` from now, which may be shorter than the semaphore's TTL.
+    // Fails with `ErrorKind::TimedOut` if the lease has already expired, its permit may then be
+    // held by someone else.
+    //
+    // # Panics
+    //
+    // Panics if `ttl` is negative.
+    pub fn renew(&mut self, ttl: Duration) -> Result<(), Error> {
+        let ttl = ttl.to_std().expect("ttl must not be negative");
+        let now = Instant::now();
+        let mut leases = self.sem.lock();
+        match leases.active.get_mut(&self.id) {
+            Some(expires) if *expires > now => {
+                *expires = now + ttl;
+                self.expires = *expires;
+                Ok(())
+            }
+            _ => Err(Error::new(ErrorKind::TimedOut, "lease expired")),
+        }
+    }
+
+    // Returns the time left until the lease expires.
+    pub fn remaining(&self) -> Duration {
+        let left = self.expires.saturating_duration_since(Instant::now());
+        Duration::from_std(left).unwrap_or(Duration::max_value())
+    }
+}
+
+impl<'a> Drop for Lease<'a> {
+    fn drop(&mut self) {
+        // A lease which was already reclaimed no longer owns a permit.
+        if self.sem.lock().active.remove(&self.id).is_some() {
+            self.sem.sem.post();
+        }
+    }
+}
@@ -1,10 +1,388 @@
 extern crate libc;
 extern crate time;
 extern crate rand;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(loom)]
+extern crate loom;
 
 mod sys;
+#[cfg(all(loom,
+          target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod model;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod tsan;
 pub use sys::{
+    InterruptPolicy,
     Semaphore,
     SemaphoreGuard,
 };
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use sys::{
+    Clock,
+    FutexMode,
+    OverflowPolicy,
+    WaitStrategy,
+};
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback"),
+          feature = "stats"))]
+pub use sys::SemaphoreStats;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback"),
+          feature = "observer"))]
+pub use sys::SemaphoreObserver;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback"),
+          feature = "watchdog"))]
+pub use sys::LongWait;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback"),
+          feature = "metrics"))]
+mod latency;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback"),
+          feature = "metrics"))]
+pub use latency::WaitLatency;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback"),
+          feature = "holders"))]
+mod leak;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback"),
+          feature = "holders"))]
+pub use leak::Holder;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback"),
+          feature = "prometheus"))]
+mod prometheus;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback"),
+          feature = "prometheus"))]
+pub use prometheus::PrometheusExporter;
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod cache;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use cache::{
+    PermitCache,
+    PermitCacheGuard,
+};
+
+mod cpus;
+mod sigsafe;
+mod outcome;
+pub use outcome::{
+    TryWaitError,
+    WaitOutcome,
+};
+
+mod padded;
+pub use padded::CachePadded;
+
+mod shared;
+pub use shared::SharedSemaphore;
+
+// `sem_t` interop, only on the POSIX backend.
+#[cfg(not(any(target_os = "macos",
+              target_os = "linux",
+              target_os = "hermit",
+              feature = "spin-fallback")))]
+mod raw;
+#[cfg(not(any(target_os = "macos",
+              target_os = "linux",
+              target_os = "hermit",
+              feature = "spin-fallback")))]
+pub use raw::SemRef;
+
+mod pool;
+pub use pool::{
+    Pool,
+    PoolGuard,
+};
+
+mod pipeline;
+pub use pipeline::{
+    Pipeline,
+    PipelineGuard,
+};
+
+mod queue;
+pub use queue::BoundedQueue;
+
+mod weighted;
+pub use weighted::{
+    WeightedSemaphore,
+    WeightedSemaphoreGuard,
+};
+
+mod limiter;
+pub use limiter::ConcurrencyLimiter;
+
+mod budget;
+pub use budget::{
+    MemoryBudget,
+    MemoryPermit,
+};
+
+mod adaptive;
+pub use adaptive::{
+    AdaptiveLimiter,
+    Aimd,
+    LimitControl,
+    LimitSample,
+};
+
+mod keyed;
+pub use keyed::{
+    KeyedSemaphore,
+    KeyedSemaphoreGuard,
+};
+
+mod quota;
+pub use quota::{
+    QuotaSemaphore,
+    QuotaSemaphoreGuard,
+};
+
+mod lease;
+pub use lease::{
+    Lease,
+    LeaseSemaphore,
+};
+
+mod timed;
+pub use timed::TimedGuard;
+
+mod ratelimit;
+pub use ratelimit::{
+    LeakyBucket,
+    RateLimit,
+    RateLimiter,
+};
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod binary;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use binary::{
+    BinarySemaphore,
+    BinarySemaphoreGuard,
+};
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod event;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use event::{
+    AutoResetEvent,
+    ManualResetEvent,
+};
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod latch;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use latch::CountdownLatch;
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod waitgroup;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use waitgroup::WaitGroup;
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod barrier;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use barrier::Barrier;
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod gate;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use gate::Gate;
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod condvar;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use condvar::Condvar;
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod priority;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use priority::{
+    PrioritySemaphore,
+    PrioritySemaphoreGuard,
+    PRIORITIES,
+};
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod oncegate;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use oncegate::OnceGate;
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod turnstile;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use turnstile::Turnstile;
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod exchanger;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use exchanger::Exchanger;
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod mutex;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use mutex::{
+    FutexMutex,
+    FutexMutexGuard,
+};
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod fair;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use fair::{
+    FairSemaphore,
+    FairSemaphoreGuard,
+    FAIR_MAX_VALUE,
+};
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod eventcount;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use eventcount::{
+    EventCount,
+    WaitKey,
+};
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod sharded;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use sharded::{
+    ShardedSemaphore,
+    ShardedSemaphoreGuard,
+};
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod pi;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use pi::{
+    PiSemaphore,
+    PiSemaphoreGuard,
+};
+
+#[cfg(target_os = "linux")]
+mod mapped;
+#[cfg(target_os = "linux")]
+pub use mapped::{
+    MappedSemaphore,
+    SemaphoreHandle,
+};
+
+// Free functions over the process-wide registry, kept in their own namespace.
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub mod labels;
+// Test-only, kept out of the crate root.
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback"),
+          feature = "fault-injection"))]
+pub mod faults;
+// Kept in its own namespace, the handler-facing API is easy to misuse outside of it.
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub mod signal;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod signalfd;
+
+#[cfg(unix)]
+mod fork;
+
+#[cfg(unix)]
+mod registry;
+
+#[cfg(unix)]
+mod named;
+#[cfg(unix)]
+pub use named::{
+    NamedSemaphore,
+    NamedSemaphoreGuard,
+    NamedSemaphoreOptions,
+};
+
+#[cfg(all(unix,
+          not(target_os = "hermit")))]
+mod sysv;
+#[cfg(all(unix,
+          not(target_os = "hermit")))]
+pub use sysv::{
+    SysVSemaphore,
+    SysVSemaphoreGuard,
+};
+
+#[cfg(target_os = "linux")]
+mod eventfd;
+#[cfg(target_os = "linux")]
+pub use eventfd::{
+    EventFdSemaphore,
+    EventFdSemaphoreGuard,
+};
+
+#[cfg(unix)]
+mod file;
+#[cfg(unix)]
+pub use file::{
+    FileSemaphore,
+    FileSemaphoreGuard,
+};
+
+#[cfg(unix)]
+mod jobserver;
+#[cfg(unix)]
+pub use jobserver::{
+    Jobserver,
+    JobserverGuard,
+};
+
+#[cfg(unix)]
+mod robust;
+#[cfg(unix)]
+pub use robust::{
+    RobustSemaphore,
+    RobustSemaphoreGuard,
+};
 
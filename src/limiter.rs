@@ -0,0 +1,134 @@
+// Concurrency limiters.
+//
+// A `ConcurrencyLimiter` runs closures with at most `limit` of them in flight at once, which is
+// what most users of a counting semaphore actually want. The permit is returned by a guard, so a
+// panicking closure releases it as well. `in_flight` and `queued` are kept for monitoring only,
+// they are updated separately from the semaphore and may be momentarily off.
+//
+// The limit can be changed while closures run. Raising it posts the new permits. Lowering it
+// takes away the permits that are free right now, and records the rest in `owed`: closures
+// finishing while permits are owed pay them off instead of posting, so running closures are never
+// interrupted, but no new ones start until the count is down to the new limit.
+use std::sync::atomic::{
+    Ordering,
+    AtomicUsize,
+};
+use std::io::Error;
+
+use time::Duration;
+
+use sys::Semaphore;
+
+pub struct ConcurrencyLimiter {
+    sem: Semaphore,
+    limit: AtomicUsize,
+    // Permits still to be withdrawn after lowering the limit.
+    owed: AtomicUsize,
+    in_flight: AtomicUsize,
+    queued: AtomicUsize,
+}
+
+// Returns the permit once the closure has finished or panicked.
+struct Running<'a> {
+    limiter: &'a ConcurrencyLimiter,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(limit: usize) -> ConcurrencyLimiter {
+        ConcurrencyLimiter {
+            sem: Semaphore::new(limit as _),
+            limit: AtomicUsize::new(limit),
+            owed: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+        }
+    }
+
+    // Runs `f` once fewer than `limit` closures are running, returning its result.
+    pub fn run<F, R>(&self, f: F) -> Result<R, Error>
+        where F: FnOnce() -> R
+    {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        let res = self.sem.wait();
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+        res?;
+        let _running = self.start();
+        Ok(f())
+    }
+
+    // Runs `f` only if it can start right away.
+    pub fn try_run<F, R>(&self, f: F) -> Result<R, Error>
+        where F: FnOnce() -> R
+    {
+        self.sem.try_wait()?;
+        let _running = self.start();
+        Ok(f())
+    }
+
+    // Runs `f` if it can start within `timeout`.
+    pub fn run_timeout<F, R>(&self, timeout: Duration, f: F) -> Result<R, Error>
+        where F: FnOnce() -> R
+    {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        let res = self.sem.wait_timeout(timeout);
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+        res?;
+        let _running = self.start();
+        Ok(f())
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    // Changes the number of closures allowed to run at once.
+    pub fn set_limit(&self, limit: usize) {
+        let old = self.limit.swap(limit, Ordering::SeqCst);
+        if limit > old {
+            let mut grow = limit - old;
+            // New permits first cancel permits still owed.
+            let _ = self.owed.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |owed| {
+                let paid = owed.min(grow);
+                grow = limit - old - paid;
+                Some(owed - paid)
+            });
+            for _ in 0..grow {
+                self.sem.post();
+            }
+        } else {
+            let mut shrink = old - limit;
+            while shrink > 0 && self.sem.try_wait().is_ok() {
+                shrink -= 1;
+            }
+            self.owed.fetch_add(shrink, Ordering::SeqCst);
+        }
+    }
+
+    // Returns the number of closures currently running.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    // Returns the number of callers waiting for their closure to start.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    // Accounts for a closure about to run, given a permit from `sem`.
+    fn start(&self) -> Running<'_> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        Running {
+            limiter: self,
+        }
+    }
+}
+
+impl<'a> Drop for Running<'a> {
+    fn drop(&mut self) {
+        self.limiter.in_flight.fetch_sub(1, Ordering::Relaxed);
+        let owed = &self.limiter.owed;
+        if owed.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_err() {
+            self.limiter.sem.post();
+        }
+    }
+}
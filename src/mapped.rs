@@ -0,0 +1,217 @@
+// Semaphores in memfd-backed shared memory.
+//
+// A `MappedSemaphore` owns a `memfd_create()` file holding a process-shared `Semaphore` behind a
+// small header, and a mapping of it. Its `SemaphoreHandle` (descriptor number and offset) can be
+// turned into a string, handed to another process which has the descriptor (inherited across
+// `fork()`/`exec()` or received over a Unix socket) and attached to there. Attaching validates the
+// header, so a stale or unrelated descriptor is rejected instead of being used as a semaphore.
+//
+// The memfd is created without `MFD_CLOEXEC` so that it survives `exec()`. The semaphore lives as
+// long as any process keeps the descriptor open or the region mapped.
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::atomic::{
+    Ordering,
+    AtomicU64,
+};
+use std::os::unix::io::RawFd;
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc::{
+    self,
+    c_void,
+    off_t,
+};
+
+use sys::Semaphore;
+
+// Identifies a region created by this module ("SEMAMFD" followed by a nul).
+const MAGIC: u64 = 0x0044_464d_414d_4553;
+// Bumped whenever the layout of `Region` or `Semaphore` changes.
+const VERSION: u32 = 8;
+
+#[repr(C)]
+struct Region {
+    // Written last by the creator, so that a valid magic implies an initialized semaphore.
+    magic: AtomicU64,
+    version: u32,
+    sem_size: u32,
+    sem: Semaphore,
+}
+
+// Serializable reference to a `MappedSemaphore`, formatted as `memfd:<fd>:<offset>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SemaphoreHandle {
+    pub fd: RawFd,
+    pub offset: u64,
+}
+
+pub struct MappedSemaphore {
+    fd: RawFd,
+    map: *mut c_void,
+    map_len: usize,
+    region: *mut Region,
+}
+
+impl MappedSemaphore {
+    // Creates a new memfd holding a process-shared semaphore with the given value.
+    pub fn create(value: u32) -> Result<MappedSemaphore, Error> {
+        let fd = unsafe {
+            libc::memfd_create(b"sema\0".as_ptr() as *const libc::c_char, 0)
+        };
+        if fd == -1 {
+            return Err(Error::last_os_error());
+        }
+        let res = unsafe {
+            libc::ftruncate(fd, mem::size_of::<Region>() as off_t)
+        };
+        if res == -1 {
+            let err = Error::last_os_error();
+            unsafe {
+                libc::close(fd);
+            }
+            return Err(err);
+        }
+
+        let sem = MappedSemaphore::map(fd, 0)?;
+        unsafe {
+            let region = sem.region;
+            ptr::addr_of_mut!((*region).version).write(VERSION);
+            ptr::addr_of_mut!((*region).sem_size).write(mem::size_of::<Semaphore>() as u32);
+            Semaphore::init_at(ptr::addr_of_mut!((*region).sem), value)?;
+            (*region).magic.store(MAGIC, Ordering::Release);
+        }
+        Ok(sem)
+    }
+
+    // Attaches to the semaphore referred to by `handle`, which must be open in this process.
+    //
+    // The descriptor is duplicated, the caller keeps ownership of `handle.fd`. Fails with
+    // `ErrorKind::InvalidData` if the region does not hold a semaphore created by this version of
+    // the crate.
+    pub fn attach(handle: SemaphoreHandle) -> Result<MappedSemaphore, Error> {
+        if !handle.offset.is_multiple_of(mem::align_of::<Region>() as u64) {
+            return Err(Error::new(ErrorKind::InvalidInput, "misaligned semaphore offset"));
+        }
+
+        let mut stat: libc::stat = unsafe {
+            mem::zeroed()
+        };
+        if unsafe { libc::fstat(handle.fd, &mut stat) } == -1 {
+            return Err(Error::last_os_error());
+        }
+        if (stat.st_size as u64) < handle.offset + mem::size_of::<Region>() as u64 {
+            return Err(Error::new(ErrorKind::InvalidData, "semaphore region out of bounds"));
+        }
+
+        let fd = unsafe {
+            libc::fcntl(handle.fd, libc::F_DUPFD_CLOEXEC, 0)
+        };
+        if fd == -1 {
+            return Err(Error::last_os_error());
+        }
+        let sem = MappedSemaphore::map(fd, handle.offset)?;
+        let region = unsafe {
+            &*sem.region
+        };
+        if region.magic.load(Ordering::Acquire) != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a semaphore region"));
+        }
+        if region.version != VERSION || region.sem_size as usize != mem::size_of::<Semaphore>() {
+            return Err(Error::new(ErrorKind::InvalidData, "incompatible semaphore region"));
+        }
+        Ok(sem)
+    }
+
+    pub fn handle(&self) -> SemaphoreHandle {
+        SemaphoreHandle {
+            fd: self.fd,
+            offset: (self.region as usize - self.map as usize) as u64,
+        }
+    }
+
+    // Maps the region at `offset` in `fd`, taking ownership of `fd`.
+    fn map(fd: RawFd, offset: u64) -> Result<MappedSemaphore, Error> {
+        let page = unsafe {
+            libc::sysconf(libc::_SC_PAGESIZE)
+        } as u64;
+        let map_offset = offset - offset % page;
+        let map_len = (offset - map_offset) as usize + mem::size_of::<Region>();
+        let map = unsafe {
+            libc::mmap(ptr::null_mut(), map_len, libc::PROT_READ | libc::PROT_WRITE,
+                       libc::MAP_SHARED, fd, map_offset as off_t)
+        };
+        if map == libc::MAP_FAILED {
+            let err = Error::last_os_error();
+            unsafe {
+                libc::close(fd);
+            }
+            return Err(err);
+        }
+        Ok(MappedSemaphore {
+            fd,
+            map,
+            map_len,
+            region: unsafe { (map as *mut u8).add((offset - map_offset) as usize) } as *mut Region,
+        })
+    }
+}
+
+impl Deref for MappedSemaphore {
+    type Target = Semaphore;
+
+    fn deref(&self) -> &Semaphore {
+        unsafe { &(*self.region).sem }
+    }
+}
+
+unsafe impl Send for MappedSemaphore {}
+unsafe impl Sync for MappedSemaphore {}
+
+impl Drop for MappedSemaphore {
+    fn drop(&mut self) {
+        // The semaphore itself is left alone, other processes may still be using it.
+        let res = unsafe {
+            libc::munmap(self.map, self.map_len)
+        };
+        debug_assert_eq!(res, 0);
+        let res = unsafe {
+            libc::close(self.fd)
+        };
+        debug_assert_eq!(res, 0);
+    }
+}
+
+impl fmt::Display for SemaphoreHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "memfd:{}:{}", self.fd, self.offset)
+    }
+}
+
+impl FromStr for SemaphoreHandle {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SemaphoreHandle, Error> {
+        let invalid = || Error::new(ErrorKind::InvalidInput, "invalid semaphore handle");
+
+        let mut parts = s.split(':');
+        if parts.next() != Some("memfd") {
+            return Err(invalid());
+        }
+        let fd = parts.next().and_then(|p| p.parse().ok()).ok_or_else(invalid)?;
+        let offset = parts.next().and_then(|p| p.parse().ok()).ok_or_else(invalid)?;
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(SemaphoreHandle {
+            fd,
+            offset,
+        })
+    }
+}
@@ -0,0 +1,189 @@
+// A futex modelled with loom primitives.
+//
+// Built with `--cfg loom`, the Linux `Semaphore` keeps its words in loom atomics and sleeps here
+// instead of in the kernel, so loom explores every interleaving of the fast path, the waiter
+// registration in `nwaiters` and the wakeups. The model follows the kernel's contract: a wait
+// compares the word and queues the thread under the same lock a wake takes, so a wake issued
+// after the word changed can't be missed, and `FUTEX_WAKE_OP` adds to the word and wakes under
+// that lock as well. Woken threads are picked in FIFO order.
+//
+// Loom has no notion of time, so a wait with a deadline times out right away instead of sleeping.
+// Spurious wakeups and requeueing are not modelled.
+//
+// Loom also treats `SeqCst` accesses as merely `AcqRel`, which breaks the store-buffering pairs the
+// semaphore relies on: a waiter registers in `nwaiters` and then checks `value`, a post adds to
+// `value` and then checks `nwaiters`, and one of them must see the other. The atomics here wrap
+// loom's and surround every `SeqCst` access with `SeqCst` fences, which loom does model.
+use std::collections::{
+    HashMap,
+    VecDeque,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc;
+use loom::sync::{
+    Condvar,
+    Mutex,
+};
+use loom::sync::atomic::{
+    self,
+    Ordering,
+};
+
+use sys::{
+    Clock,
+    FutexMode,
+};
+
+#[derive(Default)]
+struct Futexes {
+    // Tickets of the threads sleeping on each address, oldest first.
+    queues: HashMap<usize, VecDeque<u64>>,
+    next_ticket: u64,
+}
+
+loom::lazy_static! {
+    static ref FUTEXES: Mutex<Futexes> = Mutex::new(Futexes::default());
+    static ref WOKEN: Condvar = Condvar::new();
+}
+
+// Returns the loom atomic whose address the semaphore passed as its futex word.
+unsafe fn word<'a>(uaddr: *mut u32) -> &'a AtomicU32 {
+    &*(uaddr as *const AtomicU32)
+}
+
+pub(crate) fn futex_wait(uaddr: *mut u32, val: u32, deadline: *const libc::timespec,
+                         _clock: Clock, _mode: FutexMode) -> Result<i32, Error> {
+    let mut futexes = FUTEXES.lock().unwrap();
+    if unsafe { word(uaddr) }.load(Ordering::SeqCst) != val {
+        return Err(Error::from_raw_os_error(libc::EAGAIN));
+    }
+    if !deadline.is_null() {
+        return Err(Error::new(ErrorKind::TimedOut, "wait timed out"));
+    }
+    let ticket = futexes.next_ticket;
+    futexes.next_ticket += 1;
+    futexes.queues.entry(uaddr as usize).or_default().push_back(ticket);
+    // Woken once a wake has taken the ticket off the queue.
+    while futexes.queues.get(&(uaddr as usize)).is_some_and(|q| q.contains(&ticket)) {
+        futexes = WOKEN.wait(futexes).unwrap();
+    }
+    Ok(0)
+}
+
+pub(crate) fn futex_wake(uaddr: *mut u32, val: u32, _mode: FutexMode) -> Result<i32, Error> {
+    let mut futexes = FUTEXES.lock().unwrap();
+    Ok(wake(&mut futexes, uaddr, val))
+}
+
+pub(crate) fn futex_wake_op_add(uaddr: *mut u32, add: u32, wake_max: u32, _mode: FutexMode)
+                                -> Result<i32, Error> {
+    let mut futexes = FUTEXES.lock().unwrap();
+    unsafe { word(uaddr) }.fetch_add(add, Ordering::SeqCst);
+    Ok(wake(&mut futexes, uaddr, wake_max))
+}
+
+fn wake(futexes: &mut Futexes, uaddr: *mut u32, max: u32) -> i32 {
+    let woken = match futexes.queues.get_mut(&(uaddr as usize)) {
+        Some(queue) => {
+            let n = (max as usize).min(queue.len());
+            queue.drain(..n).count()
+        }
+        None => 0,
+    };
+    if woken > 0 {
+        WOKEN.notify_all();
+    }
+    woken as i32
+}
+
+// Issues a fence if `order` is `SeqCst`.
+fn fence_if_seq_cst(order: Ordering) {
+    if order == Ordering::SeqCst {
+        atomic::fence(Ordering::SeqCst);
+    }
+}
+
+macro_rules! seq_cst_atomic {
+    ($name:ident, $inner:ty, $int:ty) => {
+        pub(crate) struct $name($inner);
+
+        impl $name {
+            pub(crate) fn new(v: $int) -> $name {
+                $name(<$inner>::new(v))
+            }
+
+            pub(crate) fn load(&self, order: Ordering) -> $int {
+                fence_if_seq_cst(order);
+                self.0.load(order)
+            }
+
+            pub(crate) fn store(&self, v: $int, order: Ordering) {
+                self.0.store(v, order);
+                fence_if_seq_cst(order);
+            }
+
+            pub(crate) fn swap(&self, v: $int, order: Ordering) -> $int {
+                fence_if_seq_cst(order);
+                let prev = self.0.swap(v, order);
+                fence_if_seq_cst(order);
+                prev
+            }
+
+            pub(crate) fn fetch_add(&self, v: $int, order: Ordering) -> $int {
+                fence_if_seq_cst(order);
+                let prev = self.0.fetch_add(v, order);
+                fence_if_seq_cst(order);
+                prev
+            }
+
+            pub(crate) fn fetch_sub(&self, v: $int, order: Ordering) -> $int {
+                fence_if_seq_cst(order);
+                let prev = self.0.fetch_sub(v, order);
+                fence_if_seq_cst(order);
+                prev
+            }
+
+            pub(crate) fn fetch_max(&self, v: $int, order: Ordering) -> $int {
+                fence_if_seq_cst(order);
+                let prev = self.0.fetch_max(v, order);
+                fence_if_seq_cst(order);
+                prev
+            }
+
+            pub(crate) fn compare_exchange(&self, current: $int, new: $int, success: Ordering,
+                                           failure: Ordering) -> Result<$int, $int> {
+                fence_if_seq_cst(success);
+                let res = self.0.compare_exchange(current, new, success, failure);
+                fence_if_seq_cst(if res.is_ok() { success } else { failure });
+                res
+            }
+
+            pub(crate) fn compare_exchange_weak(&self, current: $int, new: $int,
+                                                success: Ordering, failure: Ordering)
+                                                -> Result<$int, $int> {
+                self.compare_exchange(current, new, success, failure)
+            }
+
+            pub(crate) fn fetch_update<F>(&self, set: Ordering, fetch: Ordering, mut f: F)
+                                          -> Result<$int, $int>
+                where F: FnMut($int) -> Option<$int>
+            {
+                let mut prev = self.load(fetch);
+                while let Some(next) = f(prev) {
+                    match self.compare_exchange(prev, next, set, fetch) {
+                        Ok(v) => return Ok(v),
+                        Err(v) => prev = v,
+                    }
+                }
+                Err(prev)
+            }
+        }
+    };
+}
+
+seq_cst_atomic!(AtomicU32, atomic::AtomicU32, u32);
+seq_cst_atomic!(AtomicU64, atomic::AtomicU64, u64);
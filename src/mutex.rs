@@ -0,0 +1,149 @@
+// Futex-based mutexes.
+//
+// `FutexMutex<T>` is the classic three-state futex mutex from Ulrich Drepper's "Futexes Are
+// Tricky": its one word is 0 when unlocked, 1 when locked, and 2 when locked with threads possibly
+// waiting. Locking and unlocking without contention is a single atomic operation, and unlocking
+// only makes a syscall if somebody may be asleep.
+//
+// There is no poisoning: a guard dropped while panicking unlocks as usual.
+use std::cell::UnsafeCell;
+use std::hint;
+use std::ptr;
+use std::ops::{
+    ControlFlow,
+    Deref,
+    DerefMut,
+};
+use std::sync::atomic::{
+    Ordering,
+    AtomicU32,
+};
+
+use sys::{
+    futex_wait_until,
+    futex_wake_bitset,
+    FutexMode,
+    InterruptPolicy,
+    FUTEX_BITSET_MATCH_ANY,
+};
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+const CONTENDED: u32 = 2;
+
+// Number of polls before a contended lock goes to sleep.
+const SPIN_LIMIT: u32 = 100;
+
+pub struct FutexMutex<T: ?Sized> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+pub struct FutexMutexGuard<'a, T: ?Sized + 'a> {
+    pub(crate) mutex: &'a FutexMutex<T>,
+}
+
+impl<T> FutexMutex<T> {
+    pub const fn new(value: T) -> FutexMutex<T> {
+        FutexMutex {
+            state: AtomicU32::new(UNLOCKED),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> FutexMutex<T> {
+    pub fn lock(&self) -> FutexMutexGuard<'_, T> {
+        if self.state.compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+               .is_err() {
+            self.lock_contended();
+        }
+        FutexMutexGuard {
+            mutex: self,
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<FutexMutexGuard<'_, T>> {
+        self.state.compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| FutexMutexGuard {
+                mutex: self,
+            })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn lock_contended(&self) {
+        // Briefly poll in case the holder is about to unlock.
+        for _ in 0..SPIN_LIMIT {
+            hint::spin_loop();
+            if self.state.load(Ordering::Relaxed) == UNLOCKED
+               && self.state.compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire,
+                                              Ordering::Relaxed).is_ok() {
+                return;
+            }
+        }
+        self.lock_as_contended();
+    }
+
+    // Locks the mutex, marking it contended, so that whoever unlocks it wakes a waiter. Having
+    // slept, we can't know whether others are still waiting, so we take the lock as contended
+    // too. Threads a `Condvar` requeued onto the mutex relock it this way as well.
+    pub(crate) fn lock_as_contended(&self) {
+        // Without a deadline and retrying interruptions, the wait can't fail.
+        let _ = futex_wait_until(&self.state, ptr::null(), FutexMode::Private,
+                                 InterruptPolicy::Retry, || {
+            if self.state.swap(CONTENDED, Ordering::Acquire) == UNLOCKED {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(CONTENDED)
+            }
+        });
+    }
+
+    pub(crate) fn state(&self) -> &AtomicU32 {
+        &self.state
+    }
+
+    pub(crate) fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            futex_wake_bitset(self.state.as_ptr(), 1, FUTEX_BITSET_MATCH_ANY, FutexMode::Private)
+                .unwrap();
+        }
+    }
+}
+
+impl<T: Default> Default for FutexMutex<T> {
+    fn default() -> FutexMutex<T> {
+        FutexMutex::new(T::default())
+    }
+}
+
+unsafe impl<T: ?Sized + Send> Send for FutexMutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for FutexMutex<T> {}
+
+impl<'a, T: ?Sized> Deref for FutexMutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for FutexMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for FutexMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
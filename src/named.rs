@@ -0,0 +1,439 @@
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+pub use self::os::{
+    NamedSemaphore,
+    NamedSemaphoreGuard,
+};
+
+// How many generated names `NamedSemaphoreOptions::create_unique()` tries before giving up.
+const UNIQUE_ATTEMPTS: u32 = 8;
+
+// Options for creating a named semaphore, in the style of `std::fs::OpenOptions`.
+//
+// By default a new semaphore is created with mode `0o700`, creation fails if the name is already
+// taken, and the name is left in place when the semaphore is dropped.
+#[derive(Clone, Debug)]
+pub struct NamedSemaphoreOptions {
+    mode: u32,
+    exclusive: bool,
+    unlink_on_drop: bool,
+    unlink_on_create: bool,
+}
+
+impl NamedSemaphoreOptions {
+    pub fn new() -> NamedSemaphoreOptions {
+        NamedSemaphoreOptions {
+            mode: 0o700,
+            exclusive: true,
+            unlink_on_drop: false,
+            unlink_on_create: false,
+        }
+    }
+
+    // Sets the permission bits of a newly created semaphore.
+    pub fn mode(&mut self, mode: u32) -> &mut NamedSemaphoreOptions {
+        self.mode = mode;
+        self
+    }
+
+    // Sets whether creation fails if a semaphore with the same name already exists. When `false`,
+    // the existing semaphore is opened instead and `value` is ignored.
+    pub fn exclusive(&mut self, exclusive: bool) -> &mut NamedSemaphoreOptions {
+        self.exclusive = exclusive;
+        self
+    }
+
+    // Sets whether the name is unlinked when the returned semaphore is dropped.
+    pub fn unlink_on_drop(&mut self, unlink: bool) -> &mut NamedSemaphoreOptions {
+        self.unlink_on_drop = unlink;
+        self
+    }
+
+    // Sets whether the name is unlinked as soon as the semaphore is created, so that a process
+    // which crashes can't leak it. The returned semaphore stays usable, as do handles inherited
+    // across `fork()`, but no one can open it by name.
+    pub fn unlink_on_create(&mut self, unlink: bool) -> &mut NamedSemaphoreOptions {
+        self.unlink_on_create = unlink;
+        self
+    }
+
+    pub fn create(&self, name: &str, value: u32) -> Result<NamedSemaphore, Error> {
+        NamedSemaphore::create_with(name, value, self)
+    }
+
+    // Creates a semaphore under a generated name of the form `/sema.<pid>.<random>`, returning
+    // the name along with the semaphore. Such semaphores can be found again with
+    // `NamedSemaphore::list_generated()` and cleaned up with `NamedSemaphore::unlink_stale()`.
+    //
+    // The semaphore is always created exclusively, so that a name which happens to be taken is
+    // never opened instead. A fresh name is drawn in that case, up to `UNIQUE_ATTEMPTS` times.
+    pub fn create_unique(&self, value: u32) -> Result<(String, NamedSemaphore), Error> {
+        let mut options = self.clone();
+        options.exclusive = true;
+        let mut attempts = 1;
+        loop {
+            let name = ::registry::unique_name();
+            match NamedSemaphore::create_with(&name, value, &options) {
+                Ok(sem) => {
+                    if !options.unlink_on_create {
+                        ::registry::register(&name);
+                    }
+                    return Ok((name, sem));
+                }
+                Err(ref e) if e.kind() == ErrorKind::AlreadyExists
+                              && attempts < UNIQUE_ATTEMPTS => attempts += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Default for NamedSemaphoreOptions {
+    fn default() -> NamedSemaphoreOptions {
+        NamedSemaphoreOptions::new()
+    }
+}
+
+// Named semaphores.
+//
+// Unlike `Semaphore`, these live in a system-wide namespace and can be opened by unrelated
+// processes, which makes them usable for IPC. Names follow the POSIX convention of a single
+// leading slash followed by up to `NAME_MAX - 4` non-slash characters, e.g. `/my-semaphore`.
+mod os {
+    #[cfg(any(target_os = "linux",
+              target_os = "android"))]
+    use std::cmp;
+    #[cfg(target_os = "freebsd")]
+    use std::ptr;
+    use std::ffi::CString;
+    use std::io::{
+        Error,
+        ErrorKind,
+    };
+    #[cfg(any(target_os = "linux",
+              target_os = "android"))]
+    use std::mem;
+    #[cfg(any(target_os = "linux",
+              target_os = "android"))]
+    use std::sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    };
+
+    #[cfg(not(target_os = "macos"))]
+    use libc;
+    #[cfg(not(target_os = "macos"))]
+    use libc::c_int;
+    #[cfg(any(target_os = "linux",
+              target_os = "android"))]
+    use libc::c_char;
+    use libc::{
+        c_uint,
+        sem_close,
+        sem_open,
+        sem_post,
+        sem_t,
+        sem_trywait,
+        sem_unlink,
+        sem_wait,
+        O_CREAT,
+        O_EXCL,
+        SEM_FAILED,
+    };
+    #[cfg(not(any(target_os = "macos",
+                  target_os = "freebsd")))]
+    use libc::sem_timedwait;
+    use time::Duration;
+
+    // Not exposed by the libc crate.
+    #[cfg(target_os = "freebsd")]
+    extern "C" {
+        fn sem_clockwait_np(sem: *mut sem_t, clock: libc::clockid_t, flags: c_int,
+                            rqtp: *const libc::timespec, rmtp: *mut libc::timespec) -> c_int;
+    }
+
+    use registry;
+    use sys;
+
+    use super::NamedSemaphoreOptions;
+
+    pub struct NamedSemaphore {
+        inner: *mut sem_t,
+        // Set if the name should be unlinked on drop.
+        unlink: Option<CString>,
+    }
+
+    pub struct NamedSemaphoreGuard<'a> {
+        sem: &'a NamedSemaphore,
+    }
+
+    // Converts a name to a `CString`, rejecting interior nul bytes.
+    fn to_cname(name: &str) -> Result<CString, Error> {
+        CString::new(name).map_err(|_| {
+            Error::new(ErrorKind::InvalidInput, "semaphore name contains a nul byte")
+        })
+    }
+
+    // Converts a relative `Duration` to an absolute time of `clock`, as expected by
+    // `sem_timedwait()` and its variants. Negative durations are treated as an already expired
+    // timeout, and ones too long to represent as the latest time a `timespec` holds.
+    #[cfg(not(target_os = "macos"))]
+    fn to_deadline(clock: libc::clockid_t, dur: Duration) -> libc::timespec {
+        let mut now = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe {
+            libc::clock_gettime(clock, &mut now);
+        }
+        sys::timespec_add(now, sys::to_timespec(dur))
+    }
+
+    #[cfg(any(target_os = "linux",
+              target_os = "android"))]
+    type SemClockwait = unsafe extern "C" fn(*mut sem_t, libc::clockid_t, *const libc::timespec)
+                                             -> c_int;
+
+    // Looks up `sem_clockwait()` at runtime, since glibc only has it from 2.30 and bionic from
+    // API level 30, and linking to it would keep the crate from loading on older systems.
+    #[cfg(any(target_os = "linux",
+              target_os = "android"))]
+    fn sem_clockwait() -> Option<SemClockwait> {
+        // 0 until looked up, 1 if missing.
+        static ADDR: AtomicUsize = AtomicUsize::new(0);
+        let mut addr = ADDR.load(Ordering::Relaxed);
+        if addr == 0 {
+            addr = unsafe {
+                libc::dlsym(libc::RTLD_DEFAULT, b"sem_clockwait\0".as_ptr() as *const c_char)
+            } as usize;
+            addr = cmp::max(addr, 1);
+            ADDR.store(addr, Ordering::Relaxed);
+        }
+        if addr == 1 {
+            None
+        } else {
+            Some(unsafe { mem::transmute::<usize, SemClockwait>(addr) })
+        }
+    }
+
+    // Waits on `sem` for up to `timeout`. Where the platform lets us choose, the deadline is on
+    // the monotonic clock, so that setting the system time can't make the wait end early or
+    // hang. `sem_timedwait()` only takes `CLOCK_REALTIME` deadlines.
+    #[cfg(any(target_os = "linux",
+              target_os = "android"))]
+    unsafe fn timedwait(sem: *mut sem_t, timeout: Duration) -> c_int {
+        match sem_clockwait() {
+            Some(clockwait) => {
+                clockwait(sem, libc::CLOCK_MONOTONIC, &to_deadline(libc::CLOCK_MONOTONIC, timeout))
+            }
+            None => sem_timedwait(sem, &to_deadline(libc::CLOCK_REALTIME, timeout)),
+        }
+    }
+
+    #[cfg(target_os = "freebsd")]
+    unsafe fn timedwait(sem: *mut sem_t, timeout: Duration) -> c_int {
+        sem_clockwait_np(sem, libc::CLOCK_MONOTONIC, libc::TIMER_ABSTIME,
+                         &to_deadline(libc::CLOCK_MONOTONIC, timeout), ptr::null_mut())
+    }
+
+    #[cfg(not(any(target_os = "linux",
+                  target_os = "android",
+                  target_os = "freebsd",
+                  target_os = "macos")))]
+    unsafe fn timedwait(sem: *mut sem_t, timeout: Duration) -> c_int {
+        sem_timedwait(sem, &to_deadline(libc::CLOCK_REALTIME, timeout))
+    }
+
+    impl NamedSemaphore {
+        // Creates a new named semaphore, failing with `ErrorKind::AlreadyExists` if one with the
+        // given name already exists.
+        pub fn create(name: &str, value: u32) -> Result<NamedSemaphore, Error> {
+            NamedSemaphore::create_with(name, value, &NamedSemaphoreOptions::new())
+        }
+
+        pub(crate) fn create_with(name: &str, value: u32, options: &NamedSemaphoreOptions)
+                                  -> Result<NamedSemaphore, Error> {
+            let c_name = to_cname(name)?;
+            let oflag = if options.exclusive {
+                O_CREAT | O_EXCL
+            } else {
+                O_CREAT
+            };
+            let sem = unsafe {
+                sem_open(c_name.as_ptr(), oflag, options.mode as c_uint, value as c_uint)
+            };
+            if sem == SEM_FAILED {
+                return Err(Error::last_os_error());
+            }
+            let unlink = options.unlink_on_drop && !options.unlink_on_create;
+            let sem = NamedSemaphore {
+                inner: sem,
+                unlink: if unlink { Some(c_name) } else { None },
+            };
+            if options.unlink_on_create {
+                // Dropping `sem` closes it should this fail.
+                NamedSemaphore::unlink(name)?;
+            }
+            Ok(sem)
+        }
+
+        // Opens an existing named semaphore.
+        pub fn open(name: &str) -> Result<NamedSemaphore, Error> {
+            let c_name = to_cname(name)?;
+            let sem = unsafe {
+                sem_open(c_name.as_ptr(), 0)
+            };
+            if sem == SEM_FAILED {
+                Err(Error::last_os_error())
+            } else {
+                Ok(NamedSemaphore {
+                    inner: sem,
+                    unlink: None,
+                })
+            }
+        }
+
+        // Removes a named semaphore. Processes which already have it open may keep using it, it is
+        // destroyed once all of them have closed it.
+        pub fn unlink(name: &str) -> Result<(), Error> {
+            let c_name = to_cname(name)?;
+            let res = unsafe {
+                sem_unlink(c_name.as_ptr())
+            };
+            if res == -1 {
+                Err(Error::last_os_error())
+            } else {
+                registry::deregister(name);
+                Ok(())
+            }
+        }
+
+        pub fn wait(&self) -> Result<(), Error> {
+            let res = unsafe {
+                sem_wait(self.inner)
+            };
+            if res == -1 {
+                Err(Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        }
+
+        pub fn try_wait(&self) -> Result<(), Error> {
+            let res = unsafe {
+                sem_trywait(self.inner)
+            };
+            if res == -1 {
+                Err(Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        }
+
+        #[cfg(not(target_os = "macos"))]
+        pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+            let res = unsafe {
+                timedwait(self.inner, timeout)
+            };
+            if res == -1 {
+                Err(Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        }
+
+        // OS X does not implement `sem_timedwait()`, so we poll `sem_trywait()` until the deadline
+        // passes, sleeping twice as long after every failed attempt, from `MIN_BACKOFF` up to
+        // `MAX_BACKOFF`. A waiter which has been waiting a while only notices a post at its next
+        // attempt, up to `MAX_BACKOFF` later, in exchange for not burning CPU time.
+        #[cfg(target_os = "macos")]
+        pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+            use std::thread;
+            use std::time::{
+                Duration as StdDuration,
+                Instant,
+            };
+
+            const MIN_BACKOFF: StdDuration = StdDuration::from_micros(50);
+            const MAX_BACKOFF: StdDuration = StdDuration::from_millis(10);
+
+            // Negative durations are treated as an already expired timeout, and ones too large
+            // for the clock as no timeout at all.
+            let deadline = Instant::now().checked_add(timeout.to_std().unwrap_or_default());
+            let mut backoff = MIN_BACKOFF;
+            loop {
+                match self.try_wait() {
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                    res => return res,
+                }
+                let mut sleep = backoff;
+                if let Some(deadline) = deadline {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(Error::new(ErrorKind::TimedOut, "wait timed out"));
+                    }
+                    sleep = sleep.min(deadline - now);
+                }
+                thread::sleep(sleep);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+
+        // Panics if `sem_post()` fails, see `try_post()`.
+        pub fn post(&self) {
+            if let Err(err) = self.try_post() {
+                panic!("failed to post semaphore: {}", err);
+            }
+        }
+
+        // Fails if `sem_post()` does, e.g. with `EOVERFLOW` once the count is at `SEM_VALUE_MAX`.
+        pub fn try_post(&self) -> Result<(), Error> {
+            let res = unsafe {
+                sem_post(self.inner)
+            };
+            if res == -1 {
+                return Err(Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        pub fn take(&self) -> Result<NamedSemaphoreGuard<'_>, Error> {
+            self.wait()?;
+            Ok(NamedSemaphoreGuard {
+                sem: self,
+            })
+        }
+    }
+
+    unsafe impl Send for NamedSemaphore {}
+    unsafe impl Sync for NamedSemaphore {}
+
+    impl Drop for NamedSemaphore {
+        fn drop(&mut self) {
+            let res = unsafe {
+                sem_close(self.inner)
+            };
+            debug_assert_eq!(res, 0);
+            if let Some(ref name) = self.unlink {
+                // Someone else may already have unlinked the name.
+                unsafe {
+                    sem_unlink(name.as_ptr());
+                }
+                if let Ok(name) = name.to_str() {
+                    registry::deregister(name);
+                }
+            }
+        }
+    }
+
+    impl<'a> Drop for NamedSemaphoreGuard<'a> {
+        fn drop(&mut self) {
+            if let Err(err) = self.sem.try_post() {
+                sys::lost_post(err);
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+// One-shot gates.
+//
+// A `OnceGate` starts closed and, once opened, stays open for good. Since it can never close
+// again, waiting on an open gate is a single load, with no waiter registration as a
+// `ManualResetEvent` needs. The futex word moves from `CLOSED` to `WAITING` when a thread is about
+// to sleep, so that `open()` only wakes anyone if somebody may be asleep.
+use std::ops::ControlFlow;
+use std::ptr;
+use std::sync::atomic::{
+    Ordering,
+    AtomicU32,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc;
+use time::Duration;
+
+use sys::{
+    futex_wait_until,
+    futex_wake_bitset,
+    monotonic_deadline,
+    FutexMode,
+    InterruptPolicy,
+    FUTEX_BITSET_MATCH_ANY,
+};
+
+const CLOSED: u32 = 0;
+// Closed, with threads possibly sleeping on it.
+const WAITING: u32 = 1;
+const OPEN: u32 = 2;
+
+#[repr(C)]
+pub struct OnceGate {
+    state: AtomicU32,
+    mode: FutexMode,
+}
+
+impl OnceGate {
+    pub fn new() -> OnceGate {
+        OnceGate::with_futex_mode(FutexMode::Private)
+    }
+
+    // Gates placed in memory shared with other processes must use `FutexMode::Shared`.
+    pub fn with_futex_mode(mode: FutexMode) -> OnceGate {
+        OnceGate {
+            state: AtomicU32::new(CLOSED),
+            mode,
+        }
+    }
+
+    // Opens the gate for good, releasing every waiting thread. Returns whether this call opened
+    // it, `false` if it already was.
+    pub fn open(&self) -> bool {
+        match self.state.swap(OPEN, Ordering::Release) {
+            OPEN => false,
+            WAITING => {
+                futex_wake_bitset(self.state.as_ptr(), i32::MAX as u32, FUTEX_BITSET_MATCH_ANY,
+                                  self.mode).unwrap();
+                true
+            }
+            _ => true,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state.load(Ordering::Acquire) == OPEN
+    }
+
+    // Waits until the gate is open.
+    pub fn wait(&self) -> Result<(), Error> {
+        if self.is_open() {
+            return Ok(());
+        }
+        self.wait_until(ptr::null())
+    }
+
+    pub fn try_wait(&self) -> Result<(), Error> {
+        if self.is_open() {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::WouldBlock, "wait would block"))
+        }
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        if self.is_open() {
+            return Ok(());
+        }
+        let deadline = monotonic_deadline(timeout);
+        self.wait_until(&deadline)
+    }
+
+    pub fn futex_mode(&self) -> FutexMode {
+        self.mode
+    }
+
+    fn wait_until(&self, deadline: *const libc::timespec) -> Result<(), Error> {
+        futex_wait_until(&self.state, deadline, self.mode, InterruptPolicy::Surface, || {
+            match self.state.compare_exchange(CLOSED, WAITING, Ordering::Acquire,
+                                              Ordering::Acquire) {
+                Err(OPEN) => ControlFlow::Break(()),
+                _ => ControlFlow::Continue(WAITING),
+            }
+        })
+    }
+}
+
+impl Default for OnceGate {
+    fn default() -> OnceGate {
+        OnceGate::new()
+    }
+}
+
+unsafe impl Send for OnceGate {}
+unsafe impl Sync for OnceGate {}
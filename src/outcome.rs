@@ -0,0 +1,164 @@
+// Typed results for non-blocking and timed waits.
+//
+// `try_wait()` and `wait_timeout()` report an empty semaphore and an expired timeout as
+// `io::Error`s, so that they compose with `?` alongside every other failure. Callers which treat
+// those as ordinary outcomes have to match on the `ErrorKind`, and the backends don't agree on
+// which one: a POSIX `sem_timedwait()` may report an expired timeout as `EAGAIN` rather than
+// `ETIMEDOUT`. `try_acquire()` and `acquire_timeout()` do that matching once, here.
+//
+// `wait_timeout_remaining()` also reports how much of the timeout was left, which is what a loop
+// retrying interrupted waits needs to keep to the original deadline.
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::io::{
+    Error,
+    ErrorKind,
+};
+use std::time::Instant;
+
+use time::Duration;
+
+use sys::Semaphore;
+
+// Why `Semaphore::try_acquire()` didn't take a token.
+#[derive(Debug)]
+pub enum TryWaitError {
+    // No token was available.
+    WouldBlock,
+    // The wait failed for another reason.
+    Other(Error),
+}
+
+impl From<Error> for TryWaitError {
+    fn from(err: Error) -> TryWaitError {
+        match err.kind() {
+            ErrorKind::WouldBlock => TryWaitError::WouldBlock,
+            _ => TryWaitError::Other(err),
+        }
+    }
+}
+
+impl From<TryWaitError> for Error {
+    fn from(err: TryWaitError) -> Error {
+        match err {
+            TryWaitError::WouldBlock => Error::new(ErrorKind::WouldBlock, "wait would block"),
+            TryWaitError::Other(err) => err,
+        }
+    }
+}
+
+impl fmt::Display for TryWaitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TryWaitError::WouldBlock => f.write_str("wait would block"),
+            TryWaitError::Other(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for TryWaitError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            TryWaitError::WouldBlock => None,
+            TryWaitError::Other(ref err) => Some(err),
+        }
+    }
+}
+
+// How a `Semaphore::acquire_timeout()` ended.
+#[must_use]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaitOutcome {
+    // A token was taken.
+    Acquired,
+    // The timeout passed first.
+    TimedOut,
+}
+
+impl WaitOutcome {
+    pub fn timed_out(self) -> bool {
+        self == WaitOutcome::TimedOut
+    }
+}
+
+impl Semaphore {
+    /// Takes a token if one is available right away, like `try_wait()`, but tells an empty
+    /// semaphore apart from a failure without looking at the `io::ErrorKind`.
+    ///
+    /// ```
+    /// use sema::{Semaphore, TryWaitError};
+    ///
+    /// let sem = Semaphore::new(0);
+    /// match sem.try_acquire() {
+    ///     Err(TryWaitError::WouldBlock) => {}
+    ///     res => panic!("unexpected {:?}", res),
+    /// }
+    /// ```
+    pub fn try_acquire(&self) -> Result<(), TryWaitError> {
+        self.try_wait().map_err(TryWaitError::from)
+    }
+
+    /// Waits up to `timeout` for a token, like `wait_timeout()`, but returns an expired timeout
+    /// as `WaitOutcome::TimedOut` rather than as an error.
+    ///
+    /// ```
+    /// # extern crate sema;
+    /// # extern crate time;
+    /// use sema::{Semaphore, WaitOutcome};
+    ///
+    /// # fn main() {
+    /// let sem = Semaphore::new(1);
+    /// let timeout = time::Duration::milliseconds(10);
+    /// assert_eq!(sem.acquire_timeout(timeout).unwrap(), WaitOutcome::Acquired);
+    /// assert_eq!(sem.acquire_timeout(timeout).unwrap(), WaitOutcome::TimedOut);
+    /// # }
+    /// ```
+    pub fn acquire_timeout(&self, timeout: Duration) -> Result<WaitOutcome, Error> {
+        match self.wait_timeout(timeout) {
+            Ok(()) => Ok(WaitOutcome::Acquired),
+            Err(ref err) if timed_out(err) => Ok(WaitOutcome::TimedOut),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Waits up to `timeout` for a token, like `wait_timeout()`, and also returns how much of
+    /// `timeout` was left when the wait returned, which is zero once it timed out. Waiting again
+    /// for the remainder after an interruption keeps to the original deadline.
+    ///
+    /// ```
+    /// # extern crate sema;
+    /// # extern crate time;
+    /// use std::io::ErrorKind;
+    /// use sema::Semaphore;
+    ///
+    /// # fn main() {
+    /// let sem = Semaphore::new(0);
+    /// let mut timeout = time::Duration::milliseconds(10);
+    /// let res = loop {
+    ///     match sem.wait_timeout_remaining(timeout) {
+    ///         (Err(ref e), remaining) if e.kind() == ErrorKind::Interrupted => timeout = remaining,
+    ///         (res, _) => break res,
+    ///     }
+    /// };
+    /// assert_eq!(res.unwrap_err().kind(), ErrorKind::TimedOut);
+    /// # }
+    /// ```
+    pub fn wait_timeout_remaining(&self, timeout: Duration) -> (Result<(), Error>, Duration) {
+        let start = Instant::now();
+        let res = self.wait_timeout(timeout);
+        let remaining = match res {
+            Err(ref err) if timed_out(err) => Duration::zero(),
+            _ => {
+                let elapsed = Duration::from_std(start.elapsed()).unwrap_or(Duration::max_value());
+                cmp::max(timeout.checked_sub(&elapsed).unwrap_or(Duration::zero()),
+                         Duration::zero())
+            }
+        };
+        (res, remaining)
+    }
+}
+
+fn timed_out(err: &Error) -> bool {
+    matches!(err.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock)
+}
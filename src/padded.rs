@@ -0,0 +1,55 @@
+use std::ops::{
+    Deref,
+    DerefMut,
+};
+
+/// Pads and aligns a value to a 64 byte cache line.
+///
+/// A semaphore embedded in a struct next to frequently written fields shares a cache line with
+/// them, so every update of those fields stalls threads operating on the semaphore and vice versa.
+/// Wrapping it as `CachePadded<Semaphore>` gives it a cache line of its own.
+///
+/// ```
+/// use sema::{CachePadded, Semaphore};
+///
+/// let sem = CachePadded::new(Semaphore::new(1));
+/// sem.wait().unwrap();
+/// sem.post();
+/// ```
+#[derive(Default)]
+#[repr(C, align(64))]
+pub struct CachePadded<T> {
+    value: T,
+}
+
+impl<T> CachePadded<T> {
+    pub fn new(value: T) -> CachePadded<T> {
+        CachePadded {
+            value,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for CachePadded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T> From<T> for CachePadded<T> {
+    fn from(value: T) -> CachePadded<T> {
+        CachePadded::new(value)
+    }
+}
@@ -0,0 +1,143 @@
+// Priority-inheritance binary semaphores.
+//
+// A `PiSemaphore` is built on the kernel's priority-inheritance futexes: while a thread is
+// blocked on it, the thread holding it runs with at least the blocked thread's priority, so a
+// low-priority holder can't be preempted indefinitely by medium-priority threads (priority
+// inversion).
+//
+// The futex word holds the TID of the holder, or 0 when the semaphore is available. Taking and
+// releasing it without contention is a single compare-and-swap, the kernel is only involved once
+// somebody has to block. Since the kernel tracks the holder, only the thread which took the
+// semaphore can release it, and guards can't be sent to other threads.
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{
+    Ordering,
+    AtomicU32,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc;
+use time::Duration;
+
+use sys::{
+    clock_deadline,
+    Clock,
+    futex_lock_pi,
+    futex_unlock_pi,
+    FutexMode,
+};
+use tsan;
+
+#[repr(C)]
+pub struct PiSemaphore {
+    owner: AtomicU32,
+    mode: FutexMode,
+}
+
+pub struct PiSemaphoreGuard<'a> {
+    sem: &'a PiSemaphore,
+    // The semaphore must be released by the thread which took it.
+    _not_send: PhantomData<*const ()>,
+}
+
+// Returns the TID of the calling thread.
+fn gettid() -> u32 {
+    unsafe {
+        libc::gettid() as u32
+    }
+}
+
+impl PiSemaphore {
+    // Creates an available semaphore.
+    pub fn new() -> PiSemaphore {
+        PiSemaphore::with_futex_mode(FutexMode::Private)
+    }
+
+    // Semaphores placed in memory shared with other processes must use `FutexMode::Shared`.
+    pub fn with_futex_mode(mode: FutexMode) -> PiSemaphore {
+        PiSemaphore {
+            owner: AtomicU32::new(0),
+            mode,
+        }
+    }
+
+    // Fails with `ErrorKind::Deadlock` if the calling thread already holds the semaphore.
+    pub fn wait(&self) -> Result<(), Error> {
+        self.wait_until(ptr::null())
+    }
+
+    pub fn try_wait(&self) -> Result<(), Error> {
+        match self.owner.compare_exchange(0, gettid(), Ordering::Acquire, Ordering::Relaxed) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error::new(ErrorKind::WouldBlock, "wait would block")),
+        }
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        // `FUTEX_LOCK_PI` measures its deadline against `CLOCK_REALTIME`.
+        let deadline = clock_deadline(Clock::Realtime, timeout);
+        self.wait_until(&deadline)
+    }
+
+    // Releases the semaphore. Fails with `ErrorKind::PermissionDenied` if the calling thread
+    // doesn't hold it.
+    pub fn post(&self) -> Result<(), Error> {
+        let tid = gettid();
+        // Without waiters the word holds just our TID, otherwise the kernel has to pick the next
+        // holder.
+        if self.owner.compare_exchange(tid, 0, Ordering::Release, Ordering::Relaxed).is_ok() {
+            return Ok(());
+        }
+        tsan::release(self.owner.as_ptr());
+        futex_unlock_pi(self.owner.as_ptr(), self.mode).map(|_| ())
+    }
+
+    pub fn take(&self) -> Result<PiSemaphoreGuard<'_>, Error> {
+        self.wait()?;
+        Ok(PiSemaphoreGuard {
+            sem: self,
+            _not_send: PhantomData,
+        })
+    }
+
+    pub fn futex_mode(&self) -> FutexMode {
+        self.mode
+    }
+
+    fn wait_until(&self, deadline: *const libc::timespec) -> Result<(), Error> {
+        if self.try_wait().is_ok() {
+            return Ok(());
+        }
+        loop {
+            match futex_lock_pi(self.owner.as_ptr(), deadline, self.mode) {
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                // The kernel handed us the word, out of TSan's sight.
+                Ok(_) => {
+                    tsan::acquire(self.owner.as_ptr());
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Default for PiSemaphore {
+    fn default() -> PiSemaphore {
+        PiSemaphore::new()
+    }
+}
+
+unsafe impl Send for PiSemaphore {}
+unsafe impl Sync for PiSemaphore {}
+
+impl<'a> Drop for PiSemaphoreGuard<'a> {
+    fn drop(&mut self) {
+        let res = self.sem.post();
+        debug_assert!(res.is_ok());
+    }
+}
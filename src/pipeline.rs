@@ -0,0 +1,99 @@
+// Pipeline stages.
+//
+// A `Pipeline` bounds the number of items in each of its stages with a semaphore per stage. An
+// item moves on hand over hand: `advance()` first takes a permit of the next stage and only then
+// returns the one of the current stage, so an item is never counted in neither stage, and a full
+// stage holds items back in the previous one instead of letting them pile up in between.
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use time::Duration;
+
+use sys::Semaphore;
+
+pub struct Pipeline {
+    stages: Vec<Semaphore>,
+}
+
+pub struct PipelineGuard<'a> {
+    pipeline: &'a Pipeline,
+    stage: usize,
+}
+
+impl Pipeline {
+    // Creates a pipeline with one stage per entry of `capacities`, each admitting that many items.
+    pub fn new(capacities: &[u32]) -> Pipeline {
+        Pipeline {
+            stages: capacities.iter().map(|&c| Semaphore::new(c as _)).collect(),
+        }
+    }
+
+    pub fn stages(&self) -> usize {
+        self.stages.len()
+    }
+
+    // Waits for room in `stage` and enters it. Fails with `ErrorKind::InvalidInput` if there is no
+    // such stage.
+    pub fn enter(&self, stage: usize) -> Result<PipelineGuard<'_>, Error> {
+        self.stage(stage)?.wait()?;
+        Ok(PipelineGuard {
+            pipeline: self,
+            stage,
+        })
+    }
+
+    pub fn try_enter(&self, stage: usize) -> Result<PipelineGuard<'_>, Error> {
+        self.stage(stage)?.try_wait()?;
+        Ok(PipelineGuard {
+            pipeline: self,
+            stage,
+        })
+    }
+
+    fn stage(&self, stage: usize) -> Result<&Semaphore, Error> {
+        self.stages.get(stage).ok_or_else(|| {
+            Error::new(ErrorKind::InvalidInput, "no such pipeline stage")
+        })
+    }
+}
+
+impl<'a> PipelineGuard<'a> {
+    // Returns the stage the item is in.
+    pub fn stage(&self) -> usize {
+        self.stage
+    }
+
+    // Moves on to the next stage, waiting for room in it. The item stays in its current stage if
+    // this fails, with `ErrorKind::InvalidInput` if this is the last stage.
+    pub fn advance(&mut self) -> Result<(), Error> {
+        self.pipeline.stage(self.stage + 1)?.wait()?;
+        self.moved();
+        Ok(())
+    }
+
+    pub fn try_advance(&mut self) -> Result<(), Error> {
+        self.pipeline.stage(self.stage + 1)?.try_wait()?;
+        self.moved();
+        Ok(())
+    }
+
+    pub fn advance_timeout(&mut self, timeout: Duration) -> Result<(), Error> {
+        self.pipeline.stage(self.stage + 1)?.wait_timeout(timeout)?;
+        self.moved();
+        Ok(())
+    }
+
+    // Leaves the current stage, given a permit of the next one.
+    fn moved(&mut self) {
+        self.pipeline.stages[self.stage].post();
+        self.stage += 1;
+    }
+}
+
+impl<'a> Drop for PipelineGuard<'a> {
+    fn drop(&mut self) {
+        self.pipeline.stages[self.stage].post();
+    }
+}
@@ -0,0 +1,120 @@
+// Object pools.
+//
+// A `Pool<T>` holds a set of items, such as connections, and hands them out one at a time. A
+// semaphore counts the items sitting in the pool, so checking one out blocks while all are in
+// use, and the `PoolGuard` returning it posts again.
+use std::mem::{
+    self,
+    ManuallyDrop,
+};
+use std::ops::{
+    Deref,
+    DerefMut,
+};
+use std::sync::{
+    Mutex,
+    MutexGuard,
+};
+use std::io::Error;
+
+use time::Duration;
+
+use sys::Semaphore;
+
+pub struct Pool<T> {
+    items: Mutex<Vec<T>>,
+    sem: Semaphore,
+}
+
+pub struct PoolGuard<'a, T: 'a> {
+    pool: &'a Pool<T>,
+    item: ManuallyDrop<T>,
+}
+
+impl<T> Pool<T> {
+    pub fn new(items: Vec<T>) -> Pool<T> {
+        let count = items.len();
+        Pool {
+            items: Mutex::new(items),
+            sem: Semaphore::new(count as _),
+        }
+    }
+
+    // Adds an item to the pool.
+    pub fn add(&self, item: T) {
+        self.put(item);
+    }
+
+    // Returns the number of items currently in the pool.
+    pub fn available(&self) -> usize {
+        self.lock().len()
+    }
+
+    // Checks out an item, waiting for one to be returned if all are in use.
+    pub fn get(&self) -> Result<PoolGuard<'_, T>, Error> {
+        self.sem.wait()?;
+        Ok(self.checkout())
+    }
+
+    pub fn try_get(&self) -> Result<PoolGuard<'_, T>, Error> {
+        self.sem.try_wait()?;
+        Ok(self.checkout())
+    }
+
+    pub fn get_timeout(&self, timeout: Duration) -> Result<PoolGuard<'_, T>, Error> {
+        self.sem.wait_timeout(timeout)?;
+        Ok(self.checkout())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<T>> {
+        self.items.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Takes an item, given a token from `sem`.
+    fn checkout(&self) -> PoolGuard<'_, T> {
+        let item = self.lock().pop().expect("pool semaphore out of sync with its items");
+        PoolGuard {
+            pool: self,
+            item: ManuallyDrop::new(item),
+        }
+    }
+
+    fn put(&self, item: T) {
+        self.lock().push(item);
+        self.sem.post();
+    }
+}
+
+impl<'a, T> PoolGuard<'a, T> {
+    // Removes the item from the pool for good, e.g. a connection that turned out to be broken.
+    pub fn detach(mut self) -> T {
+        let item = unsafe {
+            ManuallyDrop::take(&mut self.item)
+        };
+        mem::forget(self);
+        item
+    }
+}
+
+impl<'a, T> Deref for PoolGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.item
+    }
+}
+
+impl<'a, T> DerefMut for PoolGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.item
+    }
+}
+
+impl<'a, T> Drop for PoolGuard<'a, T> {
+    fn drop(&mut self) {
+        let item = unsafe {
+            ManuallyDrop::take(&mut self.item)
+        };
+        self.pool.put(item);
+    }
+}
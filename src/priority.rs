@@ -0,0 +1,167 @@
+// Priority semaphores.
+//
+// Waiters on a `PrioritySemaphore` state a priority from 0 to `PRIORITIES - 1`, higher being more
+// urgent, and a permit always goes to a waiter of the highest priority present. Every priority
+// has a futex bucket: waiters sleep on `value` with the bit of their priority as the bitset and
+// count themselves in `nwaiters[priority]`, and a post wakes one waiter of the highest occupied
+// bucket.
+//
+// A waiter only takes a permit while nobody of a higher priority is registered, so a lower
+// priority waiter which happens to be awake can't barge past. Permits which such a waiter left
+// alone are handed down again when the higher priority waiter leaves, in case it left without
+// taking one (timeout, signal).
+use std::array;
+use std::ptr;
+use std::sync::atomic::{
+    Ordering,
+    AtomicU32,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc;
+use time::Duration;
+
+use sys::{
+    futex_wait_bitset,
+    Clock,
+    futex_wake_bitset,
+    monotonic_deadline,
+    FutexMode,
+};
+
+// Number of distinct priorities, one per futex bitset bit.
+pub const PRIORITIES: u8 = 32;
+
+#[repr(C)]
+pub struct PrioritySemaphore {
+    value: AtomicU32,
+    nwaiters: [AtomicU32; PRIORITIES as usize],
+    mode: FutexMode,
+}
+
+pub struct PrioritySemaphoreGuard<'a> {
+    sem: &'a PrioritySemaphore,
+}
+
+impl PrioritySemaphore {
+    pub fn new(value: u32) -> PrioritySemaphore {
+        PrioritySemaphore::with_futex_mode(value, FutexMode::Private)
+    }
+
+    // Semaphores placed in memory shared with other processes must use `FutexMode::Shared`.
+    pub fn with_futex_mode(value: u32, mode: FutexMode) -> PrioritySemaphore {
+        PrioritySemaphore {
+            value: AtomicU32::new(value),
+            nwaiters: array::from_fn(|_| AtomicU32::new(0)),
+            mode,
+        }
+    }
+
+    pub fn value(&self) -> u32 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    // Returns a permit, waking a waiter of the highest priority present.
+    pub fn post(&self) {
+        // SeqCst orders it before the loads of `nwaiters`, pairing with `wait_until()` which
+        // registers before looking at the value.
+        self.value.fetch_add(1, Ordering::SeqCst);
+        self.wake_highest();
+    }
+
+    pub fn wait(&self, priority: u8) -> Result<(), Error> {
+        self.wait_until(priority, ptr::null())
+    }
+
+    // Takes a permit if one is available and no waiter of a higher priority is queued for it.
+    pub fn try_wait(&self, priority: u8) -> Result<(), Error> {
+        check_priority(priority)?;
+        if self.higher_waiting(priority) || !self.try_take() {
+            return Err(Error::new(ErrorKind::WouldBlock, "wait would block"));
+        }
+        Ok(())
+    }
+
+    pub fn wait_timeout(&self, priority: u8, timeout: Duration) -> Result<(), Error> {
+        let deadline = monotonic_deadline(timeout);
+        self.wait_until(priority, &deadline)
+    }
+
+    pub fn take(&self, priority: u8) -> Result<PrioritySemaphoreGuard<'_>, Error> {
+        self.wait(priority)?;
+        Ok(PrioritySemaphoreGuard {
+            sem: self,
+        })
+    }
+
+    pub fn futex_mode(&self) -> FutexMode {
+        self.mode
+    }
+
+    fn try_take(&self) -> bool {
+        let mut value = self.value.load(Ordering::SeqCst);
+        while value > 0 {
+            match self.value.compare_exchange_weak(value, value - 1, Ordering::SeqCst,
+                                                   Ordering::SeqCst) {
+                Ok(_) => return true,
+                Err(prev) => value = prev,
+            }
+        }
+        false
+    }
+
+    fn higher_waiting(&self, priority: u8) -> bool {
+        self.nwaiters[priority as usize + 1..].iter().any(|n| n.load(Ordering::SeqCst) > 0)
+    }
+
+    // Wakes one waiter of the highest occupied bucket, if any.
+    fn wake_highest(&self) {
+        if let Some(p) = self.nwaiters.iter().rposition(|n| n.load(Ordering::SeqCst) > 0) {
+            futex_wake_bitset(self.value.as_ptr(), 1, 1 << p, self.mode).unwrap();
+        }
+    }
+
+    fn wait_until(&self, priority: u8, deadline: *const libc::timespec) -> Result<(), Error> {
+        check_priority(priority)?;
+        let nwaiters = &self.nwaiters[priority as usize];
+        nwaiters.fetch_add(1, Ordering::SeqCst);
+        let res = loop {
+            let value = self.value.load(Ordering::SeqCst);
+            if value > 0 && !self.higher_waiting(priority) && self.try_take() {
+                break Ok(());
+            }
+            let res = futex_wait_bitset(self.value.as_ptr(), value, deadline, Clock::Monotonic,
+                                        1 << priority, self.mode);
+            if let Err(e) = res {
+                if e.kind() == ErrorKind::Interrupted || e.kind() == ErrorKind::TimedOut {
+                    break Err(e);
+                }
+            }
+        };
+        nwaiters.fetch_sub(1, Ordering::SeqCst);
+        // Lower priority waiters may have left permits alone on our account.
+        if self.value.load(Ordering::SeqCst) > 0 {
+            self.wake_highest();
+        }
+        res
+    }
+}
+
+fn check_priority(priority: u8) -> Result<(), Error> {
+    if priority >= PRIORITIES {
+        return Err(Error::new(ErrorKind::InvalidInput, "priority out of range"));
+    }
+    Ok(())
+}
+
+unsafe impl Send for PrioritySemaphore {}
+unsafe impl Sync for PrioritySemaphore {}
+
+impl<'a> Drop for PrioritySemaphoreGuard<'a> {
+    fn drop(&mut self) {
+        self.sem.post();
+    }
+}
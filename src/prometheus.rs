@@ -0,0 +1,153 @@
+// Prometheus text exposition of semaphore statistics.
+//
+// A `PrometheusExporter` holds semaphores registered under a name and renders their `stats()` in
+// the Prometheus text format, ready to be served from a `/metrics` endpoint or written to a node
+// exporter textfile. Every metric carries a `semaphore` label with the registered name, so a
+// saturated limiter shows up as `sema_permits == 0` together with a growing `sema_waiters`.
+//
+// Semaphores are held weakly: one that has been dropped is left out of the output and forgotten.
+// With the `metrics` feature the wait latency histogram is exported as a summary of its p50, p90,
+// p99 and p999. The histogram only knows each latency to within its bucket, so there is no
+// `_sum`.
+use std::fmt::Write;
+use std::sync::{
+    Arc,
+    Mutex,
+    Weak,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use sys::{
+    Semaphore,
+    SemaphoreStats,
+};
+
+#[cfg(feature = "metrics")]
+const QUANTILES: [(&str, f64); 4] = [
+    ("0.5", 50.0),
+    ("0.9", 90.0),
+    ("0.99", 99.0),
+    ("0.999", 99.9),
+];
+
+pub struct PrometheusExporter {
+    sems: Mutex<Vec<(String, Weak<Semaphore>)>>,
+}
+
+impl PrometheusExporter {
+    pub fn new() -> PrometheusExporter {
+        PrometheusExporter {
+            sems: Mutex::new(Vec::new()),
+        }
+    }
+
+    // Exports `sem` under `name`, failing with `ErrorKind::AlreadyExists` if a live semaphore is
+    // already registered under that name.
+    pub fn register(&self, name: &str, sem: &Arc<Semaphore>) -> Result<(), Error> {
+        let mut sems = self.sems.lock().unwrap_or_else(|e| e.into_inner());
+        sems.retain(|(_, s)| s.strong_count() > 0);
+        if sems.iter().any(|(n, _)| n == name) {
+            return Err(Error::new(ErrorKind::AlreadyExists, "semaphore name already registered"));
+        }
+        sems.push((name.to_owned(), Arc::downgrade(sem)));
+        Ok(())
+    }
+
+    // Stops exporting the semaphore registered under `name`, returning whether there was one.
+    pub fn unregister(&self, name: &str) -> bool {
+        let mut sems = self.sems.lock().unwrap_or_else(|e| e.into_inner());
+        let len = sems.len();
+        sems.retain(|(n, _)| n != name);
+        sems.len() != len
+    }
+
+    // Returns the names of the semaphores currently exported.
+    pub fn names(&self) -> Vec<String> {
+        let sems = self.sems.lock().unwrap_or_else(|e| e.into_inner());
+        sems.iter().filter(|(_, s)| s.strong_count() > 0).map(|(n, _)| n.clone()).collect()
+    }
+
+    // Renders the statistics of every registered semaphore in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let snapshots: Vec<(String, SemaphoreStats)> = {
+            let mut sems = self.sems.lock().unwrap_or_else(|e| e.into_inner());
+            sems.retain(|(_, s)| s.strong_count() > 0);
+            sems.iter()
+                .filter_map(|(n, s)| s.upgrade().map(|s| (escape(n), s.stats())))
+                .collect()
+        };
+
+        let mut out = String::new();
+        family(&mut out, "sema_permits", "gauge", "Permits currently available.", &snapshots,
+               |s| s.permits as u64);
+        family(&mut out, "sema_waiters", "gauge", "Threads currently blocked waiting for a permit.",
+               &snapshots, |s| s.waiters as u64);
+        family(&mut out, "sema_peak_waiters", "gauge", "Most threads ever blocked at once.",
+               &snapshots, |s| s.peak_waiters as u64);
+        family(&mut out, "sema_fast_acquisitions_total", "counter",
+               "Permits taken without blocking in the kernel.", &snapshots,
+               |s| s.fast_acquisitions);
+        family(&mut out, "sema_slow_acquisitions_total", "counter",
+               "Permits taken after blocking in the kernel.", &snapshots,
+               |s| s.slow_acquisitions);
+        family(&mut out, "sema_timeouts_total", "counter", "Waits which timed out.", &snapshots,
+               |s| s.timeouts);
+        family(&mut out, "sema_futex_syscalls_total", "counter",
+               "Futex system calls issued by posts and waits.", &snapshots, |s| s.futex_syscalls);
+        #[cfg(feature = "metrics")]
+        latency(&mut out, &snapshots);
+        out
+    }
+}
+
+impl Default for PrometheusExporter {
+    fn default() -> PrometheusExporter {
+        PrometheusExporter::new()
+    }
+}
+
+// Writes one metric family with a sample per semaphore.
+fn family<F: Fn(&SemaphoreStats) -> u64>(out: &mut String, name: &str, kind: &str, help: &str,
+                                         snapshots: &[(String, SemaphoreStats)], value: F) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (sem, stats) in snapshots {
+        let _ = writeln!(out, "{}{{semaphore=\"{}\"}} {}", name, sem, value(stats));
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn latency(out: &mut String, snapshots: &[(String, SemaphoreStats)]) {
+    let name = "sema_wait_seconds";
+    let _ = writeln!(out, "# HELP {} Time blocked waits took to get a permit.", name);
+    let _ = writeln!(out, "# TYPE {} summary", name);
+    for (sem, stats) in snapshots {
+        let hist = &stats.wait_latency;
+        for &(label, p) in QUANTILES.iter() {
+            let value = match hist.percentile(p) {
+                Some(d) => d.num_nanoseconds().unwrap_or(i64::MAX) as f64 / 1e9,
+                None => f64::NAN,
+            };
+            let _ = writeln!(out, "{}{{semaphore=\"{}\",quantile=\"{}\"}} {}", name, sem, label,
+                             value);
+        }
+        let _ = writeln!(out, "{}_count{{semaphore=\"{}\"}} {}", name, sem, hist.count());
+    }
+}
+
+// Escapes a label value as the text format requires.
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,181 @@
+// Bounded MPMC queues.
+//
+// `BoundedQueue` is the textbook producer/consumer arrangement: an `empty` semaphore counts free
+// slots and a `full` semaphore counts filled ones, so producers block while the queue is full and
+// consumers while it is empty. The items themselves live in a lock-free ring of `capacity` slots.
+//
+// Owning a token only guarantees that some slot is free (or filled), not that the slot at our
+// index is done with yet: a slow consumer may still be reading the slot a producer wraps around
+// to. Every slot therefore carries a sequence number, as in Dmitry Vyukov's bounded queue, and a
+// thread briefly spins until its slot is ready.
+use std::cell::UnsafeCell;
+use std::hint;
+use std::mem::MaybeUninit;
+use std::thread;
+use std::sync::atomic::{
+    Ordering,
+    AtomicUsize,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use time::Duration;
+
+use sys::Semaphore;
+
+pub struct BoundedQueue<T> {
+    slots: Box<[Slot<T>]>,
+    // Index of the next slot to fill.
+    tail: AtomicUsize,
+    // Index of the next slot to empty.
+    head: AtomicUsize,
+    empty: Semaphore,
+    full: Semaphore,
+}
+
+struct Slot<T> {
+    // `index` when the slot is free for the producer of that index, `index + 1` when it holds the
+    // item for the consumer of that index.
+    seq: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> BoundedQueue<T> {
+    // # Panics
+    //
+    // Panics if `capacity` is zero.
+    pub fn new(capacity: u32) -> BoundedQueue<T> {
+        assert!(capacity > 0, "capacity must be positive");
+        BoundedQueue {
+            slots: (0..capacity as usize).map(|i| {
+                Slot {
+                    seq: AtomicUsize::new(i),
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                }
+            }).collect(),
+            tail: AtomicUsize::new(0),
+            head: AtomicUsize::new(0),
+            empty: Semaphore::new(capacity as _),
+            full: Semaphore::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    // Returns the number of items in the queue. Only a snapshot while others use the queue.
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Relaxed);
+        tail.wrapping_sub(head).min(self.capacity())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Adds an item, waiting for a free slot if the queue is full.
+    pub fn push(&self, value: T) {
+        retry_interrupted(|| self.empty.wait());
+        self.put(value);
+    }
+
+    // Adds an item if there is a free slot, otherwise hands it back.
+    pub fn try_push(&self, value: T) -> Result<(), T> {
+        if self.empty.try_wait().is_err() {
+            return Err(value);
+        }
+        self.put(value);
+        Ok(())
+    }
+
+    pub fn push_timeout(&self, value: T, timeout: Duration) -> Result<(), T> {
+        if self.empty.wait_timeout(timeout).is_err() {
+            return Err(value);
+        }
+        self.put(value);
+        Ok(())
+    }
+
+    // Removes an item, waiting for one if the queue is empty.
+    pub fn pop(&self) -> T {
+        retry_interrupted(|| self.full.wait());
+        self.get()
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        if self.full.try_wait().is_err() {
+            return None;
+        }
+        Some(self.get())
+    }
+
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        if self.full.wait_timeout(timeout).is_err() {
+            return None;
+        }
+        Some(self.get())
+    }
+
+    // Stores an item, given a token from `empty`.
+    fn put(&self, value: T) {
+        let index = self.tail.fetch_add(1, Ordering::Relaxed);
+        let slot = &self.slots[index % self.slots.len()];
+        wait_for(&slot.seq, index);
+        unsafe {
+            (*slot.value.get()).write(value);
+        }
+        slot.seq.store(index.wrapping_add(1), Ordering::Release);
+        self.full.post();
+    }
+
+    // Takes an item, given a token from `full`.
+    fn get(&self) -> T {
+        let index = self.head.fetch_add(1, Ordering::Relaxed);
+        let slot = &self.slots[index % self.slots.len()];
+        wait_for(&slot.seq, index.wrapping_add(1));
+        let value = unsafe {
+            (*slot.value.get()).assume_init_read()
+        };
+        slot.seq.store(index.wrapping_add(self.slots.len()), Ordering::Release);
+        self.empty.post();
+        value
+    }
+}
+
+// Spins until `seq` reaches `target`. The thread we wait for holds a token and is in the middle of
+// its (short) access to the slot.
+fn wait_for(seq: &AtomicUsize, target: usize) {
+    let mut spins = 0;
+    while seq.load(Ordering::Acquire) != target {
+        if spins < 100 {
+            hint::spin_loop();
+            spins += 1;
+        } else {
+            thread::yield_now();
+        }
+    }
+}
+
+// Blocking waits only fail when interrupted by a signal.
+fn retry_interrupted<F: Fn() -> Result<(), Error>>(wait: F) {
+    loop {
+        match wait() {
+            Ok(()) => return,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => panic!("semaphore wait failed: {}", e),
+        }
+    }
+}
+
+impl<T> Drop for BoundedQueue<T> {
+    fn drop(&mut self) {
+        while self.try_pop().is_some() {}
+    }
+}
+
+unsafe impl<T: Send> Send for BoundedQueue<T> {}
+unsafe impl<T: Send> Sync for BoundedQueue<T> {}
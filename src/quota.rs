@@ -0,0 +1,111 @@
+// Hierarchical quotas.
+//
+// A `QuotaSemaphore` may have a parent, and a permit from it is only granted together with one
+// from each of its ancestors, e.g. a per-tenant limit nested under a global one. Permits are
+// taken from the child up to the root, always in that order, so two acquisitions can't each hold
+// a level the other is waiting for. If a level can't be acquired (no permit for `try_acquire()`,
+// timeout, signal), the levels already taken are given back before the error is returned, so a
+// failed acquisition never keeps part of a permit.
+use std::ptr;
+use std::sync::Arc;
+use std::time::Instant;
+use std::io::Error;
+
+use time::Duration;
+
+use sys::Semaphore;
+
+pub struct QuotaSemaphore {
+    sem: Semaphore,
+    parent: Option<Arc<QuotaSemaphore>>,
+}
+
+pub struct QuotaSemaphoreGuard<'a> {
+    quota: &'a QuotaSemaphore,
+}
+
+impl QuotaSemaphore {
+    // Creates a top-level quota of `value` permits.
+    pub fn new(value: u32) -> QuotaSemaphore {
+        QuotaSemaphore {
+            sem: Semaphore::new(value as _),
+            parent: None,
+        }
+    }
+
+    // Creates a quota of `value` permits nested under `parent`, whose permits it consumes as well.
+    pub fn with_parent(value: u32, parent: Arc<QuotaSemaphore>) -> QuotaSemaphore {
+        QuotaSemaphore {
+            sem: Semaphore::new(value as _),
+            parent: Some(parent),
+        }
+    }
+
+    pub fn parent(&self) -> Option<&Arc<QuotaSemaphore>> {
+        self.parent.as_ref()
+    }
+
+    // Takes a permit from this quota and every ancestor.
+    pub fn acquire(&self) -> Result<QuotaSemaphoreGuard<'_>, Error> {
+        self.acquire_with(|sem| sem.wait())?;
+        Ok(QuotaSemaphoreGuard {
+            quota: self,
+        })
+    }
+
+    pub fn try_acquire(&self) -> Result<QuotaSemaphoreGuard<'_>, Error> {
+        self.acquire_with(|sem| sem.try_wait())?;
+        Ok(QuotaSemaphoreGuard {
+            quota: self,
+        })
+    }
+
+    // Fails if the permits of all levels can't be had within `timeout` in total.
+    pub fn acquire_timeout(&self, timeout: Duration) -> Result<QuotaSemaphoreGuard<'_>, Error> {
+        // Negative durations are treated as an already expired timeout.
+        let deadline = Instant::now() + timeout.to_std().unwrap_or_default();
+        self.acquire_with(|sem| {
+            let left = deadline.saturating_duration_since(Instant::now());
+            sem.wait_timeout(Duration::from_std(left).unwrap_or(Duration::max_value()))
+        })?;
+        Ok(QuotaSemaphoreGuard {
+            quota: self,
+        })
+    }
+
+    // Takes a permit from each level with `wait`, from this one up to the root, rolling back on
+    // failure.
+    fn acquire_with<F>(&self, mut wait: F) -> Result<(), Error>
+        where F: FnMut(&Semaphore) -> Result<(), Error>
+    {
+        let mut level = self;
+        loop {
+            if let Err(e) = wait(&level.sem) {
+                self.release_below(level);
+                return Err(e);
+            }
+            match level.parent {
+                Some(ref parent) => level = parent,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    // Returns a permit to every level from this one up to, but excluding, `stop`.
+    fn release_below(&self, stop: *const QuotaSemaphore) {
+        let mut level = Some(self);
+        while let Some(quota) = level {
+            if ptr::eq(quota, stop) {
+                break;
+            }
+            quota.sem.post();
+            level = quota.parent.as_deref();
+        }
+    }
+}
+
+impl<'a> Drop for QuotaSemaphoreGuard<'a> {
+    fn drop(&mut self) {
+        self.quota.release_below(ptr::null());
+    }
+}
@@ -0,0 +1,221 @@
+// Rate limiters.
+//
+// A `RateLimiter` is a token bucket: it holds up to `burst` permits and gains `rate` permits per
+// second. Rather than having a timer thread top it up, the bucket is refilled from the time elapsed
+// since the last acquisition whenever somebody tries to acquire a permit, and a thread finding it
+// empty sleeps until the next permit is due.
+//
+// A `LeakyBucket` doesn't allow bursts: it spaces acquisitions evenly, `1 / rate` seconds apart,
+// which is what quotas that forbid bursts require. Both implement `RateLimit`, so callers can be
+// written against either policy.
+use std::cmp;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{
+    Duration as StdDuration,
+    Instant,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use time::Duration;
+
+// Operations common to all rate limiters.
+pub trait RateLimit {
+    // Waits until a permit is available and takes it.
+    fn acquire(&self);
+
+    fn try_acquire(&self) -> Result<(), Error>;
+
+    // Waits for a permit for at most `timeout`. Fails right away if the next permit is not due
+    // before the timeout expires.
+    fn acquire_timeout(&self, timeout: Duration) -> Result<(), Error>;
+}
+
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    state: Mutex<Bucket>,
+}
+
+struct Bucket {
+    // Fractional, since permits accrue continuously.
+    permits: f64,
+    refilled: Instant,
+}
+
+pub struct LeakyBucket {
+    interval: StdDuration,
+    // When the next permit may be taken.
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    // Creates a limiter handing out `rate` permits per second on average, and up to `burst` at
+    // once. It starts out full.
+    //
+    // # Panics
+    //
+    // Panics if `rate` is not positive or `burst` is zero.
+    pub fn new(rate: f64, burst: u32) -> RateLimiter {
+        assert!(rate > 0.0, "rate must be positive");
+        assert!(burst > 0, "burst must be positive");
+        RateLimiter {
+            rate,
+            burst: burst as f64,
+            state: Mutex::new(Bucket {
+                permits: burst as f64,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn burst(&self) -> u32 {
+        self.burst as u32
+    }
+
+    // Waits until a permit is available and takes it.
+    pub fn acquire(&self) {
+        acquire(|| self.try_take())
+    }
+
+    pub fn try_acquire(&self) -> Result<(), Error> {
+        try_acquire(|| self.try_take())
+    }
+
+    // Waits for a permit for at most `timeout`. Fails right away if the next permit is not due
+    // before the timeout expires.
+    pub fn acquire_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        acquire_timeout(|| self.try_take(), timeout)
+    }
+
+    // Takes a permit, or returns how long it will take for the next one to accrue.
+    fn try_take(&self) -> Result<(), StdDuration> {
+        let mut bucket = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.permits = (bucket.permits + elapsed * self.rate).min(self.burst);
+        bucket.refilled = now;
+        if bucket.permits >= 1.0 {
+            bucket.permits -= 1.0;
+            Ok(())
+        } else {
+            let wait = StdDuration::from_secs_f64((1.0 - bucket.permits) / self.rate);
+            // Never report a zero wait, which would make callers spin.
+            Err(cmp::max(wait, StdDuration::from_micros(1)))
+        }
+    }
+}
+
+impl LeakyBucket {
+    // Creates a limiter handing out `rate` permits per second, evenly spaced.
+    //
+    // # Panics
+    //
+    // Panics if `rate` is not positive.
+    pub fn new(rate: f64) -> LeakyBucket {
+        assert!(rate > 0.0, "rate must be positive");
+        LeakyBucket {
+            interval: StdDuration::from_secs_f64(1.0 / rate),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn interval(&self) -> StdDuration {
+        self.interval
+    }
+
+    // Waits until a permit is available and takes it.
+    pub fn acquire(&self) {
+        acquire(|| self.try_take())
+    }
+
+    pub fn try_acquire(&self) -> Result<(), Error> {
+        try_acquire(|| self.try_take())
+    }
+
+    // Waits for a permit for at most `timeout`. Fails right away if the next permit is not due
+    // before the timeout expires.
+    pub fn acquire_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        acquire_timeout(|| self.try_take(), timeout)
+    }
+
+    // Takes a permit, or returns how long it will take for the next one to be due.
+    fn try_take(&self) -> Result<(), StdDuration> {
+        let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if now >= *next {
+            // Time spent idle doesn't build up credit, that would allow a burst.
+            *next = now + self.interval;
+            Ok(())
+        } else {
+            Err(*next - now)
+        }
+    }
+}
+
+impl RateLimit for RateLimiter {
+    fn acquire(&self) {
+        RateLimiter::acquire(self)
+    }
+
+    fn try_acquire(&self) -> Result<(), Error> {
+        RateLimiter::try_acquire(self)
+    }
+
+    fn acquire_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        RateLimiter::acquire_timeout(self, timeout)
+    }
+}
+
+impl RateLimit for LeakyBucket {
+    fn acquire(&self) {
+        LeakyBucket::acquire(self)
+    }
+
+    fn try_acquire(&self) -> Result<(), Error> {
+        LeakyBucket::try_acquire(self)
+    }
+
+    fn acquire_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        LeakyBucket::acquire_timeout(self, timeout)
+    }
+}
+
+// The blocking and timeout logic shared by the limiters, given a function which takes a permit or
+// returns how long until the next one.
+fn acquire<F: Fn() -> Result<(), StdDuration>>(try_take: F) {
+    loop {
+        match try_take() {
+            Ok(()) => return,
+            Err(wait) => thread::sleep(wait),
+        }
+    }
+}
+
+fn try_acquire<F: Fn() -> Result<(), StdDuration>>(try_take: F) -> Result<(), Error> {
+    try_take().map_err(|_| Error::new(ErrorKind::WouldBlock, "no permit available"))
+}
+
+fn acquire_timeout<F: Fn() -> Result<(), StdDuration>>(try_take: F, timeout: Duration)
+                                                       -> Result<(), Error> {
+    // Negative durations are treated as an already expired timeout.
+    let deadline = Instant::now() + timeout.to_std().unwrap_or_default();
+    loop {
+        match try_take() {
+            Ok(()) => return Ok(()),
+            Err(wait) => {
+                if Instant::now() + wait > deadline {
+                    return Err(Error::new(ErrorKind::TimedOut, "wait timed out"));
+                }
+                thread::sleep(wait);
+            }
+        }
+    }
+}
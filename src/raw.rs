@@ -0,0 +1,80 @@
+// Interop with `sem_t`s owned by C code, on the POSIX backend.
+//
+// A C library may embed a `sem_t` in one of its structs, or place one in a shared memory segment,
+// and hand Rust code a pointer to it. Such a `sem_t` stays owned by the C code and is borrowed as a
+// `SemRef`, which can be used like any other `Semaphore` but never destroys it.
+//
+// `SemRef::from_ptr()` is the way to adopt such a `sem_t`. `Semaphore::into_raw()` hands a whole
+// semaphore over instead, `sem_t` and allocation both, like `Box::into_raw()`, and
+// `Semaphore::from_raw()` is only for taking it back afterwards: it destroys and frees the `sem_t`
+// on drop, which is never right for one C code allocated.
+
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+
+use libc;
+
+use sys::Semaphore;
+
+// A semaphore borrowed from foreign code, which goes on owning and eventually destroys it.
+//
+// Derefs to `Semaphore`, so that it can be waited on and posted like any other. Any number of
+// `SemRef`s may borrow the same `sem_t`, in any number of threads.
+pub struct SemRef<'a> {
+    sem: ManuallyDrop<Semaphore>,
+    _sem_t: PhantomData<&'a libc::sem_t>,
+}
+
+impl Semaphore {
+    /// Takes back a semaphore given up with `into_raw()`, completing the round trip.
+    ///
+    /// The `sem_t` is destroyed with `sem_destroy()`, and its memory freed, when the returned
+    /// `Semaphore` is dropped. A `sem_t` initialized and owned by C code must be borrowed with
+    /// `SemRef::from_ptr()` instead.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `into_raw()` and not passed to `from_raw()` since, and
+    /// nothing else may destroy the `sem_t` in the meantime.
+    pub unsafe fn from_raw(ptr: *mut libc::sem_t) -> Semaphore {
+        Semaphore::adopt(ptr)
+    }
+
+    // Gives up the semaphore without destroying it, returning a pointer to its `sem_t`.
+    //
+    // The `sem_t` lives in its own heap allocation from the moment the semaphore is created, so
+    // the pointer is the one every wait and post went through. The caller takes over both: the
+    // usual way to get rid of it is passing the pointer back to `from_raw()` and dropping the
+    // result, since destroying it with `sem_destroy()` leaks the allocation.
+    pub fn into_raw(self) -> *mut libc::sem_t {
+        self.release()
+    }
+}
+
+impl<'a> SemRef<'a> {
+    /// Borrows the initialized `sem_t` at `ptr` for `'a`. Dropping the `SemRef` leaves it intact.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a `sem_t` initialized with `sem_init()`, which must stay valid and in
+    /// place, and must not be destroyed, for `'a`.
+    pub unsafe fn from_ptr(ptr: *mut libc::sem_t) -> SemRef<'a> {
+        SemRef {
+            sem: ManuallyDrop::new(Semaphore::adopt(ptr)),
+            _sem_t: PhantomData,
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut libc::sem_t {
+        self.sem.raw()
+    }
+}
+
+impl<'a> Deref for SemRef<'a> {
+    type Target = Semaphore;
+
+    fn deref(&self) -> &Semaphore {
+        &self.sem
+    }
+}
@@ -0,0 +1,141 @@
+// Names generated by this crate.
+//
+// Named semaphores outlive the process which created them, so one which crashes before unlinking
+// its semaphores leaks them until reboot. To make them recognizable, generated names have the
+// form `/sema.<pid>.<random>`, recording the pid of the creating process. A semaphore is stale
+// once that process is gone, and `NamedSemaphore::unlink_stale()` removes all such semaphores.
+//
+// Linux exposes named semaphores as `/dev/shm/sem.<name>`, so they can be listed directly. Other
+// systems (notably OS X) provide no way to enumerate them, there every generated name is also
+// recorded as an empty file in a registry directory under the temporary directory.
+use std::fs;
+#[cfg(not(target_os = "linux"))]
+use std::path::PathBuf;
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc;
+use rand::{
+    thread_rng,
+    Rng,
+};
+
+use fork::is_alive;
+use named::NamedSemaphore;
+
+const PREFIX: &str = "sema.";
+
+// Length of the random part of a generated name. OS X limits names to 31 bytes, which leaves room
+// for pids of up to 12 digits.
+const RANDOM_LEN: usize = 12;
+
+// Returns a fresh name of the form `/sema.<pid>.<random>`.
+pub(crate) fn unique_name() -> String {
+    let pid = unsafe {
+        libc::getpid()
+    };
+    let random: String = thread_rng().gen_ascii_chars().take(RANDOM_LEN).collect();
+    format!("/{}{}.{}", PREFIX, pid, random)
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn register(_name: &str) {}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn deregister(_name: &str) {}
+
+#[cfg(target_os = "linux")]
+fn list() -> Result<Vec<String>, Error> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir("/dev/shm")? {
+        let file_name = entry?.file_name();
+        if let Some(name) = file_name.to_str().and_then(|n| n.strip_prefix("sem.")) {
+            if name.starts_with(PREFIX) {
+                names.push(format!("/{}", name));
+            }
+        }
+    }
+    Ok(names)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn registry_dir() -> PathBuf {
+    ::std::env::temp_dir().join("sema-names")
+}
+
+// Records a generated name. Failing to do so only means it can't be cleaned up later.
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn register(name: &str) {
+    let dir = registry_dir();
+    let _ = fs::create_dir_all(&dir)
+        .and_then(|_| fs::File::create(dir.join(name.trim_start_matches('/'))));
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn deregister(name: &str) {
+    let _ = fs::remove_file(registry_dir().join(name.trim_start_matches('/')));
+}
+
+#[cfg(not(target_os = "linux"))]
+fn list() -> Result<Vec<String>, Error> {
+    let entries = match fs::read_dir(registry_dir()) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let file_name = entry?.file_name();
+        if let Some(name) = file_name.to_str() {
+            if name.starts_with(PREFIX) {
+                names.push(format!("/{}", name));
+            }
+        }
+    }
+    Ok(names)
+}
+
+// Extracts the creator's pid from a generated name.
+fn creator(name: &str) -> Option<libc::pid_t> {
+    name.trim_start_matches('/')
+        .strip_prefix(PREFIX)?
+        .split('.')
+        .next()?
+        .parse()
+        .ok()
+}
+
+impl NamedSemaphore {
+    // Lists the named semaphores with names generated by this crate, by any process.
+    pub fn list_generated() -> Result<Vec<String>, Error> {
+        list()
+    }
+
+    // Unlinks every semaphore with a generated name whose creating process no longer exists,
+    // returning the names that were removed.
+    //
+    // Processes which still have such a semaphore open can keep using it, but it can no longer
+    // be opened by name.
+    pub fn unlink_stale() -> Result<Vec<String>, Error> {
+        let mut removed = Vec::new();
+        for name in list()? {
+            let stale = match creator(&name) {
+                Some(pid) => !is_alive(pid),
+                None => false,
+            };
+            if !stale {
+                continue;
+            }
+            match NamedSemaphore::unlink(&name) {
+                Ok(()) => removed.push(name.clone()),
+                // Someone else cleaned it up first, or it vanished without `unlink()` forgetting
+                // the name, which leaves it for us to forget.
+                Err(ref e) if e.kind() == ErrorKind::NotFound => deregister(&name),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(removed)
+    }
+}
@@ -0,0 +1,323 @@
+// Robust semaphores.
+//
+// A `RobustSemaphore` keeps track of which processes hold its permits, so that permits held by a
+// process which died without releasing them can be recovered. It is meant to be placed in memory
+// shared between processes, but works just as well within a single one.
+//
+// Waiters which cannot obtain a permit look for holders which are no longer alive. When one is
+// found its permits are returned to the semaphore and the waiter fails with `EOWNERDEAD`, in the
+// same way a robust pthread mutex does. The waiter is expected to repair whatever state the dead
+// process was protecting and then wait again.
+//
+// Since dead holders never post, waiters can't rely on being woken alone: they sleep with an
+// increasing backoff, capped at `MAX_BACKOFF`, and look for dead holders each time they wake. On
+// Linux a post also wakes a sleeping waiter through a futex on the count, so permits are handed on
+// right away. Elsewhere a permit posted while a waiter sleeps is only noticed once its sleep ends,
+// which can take up to `MAX_BACKOFF` (10ms).
+//
+// Holders are identified by pid, so a dead holder whose pid has already been reused by a new
+// process is not detected. A process which dies between taking a permit and recording itself as a
+// holder leaks that permit.
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+use std::cmp;
+use std::mem;
+use std::ptr;
+#[cfg(not(all(target_os = "linux",
+              not(feature = "spin-fallback"))))]
+use std::thread;
+use std::time::{
+    Duration as StdDuration,
+    Instant,
+};
+use std::sync::atomic::{
+    Ordering,
+    AtomicI32,
+    AtomicU32,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc::{
+    self,
+    pid_t,
+};
+use fork::is_alive;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+use sys::{
+    futex_wait_bitset,
+    futex_wake_bitset,
+    monotonic_deadline,
+    Clock,
+    FutexMode,
+    FUTEX_BITSET_MATCH_ANY,
+};
+use time::Duration;
+
+// Maximum number of processes that can hold permits at the same time.
+const MAX_HOLDERS: usize = 64;
+
+// Marks a holder slot whose permits are being recovered.
+const RECLAIMING: pid_t = -1;
+
+// Bounds of the sleep between two attempts to acquire a permit.
+const MIN_BACKOFF: StdDuration = StdDuration::from_micros(50);
+const MAX_BACKOFF: StdDuration = StdDuration::from_millis(10);
+
+#[repr(C)]
+struct Holder {
+    pid: AtomicI32,
+    permits: AtomicU32,
+}
+
+#[repr(C)]
+pub struct RobustSemaphore {
+    count: AtomicU32,
+    // Threads sleeping on `count`, only maintained where posts wake them.
+    nwaiters: AtomicU32,
+    holders: [Holder; MAX_HOLDERS],
+}
+
+pub struct RobustSemaphoreGuard<'a> {
+    sem: &'a RobustSemaphore,
+}
+
+impl RobustSemaphore {
+    pub fn new(value: u32) -> RobustSemaphore {
+        // All-zeroes is a valid semaphore without any holders.
+        let sem: RobustSemaphore = unsafe {
+            mem::zeroed()
+        };
+        sem.count.store(value, Ordering::Relaxed);
+        sem
+    }
+
+    /// Initializes a robust semaphore with the given value at `ptr`, which should point into
+    /// memory mapped into every participating process (e.g. `MAP_SHARED`).
+    ///
+    /// Fails if `ptr` is null or misaligned.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writes of `mem::size_of::<RobustSemaphore>()` bytes, must not hold
+    /// a semaphore that is in use, and must stay mapped for the lifetime `'a`.
+    pub unsafe fn init_at<'a>(ptr: *mut RobustSemaphore, value: u32)
+                              -> Result<&'a RobustSemaphore, Error> {
+        if ptr.is_null() {
+            return Err(Error::new(ErrorKind::InvalidInput, "null semaphore pointer"));
+        }
+        if !ptr.is_aligned() {
+            return Err(Error::new(ErrorKind::InvalidInput, "misaligned semaphore pointer"));
+        }
+        ptr::write(ptr, RobustSemaphore::new(value));
+        Ok(&*ptr)
+    }
+
+    /// Attaches to a robust semaphore previously initialized with `RobustSemaphore::init_at()`,
+    /// typically by another process.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to an initialized robust semaphore which stays mapped for the lifetime
+    /// `'a`.
+    pub unsafe fn from_raw_ptr<'a>(ptr: *mut RobustSemaphore) -> &'a RobustSemaphore {
+        &*ptr
+    }
+
+    // Returns the number of available permits.
+    pub fn value(&self) -> u32 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn wait(&self) -> Result<(), Error> {
+        self.wait_until(None)
+    }
+
+    pub fn try_wait(&self) -> Result<(), Error> {
+        if self.try_acquire() {
+            return self.register();
+        }
+        self.recover()?;
+        Err(Error::new(ErrorKind::WouldBlock, "wait would block"))
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        // Negative durations are treated as an already expired timeout.
+        let timeout = timeout.to_std().unwrap_or_default();
+        self.wait_until(Some(Instant::now() + timeout))
+    }
+
+    // Releases a permit held by the calling process.
+    pub fn post(&self) {
+        let pid = current_pid();
+        // Threads racing in `register()` may have left this process with more than one slot.
+        for holder in self.holders.iter().filter(|h| h.pid.load(Ordering::Relaxed) == pid) {
+            let dec = holder.permits.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                n.checked_sub(1)
+            });
+            if dec.is_ok() {
+                break;
+            }
+        }
+        self.release(1);
+    }
+
+    pub fn take(&self) -> Result<RobustSemaphoreGuard<'_>, Error> {
+        self.wait()?;
+        Ok(RobustSemaphoreGuard {
+            sem: self,
+        })
+    }
+
+    fn wait_until(&self, deadline: Option<Instant>) -> Result<(), Error> {
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            if self.try_acquire() {
+                return self.register();
+            }
+            self.recover()?;
+
+            let mut sleep = backoff;
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(Error::new(ErrorKind::TimedOut, "wait timed out"));
+                }
+                sleep = sleep.min(deadline - now);
+            }
+            self.sleep(sleep);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    // Returns `n` permits to the semaphore, waking as many sleeping waiters.
+    fn release(&self, n: u32) {
+        // SeqCst orders it before the load of `nwaiters`, pairing with `sleep()` which registers
+        // before the kernel looks at the count.
+        self.count.fetch_add(n, Ordering::SeqCst);
+        self.wake(n);
+    }
+
+    #[cfg(all(target_os = "linux",
+              not(feature = "spin-fallback")))]
+    fn wake(&self, n: u32) {
+        if self.nwaiters.load(Ordering::SeqCst) > 0 {
+            let n = cmp::min(n, i32::MAX as u32);
+            // Runs on the post path and when a guard is dropped, where a panic would be worse than
+            // a late wakeup: sleepers wake at the end of their backoff anyway.
+            let _ = futex_wake_bitset(self.count.as_ptr(), n, FUTEX_BITSET_MATCH_ANY,
+                                      FutexMode::Shared);
+        }
+    }
+
+    #[cfg(not(all(target_os = "linux",
+                  not(feature = "spin-fallback"))))]
+    fn wake(&self, _n: u32) {}
+
+    // Sleeps for `timeout`, or until a permit is posted if that is sooner. A permit posted since
+    // the last attempt to acquire one ends the sleep right away.
+    #[cfg(all(target_os = "linux",
+              not(feature = "spin-fallback")))]
+    fn sleep(&self, timeout: StdDuration) {
+        // Never longer than `MAX_BACKOFF`, so it always fits.
+        let deadline = monotonic_deadline(Duration::from_std(timeout).unwrap());
+        self.nwaiters.fetch_add(1, Ordering::SeqCst);
+        // Interruptions and timeouts alike just end the sleep, the caller tries again.
+        let _ = futex_wait_bitset(self.count.as_ptr(), 0, &deadline, Clock::Monotonic,
+                                  FUTEX_BITSET_MATCH_ANY, FutexMode::Shared);
+        self.nwaiters.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    #[cfg(not(all(target_os = "linux",
+                  not(feature = "spin-fallback"))))]
+    fn sleep(&self, timeout: StdDuration) {
+        thread::sleep(timeout);
+    }
+
+    fn try_acquire(&self) -> bool {
+        let mut d = self.count.load(Ordering::Relaxed);
+        while d > 0 {
+            match self.count.compare_exchange_weak(d, d - 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return true,
+                Err(prev) => d = prev,
+            }
+        }
+        false
+    }
+
+    // Records the calling process as holding one more permit.
+    //
+    // Slots are only ever released by `recover()`, which keeps the slot of a live process stable
+    // while its threads concurrently post and wait.
+    fn register(&self) -> Result<(), Error> {
+        let pid = current_pid();
+        if let Some(holder) = self.holders.iter().find(|h| h.pid.load(Ordering::Relaxed) == pid) {
+            holder.permits.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        for _ in 0..2 {
+            for holder in self.holders.iter() {
+                if holder.pid.compare_exchange(0, pid, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+                    holder.permits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
+            }
+            // Slots of dead processes are only freed by recovery, try to make room.
+            if let Err(e) = self.recover() {
+                self.release(1);
+                return Err(e);
+            }
+        }
+        // Give the permit back rather than hand out one that can't be recovered.
+        self.release(1);
+        Err(Error::other("too many processes holding permits"))
+    }
+
+    // Frees the slots of dead holders, returning their permits to the semaphore.
+    //
+    // Fails with `EOWNERDEAD` if any permits were recovered.
+    fn recover(&self) -> Result<(), Error> {
+        let mut recovered = 0;
+        for holder in self.holders.iter() {
+            let pid = holder.pid.load(Ordering::Relaxed);
+            if pid <= 0 || is_alive(pid) {
+                continue;
+            }
+            // Only one waiter gets to recover a given holder.
+            if holder.pid.compare_exchange(pid, RECLAIMING, Ordering::Acquire, Ordering::Relaxed)
+                     .is_err() {
+                continue;
+            }
+            let permits = holder.permits.swap(0, Ordering::Relaxed);
+            holder.pid.store(0, Ordering::Release);
+            if permits > 0 {
+                self.release(permits);
+                recovered += permits;
+            }
+        }
+        if recovered > 0 {
+            Err(Error::from_raw_os_error(libc::EOWNERDEAD))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+unsafe impl Send for RobustSemaphore {}
+unsafe impl Sync for RobustSemaphore {}
+
+impl<'a> Drop for RobustSemaphoreGuard<'a> {
+    fn drop(&mut self) {
+        self.sem.post();
+    }
+}
+
+fn current_pid() -> pid_t {
+    unsafe {
+        libc::getpid()
+    }
+}
+
@@ -0,0 +1,146 @@
+// Sharded semaphores.
+//
+// Every post and wait of a `Semaphore` modifies the same word, whose cache line ends up bouncing
+// between all the cores using it. A `ShardedSemaphore` spreads its tokens over several counters,
+// each on its own cache line: threads post to and take from the shard of the CPU they run on, and
+// only look at (steal from) the other shards when theirs is empty.
+//
+// Blocked threads wait on an `EventCount` which posts notify. Waiters register with it before
+// scanning the shards a last time, so a post either sees the waiter or the waiter sees the token.
+use std::cmp;
+use std::ptr;
+use std::thread;
+use std::sync::atomic::{
+    Ordering,
+    AtomicU32,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc;
+use time::Duration;
+
+use eventcount::EventCount;
+use padded::CachePadded;
+use sys::monotonic_deadline;
+
+pub struct ShardedSemaphore {
+    shards: Box<[CachePadded<AtomicU32>]>,
+    event: CachePadded<EventCount>,
+}
+
+pub struct ShardedSemaphoreGuard<'a> {
+    sem: &'a ShardedSemaphore,
+}
+
+impl ShardedSemaphore {
+    // Creates a semaphore with one shard per available CPU.
+    pub fn new(value: u32) -> ShardedSemaphore {
+        let cpus = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        ShardedSemaphore::with_shards(value, cpus)
+    }
+
+    // Creates a semaphore with `shards` shards (at least one), spreading `value` evenly over them.
+    pub fn with_shards(value: u32, shards: usize) -> ShardedSemaphore {
+        let shards = cmp::max(shards, 1);
+        let per_shard = value / shards as u32;
+        let extra = value as usize % shards;
+        ShardedSemaphore {
+            shards: (0..shards).map(|i| {
+                CachePadded::new(AtomicU32::new(per_shard + (i < extra) as u32))
+            }).collect(),
+            event: CachePadded::new(EventCount::new()),
+        }
+    }
+
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    // Returns the number of available tokens. Only a snapshot, as the shards are read one by one.
+    pub fn value(&self) -> u32 {
+        self.shards.iter().fold(0u32, |sum, s| sum.saturating_add(s.load(Ordering::Relaxed)))
+    }
+
+    pub fn post(&self) {
+        // SeqCst makes the token visible to waiters before they are notified.
+        self.shards[self.home()].fetch_add(1, Ordering::SeqCst);
+        self.event.notify();
+    }
+
+    pub fn wait(&self) -> Result<(), Error> {
+        self.wait_until(ptr::null())
+    }
+
+    pub fn try_wait(&self) -> Result<(), Error> {
+        if self.take_any() {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::WouldBlock, "wait would block"))
+        }
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        let deadline = monotonic_deadline(timeout);
+        self.wait_until(&deadline)
+    }
+
+    pub fn take(&self) -> Result<ShardedSemaphoreGuard<'_>, Error> {
+        self.wait()?;
+        Ok(ShardedSemaphoreGuard {
+            sem: self,
+        })
+    }
+
+    // Returns the index of the shard of the CPU the calling thread runs on.
+    fn home(&self) -> usize {
+        let cpu = unsafe {
+            libc::sched_getcpu()
+        };
+        cmp::max(cpu, 0) as usize % self.shards.len()
+    }
+
+    // Takes a token from the home shard, or failing that from any other shard.
+    fn take_any(&self) -> bool {
+        let home = self.home();
+        let n = self.shards.len();
+        (0..n).any(|i| take(&self.shards[(home + i) % n]))
+    }
+
+    fn wait_until(&self, deadline: *const libc::timespec) -> Result<(), Error> {
+        loop {
+            if self.take_any() {
+                return Ok(());
+            }
+            let key = self.event.prepare_wait();
+            if self.take_any() {
+                self.event.cancel_wait(key);
+                return Ok(());
+            }
+            if let Err(e) = self.event.commit_wait_until(key, deadline) {
+                // A token posted just before the deadline may come with our wakeup.
+                return if self.take_any() { Ok(()) } else { Err(e) };
+            }
+        }
+    }
+}
+
+// Takes a token from `shard` if it has one.
+fn take(shard: &AtomicU32) -> bool {
+    let mut v = shard.load(Ordering::Relaxed);
+    while v > 0 {
+        match shard.compare_exchange_weak(v, v - 1, Ordering::Acquire, Ordering::Relaxed) {
+            Ok(_) => return true,
+            Err(prev) => v = prev,
+        }
+    }
+    false
+}
+
+impl<'a> Drop for ShardedSemaphoreGuard<'a> {
+    fn drop(&mut self) {
+        self.sem.post();
+    }
+}
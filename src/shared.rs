@@ -0,0 +1,93 @@
+use std::ops::Deref;
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use sys::Semaphore;
+
+// A handle to a semaphore living in memory shared between processes (e.g. a `MAP_SHARED` mapping
+// or a System V shared memory segment).
+//
+// The handle does not own the semaphore: dropping it leaves the semaphore intact so that other
+// processes can keep using it. Exactly one process should eventually call `destroy()`.
+pub struct SharedSemaphore {
+    ptr: *mut Semaphore,
+}
+
+impl Semaphore {
+    /// Initializes a process-shared semaphore with the given value at `ptr`.
+    ///
+    /// `ptr` must point into memory mapped into every participating process (e.g. `MAP_SHARED`).
+    /// The semaphore occupies `mem::size_of::<Semaphore>()` bytes and must be aligned to
+    /// `mem::align_of::<Semaphore>()`. `Semaphore` is `#[repr(C)]`, but its fields depend on the
+    /// enabled features (`stats`, `observer`, `holders` and `watchdog` on Linux), so processes
+    /// only agree on its layout if they are built from the same version of this crate, for the
+    /// same target, with the same features. Nothing checks this, use `MappedSemaphore` on Linux
+    /// for a region which records the semaphore's size and version.
+    ///
+    /// Fails if `ptr` is null or misaligned, or if the platform does not support process-shared
+    /// unnamed semaphores (OS X).
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writes of `mem::size_of::<Semaphore>()` bytes and must not already
+    /// hold a semaphore that is in use. The memory must stay mapped for as long as any process
+    /// uses the semaphore, and it must only be accessed through `Semaphore` methods afterwards.
+    pub unsafe fn init_at(ptr: *mut Semaphore, value: u32) -> Result<SharedSemaphore, Error> {
+        check_ptr(ptr)?;
+        Semaphore::init_shared(ptr, value)?;
+        Ok(SharedSemaphore::from_raw_ptr(ptr))
+    }
+}
+
+impl SharedSemaphore {
+    /// Creates a handle to a semaphore previously initialized with `Semaphore::init_at()`,
+    /// typically by another process.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a semaphore initialized by `Semaphore::init_at()` which has not been
+    /// destroyed, and the memory must stay mapped for the lifetime of the handle.
+    pub unsafe fn from_raw_ptr(ptr: *mut Semaphore) -> SharedSemaphore {
+        SharedSemaphore {
+            ptr,
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut Semaphore {
+        self.ptr
+    }
+
+    /// Destroys the semaphore.
+    ///
+    /// # Safety
+    ///
+    /// No process may use the semaphore after it has been destroyed, including through other
+    /// handles.
+    pub unsafe fn destroy(self) {
+        ::std::ptr::drop_in_place(self.ptr);
+    }
+}
+
+impl Deref for SharedSemaphore {
+    type Target = Semaphore;
+
+    fn deref(&self) -> &Semaphore {
+        unsafe { &*self.ptr }
+    }
+}
+
+unsafe impl Send for SharedSemaphore {}
+unsafe impl Sync for SharedSemaphore {}
+
+// Ensures `ptr` is usable as the location of a `Semaphore`.
+fn check_ptr(ptr: *mut Semaphore) -> Result<(), Error> {
+    if ptr.is_null() {
+        Err(Error::new(ErrorKind::InvalidInput, "null semaphore pointer"))
+    } else if !ptr.is_aligned() {
+        Err(Error::new(ErrorKind::InvalidInput, "misaligned semaphore pointer"))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+// Forwarding signals to a thread.
+//
+// Very little may be done in a signal handler: no allocation, no locks, nothing which isn't
+// async-signal-safe. A `SignalSemaphore` is meant to sit in a `static` and be notified from the
+// handler, while an ordinary thread waits on it and does the real work.
+//
+// `notify_from_handler()` only performs atomic operations on the semaphore and a `FUTEX_WAKE`
+// system call, all of which are async-signal-safe, and preserves `errno` for the interrupted code.
+// Signals are recorded in `pending`, one bit per signal number, so the handling thread learns
+// which signals arrived; like the kernel's own pending set, a signal delivered several times
+// before the thread gets around to it is seen once. `count` is the futex word, it counts the
+// notifications the waiting thread hasn't consumed yet.
+//
+// `SignalFdSemaphore` is the alternative without a handler, receiving the signals through a
+// `signalfd` which can be polled along with other descriptors.
+use std::ops::ControlFlow;
+use std::ptr;
+use std::sync::atomic::{
+    Ordering,
+    AtomicU32,
+    AtomicU64,
+};
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc::{
+    self,
+    c_int,
+};
+use time::Duration;
+
+use sys::{
+    futex_wait_until,
+    futex_wake_bitset,
+    monotonic_deadline,
+    FutexMode,
+    InterruptPolicy,
+    FUTEX_BITSET_MATCH_ANY,
+};
+
+use sigsafe::SavedErrno;
+
+pub use signalfd::SignalFdSemaphore;
+
+pub struct SignalSemaphore {
+    count: AtomicU32,
+    // Bit `n - 1` is set while signal `n` is pending.
+    pending: AtomicU64,
+}
+
+// A set of signal numbers taken from a `SignalSemaphore`, iterating in ascending order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PendingSignals {
+    bits: u64,
+}
+
+// Endless iterator over the signals arriving at a `SignalSemaphore`, see `SignalSemaphore::iter()`.
+pub struct Signals<'a> {
+    sem: &'a SignalSemaphore,
+    pending: PendingSignals,
+}
+
+impl SignalSemaphore {
+    pub const fn new() -> SignalSemaphore {
+        SignalSemaphore {
+            count: AtomicU32::new(0),
+            pending: AtomicU64::new(0),
+        }
+    }
+
+    // Records that `signum` arrived and wakes the waiting thread.
+    //
+    // This is async-signal-safe and may be called from a signal handler. Signal numbers outside
+    // `1..=64` only wake the waiting thread.
+    pub fn notify_from_handler(&self, signum: c_int) {
+        let _errno = SavedErrno::save();
+        if (1..=64).contains(&signum) {
+            self.pending.fetch_or(1 << (signum - 1), Ordering::SeqCst);
+        }
+        self.count.fetch_add(1, Ordering::SeqCst);
+        // The result is ignored, there is no one to report a failure to.
+        let _ = futex_wake_bitset(self.count.as_ptr(), 1, FUTEX_BITSET_MATCH_ANY,
+                                  FutexMode::Private);
+    }
+
+    // Waits for a notification, returning the signals that arrived since the last wait. The set
+    // is empty if the signals were already collected by an earlier wait.
+    pub fn wait(&self) -> Result<PendingSignals, Error> {
+        self.wait_until(ptr::null())
+    }
+
+    pub fn try_wait(&self) -> Result<PendingSignals, Error> {
+        if self.try_consume() {
+            Ok(self.take_pending())
+        } else {
+            Err(Error::new(ErrorKind::WouldBlock, "wait would block"))
+        }
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<PendingSignals, Error> {
+        let deadline = monotonic_deadline(timeout);
+        self.wait_until(&deadline)
+    }
+
+    // Returns an iterator yielding each arriving signal number, blocking for the next one.
+    // Interrupted waits are retried.
+    pub fn iter(&self) -> Signals<'_> {
+        Signals {
+            sem: self,
+            pending: PendingSignals {
+                bits: 0,
+            },
+        }
+    }
+
+    fn try_consume(&self) -> bool {
+        self.count.fetch_update(Ordering::Acquire, Ordering::Relaxed, |n| n.checked_sub(1)).is_ok()
+    }
+
+    fn take_pending(&self) -> PendingSignals {
+        PendingSignals {
+            bits: self.pending.swap(0, Ordering::SeqCst),
+        }
+    }
+
+    fn wait_until(&self, deadline: *const libc::timespec) -> Result<PendingSignals, Error> {
+        futex_wait_until(&self.count, deadline, FutexMode::Private, InterruptPolicy::Surface, || {
+            if self.try_consume() {
+                ControlFlow::Break(self.take_pending())
+            } else {
+                ControlFlow::Continue(0)
+            }
+        })
+    }
+}
+
+impl Default for SignalSemaphore {
+    fn default() -> SignalSemaphore {
+        SignalSemaphore::new()
+    }
+}
+
+unsafe impl Send for SignalSemaphore {}
+unsafe impl Sync for SignalSemaphore {}
+
+impl PendingSignals {
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    pub fn contains(&self, signum: c_int) -> bool {
+        (1..=64).contains(&signum) && self.bits & (1 << (signum - 1)) != 0
+    }
+}
+
+impl Iterator for PendingSignals {
+    type Item = c_int;
+
+    fn next(&mut self) -> Option<c_int> {
+        if self.bits == 0 {
+            return None;
+        }
+        let bit = self.bits.trailing_zeros();
+        self.bits &= self.bits - 1;
+        Some(bit as c_int + 1)
+    }
+}
+
+impl<'a> Iterator for Signals<'a> {
+    type Item = c_int;
+
+    fn next(&mut self) -> Option<c_int> {
+        loop {
+            if let Some(signum) = self.pending.next() {
+                return Some(signum);
+            }
+            match self.sem.wait() {
+                Ok(pending) => self.pending = pending,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => panic!("waiting for signals failed: {}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,146 @@
+// Signals through a descriptor.
+//
+// A `SignalFdSemaphore` receives signals through a `signalfd` instead of a handler: the signals
+// are blocked, so the kernel keeps them pending, and each read from the descriptor consumes one
+// and reports its number. Every pending signal acts as a permit, and since the semaphore is a
+// descriptor it can be waited on with `poll()` or an event loop alongside other descriptors,
+// like an `EventFdSemaphore`.
+//
+// The signals have to be blocked in every thread for the descriptor to see them, otherwise the
+// kernel may deliver them to a thread which doesn't block them. `new()` blocks them in the calling
+// thread, threads spawned afterwards inherit its mask, so it should be called before spawning.
+use std::mem;
+use std::ptr;
+use std::os::unix::io::{
+    AsRawFd,
+    RawFd,
+};
+use std::time::Instant;
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc::{
+    self,
+    c_int,
+    c_void,
+    SFD_CLOEXEC,
+    SFD_NONBLOCK,
+};
+use time::Duration;
+
+pub struct SignalFdSemaphore {
+    fd: RawFd,
+}
+
+impl SignalFdSemaphore {
+    // Creates a semaphore receiving `signals`, blocking them in the calling thread.
+    pub fn new(signals: &[c_int]) -> Result<SignalFdSemaphore, Error> {
+        let mut set: libc::sigset_t = unsafe {
+            mem::zeroed()
+        };
+        unsafe {
+            libc::sigemptyset(&mut set);
+        }
+        for &signum in signals {
+            if unsafe { libc::sigaddset(&mut set, signum) } == -1 {
+                return Err(Error::new(ErrorKind::InvalidInput, "invalid signal number"));
+            }
+        }
+        let res = unsafe {
+            libc::pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut())
+        };
+        if res != 0 {
+            return Err(Error::from_raw_os_error(res));
+        }
+        let fd = unsafe {
+            libc::signalfd(-1, &set, SFD_NONBLOCK | SFD_CLOEXEC)
+        };
+        if fd == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(SignalFdSemaphore {
+                fd,
+            })
+        }
+    }
+
+    // Waits for one of the signals, consuming it and returning its number.
+    pub fn wait(&self) -> Result<c_int, Error> {
+        loop {
+            match self.try_wait() {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                res => return res,
+            }
+            self.poll(-1)?;
+        }
+    }
+
+    pub fn try_wait(&self) -> Result<c_int, Error> {
+        let mut info: libc::signalfd_siginfo = unsafe {
+            mem::zeroed()
+        };
+        let res = unsafe {
+            libc::read(self.fd, &mut info as *mut libc::signalfd_siginfo as *mut c_void,
+                       mem::size_of::<libc::signalfd_siginfo>())
+        };
+        if res == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(info.ssi_signo as c_int)
+        }
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<c_int, Error> {
+        // Negative durations are treated as an already expired timeout.
+        let deadline = Instant::now() + timeout.to_std().unwrap_or_default();
+        loop {
+            match self.try_wait() {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                res => return res,
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::new(ErrorKind::TimedOut, "wait timed out"));
+            }
+            // Round up so that we don't spin on sub-millisecond remainders.
+            let left = deadline - now;
+            let millis = left.as_millis() + !left.subsec_nanos().is_multiple_of(1_000_000) as u128;
+            self.poll(millis.min(c_int::MAX as u128) as c_int)?;
+        }
+    }
+
+    // Blocks until the descriptor is readable or `millis` milliseconds have passed.
+    fn poll(&self, millis: c_int) -> Result<(), Error> {
+        let mut pfd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let res = unsafe {
+            libc::poll(&mut pfd, 1, millis)
+        };
+        if res == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl AsRawFd for SignalFdSemaphore {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for SignalFdSemaphore {
+    // The signals stay blocked.
+    fn drop(&mut self) {
+        let res = unsafe {
+            libc::close(self.fd)
+        };
+        debug_assert_eq!(res, 0);
+    }
+}
@@ -0,0 +1,210 @@
+// Posting from signal handlers.
+//
+// `post_from_signal()` is the async-signal-safe way to post, so that a handler can wake a thread
+// waiting on a semaphore. It never panics and never runs user code:
+//
+// - the Linux futex semaphore only uses atomic operations and `futex()` system calls on this path,
+//   saturates at `u32::MAX` instead of applying the overflow policy, skips the `observer` and
+//   `tracing` hooks, and aborts rather than unwinds if a wakeup fails,
+// - the other backends go through `try_post()` and drop its error: the POSIX backend calls
+//   `sem_post()`, which POSIX lists as async-signal-safe, the OS X semaphore only uses atomic
+//   operations and `__ulock_wake()`, and the spin fallback only adds to an atomic counter.
+//
+// `post()` itself is not async-signal-safe: it applies the overflow policy, which may panic, panics
+// if the post fails, and calls the observer and emits trace events when those features are on.
+//
+// A failing system call still overwrites `errno`, which the interrupted code may be about to
+// read, so `post_from_signal()` also preserves it where the platform's `errno` location is known.
+#[cfg(all(any(target_os = "linux",
+              target_os = "emscripten",
+              target_os = "android",
+              target_os = "netbsd",
+              target_os = "openbsd",
+              target_os = "macos",
+              target_os = "ios",
+              target_os = "freebsd",
+              target_os = "dragonfly",
+              target_os = "solaris",
+              target_os = "illumos"),
+          not(feature = "spin-fallback")))]
+use libc::{
+    self,
+    c_int,
+};
+
+use sys::Semaphore;
+
+impl Semaphore {
+    /// Posts the semaphore from a signal handler.
+    ///
+    /// This is async-signal-safe on every platform and never panics, see the module comment. It
+    /// leaves `errno` as it was where the platform's `errno` location is known.
+    ///
+    /// ```
+    /// # extern crate libc;
+    /// # extern crate sema;
+    /// # extern crate time;
+    /// use std::ptr;
+    /// use std::sync::atomic::{AtomicPtr, Ordering};
+    /// use sema::Semaphore;
+    ///
+    /// static SEM: AtomicPtr<Semaphore> = AtomicPtr::new(ptr::null_mut());
+    ///
+    /// extern "C" fn handler(_: libc::c_int) {
+    ///     if let Some(sem) = unsafe { SEM.load(Ordering::Acquire).as_ref() } {
+    ///         sem.post_from_signal();
+    ///     }
+    /// }
+    ///
+    /// # fn main() {
+    /// let sem: &'static Semaphore = Box::leak(Box::new(Semaphore::new(0)));
+    /// SEM.store(sem as *const Semaphore as *mut Semaphore, Ordering::Release);
+    /// unsafe {
+    ///     libc::signal(libc::SIGUSR1, handler as *const () as libc::sighandler_t);
+    ///     libc::raise(libc::SIGUSR1);
+    /// }
+    /// sem.wait_timeout(time::Duration::seconds(5)).unwrap();
+    /// # }
+    /// ```
+    pub fn post_from_signal(&self) {
+        let _errno = SavedErrno::save();
+        self.post_quietly();
+    }
+
+    #[cfg(all(target_os = "linux",
+              not(feature = "spin-fallback")))]
+    fn post_quietly(&self) {
+        self.post_untraced(1);
+    }
+
+    #[cfg(not(all(target_os = "linux",
+                  not(feature = "spin-fallback"))))]
+    fn post_quietly(&self) {
+        // A failed post is dropped rather than reported, since panicking in a signal handler
+        // isn't async-signal-safe.
+        let _ = self.try_post();
+    }
+}
+
+// Restores `errno` when dropped.
+#[cfg(all(any(target_os = "linux",
+              target_os = "emscripten",
+              target_os = "android",
+              target_os = "netbsd",
+              target_os = "openbsd",
+              target_os = "macos",
+              target_os = "ios",
+              target_os = "freebsd",
+              target_os = "dragonfly",
+              target_os = "solaris",
+              target_os = "illumos"),
+          not(feature = "spin-fallback")))]
+pub(crate) struct SavedErrno {
+    errno: c_int,
+}
+
+// Elsewhere either no system call is made, as with the spin fallback, or libc doesn't expose where
+// `errno` lives, and it is left alone.
+#[cfg(not(all(any(target_os = "linux",
+                  target_os = "emscripten",
+                  target_os = "android",
+                  target_os = "netbsd",
+                  target_os = "openbsd",
+                  target_os = "macos",
+                  target_os = "ios",
+                  target_os = "freebsd",
+                  target_os = "dragonfly",
+                  target_os = "solaris",
+                  target_os = "illumos"),
+              not(feature = "spin-fallback"))))]
+pub(crate) struct SavedErrno;
+
+#[cfg(all(any(target_os = "linux",
+              target_os = "emscripten",
+              target_os = "android",
+              target_os = "netbsd",
+              target_os = "openbsd",
+              target_os = "macos",
+              target_os = "ios",
+              target_os = "freebsd",
+              target_os = "dragonfly",
+              target_os = "solaris",
+              target_os = "illumos"),
+          not(feature = "spin-fallback")))]
+impl SavedErrno {
+    pub(crate) fn save() -> SavedErrno {
+        SavedErrno {
+            errno: unsafe {
+                *errno_location()
+            },
+        }
+    }
+}
+
+#[cfg(not(all(any(target_os = "linux",
+                  target_os = "emscripten",
+                  target_os = "android",
+                  target_os = "netbsd",
+                  target_os = "openbsd",
+                  target_os = "macos",
+                  target_os = "ios",
+                  target_os = "freebsd",
+                  target_os = "dragonfly",
+                  target_os = "solaris",
+                  target_os = "illumos"),
+              not(feature = "spin-fallback"))))]
+impl SavedErrno {
+    pub(crate) fn save() -> SavedErrno {
+        SavedErrno
+    }
+}
+
+#[cfg(all(any(target_os = "linux",
+              target_os = "emscripten",
+              target_os = "android",
+              target_os = "netbsd",
+              target_os = "openbsd",
+              target_os = "macos",
+              target_os = "ios",
+              target_os = "freebsd",
+              target_os = "dragonfly",
+              target_os = "solaris",
+              target_os = "illumos"),
+          not(feature = "spin-fallback")))]
+impl Drop for SavedErrno {
+    fn drop(&mut self) {
+        unsafe {
+            *errno_location() = self.errno;
+        }
+    }
+}
+#[cfg(all(any(target_os = "linux",
+              target_os = "emscripten"),
+          not(feature = "spin-fallback")))]
+unsafe fn errno_location() -> *mut c_int {
+    libc::__errno_location()
+}
+
+#[cfg(all(any(target_os = "android",
+              target_os = "netbsd",
+              target_os = "openbsd"),
+          not(feature = "spin-fallback")))]
+unsafe fn errno_location() -> *mut c_int {
+    libc::__errno()
+}
+
+#[cfg(all(any(target_os = "macos",
+              target_os = "ios",
+              target_os = "freebsd",
+              target_os = "dragonfly"),
+          not(feature = "spin-fallback")))]
+unsafe fn errno_location() -> *mut c_int {
+    libc::__error()
+}
+
+#[cfg(all(any(target_os = "solaris",
+              target_os = "illumos"),
+          not(feature = "spin-fallback")))]
+unsafe fn errno_location() -> *mut c_int {
+    libc::___errno()
+}
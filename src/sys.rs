@@ -1,14 +1,137 @@
+#[cfg(not(any(target_os = "macos",
+              target_os = "hermit",
+              all(feature = "spin-fallback",
+                  not(unix)))))]
 use libc;
+#[cfg(not(any(target_os = "macos",
+              target_os = "hermit",
+              all(feature = "spin-fallback",
+                  not(unix)))))]
 use time::Duration;
+#[cfg(not(any(target_os = "macos",
+              target_os = "hermit",
+              all(feature = "spin-fallback",
+                  not(unix)))))]
+use std::cmp;
+use std::sync::atomic::{
+    Ordering,
+    AtomicU64,
+};
 
 pub use self::os::{
     Semaphore,
     SemaphoreGuard,
 };
+#[cfg(not(any(target_os = "linux",
+              target_os = "macos",
+              feature = "spin-fallback",
+              target_os = "hermit")))]
+use std::io::{
+    Error,
+    ErrorKind,
+};
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub use self::os::{
+    Clock,
+    FutexMode,
+    OverflowPolicy,
+    WaitStrategy,
+};
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback"),
+          feature = "stats"))]
+pub use self::os::SemaphoreStats;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback"),
+          feature = "observer"))]
+pub use self::os::SemaphoreObserver;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback"),
+          feature = "watchdog"))]
+pub use self::os::LongWait;
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+pub(crate) use self::os::{
+    clock_deadline,
+    futex_cmp_requeue,
+    futex_lock_pi,
+    futex_unlock_pi,
+    futex_wait_bitset,
+    futex_wait_until,
+    futex_wake_bitset,
+    monotonic_deadline,
+    FUTEX_BITSET_MATCH_ANY,
+};
+
+// Source of `Semaphore::id()`. Ids are unique within the process that created the semaphores.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// Called by guards whose token couldn't be posted back. Dropping a guard has no way to report the
+// error, and a panic while unwinding would abort anyway, so abort right away rather than leave the
+// semaphore a token short.
+pub(crate) fn lost_post(err: ::std::io::Error) -> ! {
+    eprintln!("sema: failed to post a guard's token back: {}", err);
+    ::std::process::abort()
+}
+
+// What a blocking wait does when a signal handler interrupts it, see
+// `Semaphore::set_interrupt_policy()`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InterruptPolicy {
+    // Fail with `ErrorKind::Interrupted`, so that a signal can get a thread out of a wait. The
+    // default.
+    #[default]
+    Surface,
+    // Go back to waiting, until the deadline of a timed wait if it has one.
+    Retry,
+}
+
+// Runs the blocking call `f` again for as long as it is interrupted and `policy` says to retry.
+#[cfg(not(any(target_os = "linux",
+              target_os = "macos",
+              feature = "spin-fallback",
+              target_os = "hermit")))]
+fn retrying<F: FnMut() -> Result<(), Error>>(policy: InterruptPolicy, mut f: F)
+                                             -> Result<(), Error> {
+    loop {
+        match f() {
+            Err(ref e) if e.kind() == ErrorKind::Interrupted
+                          && policy == InterruptPolicy::Retry => continue,
+            res => return res,
+        }
+    }
+}
+
+// The latest time a `timespec` holds. Deadlines too far away to represent are clamped to it, which
+// comes to the same as no deadline at all with a 64-bit `time_t`, and to 2038 with a 32-bit one.
+#[cfg(not(any(target_os = "macos",
+              target_os = "hermit",
+              all(feature = "spin-fallback",
+                  not(unix)))))]
+const TIMESPEC_MAX: libc::timespec = libc::timespec {
+    tv_sec: libc::time_t::MAX,
+    tv_nsec: 999_999_999,
+};
 
-// Converts a `Duration` to a `timespec`.
-fn to_timespec(dur: Duration) -> libc::timespec {
+// Converts a `Duration` to a `timespec`. Negative durations convert to zero, and ones too long to
+// represent to `TIMESPEC_MAX`.
+#[cfg(not(any(target_os = "macos",
+              target_os = "hermit",
+              all(feature = "spin-fallback",
+                  not(unix)))))]
+#[allow(clippy::unnecessary_cast)] // Not the same type on 32-bit targets.
+pub(crate) fn to_timespec(dur: Duration) -> libc::timespec {
+    let dur = cmp::max(dur, Duration::zero());
     let sec = dur.num_seconds();
+    if sec > libc::time_t::MAX as i64 {
+        return TIMESPEC_MAX;
+    }
     // Safe to unwrap since there can't be more than one second left.
     let nsec = (dur - Duration::seconds(sec)).num_nanoseconds().unwrap();
     libc::timespec {
@@ -17,15 +140,58 @@ fn to_timespec(dur: Duration) -> libc::timespec {
     }
 }
 
+// Adds the relative time `rel` to the absolute time `now`, saturating at `TIMESPEC_MAX`.
+#[cfg(not(any(target_os = "macos",
+              target_os = "hermit",
+              all(feature = "spin-fallback",
+                  not(unix)))))]
+pub(crate) fn timespec_add(now: libc::timespec, rel: libc::timespec) -> libc::timespec {
+    let mut nsec = now.tv_nsec + rel.tv_nsec;
+    let mut carry = 0;
+    if nsec >= 1_000_000_000 {
+        nsec -= 1_000_000_000;
+        carry = 1;
+    }
+    match now.tv_sec.checked_add(rel.tv_sec).and_then(|sec| sec.checked_add(carry)) {
+        Some(sec) => libc::timespec {
+            tv_sec: sec,
+            tv_nsec: nsec,
+        },
+        None => TIMESPEC_MAX,
+    }
+}
+
 // Linux-specific semaphore, implemented with futexes.
 // Heavily based on glibc `sem_t` implementation.
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
 mod os {
-    use std::mem;
+    use std::cmp;
+    use std::fmt;
+    use std::hint;
+    use std::ops::ControlFlow;
     use std::ptr;
+    use std::thread;
+    use std::time::Instant;
+    #[cfg(any(feature = "observer",
+              feature = "watchdog"))]
+    use std::time::Duration as StdDuration;
+    #[cfg(not(loom))]
     use std::sync::atomic::{
         Ordering,
-        AtomicUsize,
+        AtomicU32,
+        AtomicU64,
+    };
+    // Under loom the semaphore's words are loom atomics and it sleeps on a modelled futex.
+    #[cfg(loom)]
+    use std::sync::atomic::Ordering;
+    #[cfg(loom)]
+    use model::{
+        futex_wait,
+        futex_wake,
+        futex_wake_op_add,
+        AtomicU32,
+        AtomicU64,
     };
     use std::io::{
         Error,
@@ -35,22 +201,25 @@ mod os {
     use libc;
     use time::Duration;
 
-    use super::to_timespec;
-
-    // The number of waiters is stored in the upper half most significant bits.
-    #[cfg(target_pointer_width = "64")]
-    const NWAITERS_SHIFT: usize = 32;
-    #[cfg(target_pointer_width = "32")]
-    const NWAITERS_SHIFT: usize = 16;
-
-    // Masks out nwaiters to obtain the Semaphore's count.
-    const VALUE_MASK: usize = (!0) >> NWAITERS_SHIFT;
-
-    // Value to add to semaphoroe to add one waiter.
-    const ONE_WAITER: usize = (1 << NWAITERS_SHIFT);
-    // Value to add to semaphore to subtract one waiter.
-    const NEG_ONE_WAITER: usize = (!0 << NWAITERS_SHIFT);
-
+    use super::{
+        timespec_add,
+        to_timespec,
+        InterruptPolicy,
+    };
+    use tsan;
+    #[cfg(feature = "fault-injection")]
+    use faults;
+    #[cfg(feature = "holders")]
+    use leak::{
+        GuardTracker,
+        Holder,
+    };
+    #[cfg(feature = "metrics")]
+    use latency::{
+        self,
+        WaitLatency,
+        LATENCY_BUCKETS,
+    };
 
     // Futex syscall number.
     #[cfg(target_arch = "x86_64")]
@@ -59,20 +228,173 @@ mod os {
     const SYS_FUTEX: libc::c_long = 240;
 
     // Syscall op numbers.
-    const FUTEX_WAIT: i32 = 0;
+    #[cfg_attr(loom, allow(dead_code))]
     const FUTEX_WAKE: i32 = 1;
-
-
-    extern {
+    const FUTEX_CMP_REQUEUE: i32 = 4;
+    #[cfg_attr(loom, allow(dead_code))]
+    const FUTEX_WAKE_OP: i32 = 5;
+    const FUTEX_LOCK_PI: i32 = 6;
+    const FUTEX_UNLOCK_PI: i32 = 7;
+    const FUTEX_WAIT_BITSET: i32 = 9;
+    const FUTEX_WAKE_BITSET: i32 = 10;
+    // Bitset matching every waiter, which makes the bitset operations behave like the plain ones.
+    pub(crate) const FUTEX_BITSET_MATCH_ANY: u32 = !0;
+    // Measures `FUTEX_WAIT_BITSET` deadlines against `CLOCK_REALTIME` instead of `CLOCK_MONOTONIC`.
+    const FUTEX_CLOCK_REALTIME: i32 = 256;
+    // `FUTEX_WAKE_OP` operation adding its argument to the second futex word.
+    #[cfg_attr(loom, allow(dead_code))]
+    const FUTEX_OP_ADD: u32 = 1;
+    // Largest argument of a `FUTEX_WAKE_OP` operation, which is a signed 12-bit value.
+    const FUTEX_OP_ARG_MAX: u32 = 2047;
+    // Tells the kernel the futex is not shared with other processes, skipping the shared lookup.
+    const FUTEX_PRIVATE_FLAG: i32 = 128;
+
+
+    extern "C" {
         // Glibc doesn't provide a futex wrapper function.
         // We use this to wrap the futex syscall.
         fn syscall(number: libc::c_long, ...) -> libc::c_long;
     }
 
+    // Selects which futex opcodes a semaphore uses.
+    //
+    // Private futexes are faster, but only work when every user of the semaphore lives in the same
+    // process. Semaphores placed in memory shared between processes must use shared futexes.
+    #[repr(u32)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum FutexMode {
+        Private,
+        Shared,
+    }
+
+    impl FutexMode {
+        // Returns the flags to combine with a futex opcode.
+        fn op_flags(self) -> i32 {
+            match self {
+                FutexMode::Private => FUTEX_PRIVATE_FLAG,
+                FutexMode::Shared => 0,
+            }
+        }
+    }
+
+    // Converts a relative timeout to an absolute `CLOCK_MONOTONIC` time.
+    pub(crate) fn monotonic_deadline(timeout: Duration) -> libc::timespec {
+        clock_deadline(Clock::Monotonic, timeout)
+    }
+
+    // Converts a relative timeout to an absolute time of `clock`.
+    // Negative durations are treated as an already expired timeout.
+    pub(crate) fn clock_deadline(clock: Clock, timeout: Duration) -> libc::timespec {
+        timespec_add(clock.gettime(), to_timespec(timeout))
+    }
+
+    // Selects the clock an absolute deadline is measured against.
+    //
+    // `Monotonic` deadlines are unaffected by changes to the system time, `Realtime` ones follow
+    // them, which is what is wanted for deadlines given as a wall-clock time.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Clock {
+        Monotonic,
+        Realtime,
+    }
+
+    impl Clock {
+        // Returns the current time of the clock, as a duration since its epoch.
+        #[allow(clippy::unnecessary_cast)] // Not the same type on 32-bit targets.
+        pub fn now(self) -> Duration {
+            let ts = self.gettime();
+            Duration::seconds(ts.tv_sec as i64) + Duration::nanoseconds(ts.tv_nsec as i64)
+        }
+
+        fn gettime(self) -> libc::timespec {
+            let mut now = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            unsafe {
+                libc::clock_gettime(self.id(), &mut now);
+            }
+            now
+        }
+
+        fn id(self) -> libc::clockid_t {
+            match self {
+                Clock::Monotonic => libc::CLOCK_MONOTONIC,
+                Clock::Realtime => libc::CLOCK_REALTIME,
+            }
+        }
+
+        // Returns the flags to combine with `FUTEX_WAIT_BITSET`.
+        fn op_flags(self) -> i32 {
+            match self {
+                Clock::Monotonic => 0,
+                Clock::Realtime => FUTEX_CLOCK_REALTIME,
+            }
+        }
+    }
+
+    // Returns the time left until `deadline` of `clock`, zero if it has passed.
+    #[cfg(feature = "watchdog")]
+    fn time_until(deadline: &libc::timespec, clock: Clock) -> ::std::time::Duration {
+        let now = clock.gettime();
+        let nanos = |t: &libc::timespec| t.tv_sec as i128 * 1_000_000_000 + t.tv_nsec as i128;
+        let left = nanos(deadline) - nanos(&now);
+        ::std::time::Duration::from_nanos(left.clamp(0, u64::MAX as i128) as u64)
+    }
+
+    // Returns whether the absolute time `deadline` of `clock` has passed.
+    fn deadline_passed(deadline: &libc::timespec, clock: Clock) -> bool {
+        let now = clock.gettime();
+        (now.tv_sec, now.tv_nsec) >= (deadline.tv_sec, deadline.tv_nsec)
+    }
+
+    // Hooks for `faults`, which only test builds inject.
+    #[cfg(feature = "fault-injection")]
+    fn injected_wait_fault() -> Option<Error> {
+        faults::wait_fault()
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    fn injected_wait_fault() -> Option<Error> {
+        None
+    }
+
+    #[cfg(feature = "fault-injection")]
+    fn delay_wake() {
+        faults::delay_wake();
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    fn delay_wake() {}
+
+    #[cfg(feature = "fault-injection")]
+    fn slow_path_forced() -> bool {
+        faults::force_slow_path()
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    fn slow_path_forced() -> bool {
+        false
+    }
+
+    // Returns the address the kernel knows `word` by.
+    #[cfg(not(loom))]
+    fn futex_word(word: &AtomicU32) -> *mut u32 {
+        word.as_ptr()
+    }
+
+    // The modelled futex is keyed by the address of the loom atomic, and reads it back from there.
+    #[cfg(loom)]
+    fn futex_word(word: &AtomicU32) -> *mut u32 {
+        word as *const AtomicU32 as *mut u32
+    }
+
     // Wake at most `val` threads currently waiting on the futex.
-    fn futex_wake(uaddr: *mut u32, val: u32) -> Result<i32, Error> {
+    #[cfg(not(loom))]
+    fn futex_wake(uaddr: *mut u32, val: u32, mode: FutexMode) -> Result<i32, Error> {
+        delay_wake();
         let res = unsafe {
-            syscall(SYS_FUTEX, uaddr, FUTEX_WAKE, val)
+            syscall(SYS_FUTEX, uaddr, FUTEX_WAKE | mode.op_flags(), val)
         };
         if res == -1 {
             Err(Error::last_os_error())
@@ -81,12 +403,34 @@ mod os {
         }
     }
 
+    // Wakes at most `val` threads on the posting path. The wake only fails if `uaddr` isn't a
+    // usable futex word, and a panic can't unwind out of a signal handler, so abort instead.
+    fn futex_wake_posted(uaddr: *mut u32, val: u32, mode: FutexMode) {
+        if futex_wake(uaddr, val, mode).is_err() {
+            ::std::process::abort();
+        }
+    }
+
     // Puts the current thread to sleep on the futex.
-    // If the timeout is non-NULL, the thread wake after the timeout specified with
-    // `ErrorKind::TimedOut`.
-    fn futex_wait(uaddr: *mut u32, val: u32, timeout: *const libc::timespec) -> Result<i32, Error> {
+    // If the deadline is non-NULL, the thread wakes at that absolute time of `clock` with
+    // `ErrorKind::TimedOut`. Unlike a relative timeout, the deadline stays the same when the wait is
+    // retried, and means the same thing in every process sharing the futex.
+    #[cfg(not(loom))]
+    fn futex_wait(uaddr: *mut u32, val: u32, deadline: *const libc::timespec, clock: Clock,
+                  mode: FutexMode) -> Result<i32, Error> {
+        futex_wait_bitset(uaddr, val, deadline, clock, FUTEX_BITSET_MATCH_ANY, mode)
+    }
+
+    // Like `futex_wait()`, but only woken by wakes whose bitset intersects `bitset`.
+    pub(crate) fn futex_wait_bitset(uaddr: *mut u32, val: u32, deadline: *const libc::timespec,
+                                    clock: Clock, bitset: u32, mode: FutexMode)
+                                    -> Result<i32, Error> {
+        if let Some(err) = injected_wait_fault() {
+            return Err(err);
+        }
+        let op = FUTEX_WAIT_BITSET | mode.op_flags() | clock.op_flags();
         let res = unsafe {
-            syscall(SYS_FUTEX, uaddr, FUTEX_WAIT, val, timeout)
+            syscall(SYS_FUTEX, uaddr, op, val, deadline, ptr::null::<u32>(), bitset)
         };
         if res == -1 {
             Err(Error::last_os_error())
@@ -95,103 +439,971 @@ mod os {
         }
     }
 
-    unsafe trait AsPointer<T> {
-        unsafe fn as_ptr(&self) -> *mut T;
+    // Wakes at most `val` threads waiting with a bitset that intersects `bitset`.
+    pub(crate) fn futex_wake_bitset(uaddr: *mut u32, val: u32, bitset: u32, mode: FutexMode)
+                                    -> Result<i32, Error> {
+        delay_wake();
+        let res = unsafe {
+            syscall(SYS_FUTEX, uaddr, FUTEX_WAKE_BITSET | mode.op_flags(), val,
+                    ptr::null::<libc::timespec>(), ptr::null::<u32>(), bitset)
+        };
+        if res == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(res as i32)
+        }
     }
 
-    // This is almost definitely undefined behaviour, however this is the only method to get the
-    // address of the underlying integer contained in the atomic wrapper since the field is private.
+    // Sleeps on the futex `word` until `poll()` breaks with the wait's result. Until then `poll()`
+    // continues with the value `word` has to hold for the thread to go to sleep, and is called
+    // again after every wakeup, spurious or not. The wait fails with `ErrorKind::TimedOut` at the
+    // absolute `CLOCK_MONOTONIC` time `deadline`, if non-null, and with `ErrorKind::Interrupted`
+    // when a signal handler interrupts it, unless `interrupts` says to retry.
     //
-    // This is ONLY to pass to the kernel for futex syscalls and should never, ever, ever be done under
-    // normal circumstances.
-    unsafe impl AsPointer<usize> for AtomicUsize {
-        unsafe fn as_ptr(&self) -> *mut usize {
-            mem::transmute(self)
+    // Always a real futex, also under loom: the primitives built on it use std's atomics.
+    pub(crate) fn futex_wait_until<T, F>(word: &::std::sync::atomic::AtomicU32,
+                                         deadline: *const libc::timespec, mode: FutexMode,
+                                         interrupts: InterruptPolicy, mut poll: F)
+                                         -> Result<T, Error>
+        where F: FnMut() -> ControlFlow<T, u32>
+    {
+        loop {
+            let expected = match poll() {
+                ControlFlow::Break(res) => return Ok(res),
+                ControlFlow::Continue(expected) => expected,
+            };
+            let res = futex_wait_bitset(word.as_ptr(), expected, deadline, Clock::Monotonic,
+                                        FUTEX_BITSET_MATCH_ANY, mode);
+            if let Err(e) = res {
+                match e.kind() {
+                    ErrorKind::TimedOut => return Err(e),
+                    ErrorKind::Interrupted if interrupts == InterruptPolicy::Surface => {
+                        return Err(e)
+                    }
+                    // Interrupted and retrying, or `word` already changed. Poll again.
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // Acquires a priority-inheritance futex, which holds the TID of its owner or 0, boosting the
+    // owner while we block. The deadline, if non-null, is an absolute `CLOCK_REALTIME` time.
+    pub(crate) fn futex_lock_pi(uaddr: *mut u32, deadline: *const libc::timespec, mode: FutexMode)
+                                -> Result<i32, Error> {
+        let res = unsafe {
+            syscall(SYS_FUTEX, uaddr, FUTEX_LOCK_PI | mode.op_flags(), 0, deadline)
+        };
+        if res == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(res as i32)
+        }
+    }
+
+    // Releases a priority-inheritance futex owned by the calling thread, handing it to the highest
+    // priority waiter.
+    pub(crate) fn futex_unlock_pi(uaddr: *mut u32, mode: FutexMode) -> Result<i32, Error> {
+        let res = unsafe {
+            syscall(SYS_FUTEX, uaddr, FUTEX_UNLOCK_PI | mode.op_flags())
+        };
+        if res == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(res as i32)
+        }
+    }
+
+    // Atomically adds `add` to the futex word and wakes at most `wake` threads waiting on it, in a
+    // single syscall. `add` must not exceed `FUTEX_OP_ARG_MAX`.
+    #[cfg(not(loom))]
+    fn futex_wake_op_add(uaddr: *mut u32, add: u32, wake: u32, mode: FutexMode)
+                         -> Result<i32, Error> {
+        // The operation is applied to the second address and its result never triggers a second
+        // wake, since that wakes nobody.
+        let op = (FUTEX_OP_ADD << 28) | (add << 12);
+        delay_wake();
+        let res = unsafe {
+            syscall(SYS_FUTEX, uaddr, FUTEX_WAKE_OP | mode.op_flags(), wake, 0usize, uaddr, op)
+        };
+        if res == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(res as i32)
         }
     }
 
+    // Wakes at most `wake` threads waiting on `uaddr` and moves at most `requeue` of the others to
+    // wait on `uaddr2` instead, provided `uaddr` still holds `expected`. Otherwise fails with
+    // `ErrorKind::WouldBlock`. Returns the number of threads woken or requeued.
+    pub(crate) fn futex_cmp_requeue(uaddr: *mut u32, wake: u32, requeue: u32, uaddr2: *mut u32,
+                                    expected: u32, mode: FutexMode) -> Result<i32, Error> {
+        // The requeue limit is passed in place of the timeout pointer.
+        let res = unsafe {
+            syscall(SYS_FUTEX, uaddr, FUTEX_CMP_REQUEUE | mode.op_flags(), wake,
+                    requeue as usize, uaddr2, expected)
+        };
+        if res == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(res as i32)
+        }
+    }
+
+    // Upper bound on the number of times a waiter polls the semaphore before blocking.
+    const MAX_SPINS: u32 = 100;
+    // Number of times `WaitStrategy::YieldThenBlock` yields before blocking.
+    const YIELD_LIMIT: u32 = 16;
+
+    // Selects what a waiter does while no token is available.
+    //
+    // Spinning trades CPU time for wakeup latency: a spinning waiter notices a post without the
+    // kernel's involvement, but burns its core while doing so.
+    #[repr(C, u32)]
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum WaitStrategy {
+        // Spin for an adaptively chosen number of polls, then block. The default.
+        #[default]
+        Adaptive,
+        // Block right away.
+        Block,
+        // Spin for the given number of polls, then block.
+        SpinThenBlock {
+            spins: u32,
+        },
+        // Never block. Timeouts are still honoured.
+        SpinOnly,
+        // Yield the timeslice a few times, then block.
+        YieldThenBlock,
+    }
+
+    // What a post does when the count would exceed `u32::MAX`, see
+    // `Semaphore::set_overflow_policy()`.
+    #[repr(u32)]
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum OverflowPolicy {
+        // Panic in debug builds and saturate in release builds, like arithmetic overflow. The
+        // default.
+        #[default]
+        DebugPanic,
+        // Always panic.
+        Panic,
+        // Stop at `u32::MAX`, dropping the tokens that don't fit.
+        Saturate,
+    }
+
+    // The count and the number of waiters live in separate words, as in glibc's 32-bit `sem_t`.
+    // Waiters only register in `nwaiters` once they are about to block, so posts and the fast path
+    // touch `value` alone, and the count can use the full 32 bits of the futex word.
+    //
+    // In handoff mode, tokens posted while threads are blocked go to `handed` instead, where only
+    // those threads can take them, and blocked threads sleep on `handed` rather than `value`.
+    #[repr(C)]
     pub struct Semaphore {
-        data: AtomicUsize,
+        // Number of available tokens. This is the futex word.
+        value: AtomicU32,
+        // Number of threads blocked or about to block in `wait_slow()`.
+        nwaiters: AtomicU32,
+        // Tokens reserved for blocked threads. The futex word in handoff mode.
+        handed: AtomicU32,
+        handoff: bool,
+        mode: FutexMode,
+        strategy: WaitStrategy,
+        interrupts: InterruptPolicy,
+        overflow: OverflowPolicy,
+        // Running average of the spins it took to get a token, used to size the next spin.
+        spins: AtomicU32,
+        id: u64,
+        #[cfg(feature = "stats")]
+        stats: Stats,
+        #[cfg(feature = "observer")]
+        observer: Option<Box<dyn SemaphoreObserver>>,
+        #[cfg(feature = "holders")]
+        guards: GuardTracker,
+        #[cfg(feature = "watchdog")]
+        watchdog: Option<Watchdog>,
+    }
+
+    #[cfg(feature = "watchdog")]
+    type WatchdogCallback = dyn Fn(&Semaphore, &LongWait) + Send + Sync;
+
+    // Threshold after which a blocked wait is reported, and who to report it to.
+    #[cfg(feature = "watchdog")]
+    struct Watchdog {
+        threshold: StdDuration,
+        callback: Box<WatchdogCallback>,
+    }
+
+    // The state of a semaphore when one of its waiters has been blocked for longer than the
+    // watchdog threshold, see `Semaphore::set_watchdog()`.
+    #[cfg(feature = "watchdog")]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct LongWait {
+        // How long the waiter has been blocked so far.
+        pub waited: StdDuration,
+        pub permits: u32,
+        // Threads blocked on the semaphore, including the one reported.
+        pub waiters: u32,
+    }
+
+    // Contention counters, only maintained with the `stats` feature.
+    #[repr(C)]
+    #[cfg_attr(not(feature = "stats"), allow(dead_code))]
+    struct Stats {
+        fast: AtomicU64,
+        slow: AtomicU64,
+        syscalls: AtomicU64,
+        timeouts: AtomicU64,
+        peak_waiters: AtomicU32,
+        // Histogram of blocked wait latencies, with the `metrics` feature.
+        #[cfg(feature = "metrics")]
+        latency: [AtomicU64; LATENCY_BUCKETS],
+    }
+
+    impl Default for Stats {
+        fn default() -> Stats {
+            Stats {
+                fast: AtomicU64::new(0),
+                slow: AtomicU64::new(0),
+                syscalls: AtomicU64::new(0),
+                timeouts: AtomicU64::new(0),
+                peak_waiters: AtomicU32::new(0),
+                #[cfg(feature = "metrics")]
+                latency: ::std::array::from_fn(|_| AtomicU64::new(0)),
+            }
+        }
+    }
+
+    // A snapshot of a semaphore's contention counters, see `Semaphore::stats()`.
+    #[cfg(feature = "stats")]
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct SemaphoreStats {
+        // Tokens taken without blocking in the kernel, including after spinning.
+        pub fast_acquisitions: u64,
+        // Tokens taken after blocking in the kernel.
+        pub slow_acquisitions: u64,
+        // Futex system calls issued by posts and waits.
+        pub futex_syscalls: u64,
+        pub timeouts: u64,
+        // Tokens currently available.
+        pub permits: u32,
+        // Threads currently blocked, and the most that ever were at once.
+        pub waiters: u32,
+        pub peak_waiters: u32,
+        // How long blocked waits took until they got a token, with the `metrics` feature.
+        #[cfg(feature = "metrics")]
+        pub wait_latency: WaitLatency,
+    }
+
+    // Callbacks on a semaphore's contention events, installed with `Semaphore::set_observer()`
+    // under the `observer` feature. They run on the thread that caused the event, so they should
+    // be quick and must not wait on the semaphore itself. Every method does nothing by default.
+    #[cfg(feature = "observer")]
+    pub trait SemaphoreObserver: Send + Sync {
+        // A thread found no token and is about to block.
+        fn on_block(&self, _sem: &Semaphore) {}
+        // A blocked thread got a token after waiting for `waited`.
+        fn on_wake(&self, _sem: &Semaphore, _waited: StdDuration) {}
+        // A blocked thread gave up because its timeout expired.
+        fn on_timeout(&self, _sem: &Semaphore) {}
+        // `n` tokens were posted. Not reported by `post_from_signal()`.
+        fn on_post(&self, _sem: &Semaphore, _n: u32) {}
     }
 
     pub struct SemaphoreGuard<'a> {
         sem: &'a Semaphore,
+        // Identifies the guard to the semaphore's `GuardTracker`, with the `holders` feature.
+        #[cfg(feature = "holders")]
+        id: u64,
     }
 
     impl Semaphore {
-        pub fn new(value: usize) -> Semaphore {
+        // Semaphores created here are local to the process, so they use private futexes which
+        // spare the kernel the lookup of the backing mapping.
+        pub fn new(value: u32) -> Semaphore {
+            Semaphore::with_futex_mode(value, FutexMode::Private)
+        }
+
+        // Nothing is allocated from the kernel, so this never fails. It's here for parity with
+        // the POSIX backend.
+        pub fn try_new(value: u32) -> Result<Semaphore, Error> {
+            Ok(Semaphore::new(value))
+        }
+
+        pub fn with_futex_mode(value: u32, mode: FutexMode) -> Semaphore {
             Semaphore {
-                data: AtomicUsize::new(value),
+                value: AtomicU32::new(value),
+                nwaiters: AtomicU32::new(0),
+                handed: AtomicU32::new(0),
+                handoff: false,
+                mode,
+                strategy: WaitStrategy::Adaptive,
+                interrupts: InterruptPolicy::Surface,
+                overflow: OverflowPolicy::DebugPanic,
+                spins: AtomicU32::new(0),
+                id: super::next_id(),
+                #[cfg(feature = "stats")]
+                stats: Stats::default(),
+                #[cfg(feature = "observer")]
+                observer: None,
+                #[cfg(feature = "holders")]
+                guards: GuardTracker::new(value),
+                #[cfg(feature = "watchdog")]
+                watchdog: None,
             }
         }
 
+        pub fn with_wait_strategy(value: u32, strategy: WaitStrategy) -> Semaphore {
+            let mut sem = Semaphore::new(value);
+            sem.set_wait_strategy(strategy);
+            sem
+        }
+
+        pub fn with_interrupt_policy(value: u32, policy: InterruptPolicy) -> Semaphore {
+            let mut sem = Semaphore::new(value);
+            sem.set_interrupt_policy(policy);
+            sem
+        }
+
+        pub fn with_overflow_policy(value: u32, policy: OverflowPolicy) -> Semaphore {
+            let mut sem = Semaphore::new(value);
+            sem.set_overflow_policy(policy);
+            sem
+        }
+
+        // Initializes a semaphore in place, in memory that may be shared with other processes.
+        pub(crate) unsafe fn init_shared(ptr: *mut Semaphore, value: u32) -> Result<(), Error> {
+            ptr::write(ptr, Semaphore::with_futex_mode(value, FutexMode::Shared));
+            Ok(())
+        }
+
+        // Clears the waiter count inherited from the parent. Only the forking thread survives in
+        // the child, so none of the recorded waiters exist there.
+        pub(crate) unsafe fn reset_after_fork(&self) {
+            self.nwaiters.store(0, Ordering::Relaxed);
+            let handed = self.handed.swap(0, Ordering::Relaxed);
+            self.value.fetch_add(handed, Ordering::Relaxed);
+        }
+
+        // Returns an id which stays the same for the semaphore's lifetime and isn't shared with any
+        // other semaphore created by this process, for correlating log lines. Also shown by
+        // `Debug` and in trace events.
+        pub fn id(&self) -> u64 {
+            self.id
+        }
+
+        pub fn futex_mode(&self) -> FutexMode {
+            self.mode
+        }
+
+        pub fn wait_strategy(&self) -> WaitStrategy {
+            self.strategy
+        }
+
+        pub fn set_wait_strategy(&mut self, strategy: WaitStrategy) {
+            self.strategy = strategy;
+        }
+
+        pub fn interrupt_policy(&self) -> InterruptPolicy {
+            self.interrupts
+        }
+
+        // Selects whether a wait interrupted by a signal handler fails with
+        // `ErrorKind::Interrupted`, which lets a handler wake a thread out of `wait()`, or goes
+        // back to waiting. Handlers installed with `SA_RESTART` don't interrupt waits either way.
+        pub fn set_interrupt_policy(&mut self, policy: InterruptPolicy) {
+            self.interrupts = policy;
+        }
+
+        pub fn overflow_policy(&self) -> OverflowPolicy {
+            self.overflow
+        }
+
+        // Selects what `post()` and `post_many()` do when the tokens would take the count past
+        // `u32::MAX`, tokens handed to blocked threads included. Without a check the count would
+        // wrap around and the tokens would be lost. `post_from_signal()` saturates whatever the
+        // policy, since it mustn't panic, and `checked_post_many()` fails instead.
+        pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+            self.overflow = policy;
+        }
+
+        pub fn handoff(&self) -> bool {
+            self.handoff
+        }
+
+        // In handoff mode, a post made while threads are blocked in `wait()` hands the token
+        // directly to one of them, instead of publishing it where a newly arriving thread could
+        // take it first. This bounds the time between a blocked thread being woken and it getting
+        // its token, at the cost of some throughput.
+        pub fn set_handoff(&mut self, handoff: bool) {
+            self.handoff = handoff;
+        }
+
+        // Installs `observer` to be told about blocking waits and posts, replacing any previous
+        // one. An observer only sees the operations of this process.
+        #[cfg(feature = "observer")]
+        pub fn set_observer<O: SemaphoreObserver + 'static>(&mut self, observer: O) {
+            self.observer = Some(Box::new(observer));
+        }
+
+        #[cfg(feature = "observer")]
+        pub fn clear_observer(&mut self) {
+            self.observer = None;
+        }
+
+        // Calls `callback` whenever a wait has been blocked for longer than `threshold`, once per
+        // wait, with the semaphore's state at that moment. With the `tracing` feature a warning is
+        // emitted as well. The callback runs on the blocked thread, which goes back to waiting
+        // once it returns, so a stuck wait is reported while it is still stuck.
+        #[cfg(feature = "watchdog")]
+        pub fn set_watchdog<F>(&mut self, threshold: Duration, callback: F)
+            where F: Fn(&Semaphore, &LongWait) + Send + Sync + 'static
+        {
+            self.watchdog = Some(Watchdog {
+                threshold: threshold.to_std().unwrap_or_default(),
+                callback: Box::new(callback),
+            });
+        }
+
+        #[cfg(feature = "watchdog")]
+        pub fn clear_watchdog(&mut self) {
+            self.watchdog = None;
+        }
+
         pub fn post(&self) {
-            let d = self.data.load(Ordering::Relaxed);
-            // Release, pending the acquire which will establish happens-before relation.
-            self.data.fetch_add(1, Ordering::Release);
+            self.post_many(1);
+        }
+
+        // Returns the contention counters accumulated since the semaphore was created.
+        #[cfg(feature = "stats")]
+        pub fn stats(&self) -> SemaphoreStats {
+            SemaphoreStats {
+                fast_acquisitions: self.stats.fast.load(Ordering::Relaxed),
+                slow_acquisitions: self.stats.slow.load(Ordering::Relaxed),
+                futex_syscalls: self.stats.syscalls.load(Ordering::Relaxed),
+                timeouts: self.stats.timeouts.load(Ordering::Relaxed),
+                permits: self.value.load(Ordering::Relaxed),
+                waiters: self.nwaiters.load(Ordering::Relaxed),
+                peak_waiters: self.stats.peak_waiters.load(Ordering::Relaxed),
+                #[cfg(feature = "metrics")]
+                wait_latency: WaitLatency::load(&self.stats.latency),
+            }
+        }
+
+        // Releases `n` tokens at once, waking as many waiters as can take one with a single
+        // syscall.
+        pub fn post_many(&self, n: u32) {
+            let n = self.admit(n);
+            self.trace_post(n);
+            self.observe_post(n);
+            self.post_untraced(n);
+        }
 
-            // If there are any waiters, wake one.
-            if (d >> NWAITERS_SHIFT) > 0 {
-                futex_wake(self.value_ptr(), 1).unwrap();
+        pub fn checked_post(&self) -> Result<(), Error> {
+            self.checked_post_many(1)
+        }
+
+        // For parity with the other backends, whose `post()` can fail. Same as `checked_post()`.
+        pub fn try_post(&self) -> Result<(), Error> {
+            self.checked_post()
+        }
+
+        // Like `post_many()`, but fails without posting anything if the tokens would take the
+        // count past `u32::MAX`. Posts racing with this one may still take the room it found, and
+        // then the overflow policy applies.
+        pub fn checked_post_many(&self, n: u32) -> Result<(), Error> {
+            if n > self.room() {
+                return Err(Error::other("semaphore count overflow"));
+            }
+            self.post_many(n);
+            Ok(())
+        }
+
+        // Returns how many tokens can be posted before the count reaches `u32::MAX`.
+        fn room(&self) -> u32 {
+            let count = self.value.load(Ordering::SeqCst)
+                .saturating_add(self.handed.load(Ordering::SeqCst));
+            u32::MAX - count
+        }
+
+        // Returns how many of `n` tokens fit below `u32::MAX`, applying the overflow policy to the
+        // rest.
+        fn admit(&self, n: u32) -> u32 {
+            let room = self.room();
+            if n <= room {
+                return n;
+            }
+            match self.overflow {
+                OverflowPolicy::DebugPanic if cfg!(debug_assertions) => {
+                    panic!("semaphore count overflow")
+                }
+                OverflowPolicy::Panic => panic!("semaphore count overflow"),
+                _ => room,
+            }
+        }
+
+        // Adds `n` tokens to `value`, stopping at `u32::MAX` if racing posts left less room than
+        // was found when the tokens were admitted.
+        fn publish(&self, n: u32) {
+            let _ = self.value.fetch_update(Ordering::SeqCst, Ordering::SeqCst,
+                                            |v| Some(v.saturating_add(n)));
+        }
+
+        // Posts without emitting trace events, which isn't async-signal-safe.
+        pub(crate) fn post_untraced(&self, n: u32) {
+            // Saturates, as panicking isn't async-signal-safe either.
+            let n = cmp::min(n, self.room());
+            if n == 0 {
+                return;
+            }
+            // The tokens may be added by the kernel, out of TSan's sight.
+            tsan::release(self.value_ptr());
+            if self.handoff {
+                return self.post_handoff(n);
+            }
+            // With threads already blocked a syscall is needed anyway, so let the kernel add the
+            // tokens and wake the waiters together under the futex's lock, instead of publishing
+            // the tokens first and leaving them to whoever shows up before the wakeup is issued.
+            // The kernel's addition wraps, so leave counts near the limit to `publish()`.
+            let waiters = self.nwaiters.load(Ordering::SeqCst);
+            if waiters > 0 && n <= FUTEX_OP_ARG_MAX
+               && self.value.load(Ordering::Relaxed) <= u32::MAX / 2 {
+                // Wake up to one thread per token rather than per waiter counted above: more
+                // threads may have registered and gone to sleep since, before the tokens arrive.
+                // The kernel only wakes threads that are actually asleep.
+                self.record(|s| s.syscalls.fetch_add(1, Ordering::Relaxed));
+                if futex_wake_op_add(self.value_ptr(), n, n, self.mode).is_ok() {
+                    return;
+                }
+            }
+
+            // Release, pending the acquire which will establish happens-before relation. SeqCst
+            // orders it before the load of `nwaiters`, pairing with `wait_slow()` which registers
+            // before checking the value: either we see the waiter, or it sees the tokens.
+            self.publish(n);
+
+            // If there are any waiters, wake up to one per token.
+            let waiters = self.nwaiters.load(Ordering::SeqCst);
+            if waiters > 0 {
+                let wake = cmp::min(cmp::min(n, waiters), i32::MAX as u32);
+                self.record(|s| s.syscalls.fetch_add(1, Ordering::Relaxed));
+                futex_wake_posted(self.value_ptr(), wake, self.mode);
             }
         }
 
         pub fn wait(&self) -> Result<(), Error> {
-            self.wait_fast(false).or_else(|_| {
-                self.wait_slow(ptr::null())
-            })
+            self.wait_until(ptr::null(), Clock::Monotonic)
+        }
+
+        // Like `wait()`, but goes back to waiting when interrupted whatever the interrupt policy.
+        // Without a deadline there is nothing else a wait can fail with, so neither can this.
+        pub fn wait_infallible(&self) {
+            while self.wait().is_err() {}
         }
 
         pub fn try_wait(&self) -> Result<(), Error> {
-            self.wait_fast(true)
+            self.wait_fast(true)?;
+            self.record(|s| s.fast.fetch_add(1, Ordering::Relaxed));
+            Ok(())
         }
 
+        // Takes up to `n` tokens without blocking, returning how many were taken.
+        pub fn try_wait_many(&self, n: u32) -> u32 {
+            let mut v = self.value.load(Ordering::Relaxed);
+            loop {
+                let taken = cmp::min(v, n);
+                if taken == 0 {
+                    return 0;
+                }
+                match self.value.compare_exchange(v, v - taken, Ordering::Acquire,
+                                                  Ordering::Relaxed) {
+                    Ok(_) => {
+                        tsan::acquire(self.value_ptr());
+                        self.record(|s| s.fast.fetch_add(taken as u64, Ordering::Relaxed));
+                        return taken;
+                    }
+                    Err(prev) => v = prev,
+                }
+            }
+        }
+
+        // Returns whether any thread is blocked waiting for a token.
+        pub(crate) fn has_waiters(&self) -> bool {
+            self.nwaiters.load(Ordering::SeqCst) > 0
+        }
+
+        // Returns the number of tokens available right now.
+        pub(crate) fn permits(&self) -> u32 {
+            self.value.load(Ordering::Relaxed)
+        }
+
+        // Returns the number of threads blocked waiting for a token right now.
+        pub(crate) fn waiters(&self) -> u32 {
+            self.nwaiters.load(Ordering::Relaxed)
+        }
+
+        // A zero or negative `timeout` only takes a token that is available right away, and one
+        // too long for a `timespec` is clamped to the latest deadline it holds.
         pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
-            self.wait_fast(false).or_else(|_| {
-                let ts = to_timespec(timeout);
-                self.wait_slow(&ts)
-            })
+            // Computed before the fast path so that it doesn't eat into the timeout.
+            let deadline = monotonic_deadline(timeout);
+            self.wait_until(&deadline, Clock::Monotonic)
+        }
+
+        // Waits until the absolute time `deadline` of `clock`, given as a duration since the
+        // clock's epoch, e.g. `Clock::Realtime.now() + Duration::seconds(1)`.
+        pub fn wait_deadline(&self, clock: Clock, deadline: Duration) -> Result<(), Error> {
+            // Times before the epoch are treated as already passed.
+            let deadline = to_timespec(deadline);
+            self.wait_until(&deadline, clock)
         }
 
-        pub fn take(&self) -> Result<SemaphoreGuard, Error> {
-            try!(self.wait());
+        pub fn take(&self) -> Result<SemaphoreGuard<'_>, Error> {
+            self.wait()?;
             Ok(SemaphoreGuard {
                 sem: self,
+                #[cfg(feature = "holders")]
+                id: self.track_guard(),
             })
         }
 
-        // Returns a pointer to the value of the atomic counter.
-        // This is used to abstract over platform pointer width and endianness differences.
+        // Returns the number of guards from `take()` that are still alive, or were forgotten.
+        // Semaphores using shared futexes are not tracked and always return 0.
+        #[cfg(feature = "holders")]
+        pub fn outstanding_guards(&self) -> usize {
+            self.guards.outstanding()
+        }
+
+        // Returns the threads holding the guards from `take()` that are still alive, one entry per
+        // guard, in the order they were taken. Tokens taken with `wait()` have no holder.
+        #[cfg(feature = "holders")]
+        pub fn holders(&self) -> Vec<Holder> {
+            self.guards.holders()
+        }
+
+        // Wakes up to `wake` threads blocked in a futex wait on `from` and moves up to `requeue`
+        // others over to the semaphore, where they sleep until a token is posted, without waking
+        // them. This is the building block for condition variables which signal through a
+        // semaphore without waking every waiter at once.
+        //
+        // Nothing happens if `from` no longer holds `expected`, in which case this fails with
+        // `ErrorKind::WouldBlock`. Returns the number of threads woken or requeued.
+        //
+        // Every thread woken or requeued is counted as a waiter of the semaphore, and must go on
+        // to take a token with `wait_requeued()`. `from` must be waited on with the same
+        // `FutexMode` as the semaphore.
+        #[cfg(not(loom))]
+        pub fn requeue_from(&self, from: &AtomicU32, expected: u32, wake: u32, requeue: u32)
+                            -> Result<u32, Error> {
+            let wake = cmp::min(wake, i32::MAX as u32);
+            let requeue = cmp::min(requeue, i32::MAX as u32 - wake);
+            // Count the threads up front, so that a post made while they are being moved wakes
+            // them. Unused registrations are dropped again below.
+            let waiters = self.nwaiters.fetch_add(wake + requeue, Ordering::SeqCst);
+            self.record(|s| {
+                s.peak_waiters.fetch_max(waiters + wake + requeue, Ordering::Relaxed);
+                s.syscalls.fetch_add(1, Ordering::Relaxed)
+            });
+            let res = futex_cmp_requeue(from.as_ptr(), wake, requeue, self.futex_ptr(), expected,
+                                        self.mode);
+            let moved = *res.as_ref().unwrap_or(&0) as u32;
+            self.nwaiters.fetch_sub(wake + requeue - moved, Ordering::SeqCst);
+            // Tokens posted before the threads arrived didn't wake them.
+            if moved > 0 && (self.value.load(Ordering::SeqCst) > 0
+                             || self.handed.load(Ordering::SeqCst) > 0) {
+                self.record(|s| s.syscalls.fetch_add(1, Ordering::Relaxed));
+                futex_wake_posted(self.futex_ptr(), moved, self.mode);
+            }
+            res.map(|_| moved)
+        }
+
+        // Takes a token after having been woken or requeued by `requeue_from()`.
+        #[cfg(not(loom))]
+        pub fn wait_requeued(&self) -> Result<(), Error> {
+            self.observed(|| {
+                self.traced(|| self.timed(|| self.wait_registered(ptr::null(), Clock::Monotonic)))
+            })
+        }
+
+        // Updates the contention counters, if they are maintained.
+        #[cfg(feature = "stats")]
+        fn record<F: FnOnce(&Stats) -> R, R>(&self, f: F) {
+            f(&self.stats);
+        }
+
+        #[cfg(not(feature = "stats"))]
+        fn record<F: FnOnce(&Stats) -> R, R>(&self, _f: F) {}
+
+        // Runs the blocking wait `f`, recording its latency if it gets a token.
+        #[cfg(feature = "metrics")]
+        fn timed<F: FnOnce() -> Result<(), Error>>(&self, f: F) -> Result<(), Error> {
+            let start = ::std::time::Instant::now();
+            let res = f();
+            if res.is_ok() {
+                latency::record(&self.stats.latency, start.elapsed());
+            }
+            res
+        }
+
+        #[cfg(not(feature = "metrics"))]
+        fn timed<F: FnOnce() -> Result<(), Error>>(&self, f: F) -> Result<(), Error> {
+            f()
+        }
+
+        // Runs the blocking wait `f` in a span, and reports how long it took and how it ended.
+        #[cfg(feature = "tracing")]
+        fn traced<F: FnOnce() -> Result<(), Error>>(&self, f: F) -> Result<(), Error> {
+            let span = ::tracing::debug_span!("sema_wait", sem = self.id());
+            let _entered = span.enter();
+            let start = ::std::time::Instant::now();
+            let res = f();
+            let outcome = match res {
+                Ok(()) => "acquired",
+                Err(ref e) if e.kind() == ErrorKind::TimedOut => "timed out",
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => "interrupted",
+                Err(_) => "failed",
+            };
+            ::tracing::debug!(sem = self.id(), waited_us = start.elapsed().as_micros() as u64,
+                              outcome, "slow-path wait finished");
+            res
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        fn traced<F: FnOnce() -> Result<(), Error>>(&self, f: F) -> Result<(), Error> {
+            f()
+        }
+
+        // Records a new guard, unless the semaphore may be shared with other processes.
+        #[cfg(feature = "holders")]
+        fn track_guard(&self) -> u64 {
+            match self.mode {
+                FutexMode::Private => self.guards.track(),
+                FutexMode::Shared => 0,
+            }
+        }
+
+        // Returns when a blocked wait starting now began, if the watchdog needs to know.
+        #[cfg(feature = "watchdog")]
+        fn watch_start(&self) -> Option<Instant> {
+            self.watchdog.as_ref().map(|_| Instant::now())
+        }
+
+        #[cfg(not(feature = "watchdog"))]
+        fn watch_start(&self) -> Option<Instant> {
+            None
+        }
+
+        // Sleeps on `uaddr` while it holds 0, like `futex_wait()`. If `watch` holds the start of a
+        // wait which isn't over by the watchdog threshold, wakes up at the threshold to report it
+        // and returns as if woken spuriously, clearing `watch` so the wait is reported only once.
+        #[cfg(feature = "watchdog")]
+        fn futex_wait_watched(&self, uaddr: *mut u32, deadline: *const libc::timespec,
+                              clock: Clock, watch: &mut Option<Instant>) -> Result<i32, Error> {
+            let (start, watchdog) = match (*watch, self.watchdog.as_ref()) {
+                (Some(start), Some(watchdog)) => (start, watchdog),
+                _ => return futex_wait(uaddr, 0, deadline, clock, self.mode),
+            };
+            let until = (start + watchdog.threshold).saturating_duration_since(Instant::now());
+            // The caller's deadline comes first, the watchdog never gets to fire.
+            if !deadline.is_null() && unsafe { time_until(&*deadline, clock) } <= until {
+                return futex_wait(uaddr, 0, deadline, clock, self.mode);
+            }
+            let at = clock_deadline(Clock::Monotonic,
+                                    Duration::from_std(until).unwrap_or(Duration::max_value()));
+            match futex_wait(uaddr, 0, &at, Clock::Monotonic, self.mode) {
+                Err(ref e) if e.kind() == ErrorKind::TimedOut => {
+                    *watch = None;
+                    let wait = LongWait {
+                        waited: start.elapsed(),
+                        permits: self.value.load(Ordering::Relaxed),
+                        waiters: self.nwaiters.load(Ordering::Relaxed),
+                    };
+                    #[cfg(feature = "tracing")]
+                    ::tracing::warn!(sem = self.id(), waited_ms = wait.waited.as_millis() as u64,
+                                     permits = wait.permits, waiters = wait.waiters,
+                                     "wait exceeded watchdog threshold");
+                    (watchdog.callback)(self, &wait);
+                    Ok(0)
+                }
+                res => res,
+            }
+        }
+
+        #[cfg(not(feature = "watchdog"))]
+        fn futex_wait_watched(&self, uaddr: *mut u32, deadline: *const libc::timespec,
+                              clock: Clock, _watch: &mut Option<Instant>) -> Result<i32, Error> {
+            futex_wait(uaddr, 0, deadline, clock, self.mode)
+        }
+
+        // Reports a post of `n` tokens to the observer.
+        #[cfg(feature = "observer")]
+        fn observe_post(&self, n: u32) {
+            if let Some(ref observer) = self.observer {
+                observer.on_post(self, n);
+            }
+        }
+
+        #[cfg(not(feature = "observer"))]
+        fn observe_post(&self, _n: u32) {}
+
+        // Runs the blocking wait `f`, reporting it to the observer.
+        #[cfg(feature = "observer")]
+        fn observed<F: FnOnce() -> Result<(), Error>>(&self, f: F) -> Result<(), Error> {
+            let observer = match self.observer {
+                Some(ref observer) => observer,
+                None => return f(),
+            };
+            observer.on_block(self);
+            let start = ::std::time::Instant::now();
+            let res = f();
+            match res {
+                Ok(()) => observer.on_wake(self, start.elapsed()),
+                Err(ref e) if e.kind() == ErrorKind::TimedOut => observer.on_timeout(self),
+                Err(_) => {}
+            }
+            res
+        }
+
+        #[cfg(not(feature = "observer"))]
+        fn observed<F: FnOnce() -> Result<(), Error>>(&self, f: F) -> Result<(), Error> {
+            f()
+        }
+
+        // Reports a post of `n` tokens which is going to wake blocked threads.
+        #[cfg(feature = "tracing")]
+        fn trace_post(&self, n: u32) {
+            let waiters = self.nwaiters.load(Ordering::Relaxed);
+            if waiters > 0 {
+                ::tracing::trace!(sem = self.id(), tokens = n, waiters, "post waking waiters");
+            }
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        fn trace_post(&self, _n: u32) {}
+
+        // Counts a wait which ended with `res`, after blocking in the kernel or not.
+        fn record_wait(&self, res: &Result<(), Error>, blocked: bool) {
+            self.record(|s| {
+                match *res {
+                    Ok(()) if blocked => s.slow.fetch_add(1, Ordering::Relaxed),
+                    Ok(()) => s.fast.fetch_add(1, Ordering::Relaxed),
+                    Err(ref e) if e.kind() == ErrorKind::TimedOut => {
+                        s.timeouts.fetch_add(1, Ordering::Relaxed)
+                    }
+                    Err(_) => 0,
+                }
+            });
+        }
+
+        // Returns the word blocked threads sleep on.
+        #[cfg_attr(loom, allow(dead_code))]
+        fn futex_ptr(&self) -> *mut u32 {
+            if self.handoff {
+                self.handed_ptr()
+            } else {
+                self.value_ptr()
+            }
+        }
+
+        // Returns a pointer to the futex word.
         fn value_ptr(&self) -> *mut u32 {
-            #[cfg(any(target_endian = "little",
-                      target_pointer_width = "32"))]
-            const VALUE_OFFSET: isize = 0;
-            #[cfg(all(target_endian = "big",
-                      target_pointer_width = "64"))]
-            const VALUE_OFFSET: isize = 1;
+            futex_word(&self.value)
+        }
 
-            unsafe {
-                (self.data.as_ptr() as *mut u32).offset(VALUE_OFFSET)
+        fn handed_ptr(&self) -> *mut u32 {
+            futex_word(&self.handed)
+        }
+
+        // Hands up to one token per blocked thread to them, publishing the rest.
+        fn post_handoff(&self, n: u32) {
+            let waiters = self.nwaiters.load(Ordering::SeqCst);
+            let handed = cmp::min(n, waiters);
+            if handed > 0 {
+                self.handed.fetch_add(handed, Ordering::SeqCst);
+                self.record(|s| s.syscalls.fetch_add(1, Ordering::Relaxed));
+                futex_wake_posted(self.handed_ptr(), cmp::min(handed, i32::MAX as u32),
+                                  self.mode);
+                // The waiters may all have given up in the meantime.
+                if self.nwaiters.load(Ordering::SeqCst) == 0 {
+                    self.reclaim_handed();
+                }
+            }
+            if n > handed {
+                self.publish(n - handed);
+                // Threads may have blocked after `nwaiters` was read, having already found `value`
+                // empty. Move tokens over so that they are woken. Some of those counted may have
+                // been served already, surplus handed tokens are reclaimed by the last one out.
+                let waiters = self.nwaiters.load(Ordering::SeqCst);
+                if waiters > 0 {
+                    self.hand_over(cmp::min(n - handed, waiters));
+                }
+            }
+        }
+
+        // Moves up to `n` tokens from `value` to `handed`, waking a blocked thread for each.
+        fn hand_over(&self, n: u32) {
+            // Not `wait_fast()`, whose error allocates, this runs as part of `post_from_signal()`
+            // which has to stay async-signal-safe.
+            let taken = self.value.fetch_update(Ordering::Acquire, Ordering::Relaxed, |v| {
+                if v == 0 { None } else { Some(v - cmp::min(v, n)) }
+            });
+            if let Ok(v) = taken {
+                let moved = cmp::min(v, n);
+                tsan::acquire(self.value_ptr());
+                self.handed.fetch_add(moved, Ordering::SeqCst);
+                self.record(|s| s.syscalls.fetch_add(1, Ordering::Relaxed));
+                futex_wake_posted(self.handed_ptr(), cmp::min(moved, i32::MAX as u32),
+                                  self.mode);
+                if self.nwaiters.load(Ordering::SeqCst) == 0 {
+                    self.reclaim_handed();
+                }
+            }
+        }
+
+        // Publishes the handed tokens nobody is left to take.
+        fn reclaim_handed(&self) {
+            let handed = self.handed.swap(0, Ordering::SeqCst);
+            if handed > 0 {
+                self.publish(handed);
+                // Threads may have registered since `nwaiters` was found empty, and gone to sleep
+                // after finding neither word holding a token. Hand tokens back so they are woken.
+                let waiters = self.nwaiters.load(Ordering::SeqCst);
+                if waiters > 0 {
+                    self.hand_over(cmp::min(handed, waiters));
+                }
+            }
+        }
+
+        // Takes a handed token if there is one.
+        fn take_handed(&self) -> bool {
+            let mut h = self.handed.load(Ordering::SeqCst);
+            while h > 0 {
+                match self.handed.compare_exchange(h, h - 1, Ordering::Acquire, Ordering::SeqCst) {
+                    Ok(_) => return true,
+                    Err(prev) => h = prev,
+                }
             }
+            false
         }
 
         // Will grab a token if one is available. Otherwise, returns `ErrorKind::WouldBlock`.
         fn wait_fast(&self, definitive_result: bool) -> Result<(), Error> {
-            let mut d = self.data.load(Ordering::Relaxed);
+            let mut v = self.value.load(Ordering::Relaxed);
             loop {
                 // Check if there is a token available.
-                if (d & VALUE_MASK) == 0 {
+                if v == 0 {
                     // No token available. Need to call `wait_slow()` and block.
                     return Err(Error::new(ErrorKind::WouldBlock, "wait would block"));
                 }
                 // Grab the token and establish synchronizes-with between threads.
-                let prev = self.data.compare_and_swap(d, d - 1, Ordering::Acquire);
-                if prev == d {
+                match self.value.compare_exchange(v, v - 1, Ordering::Acquire, Ordering::Relaxed) {
                     // Swap was successful and we have taken a token.
-                    return Ok(())
-                } else {
+                    Ok(_) => {
+                        tsan::acquire(self.value_ptr());
+                        return Ok(());
+                    }
                     // Swap was unsuccessful. Update variable and possibly loop.
-                    d = prev;
+                    Err(prev) => v = prev,
                 }
                 if definitive_result {
                     continue;
@@ -201,46 +1413,201 @@ mod os {
             }
         }
 
-        fn wait_slow(&self, timeout: *const libc::timespec) -> Result<(), Error> {
-            let mut d = self.data.fetch_add(ONE_WAITER, Ordering::Relaxed);
+        // Waits for a token according to the semaphore's `WaitStrategy`.
+        fn wait_until(&self, deadline: *const libc::timespec, clock: Clock) -> Result<(), Error> {
+            if slow_path_forced() {
+                return self.wait_slow(deadline, clock);
+            }
+            if self.wait_fast(false).is_ok() {
+                self.record(|s| s.fast.fetch_add(1, Ordering::Relaxed));
+                return Ok(());
+            }
+            // An expired deadline, as from a zero or negative timeout, makes this a `try_wait()`
+            // that fails with `ErrorKind::TimedOut`, so don't poll for a token that isn't there.
+            let expired = !deadline.is_null() && unsafe { deadline_passed(&*deadline, clock) };
+            let polled = match self.strategy {
+                WaitStrategy::SpinOnly => return self.wait_spin_only(deadline, clock),
+                _ if expired => false,
+                WaitStrategy::Adaptive => self.wait_spin(),
+                WaitStrategy::Block => false,
+                WaitStrategy::SpinThenBlock { spins } => self.poll(spins, false).0,
+                WaitStrategy::YieldThenBlock => self.poll(YIELD_LIMIT, true).0,
+            };
+            if polled {
+                self.record(|s| s.fast.fetch_add(1, Ordering::Relaxed));
+                Ok(())
+            } else {
+                self.wait_slow(deadline, clock)
+            }
+        }
+
+        // Polls for a token up to `limit` times, spinning or yielding in between. Returns whether
+        // a token was taken and the number of polls it took.
+        fn poll(&self, limit: u32, yield_now: bool) -> (bool, u32) {
+            for n in 1..limit + 1 {
+                if yield_now {
+                    thread::yield_now();
+                } else {
+                    hint::spin_loop();
+                }
+                if self.value.load(Ordering::Relaxed) != 0 && self.wait_fast(true).is_ok() {
+                    return (true, n);
+                }
+            }
+            (false, limit)
+        }
+
+        // Polls for a token for a while before resorting to `wait_slow()`, which saves the two
+        // syscalls of a sleep and wakeup when tokens are held only briefly.
+        //
+        // Like glibc's adaptive mutexes, the number of polls tracks how long it recently took for
+        // a token to show up, up to `MAX_SPINS`.
+        fn wait_spin(&self) -> bool {
+            let estimate = self.spins.load(Ordering::Relaxed);
+            let limit = cmp::min(MAX_SPINS, estimate * 2 + 10);
+            let (taken, n) = self.poll(limit, false);
+            let estimate = estimate as i32;
+            self.spins.store((estimate + (n as i32 - estimate) / 8) as u32, Ordering::Relaxed);
+            taken
+        }
+
+        fn wait_spin_only(&self, deadline: *const libc::timespec, clock: Clock)
+                          -> Result<(), Error> {
+            let res = loop {
+                if self.poll(MAX_SPINS, false).0 {
+                    break Ok(());
+                }
+                if !deadline.is_null() && unsafe { deadline_passed(&*deadline, clock) } {
+                    break Err(Error::new(ErrorKind::TimedOut, "wait timed out"));
+                }
+            };
+            self.record_wait(&res, false);
+            res
+        }
+
+        fn wait_slow(&self, deadline: *const libc::timespec, clock: Clock) -> Result<(), Error> {
+            // Register before looking for tokens, see `post_many()` and `post_handoff()`.
+            let waiters = self.nwaiters.fetch_add(1, Ordering::SeqCst);
+            self.record(|s| s.peak_waiters.fetch_max(waiters + 1, Ordering::Relaxed));
+            self.observed(|| self.traced(|| self.timed(|| self.wait_registered(deadline, clock))))
+        }
+
+        // Waits as a thread already counted in `nwaiters`, and removes it from the count once done.
+        fn wait_registered(&self, deadline: *const libc::timespec, clock: Clock)
+                           -> Result<(), Error> {
+            if self.handoff {
+                return self.wait_handoff(deadline, clock);
+            }
+            let mut v = self.value.load(Ordering::SeqCst);
+            let mut watch = self.watch_start();
 
             // Wait for a token to become available.
-            loop {
+            let res = loop {
                 // If there is no token avalable, sleep until there is.
-                if (d & VALUE_MASK) == 0 {
-                    let res = futex_wait(self.value_ptr(), 0, timeout);
-
-                    // If `futex_wait` timed out, or was interrupted by a signal, return this error to
-                    // the caller. Otherwise we retry.
+                if v == 0 {
+                    self.record(|s| s.syscalls.fetch_add(1, Ordering::Relaxed));
+                    let res = self.futex_wait_watched(self.value_ptr(), deadline, clock,
+                                                      &mut watch);
+
+                    // If `futex_wait` timed out, or was interrupted by a signal and the policy
+                    // says so, return this error to the caller. Otherwise we retry, with the same
+                    // absolute deadline, so that signals can't stretch the wait.
                     if let Err(e) = res {
-                        if e.kind() == ErrorKind::Interrupted || e.kind() == ErrorKind::TimedOut {
-                            self.data.fetch_add(NEG_ONE_WAITER, Ordering::Relaxed);
-                            return Err(e);
+                        if self.gives_up(&e) {
+                            break Err(e);
                         }
                     }
 
-                    d = self.data.load(Ordering::Relaxed);
+                    v = self.value.load(Ordering::Relaxed);
                 } else {
-                    // There is a token available, try to take the token and decrement the number of
-                    // waiters. Return if we are successful, loop if not.
-                    let prev = self.data.compare_and_swap(d, (d - 1) - ONE_WAITER, Ordering::Acquire);
-                    if prev == d {
+                    // There is a token available, try to take it. Return if we are successful,
+                    // loop if not.
+                    match self.value.compare_exchange(v, v - 1, Ordering::Acquire,
+                                                      Ordering::Relaxed) {
                         // Swap was successful and we have synchronizes-with relationship.
-                        return Ok(())
-                    } else {
+                        Ok(_) => {
+                            tsan::acquire(self.value_ptr());
+                            break Ok(());
+                        }
                         // Swap was unsuccessful. Update variable and retry.
-                        d = prev;
+                        Err(prev) => v = prev,
                     }
                 }
+            };
+            self.nwaiters.fetch_sub(1, Ordering::Relaxed);
+            self.record_wait(&res, true);
+            res
+        }
+
+        // Returns whether a blocked wait failing with `e` ends, instead of blocking again.
+        fn gives_up(&self, e: &Error) -> bool {
+            match e.kind() {
+                ErrorKind::TimedOut => true,
+                ErrorKind::Interrupted => self.interrupts == InterruptPolicy::Surface,
+                _ => false,
             }
         }
+
+        fn wait_handoff(&self, deadline: *const libc::timespec, clock: Clock)
+                        -> Result<(), Error> {
+            let mut watch = self.watch_start();
+            let res = loop {
+                if self.take_handed() || self.wait_fast(true).is_ok() {
+                    break Ok(());
+                }
+                self.record(|s| s.syscalls.fetch_add(1, Ordering::Relaxed));
+                let res = self.futex_wait_watched(self.handed_ptr(), deadline, clock, &mut watch);
+                if let Err(e) = res {
+                    if self.gives_up(&e) {
+                        // A token handed over just now may have been meant for us, and its
+                        // wakeup consumed by this thread.
+                        if self.take_handed() {
+                            break Ok(());
+                        }
+                        break Err(e);
+                    }
+                }
+            };
+            self.nwaiters.fetch_sub(1, Ordering::SeqCst);
+            // Don't strand tokens handed over after we last looked.
+            if self.nwaiters.load(Ordering::SeqCst) == 0 && self.handed.load(Ordering::SeqCst) > 0 {
+                self.reclaim_handed();
+            }
+            self.record_wait(&res, true);
+            res
+        }
     }
 
     unsafe impl Send for Semaphore {}
     unsafe impl Sync for Semaphore {}
 
+    impl fmt::Debug for Semaphore {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("Semaphore")
+             .field("id", &self.id)
+             .field("value", &self.value.load(Ordering::Relaxed))
+             .field("waiters", &self.nwaiters.load(Ordering::Relaxed))
+             .field("mode", &self.mode)
+             .field("strategy", &self.strategy)
+             .field("handoff", &self.handoff)
+             .finish()
+        }
+    }
+
+    // Reports guards that were never dropped, and permits that were never posted back.
+    #[cfg(feature = "leak-check")]
+    impl Drop for Semaphore {
+        fn drop(&mut self) {
+            if self.mode == FutexMode::Private {
+                self.guards.report(self.id, self.value.load(Ordering::Relaxed));
+            }
+        }
+    }
+
     impl<'a> Drop for SemaphoreGuard<'a> {
         fn drop(&mut self) {
+            #[cfg(feature = "holders")]
+            self.sem.guards.untrack(self.id);
             self.sem.post();
         }
     }
@@ -250,12 +1617,19 @@ mod os {
 //
 // This is the basic, non-shared semaphore that is present on most unix-likes. OS X is excluded as
 // it does not implement process local semaphores, and Linux is omitted because we have our own
-// implementation instead.
+// implementation instead. Targets using the spin fallback are excluded as well.
 #[cfg(not(any(target_os = "macos",
-              target_os = "linux")))]
+              target_os = "linux",
+              target_os = "hermit",
+              feature = "spin-fallback")))]
 mod os {
     use std::cell::UnsafeCell;
-    use std::mem;
+    use std::fmt;
+    use std::mem::{
+        self,
+        MaybeUninit,
+    };
+    use std::ptr;
     use std::io::Error;
 
     use time::Duration;
@@ -263,31 +1637,70 @@ mod os {
         self,
         c_int,
         c_uint,
+        sem_destroy,
+        sem_getvalue,
+        sem_init,
+        sem_post,
+        sem_t,
+        sem_trywait,
+        sem_wait,
     };
+    #[cfg(not(target_os = "freebsd"))]
+    use libc::sem_timedwait;
 
-    use super::to_timespec;
+    use super::{
+        timespec_add,
+        to_timespec,
+        InterruptPolicy,
+    };
+
+    // Not exposed by the libc crate.
+    #[cfg(target_os = "freebsd")]
+    extern "C" {
+        fn sem_clockwait_np(sem: *mut sem_t, clock: libc::clockid_t, flags: c_int,
+                            rqtp: *const libc::timespec, rmtp: *mut libc::timespec) -> c_int;
+    }
 
-    #[cfg(target_pointer_width = "64")]
-    const SIZEOF_SEM_T: usize = 32;
-    #[cfg(not(target_pointer_width = "64"))]
-    const SIZEOF_SEM_T: usize = 16;
+    // The clock timed waits measure their deadline against. Where the platform lets us choose it,
+    // that is the monotonic clock, so that setting the system time can't make a wait end early or
+    // hang. `sem_timedwait()` only takes `CLOCK_REALTIME` deadlines.
+    #[cfg(target_os = "freebsd")]
+    const WAIT_CLOCK: libc::clockid_t = libc::CLOCK_MONOTONIC;
+    #[cfg(not(target_os = "freebsd"))]
+    const WAIT_CLOCK: libc::clockid_t = libc::CLOCK_REALTIME;
+
+    // Converts a relative timeout to an absolute time of `WAIT_CLOCK`.
+    fn deadline(timeout: Duration) -> libc::timespec {
+        let mut now = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe {
+            libc::clock_gettime(WAIT_CLOCK, &mut now);
+        }
+        // Negative durations are treated as an already expired timeout.
+        timespec_add(now, to_timespec(timeout))
+    }
 
-    extern {
-        fn sem_init(sem: *mut sem_t, pshared: c_int, value: c_uint) -> c_int;
-        fn sem_post(sem: *mut sem_t) -> c_int;
-        fn sem_wait(sem: *mut sem_t) -> c_int;
-        fn sem_trywait(sem: *mut sem_t) -> c_int;
-        fn sem_timedwait(sem: *mut sem_t, timeout: *const libc::timespec) -> c_int;
-        fn sem_destroy(sem: *mut sem_t) -> c_int;
+    // Waits on `sem` until the absolute time `deadline` of `WAIT_CLOCK`.
+    #[cfg(target_os = "freebsd")]
+    unsafe fn sem_clockwait(sem: *mut sem_t, deadline: &libc::timespec) -> c_int {
+        sem_clockwait_np(sem, WAIT_CLOCK, libc::TIMER_ABSTIME, deadline, ptr::null_mut())
     }
-    #[repr(C)]
-    #[derive(Debug)]
-    struct sem_t {
-        __opaque: [u8; SIZEOF_SEM_T],
+
+    #[cfg(not(target_os = "freebsd"))]
+    unsafe fn sem_clockwait(sem: *mut sem_t, deadline: &libc::timespec) -> c_int {
+        sem_timedwait(sem, deadline)
     }
 
+    #[repr(C)]
     pub struct Semaphore {
         inner: UnsafeCell<sem_t>,
+        id: u64,
+        interrupts: InterruptPolicy,
+        // The `sem_t` in use, allocated on the heap by `new()` so that it never moves, or adopted
+        // with `from_raw()`. Null for a process-shared semaphore, which uses `inner` instead.
+        raw: *mut sem_t,
     }
 
     pub struct SemaphoreGuard<'a> {
@@ -295,34 +1708,110 @@ mod os {
     }
 
     impl Semaphore {
+        // Panics if `sem_init()` fails, see `try_new()`.
         pub fn new(value: u32) -> Semaphore {
-            let mut sem: sem_t = unsafe {
-                mem::uninitialized()
-            };
+            match Semaphore::try_new(value) {
+                Ok(sem) => sem,
+                Err(err) => panic!("failed to create semaphore: {}", err),
+            }
+        }
+
+        // Fails if `sem_init()` does, e.g. with `EINVAL` for a `value` above `SEM_VALUE_MAX`.
+        pub fn try_new(value: u32) -> Result<Semaphore, Error> {
+            // A `sem_t` must not be moved once initialized, so it gets its own allocation rather
+            // than living in `inner`.
+            let raw = Box::into_raw(Box::new(MaybeUninit::<sem_t>::uninit())) as *mut sem_t;
             let res = unsafe {
-                sem_init(&mut sem, 0, value as c_uint)
+                sem_init(raw, 0, value as c_uint)
             };
-            debug_assert_eq!(res, 0);
+            if res == -1 {
+                let err = Error::last_os_error();
+                drop(unsafe { Box::from_raw(raw as *mut MaybeUninit<sem_t>) });
+                return Err(err);
+            }
+
+            Ok(unsafe { Semaphore::adopt(raw) })
+        }
+
+        pub fn with_interrupt_policy(value: u32, policy: InterruptPolicy) -> Semaphore {
+            let mut sem = Semaphore::new(value);
+            sem.set_interrupt_policy(policy);
+            sem
+        }
 
+        // Initializes a process-shared semaphore in place. The `sem_t` must not be moved once
+        // initialized with `pshared` set, so unlike `new()` it is created directly at `ptr`.
+        pub(crate) unsafe fn init_shared(ptr: *mut Semaphore, value: u32) -> Result<(), Error> {
+            let sem = ptr::addr_of_mut!((*ptr).inner) as *mut sem_t;
+            if sem_init(sem, 1, value as c_uint) == -1 {
+                return Err(Error::last_os_error());
+            }
+            ptr::addr_of_mut!((*ptr).id).write(super::next_id());
+            ptr::addr_of_mut!((*ptr).interrupts).write(InterruptPolicy::Surface);
+            ptr::addr_of_mut!((*ptr).raw).write(ptr::null_mut());
+            Ok(())
+        }
+
+        // Wraps the initialized `sem_t` at `raw`, a `Box<sem_t>` which is destroyed and freed on
+        // drop. `SemRef` never drops it, so that it can borrow any `sem_t`.
+        pub(crate) unsafe fn adopt(raw: *mut sem_t) -> Semaphore {
             Semaphore {
-                inner: UnsafeCell::new(sem),
+                inner: UnsafeCell::new(mem::zeroed()),
+                id: super::next_id(),
+                interrupts: InterruptPolicy::Surface,
+                raw,
             }
         }
 
-        pub fn wait(&self) -> Result<(), Error> {
-            let res = unsafe {
-                sem_wait(self.inner.get())
-            };
-            if res == -1 {
-                Err(Error::last_os_error())
+        // Gives up the `sem_t` and its allocation without destroying either. Only a process-shared
+        // semaphore lives in `inner`, and those are never owned by value.
+        pub(crate) fn release(self) -> *mut sem_t {
+            debug_assert!(!self.raw.is_null());
+            let raw = self.raw;
+            mem::forget(self);
+            raw
+        }
+
+        // The `sem_t` in use, see `SemRef::as_ptr()`.
+        pub(crate) fn raw(&self) -> *mut sem_t {
+            self.sem()
+        }
+
+        fn sem(&self) -> *mut sem_t {
+            if self.raw.is_null() {
+                self.inner.get()
             } else {
-                Ok(())
+                self.raw
             }
         }
 
+        // The `sem_t` may record waiters which only exist in the parent, recreate it with the
+        // same value.
+        pub(crate) unsafe fn reset_after_fork(&self) {
+            let mut value: c_int = 0;
+            let res = sem_getvalue(self.sem(), &mut value);
+            debug_assert_eq!(res, 0);
+            sem_destroy(self.sem());
+            let res = sem_init(self.sem(), 0, value.max(0) as c_uint);
+            debug_assert_eq!(res, 0);
+        }
+
+        pub fn wait(&self) -> Result<(), Error> {
+            super::retrying(self.interrupts, || {
+                let res = unsafe {
+                    sem_wait(self.sem())
+                };
+                if res == -1 {
+                    Err(Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            })
+        }
+
         pub fn try_wait(&self) -> Result<(), Error> {
             let res = unsafe {
-                sem_trywait(self.inner.get())
+                sem_trywait(self.sem())
             };
             if res == -1 {
                 Err(Error::last_os_error())
@@ -332,26 +1821,63 @@ mod os {
         }
 
         pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+            // Computed once, so that retrying an interrupted wait doesn't extend it.
+            let deadline = deadline(timeout);
+            super::retrying(self.interrupts, || {
+                let res = unsafe {
+                    sem_clockwait(self.sem(), &deadline)
+                };
+                if res == -1 {
+                    Err(Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            })
+        }
+
+        // Returns an id unique among the semaphores created by this process, see the Linux
+        // `Semaphore::id()`.
+        pub fn id(&self) -> u64 {
+            self.id
+        }
+
+        // Selects what a wait interrupted by a signal handler does, see the Linux
+        // `Semaphore::set_interrupt_policy()`.
+        pub fn set_interrupt_policy(&mut self, policy: InterruptPolicy) {
+            self.interrupts = policy;
+        }
+
+        pub fn interrupt_policy(&self) -> InterruptPolicy {
+            self.interrupts
+        }
+
+        // Panics if `sem_post()` fails, see `try_post()`.
+        pub fn post(&self) {
+            if let Err(err) = self.try_post() {
+                panic!("failed to post semaphore: {}", err);
+            }
+        }
+
+        // Fails if `sem_post()` does, e.g. with `EOVERFLOW` once the count is at `SEM_VALUE_MAX`.
+        pub fn try_post(&self) -> Result<(), Error> {
             let res = unsafe {
-                let ts = to_timespec(timeout);
-                sem_timedwait(self.inner.get(), &ts)
+                sem_post(self.sem())
             };
             if res == -1 {
-                Err(Error::last_os_error())
-            } else {
-                Ok(())
+                return Err(Error::last_os_error());
             }
+            Ok(())
         }
 
-        pub fn post(&self) {
-            let res = unsafe {
-                sem_post(self.inner.get())
-            };
-            debug_assert_eq!(res, 0);
+        // `sem_post()` only releases a single token.
+        pub fn post_many(&self, n: u32) {
+            for _ in 0..n {
+                self.post();
+            }
         }
 
-        pub fn take(&self) -> Result<SemaphoreGuard, Error> {
-            try!(self.wait());
+        pub fn take(&self) -> Result<SemaphoreGuard<'_>, Error> {
+            self.wait()?;
             Ok(SemaphoreGuard { 
                 sem: self,
             })
@@ -364,70 +1890,113 @@ mod os {
     impl Drop for Semaphore {
         fn drop(&mut self) {
             let res = unsafe {
-                sem_destroy(self.inner.get())
+                sem_destroy(self.sem())
             };
             debug_assert_eq!(res, 0);
+            if !self.raw.is_null() {
+                drop(unsafe { Box::from_raw(self.raw) });
+            }
+        }
+    }
+
+    impl fmt::Debug for Semaphore {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("Semaphore")
+             .field("id", &self.id)
+             .finish()
         }
     }
 
     impl<'a> Drop for SemaphoreGuard<'a> {
         fn drop(&mut self) {
-            self.sem.post();
+            if let Err(err) = self.sem.try_post() {
+                super::lost_post(err);
+            }
         }
     }
 }
 
 // OS X specific semaphores.
 //
-// OS X does not implement `sem_init()` and process-local semaphores, however it does implement
-// process-shared semaphores. We use the latter, with randomly generated names to implement
-// pseudo-local semaphores. Semantically they should operate identically.
-#[cfg(target_os = "macos")]
+// OS X does not implement `sem_init()` and process-local semaphores, and has no `sem_timedwait()`
+// either. Process-local semaphores are built like the Linux ones instead: a count in an atomic
+// word, on which blocked threads sleep with `__ulock_wait()`, the primitive behind libc++'s and
+// Rust's own futex-based locks. It takes a relative timeout, which the kernel measures on the
+// monotonic clock.
+//
+// Semaphores which other processes can open are `NamedSemaphore`s.
+#[cfg(all(target_os = "macos",
+          not(feature = "spin-fallback")))]
 mod os {
-    use std::ffi::CString;
-    use std::cell::UnsafeCell;
-    use std::io::Error;
-
-    use rand::{
-        thread_rng,
-        Rng,
+    use std::cmp;
+    use std::fmt;
+    use std::io::{
+        Error,
+        ErrorKind,
+    };
+    use std::sync::atomic::{
+        AtomicU32,
+        Ordering,
+    };
+    use std::time::{
+        Duration as StdDuration,
+        Instant,
     };
+
     use libc::{
-        self,
         c_int,
-        c_uint,
-        c_char,
-        mode_t,
-        O_CREAT,
-        O_EXCL,
-        S_IRWXU,
+        c_void,
     };
     use time::Duration;
 
-    use super::to_timespec;
+    use super::InterruptPolicy;
 
-    const SEM_NAME_MAX: usize = 28; // No definitive value for this on OS X. Erring on the side of caution.
-    const SEM_FAILED: *mut sem_t = 0 as *mut sem_t;
+    // From XNU's `sys/ulock.h`.
+    const UL_COMPARE_AND_WAIT: u32 = 1;
+    const ULF_WAKE_ALL: u32 = 0x0000_0100;
+    const ULF_NO_ERRNO: u32 = 0x0100_0000;
 
-    extern {
-        fn sem_open(name: *const c_char, oflag: c_int, mode: mode_t, value: c_uint) -> *mut sem_t;
-        fn sem_post(sem: *mut sem_t) -> c_int;
-        fn sem_wait(sem: *mut sem_t) -> c_int;
-        fn sem_trywait(sem: *mut sem_t) -> c_int;
-        fn sem_timedwait(sem: *mut sem_t, timeout: *const libc::timespec) -> c_int;
-        fn sem_close(sem: *mut sem_t) -> c_int;
-        fn sem_unlink(sem: *const c_char) -> c_int;
+    extern "C" {
+        fn __ulock_wait(operation: u32, addr: *mut c_void, value: u64, timeout_us: u32) -> c_int;
+        fn __ulock_wake(operation: u32, addr: *mut c_void, wake_value: u64) -> c_int;
     }
 
-    #[repr(C)]
-    #[derive(Debug)]
-    pub struct sem_t {
-        __opaque: c_int,
+    // Sleeps while `word` holds `value`, for at most `timeout` if given. Fails with
+    // `ErrorKind::TimedOut` once the timeout passed, and may return early for no reason.
+    fn ulock_wait(word: &AtomicU32, value: u32, timeout: Option<StdDuration>)
+                  -> Result<(), Error> {
+        // Zero means no timeout, so round short timeouts up rather than down. Longer timeouts
+        // than fit are cut short, and the caller waits again.
+        let timeout_us = match timeout {
+            Some(t) => cmp::min(cmp::max(t.as_micros(), 1), u32::MAX as u128) as u32,
+            None => 0,
+        };
+        let res = unsafe {
+            __ulock_wait(UL_COMPARE_AND_WAIT | ULF_NO_ERRNO, word.as_ptr() as *mut c_void,
+                         value as u64, timeout_us)
+        };
+        if res >= 0 {
+            Ok(())
+        } else {
+            Err(Error::from_raw_os_error(-res))
+        }
+    }
+
+    // Wakes one thread sleeping on `word`, or all of them.
+    fn ulock_wake(word: &AtomicU32, all: bool) {
+        let op = UL_COMPARE_AND_WAIT | ULF_NO_ERRNO | if all { ULF_WAKE_ALL } else { 0 };
+        unsafe {
+            // Fails with `ENOENT` if nobody was sleeping after all.
+            __ulock_wake(op, word.as_ptr() as *mut c_void, 0);
+        }
     }
 
     pub struct Semaphore {
-        inner: UnsafeCell<*mut sem_t>,
-        name: CString,
+        value: AtomicU32,
+        // Number of threads blocked or about to block in `__ulock_wait()`.
+        nwaiters: AtomicU32,
+        id: u64,
+        interrupts: InterruptPolicy,
     }
 
     pub struct SemaphoreGuard<'a> {
@@ -436,88 +2005,345 @@ mod os {
 
     impl Semaphore {
         pub fn new(value: u32) -> Semaphore {
-            let name: String = thread_rng().gen_ascii_chars().take(SEM_NAME_MAX).collect();
-            let c_name = CString::new(name).unwrap(); // Rng does not emit 0 bytes.
-
-            let sem: *mut sem_t = unsafe {
-                sem_open(c_name.as_ptr(), O_CREAT | O_EXCL, S_IRWXU, value as c_uint)
-            };
-            debug_assert!(sem != SEM_FAILED);
-
             Semaphore {
-                inner: UnsafeCell::new(sem),
-                name: c_name,
+                value: AtomicU32::new(value),
+                nwaiters: AtomicU32::new(0),
+                id: super::next_id(),
+                interrupts: InterruptPolicy::Surface,
             }
         }
 
+        // Nothing is allocated from the kernel, so this never fails.
+        pub fn try_new(value: u32) -> Result<Semaphore, Error> {
+            Ok(Semaphore::new(value))
+        }
+
+        pub fn with_interrupt_policy(value: u32, policy: InterruptPolicy) -> Semaphore {
+            let mut sem = Semaphore::new(value);
+            sem.set_interrupt_policy(policy);
+            sem
+        }
+
+        // OS X has no process-shared unnamed semaphores, named semaphores must be used instead.
+        pub(crate) unsafe fn init_shared(_ptr: *mut Semaphore, _value: u32) -> Result<(), Error> {
+            Err(Error::new(ErrorKind::Unsupported,
+                           "process-shared unnamed semaphores are not supported"))
+        }
+
+        // Clears the waiter count inherited from the parent, only the forking thread survives in
+        // the child.
+        pub(crate) unsafe fn reset_after_fork(&self) {
+            self.nwaiters.store(0, Ordering::Relaxed);
+        }
+
         pub fn wait(&self) -> Result<(), Error> {
-            let res = unsafe {
-                sem_wait(*self.inner.get())
-            };
-            if res == -1 {
-                Err(Error::last_os_error())
-            } else {
-                Ok(())
-            }
+            self.wait_until(None)
         }
 
         pub fn try_wait(&self) -> Result<(), Error> {
-            let res = unsafe {
-                sem_trywait(*self.inner.get())
-            };
-            if res == -1 {
-                Err(Error::last_os_error())
-            } else {
-                Ok(())
+            let mut v = self.value.load(Ordering::Relaxed);
+            while v > 0 {
+                match self.value.compare_exchange_weak(v, v - 1, Ordering::Acquire,
+                                                       Ordering::Relaxed) {
+                    Ok(_) => return Ok(()),
+                    Err(prev) => v = prev,
+                }
             }
+            Err(Error::new(ErrorKind::WouldBlock, "wait would block"))
         }
 
         pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
-            let res = unsafe {
-                let ts = to_timespec(timeout);
-                sem_timedwait(*self.inner.get(), &ts)
-            };
-            if res == -1 {
-                Err(Error::last_os_error())
-            } else {
-                Ok(())
+            // Negative durations are treated as an already expired timeout, and ones too long to
+            // represent as none at all.
+            let deadline = Instant::now().checked_add(timeout.to_std().unwrap_or_default());
+            self.wait_until(deadline)
+        }
+
+        // Waits until `deadline` if given.
+        fn wait_until(&self, deadline: Option<Instant>) -> Result<(), Error> {
+            loop {
+                if self.try_wait().is_ok() {
+                    return Ok(());
+                }
+                let timeout = match deadline {
+                    Some(deadline) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            return Err(Error::new(ErrorKind::TimedOut, "wait timed out"));
+                        }
+                        Some(deadline - now)
+                    }
+                    None => None,
+                };
+                // SeqCst pairs with `post_many()`, which adds to the value before checking for
+                // waiters: either it sees this one, or the sleep below finds the value nonzero.
+                self.nwaiters.fetch_add(1, Ordering::SeqCst);
+                let res = ulock_wait(&self.value, 0, timeout);
+                self.nwaiters.fetch_sub(1, Ordering::SeqCst);
+                match res {
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted
+                                  && self.interrupts == InterruptPolicy::Surface => return res,
+                    // Timeouts are checked against the deadline above, as a long one may have
+                    // been cut short.
+                    _ => {}
+                }
             }
         }
 
+        // Returns an id unique among the semaphores created by this process, see the Linux
+        // `Semaphore::id()`.
+        pub fn id(&self) -> u64 {
+            self.id
+        }
+
+        // Selects what a wait interrupted by a signal handler does, see the Linux
+        // `Semaphore::set_interrupt_policy()`.
+        pub fn set_interrupt_policy(&mut self, policy: InterruptPolicy) {
+            self.interrupts = policy;
+        }
+
+        pub fn interrupt_policy(&self) -> InterruptPolicy {
+            self.interrupts
+        }
+
         pub fn post(&self) {
-            let res = unsafe {
-                sem_post(*self.inner.get())
-            };
-            debug_assert_eq!(res, 0);
+            self.post_many(1);
+        }
+
+        // Panics if the tokens can't be posted, see `try_post()`.
+        pub fn post_many(&self, n: u32) {
+            if let Err(err) = self.add(n) {
+                panic!("failed to post semaphore: {}", err);
+            }
+        }
+
+        // Fails if the count would pass `u32::MAX`.
+        pub fn try_post(&self) -> Result<(), Error> {
+            self.add(1)
+        }
+
+        fn add(&self, n: u32) -> Result<(), Error> {
+            if n == 0 {
+                return Ok(());
+            }
+            if self.value.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |v| v.checked_add(n))
+                    .is_err() {
+                return Err(Error::other("semaphore count overflow"));
+            }
+            if self.nwaiters.load(Ordering::SeqCst) > 0 {
+                ulock_wake(&self.value, n > 1);
+            }
+            Ok(())
         }
 
-        pub fn take(&self) -> Result<SemaphoreGuard, Error> {
-            try!(self.wait());
+        pub fn take(&self) -> Result<SemaphoreGuard<'_>, Error> {
+            self.wait()?;
             Ok(SemaphoreGuard { 
                 sem: self,
             })
         }
     }
 
-    unsafe impl Send for Semaphore {}
-    unsafe impl Sync for Semaphore {}
 
-    impl Drop for Semaphore {
+    impl fmt::Debug for Semaphore {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("Semaphore")
+             .field("id", &self.id)
+             .finish()
+        }
+    }
+
+    impl<'a> Drop for SemaphoreGuard<'a> {
         fn drop(&mut self) {
-            let res = unsafe {
-                sem_close(*self.inner.get())
-            };
-            debug_assert_eq!(res, 0);
-            let res = unsafe {
-                sem_unlink(self.name.as_ptr())
-            };
-            debug_assert_eq!(res, 0);
+            if let Err(err) = self.sem.try_post() {
+                super::lost_post(err);
+            }
+        }
+    }
+}
+
+// Spin-based fallback semaphore.
+//
+// Used on targets without an OS semaphore (unikernels such as HermitCore, custom kernels), or on
+// any target when the `spin-fallback` feature is enabled. Waiters never sleep in the kernel, they
+// spin for a short while and then yield their timeslice until a token becomes available.
+#[cfg(any(feature = "spin-fallback",
+          target_os = "hermit"))]
+mod os {
+    use std::fmt;
+    use std::hint;
+    use std::ptr;
+    use std::thread;
+    use std::time::Instant;
+    use std::sync::atomic::{
+        Ordering,
+        AtomicU32,
+    };
+    use std::io::{
+        Error,
+        ErrorKind,
+    };
+
+    use time::Duration;
+
+    use super::InterruptPolicy;
+
+    // Number of busy-wait iterations before each call to `thread::yield_now()`.
+    const SPIN_LIMIT: u32 = 64;
+
+    #[repr(C)]
+    pub struct Semaphore {
+        count: AtomicU32,
+        id: u64,
+        // Kept for parity with the other backends, nothing interrupts a spinning wait.
+        interrupts: InterruptPolicy,
+    }
+
+    pub struct SemaphoreGuard<'a> {
+        sem: &'a Semaphore,
+    }
+
+    impl Semaphore {
+        pub fn new(value: u32) -> Semaphore {
+            Semaphore {
+                count: AtomicU32::new(value),
+                id: super::next_id(),
+                interrupts: InterruptPolicy::Surface,
+            }
+        }
+
+        // A count in memory can always be created.
+        pub fn try_new(value: u32) -> Result<Semaphore, Error> {
+            Ok(Semaphore::new(value))
+        }
+
+        pub fn with_interrupt_policy(value: u32, policy: InterruptPolicy) -> Semaphore {
+            let mut sem = Semaphore::new(value);
+            sem.set_interrupt_policy(policy);
+            sem
+        }
+
+        // Initializes a semaphore in place. Atomics work just as well across processes.
+        pub(crate) unsafe fn init_shared(ptr: *mut Semaphore, value: u32) -> Result<(), Error> {
+            ptr::write(ptr, Semaphore::new(value));
+            Ok(())
+        }
+
+        // Only the count is stored, which stays meaningful in the child.
+        pub(crate) unsafe fn reset_after_fork(&self) {}
+
+        // Returns an id unique among the semaphores created by this process, see the Linux
+        // `Semaphore::id()`.
+        pub fn id(&self) -> u64 {
+            self.id
+        }
+
+        // Selects what a wait interrupted by a signal handler does, see the Linux
+        // `Semaphore::set_interrupt_policy()`.
+        pub fn set_interrupt_policy(&mut self, policy: InterruptPolicy) {
+            self.interrupts = policy;
+        }
+
+        pub fn interrupt_policy(&self) -> InterruptPolicy {
+            self.interrupts
+        }
+
+        pub fn post(&self) {
+            self.post_many(1);
+        }
+
+        // Panics if the count would pass `u32::MAX`, see `try_post()`.
+        pub fn post_many(&self, n: u32) {
+            if let Err(err) = self.add(n) {
+                panic!("failed to post semaphore: {}", err);
+            }
+        }
+
+        // Fails if the count would pass `u32::MAX`.
+        pub fn try_post(&self) -> Result<(), Error> {
+            self.add(1)
+        }
+
+        fn add(&self, n: u32) -> Result<(), Error> {
+            // Release, pending the acquire which will establish happens-before relation.
+            match self.count.fetch_update(Ordering::Release, Ordering::Relaxed,
+                                          |c| c.checked_add(n)) {
+                Ok(_) => Ok(()),
+                Err(_) => Err(Error::other("semaphore count overflow")),
+            }
+        }
+
+        pub fn wait(&self) -> Result<(), Error> {
+            self.wait_infallible();
+            Ok(())
+        }
+
+        // Spinning waits can't fail, see the Linux `Semaphore::wait_infallible()`.
+        pub fn wait_infallible(&self) {
+            while self.try_wait().is_err() {
+                self.backoff();
+            }
+        }
+
+        pub fn try_wait(&self) -> Result<(), Error> {
+            let mut d = self.count.load(Ordering::Relaxed);
+            while d > 0 {
+                match self.count.compare_exchange_weak(d, d - 1, Ordering::Acquire, Ordering::Relaxed) {
+                    Ok(_) => return Ok(()),
+                    Err(prev) => d = prev,
+                }
+            }
+            Err(Error::new(ErrorKind::WouldBlock, "wait would block"))
+        }
+
+        pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+            // Negative durations are treated as an already expired timeout.
+            let timeout = timeout.to_std().unwrap_or_default();
+            let start = Instant::now();
+            loop {
+                if self.try_wait().is_ok() {
+                    return Ok(());
+                }
+                if start.elapsed() >= timeout {
+                    return Err(Error::new(ErrorKind::TimedOut, "wait timed out"));
+                }
+                self.backoff();
+            }
+        }
+
+        pub fn take(&self) -> Result<SemaphoreGuard<'_>, Error> {
+            self.wait()?;
+            Ok(SemaphoreGuard {
+                sem: self,
+            })
+        }
+
+        // Spins until a token might be available, yielding if none shows up in time.
+        fn backoff(&self) {
+            for _ in 0..SPIN_LIMIT {
+                if self.count.load(Ordering::Relaxed) > 0 {
+                    return;
+                }
+                hint::spin_loop();
+            }
+            thread::yield_now();
+        }
+    }
+
+    impl fmt::Debug for Semaphore {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("Semaphore")
+             .field("id", &self.id)
+             .field("value", &self.count.load(Ordering::Relaxed))
+             .finish()
         }
     }
 
     impl<'a> Drop for SemaphoreGuard<'a> {
         fn drop(&mut self) {
-            self.sem.post();
+            if let Err(err) = self.sem.try_post() {
+                super::lost_post(err);
+            }
         }
     }
 }
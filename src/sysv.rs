@@ -0,0 +1,255 @@
+// System V semaphores.
+//
+// A `SysVSemaphore` is a single-member System V semaphore set. Every operation is issued with
+// `SEM_UNDO`, so the kernel reverts the net effect of a process's waits and posts when it exits.
+// A process that dies while holding permits therefore gives them back, which makes these the
+// crash-safe choice for counting across processes. For the undo bookkeeping to balance, permits
+// should be released by the process which acquired them (e.g. through `take()`).
+//
+// Semaphore sets persist until they are explicitly removed with `remove()`, even those created
+// with IPC_PRIVATE.
+#[cfg(target_os = "linux")]
+use std::cmp;
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use libc::{
+    self,
+    c_int,
+    c_short,
+    key_t,
+    IPC_CREAT,
+    IPC_EXCL,
+    IPC_NOWAIT,
+    IPC_PRIVATE,
+    IPC_RMID,
+};
+#[cfg(not(target_os = "freebsd"))]
+use libc::{
+    GETVAL,
+    SETVAL,
+    SEM_UNDO,
+};
+use sys;
+use time::Duration;
+
+// Permissions of newly created semaphore sets.
+const SEM_MODE: c_int = 0o600;
+
+// Not exposed by the libc crate on FreeBSD, see <sys/sem.h>.
+#[cfg(target_os = "freebsd")]
+const GETVAL: c_int = 5;
+#[cfg(target_os = "freebsd")]
+const SETVAL: c_int = 8;
+#[cfg(target_os = "freebsd")]
+const SEM_UNDO: c_int = 0o10000;
+
+#[cfg(target_os = "linux")]
+extern "C" {
+    // Not exposed by the libc crate.
+    fn semtimedop(semid: c_int, sops: *mut libc::sembuf, nsops: libc::size_t,
+                  timeout: *const libc::timespec) -> c_int;
+}
+
+pub struct SysVSemaphore {
+    id: c_int,
+}
+
+pub struct SysVSemaphoreGuard<'a> {
+    sem: &'a SysVSemaphore,
+}
+
+impl SysVSemaphore {
+    // Creates a new semaphore set for `key`, failing with `ErrorKind::AlreadyExists` if one
+    // already exists.
+    pub fn create(key: key_t, value: u32) -> Result<SysVSemaphore, Error> {
+        SysVSemaphore::create_with_flags(key, IPC_CREAT | IPC_EXCL | SEM_MODE, value)
+    }
+
+    // Creates a new semaphore set which can only be found through its id, e.g. by children
+    // created with `fork()`.
+    pub fn private(value: u32) -> Result<SysVSemaphore, Error> {
+        SysVSemaphore::create_with_flags(IPC_PRIVATE, IPC_CREAT | SEM_MODE, value)
+    }
+
+    // Opens the existing semaphore set for `key`.
+    pub fn open(key: key_t) -> Result<SysVSemaphore, Error> {
+        let id = unsafe {
+            libc::semget(key, 1, 0)
+        };
+        if id == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(SysVSemaphore::from_id(id))
+        }
+    }
+
+    // Wraps an existing semaphore set id, as returned by `id()`.
+    pub fn from_id(id: c_int) -> SysVSemaphore {
+        SysVSemaphore {
+            id,
+        }
+    }
+
+    pub fn id(&self) -> c_int {
+        self.id
+    }
+
+    // Returns the current number of available permits.
+    pub fn value(&self) -> Result<u32, Error> {
+        let res = unsafe {
+            libc::semctl(self.id, 0, GETVAL)
+        };
+        if res == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(res as u32)
+        }
+    }
+
+    pub fn wait(&self) -> Result<(), Error> {
+        self.op(-1, 0)
+    }
+
+    pub fn try_wait(&self) -> Result<(), Error> {
+        self.op(-1, IPC_NOWAIT).map_err(|e| {
+            // EAGAIN already maps to `WouldBlock`, but be explicit about it.
+            if e.kind() == ErrorKind::WouldBlock {
+                Error::new(ErrorKind::WouldBlock, "wait would block")
+            } else {
+                e
+            }
+        })
+    }
+
+    // `semtimedop()` takes a relative timeout, which the kernel measures on the monotonic clock.
+    #[cfg(target_os = "linux")]
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        let mut buf = sembuf(-1, 0);
+        // Negative durations are treated as an already expired timeout, rather than rejected.
+        let timeout = cmp::max(timeout, Duration::zero());
+        let sec = timeout.num_seconds();
+        let nsec = (timeout - Duration::seconds(sec)).num_nanoseconds().unwrap();
+        let ts = libc::timespec {
+            tv_sec: sec as libc::time_t,
+            tv_nsec: nsec as libc::c_long,
+        };
+        let res = unsafe {
+            semtimedop(self.id, &mut buf, 1, &ts)
+        };
+        if res == -1 {
+            let err = Error::last_os_error();
+            if err.raw_os_error() == Some(libc::EAGAIN) {
+                Err(Error::new(ErrorKind::TimedOut, "wait timed out"))
+            } else {
+                Err(err)
+            }
+        } else {
+            Ok(())
+        }
+    }
+
+    // There is no `semtimedop()` outside of Linux, so we poll until the deadline passes.
+    #[cfg(not(target_os = "linux"))]
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        use std::thread;
+        use std::time::Instant;
+
+        // Negative durations are treated as an already expired timeout, and ones too large for
+        // the clock as no timeout at all.
+        let deadline = Instant::now().checked_add(timeout.to_std().unwrap_or_default());
+        loop {
+            match self.try_wait() {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                res => return res,
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(Error::new(ErrorKind::TimedOut, "wait timed out"));
+            }
+            thread::sleep(::std::time::Duration::from_millis(1));
+        }
+    }
+
+    // Panics if `semop()` fails, see `try_post()`.
+    pub fn post(&self) {
+        if let Err(err) = self.try_post() {
+            panic!("failed to post semaphore: {}", err);
+        }
+    }
+
+    // Fails if `semop()` does, e.g. with `ERANGE` once the count is at `SEMVMX`, or with `EIDRM`
+    // if the set was removed.
+    pub fn try_post(&self) -> Result<(), Error> {
+        self.op(1, 0)
+    }
+
+    pub fn take(&self) -> Result<SysVSemaphoreGuard<'_>, Error> {
+        self.wait()?;
+        Ok(SysVSemaphoreGuard {
+            sem: self,
+        })
+    }
+
+    // Removes the semaphore set from the system, waking all waiters with an error.
+    pub fn remove(self) -> Result<(), Error> {
+        let res = unsafe {
+            libc::semctl(self.id, 0, IPC_RMID)
+        };
+        if res == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn create_with_flags(key: key_t, flags: c_int, value: u32) -> Result<SysVSemaphore, Error> {
+        let id = unsafe {
+            libc::semget(key, 1, flags)
+        };
+        if id == -1 {
+            return Err(Error::last_os_error());
+        }
+        let res = unsafe {
+            libc::semctl(id, 0, SETVAL, value as c_int)
+        };
+        if res == -1 {
+            let err = Error::last_os_error();
+            unsafe {
+                libc::semctl(id, 0, IPC_RMID);
+            }
+            return Err(err);
+        }
+        Ok(SysVSemaphore::from_id(id))
+    }
+
+    // Applies `delta` to the semaphore with `SEM_UNDO` and the given extra flags.
+    fn op(&self, delta: c_short, flags: c_int) -> Result<(), Error> {
+        let mut buf = sembuf(delta, flags);
+        let res = unsafe {
+            libc::semop(self.id, &mut buf, 1)
+        };
+        if res == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a> Drop for SysVSemaphoreGuard<'a> {
+    fn drop(&mut self) {
+        if let Err(err) = self.sem.try_post() {
+            sys::lost_post(err);
+        }
+    }
+}
+
+fn sembuf(delta: c_short, flags: c_int) -> libc::sembuf {
+    libc::sembuf {
+        sem_num: 0,
+        sem_op: delta,
+        sem_flg: (flags | SEM_UNDO) as c_short,
+    }
+}
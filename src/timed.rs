@@ -0,0 +1,110 @@
+// Guards with a deadline.
+//
+// A `TimedGuard` holds a permit of a shared `Semaphore` and returns it when dropped or when its
+// deadline passes, whichever comes first. It is a safety net for permits held across code which
+// might never give them back, such as callbacks into foreign code.
+//
+// Each guard has a watchdog thread which sleeps until the deadline. Whoever of the guard and the
+// watchdog sets `posted` first returns the permit, so it is returned exactly once. The thread
+// makes these far more expensive than plain guards, they suit permits held for a long time.
+use std::sync::{
+    Arc,
+    Condvar,
+    Mutex,
+    MutexGuard,
+};
+use std::thread::{
+    self,
+    JoinHandle,
+};
+use std::io::Error;
+
+use time::Duration;
+
+use sys::Semaphore;
+
+struct Watch {
+    sem: Arc<Semaphore>,
+    posted: Mutex<bool>,
+    released: Condvar,
+}
+
+pub struct TimedGuard {
+    watch: Arc<Watch>,
+    watchdog: Option<JoinHandle<()>>,
+}
+
+impl TimedGuard {
+    // Takes a permit from `sem` which is returned after `hold` at the latest.
+    pub fn acquire(sem: Arc<Semaphore>, hold: Duration) -> Result<TimedGuard, Error> {
+        sem.wait()?;
+        TimedGuard::start(sem, hold)
+    }
+
+    pub fn try_acquire(sem: Arc<Semaphore>, hold: Duration) -> Result<TimedGuard, Error> {
+        sem.try_wait()?;
+        TimedGuard::start(sem, hold)
+    }
+
+    // Returns whether the deadline passed and the permit was returned already.
+    pub fn is_expired(&self) -> bool {
+        *self.watch.lock()
+    }
+
+    // Starts the watchdog, given a permit from `sem`.
+    fn start(sem: Arc<Semaphore>, hold: Duration) -> Result<TimedGuard, Error> {
+        // Negative durations are treated as an already expired deadline.
+        let hold = hold.to_std().unwrap_or_default();
+        let watch = Arc::new(Watch {
+            sem,
+            posted: Mutex::new(false),
+            released: Condvar::new(),
+        });
+        let watchdog = {
+            let watch = watch.clone();
+            thread::Builder::new().name("sema-watchdog".into()).spawn(move || {
+                let posted = watch.lock();
+                let (posted, _) = watch.released
+                                       .wait_timeout_while(posted, hold, |posted| !*posted)
+                                       .unwrap_or_else(|e| e.into_inner());
+                watch.post(posted);
+            })
+        };
+        match watchdog {
+            Ok(watchdog) => {
+                Ok(TimedGuard {
+                    watch,
+                    watchdog: Some(watchdog),
+                })
+            }
+            Err(e) => {
+                watch.sem.post();
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Watch {
+    fn lock(&self) -> MutexGuard<'_, bool> {
+        self.posted.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Returns the permit unless that has happened already.
+    fn post(&self, mut posted: MutexGuard<'_, bool>) {
+        if !*posted {
+            *posted = true;
+            self.sem.post();
+        }
+    }
+}
+
+impl Drop for TimedGuard {
+    fn drop(&mut self) {
+        self.watch.post(self.watch.lock());
+        self.watch.released.notify_one();
+        if let Some(watchdog) = self.watchdog.take() {
+            let _ = watchdog.join();
+        }
+    }
+}
@@ -0,0 +1,43 @@
+// ThreadSanitizer annotations.
+//
+// TSan infers happens-before edges from the atomic operations it instruments, but some of ours
+// synchronize through the kernel instead: `FUTEX_WAKE_OP` adds posted tokens to a `Semaphore`
+// inside the kernel, and `FUTEX_LOCK_PI`/`FUTEX_UNLOCK_PI` pass a `PiSemaphore` from one owner to
+// the next there. TSan never sees those writes, so a thread taking the permit looks unsynchronized
+// with the thread that released it, and every access to data guarded by the permit is reported as
+// a race. With the `sanitize` feature, releases and acquisitions through the kernel are announced
+// explicitly on the futex word's address.
+//
+// The annotations are provided by the TSan runtime, so the feature only links in builds with
+// `-Zsanitizer=thread`. Without it these are no-ops.
+#[cfg(feature = "sanitize")]
+use libc::c_void;
+
+#[cfg(feature = "sanitize")]
+extern "C" {
+    fn __tsan_acquire(addr: *mut c_void);
+    fn __tsan_release(addr: *mut c_void);
+}
+
+// Marks the point after which the calling thread has synchronized with every `release(addr)` so
+// far.
+#[cfg(feature = "sanitize")]
+pub(crate) fn acquire<T>(addr: *const T) {
+    unsafe {
+        __tsan_acquire(addr as *mut c_void);
+    }
+}
+
+#[cfg(not(feature = "sanitize"))]
+pub(crate) fn acquire<T>(_addr: *const T) {}
+
+// Marks the point before which the calling thread's accesses are published to `acquire(addr)`.
+#[cfg(feature = "sanitize")]
+pub(crate) fn release<T>(addr: *const T) {
+    unsafe {
+        __tsan_release(addr as *mut c_void);
+    }
+}
+
+#[cfg(not(feature = "sanitize"))]
+pub(crate) fn release<T>(_addr: *const T) {}
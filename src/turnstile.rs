@@ -0,0 +1,65 @@
+// Turnstiles, as described in The Little Book of Semaphores.
+//
+// A `Turnstile` lets threads through one at a time: passing takes its single token and puts it
+// straight back. Locking it keeps the token until it is unlocked, so arriving threads pile up in
+// front of it and then trickle through one after the other, which is the building block of the
+// book's reusable barriers and queues.
+use std::io::Error;
+
+use time::Duration;
+
+use binary::BinarySemaphore;
+use sys::FutexMode;
+
+#[repr(C)]
+pub struct Turnstile {
+    sem: BinarySemaphore,
+}
+
+impl Turnstile {
+    pub fn new(locked: bool) -> Turnstile {
+        Turnstile::with_futex_mode(locked, FutexMode::Private)
+    }
+
+    // Turnstiles placed in memory shared with other processes must use `FutexMode::Shared`.
+    pub fn with_futex_mode(locked: bool, mode: FutexMode) -> Turnstile {
+        Turnstile {
+            sem: BinarySemaphore::with_futex_mode(!locked, mode),
+        }
+    }
+
+    // Waits until the turnstile is unlocked, then passes through it.
+    pub fn pass(&self) -> Result<(), Error> {
+        self.sem.wait()?;
+        self.sem.post();
+        Ok(())
+    }
+
+    pub fn try_pass(&self) -> Result<(), Error> {
+        self.sem.try_wait()?;
+        self.sem.post();
+        Ok(())
+    }
+
+    pub fn pass_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        self.sem.wait_timeout(timeout)?;
+        self.sem.post();
+        Ok(())
+    }
+
+    // Locks the turnstile, waiting for a thread which is passing to get through. Locking a
+    // turnstile which is already locked waits until it is unlocked.
+    pub fn lock(&self) -> Result<(), Error> {
+        self.sem.wait()
+    }
+
+    // Unlocks the turnstile, letting the waiting threads through one at a time. Does nothing if it
+    // isn't locked.
+    pub fn unlock(&self) {
+        self.sem.post();
+    }
+
+    pub fn futex_mode(&self) -> FutexMode {
+        self.sem.futex_mode()
+    }
+}
@@ -0,0 +1,129 @@
+// Go-style wait groups.
+//
+// A `WaitGroup` counts outstanding tasks: `add()` registers them, `done()` marks one finished, and
+// `wait()` blocks until none are left. As in Go, a wait group can be reused once the count has
+// dropped to zero, and adds which start a new round (raise the count from zero) must happen before
+// the `wait()` meant to observe them.
+//
+// Waiters sleep on `generation`, which is bumped every time the count drops to zero, rather than on
+// the count itself. A round which starts right after the previous one ended would otherwise keep
+// the previous round's waiters asleep.
+use std::ops::ControlFlow;
+use std::ptr;
+use std::sync::atomic::{
+    Ordering,
+    AtomicU32,
+};
+use std::io::Error;
+
+use libc;
+use time::Duration;
+
+use sys::{
+    futex_wait_until,
+    futex_wake_bitset,
+    monotonic_deadline,
+    FutexMode,
+    InterruptPolicy,
+    FUTEX_BITSET_MATCH_ANY,
+};
+
+#[repr(C)]
+pub struct WaitGroup {
+    count: AtomicU32,
+    generation: AtomicU32,
+    mode: FutexMode,
+}
+
+impl WaitGroup {
+    pub fn new() -> WaitGroup {
+        WaitGroup::with_futex_mode(FutexMode::Private)
+    }
+
+    // Wait groups placed in memory shared with other processes must use `FutexMode::Shared`.
+    pub fn with_futex_mode(mode: FutexMode) -> WaitGroup {
+        WaitGroup {
+            count: AtomicU32::new(0),
+            generation: AtomicU32::new(0),
+            mode,
+        }
+    }
+
+    // Adds `delta`, which may be negative, to the count, releasing the waiters if it drops to
+    // zero.
+    //
+    // # Panics
+    //
+    // Panics if the count would become negative or overflow.
+    pub fn add(&self, delta: i32) {
+        let mut c = self.count.load(Ordering::Relaxed);
+        loop {
+            let new = c as i64 + delta as i64;
+            if new < 0 {
+                panic!("negative WaitGroup counter");
+            }
+            if new > u32::MAX as i64 {
+                panic!("WaitGroup counter overflow");
+            }
+            // SeqCst orders it before the generation bump, pairing with `wait_until()`.
+            match self.count.compare_exchange_weak(c, new as u32, Ordering::SeqCst,
+                                                   Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(prev) => c = prev,
+            }
+        }
+        if c > 0 && c as i64 + delta as i64 == 0 {
+            self.generation.fetch_add(1, Ordering::SeqCst);
+            futex_wake_bitset(self.generation.as_ptr(), i32::MAX as u32, FUTEX_BITSET_MATCH_ANY,
+                              self.mode).unwrap();
+        }
+    }
+
+    // Marks one task as finished.
+    pub fn done(&self) {
+        self.add(-1);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    // Waits until the count is zero.
+    pub fn wait(&self) -> Result<(), Error> {
+        self.wait_until(ptr::null())
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        let deadline = monotonic_deadline(timeout);
+        self.wait_until(&deadline)
+    }
+
+    pub fn futex_mode(&self) -> FutexMode {
+        self.mode
+    }
+
+    fn wait_until(&self, deadline: *const libc::timespec) -> Result<(), Error> {
+        // Read the generation first: if the count is still non-zero afterwards, the bump which
+        // ends this round is yet to come.
+        let generation = self.generation.load(Ordering::SeqCst);
+        if self.count.load(Ordering::SeqCst) == 0 {
+            return Ok(());
+        }
+        futex_wait_until(&self.generation, deadline, self.mode, InterruptPolicy::Surface, || {
+            if self.generation.load(Ordering::Acquire) != generation {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(generation)
+            }
+        })
+    }
+}
+
+impl Default for WaitGroup {
+    fn default() -> WaitGroup {
+        WaitGroup::new()
+    }
+}
+
+unsafe impl Send for WaitGroup {}
+unsafe impl Sync for WaitGroup {}
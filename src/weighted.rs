@@ -0,0 +1,169 @@
+// Weighted semaphores.
+//
+// A `WeightedSemaphore` manages a budget of `size` units, e.g. bytes of memory, and callers
+// acquire as many units as their work needs. As with Go's `semaphore.Weighted`, requests are
+// granted in arrival order: once a request has to wait, later requests queue up behind it even if
+// they would fit, so heavy requests can't be starved by a stream of light ones.
+//
+// Waiters queue in a `VecDeque` under a mutex and wait on a condition variable, which every
+// release and every departure from the head of the queue signals.
+use std::collections::VecDeque;
+use std::sync::{
+    Condvar,
+    Mutex,
+    MutexGuard,
+};
+use std::time::Instant;
+use std::io::{
+    Error,
+    ErrorKind,
+};
+
+use time::Duration;
+
+pub struct WeightedSemaphore {
+    size: u64,
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+struct State {
+    // Units currently acquired.
+    used: u64,
+    next_ticket: u64,
+    // Tickets and weights of the waiting requests, in arrival order.
+    queue: VecDeque<(u64, u64)>,
+}
+
+pub struct WeightedSemaphoreGuard<'a> {
+    sem: &'a WeightedSemaphore,
+    weight: u64,
+}
+
+impl WeightedSemaphore {
+    pub fn new(size: u64) -> WeightedSemaphore {
+        WeightedSemaphore {
+            size,
+            state: Mutex::new(State {
+                used: 0,
+                next_ticket: 0,
+                queue: VecDeque::new(),
+            }),
+            cond: Condvar::new(),
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    // Returns the number of units not currently acquired.
+    pub fn available(&self) -> u64 {
+        self.size - self.lock().used
+    }
+
+    // Fails with `ErrorKind::InvalidInput` if `weight` exceeds the size of the semaphore, since
+    // such a request could never be granted.
+    pub fn acquire(&self, weight: u64) -> Result<(), Error> {
+        self.acquire_until(weight, None)
+    }
+
+    // Succeeds only if the units are available and nobody is queued for them.
+    pub fn try_acquire(&self, weight: u64) -> Result<(), Error> {
+        self.check_weight(weight)?;
+        let mut state = self.lock();
+        if state.queue.is_empty() && self.size - state.used >= weight {
+            state.used += weight;
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::WouldBlock, "acquire would block"))
+        }
+    }
+
+    pub fn acquire_timeout(&self, weight: u64, timeout: Duration) -> Result<(), Error> {
+        // Negative durations are treated as an already expired timeout.
+        let deadline = Instant::now() + timeout.to_std().unwrap_or_default();
+        self.acquire_until(weight, Some(deadline))
+    }
+
+    // Returns `weight` units to the semaphore.
+    //
+    // # Panics
+    //
+    // Panics if more units are released than are acquired.
+    pub fn release(&self, weight: u64) {
+        let mut state = self.lock();
+        assert!(weight <= state.used, "released more than held");
+        state.used -= weight;
+        drop(state);
+        self.cond.notify_all();
+    }
+
+    pub fn take(&self, weight: u64) -> Result<WeightedSemaphoreGuard<'_>, Error> {
+        self.acquire(weight)?;
+        Ok(WeightedSemaphoreGuard {
+            sem: self,
+            weight,
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn check_weight(&self, weight: u64) -> Result<(), Error> {
+        if weight > self.size {
+            Err(Error::new(ErrorKind::InvalidInput, "weight exceeds semaphore size"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn acquire_until(&self, weight: u64, deadline: Option<Instant>) -> Result<(), Error> {
+        self.check_weight(weight)?;
+        let mut state = self.lock();
+        if state.queue.is_empty() && self.size - state.used >= weight {
+            state.used += weight;
+            return Ok(());
+        }
+
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.queue.push_back((ticket, weight));
+        loop {
+            if state.queue.front() == Some(&(ticket, weight)) && self.size - state.used >= weight {
+                state.queue.pop_front();
+                state.used += weight;
+                drop(state);
+                // The next request in line may fit as well.
+                self.cond.notify_all();
+                return Ok(());
+            }
+            state = match deadline {
+                None => self.cond.wait(state).unwrap_or_else(|e| e.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        let head = state.queue.front() == Some(&(ticket, weight));
+                        state.queue.retain(|&(t, _)| t != ticket);
+                        drop(state);
+                        // Leaving the head of the queue lets the next request in line proceed.
+                        if head {
+                            self.cond.notify_all();
+                        }
+                        return Err(Error::new(ErrorKind::TimedOut, "wait timed out"));
+                    }
+                    self.cond.wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+            };
+        }
+    }
+}
+
+impl<'a> Drop for WeightedSemaphoreGuard<'a> {
+    fn drop(&mut self) {
+        self.sem.release(self.weight);
+    }
+}
@@ -0,0 +1,33 @@
+extern crate sema;
+extern crate time;
+
+use std::thread;
+
+use sema::{
+    AdaptiveLimiter,
+    Aimd,
+    LimitSample,
+};
+use time::Duration;
+
+#[test]
+fn aimd_grows_and_backs_off() {
+    let limiter = AdaptiveLimiter::new(4, 1, 6, Aimd::new(Duration::milliseconds(20), 0.5));
+    for _ in 0..5 {
+        limiter.run(|| ()).unwrap();
+    }
+    assert_eq!(limiter.limit(), 6);
+    limiter.run(|| thread::sleep(::std::time::Duration::from_millis(30))).unwrap();
+    assert_eq!(limiter.limit(), 3);
+}
+
+#[test]
+fn closure_control_is_clamped() {
+    let limiter = AdaptiveLimiter::new(2, 1, 4, |sample: &LimitSample| sample.queued + 100);
+    limiter.run(|| ()).unwrap();
+    assert_eq!(limiter.limit(), 4);
+    let limiter = AdaptiveLimiter::new(2, 1, 4, |_: &LimitSample| 0);
+    limiter.run(|| ()).unwrap();
+    assert_eq!(limiter.limit(), 1);
+    limiter.run(|| assert!(limiter.try_run(|| ()).is_err())).unwrap();
+}
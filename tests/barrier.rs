@@ -0,0 +1,55 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::thread;
+
+use sema::Barrier;
+use time::Duration;
+
+// Every round has exactly one leader, and nobody leaves a round before everyone arrived.
+#[test]
+fn reusable_across_rounds() {
+    let barrier = Arc::new(Barrier::new(4));
+    let arrived = Arc::new(AtomicUsize::new(0));
+    let threads: Vec<_> = (0..4).map(|_| {
+        let barrier = barrier.clone();
+        let arrived = arrived.clone();
+        thread::spawn(move || {
+            let mut leaders = 0;
+            for round in 1..101 {
+                arrived.fetch_add(1, Ordering::SeqCst);
+                if barrier.wait().unwrap() {
+                    leaders += 1;
+                }
+                assert!(arrived.load(Ordering::SeqCst) >= round * 4);
+                barrier.wait().unwrap();
+            }
+            leaders
+        })
+    }).collect();
+    let leaders: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+    assert_eq!(leaders, 100);
+}
+
+// A thread timing out no longer counts towards the round.
+#[test]
+fn timeout_withdraws_arrival() {
+    let barrier = Arc::new(Barrier::new(2));
+    assert!(barrier.wait_timeout(Duration::milliseconds(10)).is_err());
+
+    let other = {
+        let barrier = barrier.clone();
+        thread::spawn(move || barrier.wait_timeout(Duration::seconds(5)).unwrap())
+    };
+    thread::sleep(::std::time::Duration::from_millis(50));
+    let leader = barrier.wait().unwrap();
+    assert!(leader != other.join().unwrap());
+}
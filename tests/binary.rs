@@ -0,0 +1,38 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+
+use sema::BinarySemaphore;
+use time::Duration;
+
+#[test]
+fn extra_posts_are_idempotent() {
+    let sem = BinarySemaphore::new(false);
+    sem.post();
+    sem.post();
+    assert!(sem.is_available());
+    assert!(sem.try_wait().is_ok());
+    assert!(sem.try_wait().is_err());
+
+    sem.post();
+    sem.reset();
+    assert!(sem.wait_timeout(Duration::milliseconds(10)).is_err());
+}
+
+#[test]
+fn post_wakes_waiter() {
+    let sem = Arc::new(BinarySemaphore::new(false));
+    let waiter = {
+        let sem = sem.clone();
+        thread::spawn(move || sem.wait_timeout(Duration::seconds(5)).is_ok())
+    };
+    thread::sleep(::std::time::Duration::from_millis(50));
+    sem.post();
+    assert!(waiter.join().unwrap());
+    assert!(!sem.is_available());
+}
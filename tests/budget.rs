@@ -0,0 +1,36 @@
+extern crate sema;
+extern crate time;
+
+use std::io::ErrorKind;
+
+use sema::MemoryBudget;
+use time::Duration;
+
+#[test]
+fn permits_release_bytes() {
+    let budget = MemoryBudget::new(1024);
+    let a = budget.acquire(600).unwrap();
+    assert_eq!(a.bytes(), 600);
+    assert_eq!(budget.available(), 424);
+    assert!(budget.try_acquire(500).is_err());
+    assert!(budget.acquire_timeout(500, Duration::milliseconds(10)).is_err());
+    drop(a);
+    assert_eq!(budget.available(), 1024);
+}
+
+#[test]
+fn shrink_releases_early() {
+    let budget = MemoryBudget::new(100);
+    let mut permit = budget.acquire(80).unwrap();
+    permit.shrink(30);
+    assert_eq!(budget.available(), 70);
+    let _other = budget.try_acquire(70).unwrap();
+    drop(permit);
+    assert_eq!(budget.available(), 30);
+}
+
+#[test]
+fn oversized_request_fails() {
+    let budget = MemoryBudget::new(100);
+    assert_eq!(budget.acquire(101).err().unwrap().kind(), ErrorKind::InvalidInput);
+}
@@ -0,0 +1,71 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+
+use sema::{
+    Condvar,
+    FutexMutex,
+};
+use time::Duration;
+
+// Every waiter sees the broadcast, having the mutex to itself when it does.
+#[test]
+fn notify_all_wakes_everyone() {
+    let pair = Arc::new((FutexMutex::new((false, 0)), Condvar::new()));
+    let waiters: Vec<_> = (0..8).map(|_| {
+        let pair = pair.clone();
+        thread::spawn(move || {
+            let (ref mutex, ref cond) = *pair;
+            let mut guard = cond.wait_while(mutex.lock(), |state| !state.0);
+            guard.1 += 1;
+        })
+    }).collect();
+
+    thread::sleep(::std::time::Duration::from_millis(50));
+    {
+        let (ref mutex, ref cond) = *pair;
+        mutex.lock().0 = true;
+        cond.notify_all();
+    }
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+    assert_eq!(pair.0.lock().1, 8);
+}
+
+#[test]
+fn notify_one_hands_over_items() {
+    let pair = Arc::new((FutexMutex::new(0u32), Condvar::new()));
+    let consumer = {
+        let pair = pair.clone();
+        thread::spawn(move || {
+            let (ref mutex, ref cond) = *pair;
+            let mut taken = 0;
+            while taken < 100 {
+                let mut items = cond.wait_while(mutex.lock(), |items| *items == 0);
+                taken += *items;
+                *items = 0;
+            }
+            taken
+        })
+    };
+    for _ in 0..100 {
+        let (ref mutex, ref cond) = *pair;
+        *mutex.lock() += 1;
+        cond.notify_one();
+    }
+    assert_eq!(consumer.join().unwrap(), 100);
+}
+
+#[test]
+fn wait_timeout_times_out() {
+    let mutex = FutexMutex::new(());
+    let cond = Condvar::new();
+    let (_guard, timed_out) = cond.wait_timeout(mutex.lock(), Duration::milliseconds(10));
+    assert!(timed_out);
+}
@@ -0,0 +1,19 @@
+extern crate sema;
+
+use sema::Semaphore;
+
+#[test]
+fn try_new() {
+    let sem = Semaphore::try_new(1).unwrap();
+    sem.try_wait().unwrap();
+    assert!(sem.try_wait().is_err());
+    sem.post();
+    sem.wait().unwrap();
+}
+
+#[test]
+fn try_post() {
+    let sem = Semaphore::try_new(0).unwrap();
+    sem.try_post().unwrap();
+    sem.try_wait().unwrap();
+}
@@ -0,0 +1,29 @@
+extern crate sema;
+
+use std::thread;
+
+use sema::Semaphore;
+
+fn permits(sem: &Semaphore) -> usize {
+    let mut n = 0;
+    while sem.try_wait().is_ok() {
+        n += 1;
+    }
+    n
+}
+
+#[test]
+fn sized_to_cpus() {
+    let cpus = thread::available_parallelism().unwrap().get();
+    assert_eq!(permits(&Semaphore::per_cpu()), cpus);
+    assert_eq!(permits(&Semaphore::per_cpu_scaled(2.0)), cpus * 2);
+    assert_eq!(permits(&Semaphore::per_cpu_scaled(0.01)), 1);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn quota_never_exceeds_cpus() {
+    let cpus = thread::available_parallelism().unwrap().get();
+    let n = permits(&Semaphore::per_cpu_quota());
+    assert!(n >= 1 && n <= cpus);
+}
@@ -0,0 +1,480 @@
+#![cfg(target_os = "linux")]
+
+// Cross-process tests.
+//
+// The parts of each test that must run in another process are played by helpers: this test binary
+// re-executed with `SEMA_HELPER` naming a role, which `helper()` below dispatches on (in a normal
+// run it does nothing). Helpers report progress to the parent as lines on stdout, such as
+// "holding" once they have taken a permit, and exit successfully if they saw what they expected.
+// A crash is simulated by killing the helper with SIGKILL, so nothing it holds is released by
+// destructors.
+
+extern crate libc;
+extern crate sema;
+extern crate time;
+
+use std::env;
+use std::fs;
+use std::io::{
+    BufRead,
+    BufReader,
+    Error,
+    ErrorKind,
+};
+use std::mem;
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+use std::process::{
+    self,
+    Child,
+    ChildStdout,
+    Command,
+    Stdio,
+};
+use std::ptr;
+use std::thread;
+use std::time::{
+    Duration as StdDuration,
+    Instant,
+};
+
+use sema::{
+    FileSemaphore,
+    Jobserver,
+    MappedSemaphore,
+    NamedSemaphore,
+    NamedSemaphoreOptions,
+    RobustSemaphore,
+    SysVSemaphore,
+};
+use time::Duration;
+
+// The role a re-executed test binary plays, and its argument.
+const ROLE: &str = "SEMA_HELPER";
+const ARG: &str = "SEMA_HELPER_ARG";
+
+// How long a helper waits before giving up.
+const PATIENCE: i64 = 5;
+
+struct Helper {
+    child: Child,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Helper {
+    fn spawn(role: &str, arg: &str) -> Helper {
+        let mut child = Command::new(env::current_exe().unwrap())
+            .args(["helper", "--exact", "--nocapture", "--quiet", "--test-threads", "1"])
+            .env(ROLE, role)
+            .env(ARG, arg)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Helper {
+            child,
+            stdout,
+        }
+    }
+
+    // Reads the helper's output until it reports `state`, skipping the test harness' own.
+    fn expect(&mut self, state: &str) {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let n = self.stdout.read_line(&mut line).unwrap();
+            assert!(n > 0, "helper exited before reporting {:?}", state);
+            if line.trim_end() == state {
+                return;
+            }
+        }
+    }
+
+    // Like `expect()`, for a state reported as "<state> <value>", and returns the value.
+    fn expect_value(&mut self, state: &str) -> String {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let n = self.stdout.read_line(&mut line).unwrap();
+            assert!(n > 0, "helper exited before reporting {:?}", state);
+            let mut words = line.trim_end().splitn(2, ' ');
+            if words.next() == Some(state) {
+                if let Some(value) = words.next() {
+                    return value.to_owned();
+                }
+            }
+        }
+    }
+
+    fn succeeded(&mut self) -> bool {
+        self.child.wait().unwrap().success()
+    }
+
+    // Kills the helper as abruptly as a crash would, and reaps it.
+    fn kill(&mut self) {
+        self.child.kill().unwrap();
+        self.child.wait().unwrap();
+    }
+}
+
+impl Drop for Helper {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// Reports `state` to the parent.
+fn report(state: &str) {
+    println!("{}", state);
+}
+
+// Takes a permit with `wait`, reports "holding" and keeps it until killed.
+fn hold<F: FnOnce() -> Result<(), Error>>(wait: F) -> bool {
+    wait().unwrap();
+    report("holding");
+    thread::sleep(StdDuration::from_secs(PATIENCE as u64 * 12));
+    false
+}
+
+// Reports "waiting" and returns whether `wait` then succeeded.
+fn woken<F: FnOnce(Duration) -> Result<(), Error>>(wait: F) -> bool {
+    report("waiting");
+    wait(Duration::seconds(PATIENCE)).is_ok()
+}
+
+// Returns whether `wait` timed out, and only once the timeout had passed.
+fn timed_out<F: FnOnce(Duration) -> Result<(), Error>>(wait: F) -> bool {
+    let start = Instant::now();
+    let res = wait(Duration::milliseconds(100));
+    let elapsed = start.elapsed();
+    res.map_err(|e| e.kind()) == Err(ErrorKind::TimedOut)
+        && elapsed >= StdDuration::from_millis(100)
+        && elapsed < StdDuration::from_secs(PATIENCE as u64)
+}
+
+#[test]
+fn helper() {
+    let role = match env::var(ROLE) {
+        Ok(role) => role,
+        Err(_) => return,
+    };
+    let arg = env::var(ARG).unwrap();
+    let ok = match &role[..] {
+        "named-post" => {
+            NamedSemaphore::open(&arg).unwrap().post();
+            true
+        }
+        "named-wait" => {
+            let sem = NamedSemaphore::open(&arg).unwrap();
+            woken(|t| sem.wait_timeout(t))
+        }
+        "named-timeout" => {
+            let sem = NamedSemaphore::open(&arg).unwrap();
+            timed_out(|t| sem.wait_timeout(t))
+        }
+        "named-hold" => {
+            let sem = NamedSemaphore::open(&arg).unwrap();
+            hold(|| sem.wait())
+        }
+        "mapped-post" => {
+            MappedSemaphore::attach(arg.parse().unwrap()).unwrap().post();
+            true
+        }
+        "mapped-wait" => {
+            let sem = MappedSemaphore::attach(arg.parse().unwrap()).unwrap();
+            woken(|t| sem.wait_timeout(t))
+        }
+        "mapped-timeout" => {
+            let sem = MappedSemaphore::attach(arg.parse().unwrap()).unwrap();
+            timed_out(|t| sem.wait_timeout(t))
+        }
+        "mapped-hold" => {
+            let sem = MappedSemaphore::attach(arg.parse().unwrap()).unwrap();
+            hold(|| sem.wait())
+        }
+        "sysv-hold" => {
+            let sem = SysVSemaphore::from_id(arg.parse().unwrap());
+            hold(|| sem.wait())
+        }
+        "robust-hold" => {
+            let sem = unsafe {
+                RobustSemaphore::from_raw_ptr(map_memfd(arg.parse().unwrap()))
+            };
+            hold(|| sem.wait())
+        }
+        "named-unique" => {
+            // Left behind on exit, as by a crash.
+            let (name, _sem) = NamedSemaphoreOptions::new().create_unique(0).unwrap();
+            report(&format!("created {}", name));
+            true
+        }
+        "file-post" => {
+            FileSemaphore::open(&arg).unwrap().post().unwrap();
+            true
+        }
+        "file-wait" => {
+            let sem = FileSemaphore::open(&arg).unwrap();
+            woken(|t| sem.wait_timeout(t))
+        }
+        "file-hold" => {
+            let sem = FileSemaphore::open(&arg).unwrap();
+            hold(|| sem.wait())
+        }
+        "jobserver-take" => {
+            // Joins through `MAKEFLAGS`, as a child of `make` would.
+            env::remove_var("CARGO_MAKEFLAGS");
+            env::set_var("MAKEFLAGS", format!("-j2 {}", arg));
+            let js = Jobserver::from_env().unwrap();
+            let guard = js.take().unwrap();
+            report("holding");
+            thread::sleep(StdDuration::from_millis(200));
+            drop(guard);
+            true
+        }
+        _ => panic!("unknown helper role {:?}", role),
+    };
+    process::exit(if ok { 0 } else { 1 });
+}
+
+// Creates a named semaphore which is unlinked again when dropped.
+fn named(tag: &str, value: u32) -> (String, NamedSemaphore) {
+    let name = format!("/sema-cross-process-{}-{}", process::id(), tag);
+    let sem = NamedSemaphoreOptions::new().unlink_on_drop(true).create(&name, value).unwrap();
+    (name, sem)
+}
+
+// A directory removed again when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(tag: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("sema-cross-process-{}-{}", process::id(), tag));
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    fn file(&self, name: &str) -> String {
+        self.0.join(name).to_str().unwrap().to_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// Creates a memfd large enough for a `T`, inherited by helpers, and maps it.
+fn memfd<T>() -> (RawFd, *mut T) {
+    let fd = unsafe {
+        libc::memfd_create(b"sema-test\0".as_ptr() as *const libc::c_char, 0)
+    };
+    assert!(fd != -1);
+    assert!(unsafe { libc::ftruncate(fd, mem::size_of::<T>() as libc::off_t) } != -1);
+    (fd, map_memfd(fd))
+}
+
+fn map_memfd<T>(fd: RawFd) -> *mut T {
+    let ptr = unsafe {
+        libc::mmap(ptr::null_mut(), mem::size_of::<T>(), libc::PROT_READ | libc::PROT_WRITE,
+                   libc::MAP_SHARED, fd, 0)
+    };
+    assert!(ptr != libc::MAP_FAILED);
+    ptr as *mut T
+}
+
+#[test]
+fn named_post_wakes_other_process() {
+    let (name, sem) = named("post-wakes", 0);
+    let mut waiter = Helper::spawn("named-wait", &name);
+    waiter.expect("waiting");
+    thread::sleep(StdDuration::from_millis(50));
+    sem.post();
+    assert!(waiter.succeeded());
+}
+
+#[test]
+fn named_wakes_on_post_from_other_process() {
+    let (name, sem) = named("wakes-on-post", 0);
+    let mut poster = Helper::spawn("named-post", &name);
+    sem.wait_timeout(Duration::seconds(PATIENCE)).unwrap();
+    assert!(poster.succeeded());
+}
+
+#[test]
+fn named_timeout_in_other_process() {
+    let (name, _sem) = named("timeout", 0);
+    assert!(Helper::spawn("named-timeout", &name).succeeded());
+}
+
+// POSIX semaphores don't know who holds their permits, so one held by a crashed process is gone.
+#[test]
+fn named_permit_of_crashed_process_is_lost() {
+    let (name, sem) = named("crash", 1);
+    let mut holder = Helper::spawn("named-hold", &name);
+    holder.expect("holding");
+    holder.kill();
+    assert_eq!(sem.try_wait().unwrap_err().kind(), ErrorKind::WouldBlock);
+}
+
+// A generated name is stale once the process which created it has exited.
+#[test]
+fn named_unlink_stale_removes_names_of_dead_processes() {
+    let mut creator = Helper::spawn("named-unique", "");
+    let stale = creator.expect_value("created");
+    assert!(creator.succeeded());
+    let (live, sem) = NamedSemaphoreOptions::new().unlink_on_drop(true).create_unique(0).unwrap();
+    assert!(NamedSemaphore::list_generated().unwrap().contains(&stale));
+
+    let removed = NamedSemaphore::unlink_stale().unwrap();
+    assert!(removed.contains(&stale), "{:?}", removed);
+    assert!(!removed.contains(&live), "{:?}", removed);
+    let listed = NamedSemaphore::list_generated().unwrap();
+    assert!(!listed.contains(&stale) && listed.contains(&live), "{:?}", listed);
+    match NamedSemaphore::open(&stale) {
+        Err(e) => assert_eq!(e.kind(), ErrorKind::NotFound),
+        Ok(_) => panic!("opened a stale name"),
+    }
+    // The live semaphore is untouched.
+    sem.post();
+    NamedSemaphore::open(&live).unwrap().try_wait().unwrap();
+}
+
+#[test]
+fn mapped_post_wakes_other_process() {
+    let sem = MappedSemaphore::create(0).unwrap();
+    let mut waiter = Helper::spawn("mapped-wait", &sem.handle().to_string());
+    waiter.expect("waiting");
+    thread::sleep(StdDuration::from_millis(50));
+    sem.post();
+    assert!(waiter.succeeded());
+}
+
+#[test]
+fn mapped_wakes_on_post_from_other_process() {
+    let sem = MappedSemaphore::create(0).unwrap();
+    let mut poster = Helper::spawn("mapped-post", &sem.handle().to_string());
+    sem.wait_timeout(Duration::seconds(PATIENCE)).unwrap();
+    assert!(poster.succeeded());
+}
+
+#[test]
+fn mapped_timeout_in_other_process() {
+    let sem = MappedSemaphore::create(0).unwrap();
+    assert!(Helper::spawn("mapped-timeout", &sem.handle().to_string()).succeeded());
+}
+
+#[test]
+fn mapped_permit_of_crashed_process_is_lost() {
+    let sem = MappedSemaphore::create(1).unwrap();
+    let mut holder = Helper::spawn("mapped-hold", &sem.handle().to_string());
+    holder.expect("holding");
+    holder.kill();
+    assert_eq!(sem.try_wait().unwrap_err().kind(), ErrorKind::WouldBlock);
+}
+
+// A waiter dying in the kernel must not swallow a later post.
+#[test]
+fn mapped_crashed_waiter_takes_no_permit() {
+    let sem = MappedSemaphore::create(0).unwrap();
+    let handle = sem.handle().to_string();
+    let mut waiter = Helper::spawn("mapped-wait", &handle);
+    waiter.expect("waiting");
+    thread::sleep(StdDuration::from_millis(50));
+    waiter.kill();
+
+    sem.post();
+    let mut other = Helper::spawn("mapped-wait", &handle);
+    assert!(other.succeeded());
+    sem.post();
+    sem.try_wait().unwrap();
+}
+
+// `SEM_UNDO` gives back the permits of a process which exits without posting them.
+#[test]
+fn sysv_permit_of_crashed_process_is_returned() {
+    let sem = SysVSemaphore::private(1).unwrap();
+    let mut holder = Helper::spawn("sysv-hold", &sem.id().to_string());
+    holder.expect("holding");
+    assert_eq!(sem.value().unwrap(), 0);
+    holder.kill();
+    let res = sem.wait_timeout(Duration::seconds(PATIENCE));
+    sem.remove().unwrap();
+    res.unwrap();
+}
+
+#[test]
+fn sysv_try_post_reports_removed_set() {
+    let sem = SysVSemaphore::private(0).unwrap();
+    let stale = SysVSemaphore::from_id(sem.id());
+    sem.remove().unwrap();
+    assert!(stale.try_post().is_err());
+}
+
+#[test]
+fn robust_permit_of_crashed_process_is_recovered() {
+    let (fd, ptr) = memfd::<RobustSemaphore>();
+    let sem = unsafe {
+        RobustSemaphore::init_at(ptr, 1).unwrap()
+    };
+    let mut holder = Helper::spawn("robust-hold", &fd.to_string());
+    holder.expect("holding");
+    assert_eq!(sem.try_wait().unwrap_err().kind(), ErrorKind::WouldBlock);
+    holder.kill();
+    let err = sem.wait_timeout(Duration::seconds(PATIENCE)).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EOWNERDEAD));
+    sem.wait_timeout(Duration::seconds(PATIENCE)).unwrap();
+    assert_eq!(sem.value(), 0);
+}
+
+#[test]
+fn file_permit_is_shared_between_processes() {
+    let dir = TempDir::new("file-shared");
+    let path = dir.file("sem");
+    let sem = FileSemaphore::create(&path, 1).unwrap();
+    let mut holder = Helper::spawn("file-hold", &path);
+    holder.expect("holding");
+    assert_eq!(sem.try_wait().unwrap_err().kind(), ErrorKind::WouldBlock);
+    assert_eq!(sem.value().unwrap(), 0);
+    // Nothing gives back the permit of a process which dies holding it.
+    holder.kill();
+    assert_eq!(sem.value().unwrap(), 0);
+
+    let mut waiter = Helper::spawn("file-wait", &path);
+    waiter.expect("waiting");
+    sem.post().unwrap();
+    assert!(waiter.succeeded());
+    assert_eq!(sem.value().unwrap(), 0);
+}
+
+// The count lives in the file, so it outlasts every process which had the file open.
+#[test]
+fn file_count_survives_reopening() {
+    let dir = TempDir::new("file-reopen");
+    let path = dir.file("sem");
+    let sem = FileSemaphore::create(&path, 2).unwrap();
+    sem.try_wait().unwrap();
+    drop(sem);
+    assert!(Helper::spawn("file-post", &path).succeeded());
+
+    let sem = FileSemaphore::open(&path).unwrap();
+    assert_eq!(sem.value().unwrap(), 2);
+    sem.try_wait().unwrap();
+    sem.try_wait().unwrap();
+    assert_eq!(sem.try_wait().unwrap_err().kind(), ErrorKind::WouldBlock);
+    // Creating it again fails rather than resetting the count.
+    let err = FileSemaphore::create(&path, 5).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+}
+
+// The jobserver pipe is inherited by the helper, which finds it through `MAKEFLAGS`.
+#[test]
+fn jobserver_token_round_trips_through_inherited_pipe() {
+    let js = Jobserver::new(1).unwrap();
+    let mut child = Helper::spawn("jobserver-take", &js.makeflags());
+    child.expect("holding");
+    assert_eq!(js.try_wait().unwrap_err().kind(), ErrorKind::WouldBlock);
+    assert!(child.succeeded());
+    js.wait_timeout(Duration::seconds(PATIENCE)).unwrap();
+}
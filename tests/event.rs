@@ -0,0 +1,66 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::thread;
+
+use sema::{
+    AutoResetEvent,
+    ManualResetEvent,
+};
+use time::Duration;
+
+// Each set releases exactly one of the waiting threads.
+#[test]
+fn auto_reset_releases_one() {
+    let event = Arc::new(AutoResetEvent::new(false));
+    let released = Arc::new(AtomicUsize::new(0));
+    let waiters: Vec<_> = (0..3).map(|_| {
+        let event = event.clone();
+        let released = released.clone();
+        thread::spawn(move || {
+            if event.wait_timeout(Duration::milliseconds(300)).is_ok() {
+                released.fetch_add(1, Ordering::SeqCst);
+            }
+        })
+    }).collect();
+
+    thread::sleep(::std::time::Duration::from_millis(50));
+    event.set();
+    thread::sleep(::std::time::Duration::from_millis(50));
+    assert_eq!(released.load(Ordering::SeqCst), 1);
+    assert!(!event.is_set());
+
+    event.set();
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+    assert_eq!(released.load(Ordering::SeqCst), 2);
+}
+
+// Setting releases every waiter, and the event stays set until reset.
+#[test]
+fn manual_reset_releases_all() {
+    let event = Arc::new(ManualResetEvent::new(false));
+    let waiters: Vec<_> = (0..4).map(|_| {
+        let event = event.clone();
+        thread::spawn(move || event.wait_timeout(Duration::seconds(5)).is_ok())
+    }).collect();
+
+    thread::sleep(::std::time::Duration::from_millis(50));
+    event.set();
+    for waiter in waiters {
+        assert!(waiter.join().unwrap());
+    }
+    assert!(event.try_wait().is_ok());
+
+    event.reset();
+    assert!(event.wait_timeout(Duration::milliseconds(10)).is_err());
+}
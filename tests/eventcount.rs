@@ -0,0 +1,58 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::thread;
+
+use sema::EventCount;
+use time::Duration;
+
+// A consumer blocked on an empty counter sees every item the producer publishes.
+#[test]
+fn no_lost_wakeups() {
+    let items = Arc::new(AtomicUsize::new(0));
+    let event = Arc::new(EventCount::new());
+
+    let consumer = {
+        let items = items.clone();
+        let event = event.clone();
+        thread::spawn(move || {
+            let mut taken = 0;
+            while taken < 1000 {
+                let n = items.swap(0, Ordering::SeqCst);
+                if n > 0 {
+                    taken += n;
+                    continue;
+                }
+                let key = event.prepare_wait();
+                if items.load(Ordering::SeqCst) > 0 {
+                    event.cancel_wait(key);
+                    continue;
+                }
+                event.commit_wait(key);
+            }
+            taken
+        })
+    };
+
+    for _ in 0..1000 {
+        items.fetch_add(1, Ordering::SeqCst);
+        event.notify();
+    }
+    assert_eq!(consumer.join().unwrap(), 1000);
+}
+
+#[test]
+fn commit_wait_times_out() {
+    let event = EventCount::new();
+    let key = event.prepare_wait();
+    let err = event.commit_wait_timeout(key, Duration::milliseconds(10)).unwrap_err();
+    assert_eq!(err.kind(), ::std::io::ErrorKind::TimedOut);
+}
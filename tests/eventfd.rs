@@ -0,0 +1,68 @@
+#![cfg(target_os = "linux")]
+
+extern crate libc;
+extern crate sema;
+extern crate time;
+
+use std::io::ErrorKind;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+
+use sema::EventFdSemaphore;
+use time::Duration;
+
+extern "C" fn ignore(_: libc::c_int) {}
+
+fn sigset(signals: &[libc::c_int]) -> libc::sigset_t {
+    unsafe {
+        let mut set: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for &signum in signals {
+            libc::sigaddset(&mut set, signum);
+        }
+        set
+    }
+}
+
+#[test]
+fn sigmask_wait_is_interrupted_by_pending_signal() {
+    let sem = EventFdSemaphore::new(0).unwrap();
+    unsafe {
+        libc::signal(libc::SIGUSR2, ignore as *const () as libc::sighandler_t);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &sigset(&[libc::SIGUSR2]), ptr::null_mut());
+        libc::raise(libc::SIGUSR2);
+    }
+    // The signal stays pending until the wait unblocks it.
+    let err = sem.wait_with_sigmask(&sigset(&[])).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Interrupted);
+    unsafe {
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, &sigset(&[libc::SIGUSR2]), ptr::null_mut());
+    }
+}
+
+#[test]
+fn sigmask_wait_takes_permit() {
+    let sem = EventFdSemaphore::new(1).unwrap();
+    let mask = sigset(&[]);
+    sem.wait_with_sigmask(&mask).unwrap();
+    let err = sem.wait_timeout_with_sigmask(Duration::milliseconds(10), &mask).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    sem.post();
+    sem.wait_timeout_with_sigmask(Duration::seconds(1), &mask).unwrap();
+}
+
+#[test]
+fn try_post_fails_at_counter_limit() {
+    let sem = EventFdSemaphore::new(0).unwrap();
+    // The largest value an eventfd counter can hold.
+    let buf: u64 = u64::MAX - 1;
+    let res = unsafe {
+        libc::write(sem.as_raw_fd(), &buf as *const u64 as *const libc::c_void, 8)
+    };
+    assert_eq!(res, 8);
+    let err = sem.try_post().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    sem.wait().unwrap();
+    sem.try_post().unwrap();
+}
@@ -0,0 +1,31 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+
+use sema::Exchanger;
+use time::Duration;
+
+#[test]
+fn pairs_swap_values() {
+    let exchanger = Arc::new(Exchanger::new());
+    let other = {
+        let exchanger = exchanger.clone();
+        thread::spawn(move || {
+            (0..100).map(|i| exchanger.exchange(i * 2)).collect::<Vec<u32>>()
+        })
+    };
+    let mine: Vec<u32> = (0..100).map(|i| exchanger.exchange(i * 2 + 1)).collect();
+    assert_eq!(mine, (0..100).map(|i| i * 2).collect::<Vec<u32>>());
+    assert_eq!(other.join().unwrap(), (0..100).map(|i| i * 2 + 1).collect::<Vec<u32>>());
+}
+
+#[test]
+fn timeout_returns_value() {
+    let exchanger = Exchanger::new();
+    assert_eq!(exchanger.exchange_timeout("mine", Duration::milliseconds(10)), Err("mine"));
+}
@@ -0,0 +1,59 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+
+use std::io::ErrorKind;
+use std::mem;
+use std::panic;
+use std::ptr;
+
+use sema::{
+    FairSemaphore,
+    FAIR_MAX_VALUE,
+};
+
+#[test]
+fn largest_value_is_usable() {
+    let sem = FairSemaphore::new(FAIR_MAX_VALUE);
+    assert_eq!(sem.value(), FAIR_MAX_VALUE);
+    sem.try_wait().unwrap();
+    sem.try_wait().unwrap();
+    assert_eq!(sem.value(), FAIR_MAX_VALUE - 2);
+    sem.post();
+    sem.post();
+    assert_eq!(sem.value(), FAIR_MAX_VALUE);
+}
+
+#[test]
+#[should_panic(expected = "too many permits")]
+fn new_rejects_values_past_limit() {
+    FairSemaphore::new(FAIR_MAX_VALUE + 1);
+}
+
+#[test]
+fn init_at_rejects_values_past_limit() {
+    let mut storage = mem::MaybeUninit::<FairSemaphore>::uninit();
+    let err = unsafe {
+        FairSemaphore::init_at(storage.as_mut_ptr(), u32::MAX).err().unwrap()
+    };
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    let sem = unsafe {
+        FairSemaphore::init_at(storage.as_mut_ptr(), FAIR_MAX_VALUE).unwrap()
+    };
+    sem.try_wait().unwrap();
+    unsafe {
+        ptr::drop_in_place(storage.as_mut_ptr());
+    }
+}
+
+#[test]
+fn post_past_limit_panics() {
+    let sem = FairSemaphore::new(FAIR_MAX_VALUE);
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| sem.post()));
+    assert!(res.is_err());
+    // The failed post left the permits as they were.
+    assert_eq!(sem.value(), FAIR_MAX_VALUE);
+    sem.try_wait().unwrap();
+    sem.post();
+}
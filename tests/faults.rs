@@ -0,0 +1,134 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback"),
+           feature = "fault-injection"))]
+
+extern crate sema;
+extern crate time;
+
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::thread;
+use std::time::{
+    Duration as StdDuration,
+    Instant,
+};
+
+use sema::{
+    InterruptPolicy,
+    Semaphore,
+};
+use sema::faults::{
+    self,
+    Faults,
+};
+use time::Duration;
+
+#[test]
+fn injected_interrupt_fails_wait() {
+    let sem = Semaphore::new(0);
+    let _faults = faults::inject(Faults { interrupts: 1, ..Faults::default() });
+    assert_eq!(sem.wait().unwrap_err().kind(), ErrorKind::Interrupted);
+    assert_eq!(faults::current().interrupts, 0);
+    // The failed wait no longer counts as a waiter.
+    sem.post();
+    sem.wait().unwrap();
+}
+
+#[test]
+fn injected_timeout_fails_wait_without_deadline() {
+    let sem = Semaphore::new(0);
+    let _faults = faults::inject(Faults { interrupts: 1, timeouts: 1, ..Faults::default() });
+    assert_eq!(sem.wait().unwrap_err().kind(), ErrorKind::Interrupted);
+    assert_eq!(sem.wait().unwrap_err().kind(), ErrorKind::TimedOut);
+    assert_eq!(sem.wait_timeout(Duration::milliseconds(1)).unwrap_err().kind(),
+               ErrorKind::TimedOut);
+}
+
+#[test]
+fn retry_policy_absorbs_interrupts() {
+    let sem = Semaphore::with_interrupt_policy(0, InterruptPolicy::Retry);
+    let _faults = faults::inject(Faults { interrupts: 3, ..Faults::default() });
+    assert_eq!(sem.wait_timeout(Duration::milliseconds(20)).unwrap_err().kind(),
+               ErrorKind::TimedOut);
+    assert_eq!(faults::current().interrupts, 0);
+}
+
+#[test]
+fn infallible_wait_outlasts_faults() {
+    let sem = Arc::new(Semaphore::new(0));
+    let poster = {
+        let sem = sem.clone();
+        thread::spawn(move || {
+            thread::sleep(StdDuration::from_millis(20));
+            sem.post();
+        })
+    };
+    let _faults = faults::inject(Faults { interrupts: 2, timeouts: 2, ..Faults::default() });
+    sem.wait_infallible();
+    assert_eq!(faults::current(), Faults::default());
+    poster.join().unwrap();
+}
+
+#[test]
+fn available_token_needs_no_futex_wait() {
+    let sem = Semaphore::new(1);
+    let _faults = faults::inject(Faults {
+        force_slow_path: true,
+        interrupts: 1,
+        ..Faults::default()
+    });
+    sem.wait().unwrap();
+    assert_eq!(faults::current().interrupts, 1);
+}
+
+#[test]
+fn guard_restores_previous_faults() {
+    let outer = Faults { timeouts: 2, ..Faults::default() };
+    let _outer = faults::inject(outer);
+    {
+        let _inner = faults::inject(Faults { interrupts: 1, ..Faults::default() });
+        assert_eq!(faults::current().timeouts, 0);
+    }
+    assert_eq!(faults::current(), outer);
+}
+
+#[test]
+fn faults_are_per_thread() {
+    let sem = Arc::new(Semaphore::new(0));
+    let _faults = faults::inject(Faults { interrupts: 1, ..Faults::default() });
+    let waiter = {
+        let sem = sem.clone();
+        thread::spawn(move || sem.wait())
+    };
+    thread::sleep(StdDuration::from_millis(20));
+    sem.post();
+    waiter.join().unwrap().unwrap();
+    assert_eq!(faults::current().interrupts, 1);
+}
+
+#[test]
+fn wake_delay_holds_back_post() {
+    let sem = Arc::new(Semaphore::new(0));
+    let waiter = {
+        let sem = sem.clone();
+        thread::spawn(move || sem.wait())
+    };
+    thread::sleep(StdDuration::from_millis(20));
+    let delay = StdDuration::from_millis(50);
+    let _faults = faults::inject(Faults { wake_delay: Some(delay), ..Faults::default() });
+    let start = Instant::now();
+    sem.post();
+    assert!(start.elapsed() >= delay);
+    waiter.join().unwrap().unwrap();
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn forced_slow_path_counts_as_slow() {
+    let sem = Semaphore::new(1);
+    let _faults = faults::inject(Faults { force_slow_path: true, ..Faults::default() });
+    sem.wait().unwrap();
+    let stats = sem.stats();
+    assert_eq!(stats.fast_acquisitions, 0);
+    assert_eq!(stats.slow_acquisitions, 1);
+}
@@ -0,0 +1,121 @@
+#![cfg(unix)]
+
+extern crate libc;
+extern crate sema;
+extern crate time;
+
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use sema::Semaphore;
+use time::Duration;
+
+// Runs `f` in a forked child and returns whether it succeeded.
+fn in_child<F: FnOnce() -> bool>(f: F) -> bool {
+    let pid = unsafe {
+        libc::fork()
+    };
+    assert!(pid != -1);
+    if pid == 0 {
+        let ok = f();
+        unsafe {
+            libc::_exit(if ok { 0 } else { 1 });
+        }
+    }
+    let mut status = 0;
+    unsafe {
+        libc::waitpid(pid, &mut status, 0);
+    }
+    libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0
+}
+
+// Starts a thread blocked in `wait()` on `sem`, which returns once `sem` is posted.
+fn blocked_waiter(sem: &Arc<Semaphore>) -> thread::JoinHandle<()> {
+    let waiter = sem.clone();
+    let handle = thread::spawn(move || waiter.wait().unwrap());
+    wait_for_waiters(sem, 1);
+    handle
+}
+
+#[cfg(all(target_os = "linux",
+          feature = "stats",
+          not(feature = "spin-fallback")))]
+fn wait_for_waiters(sem: &Semaphore, n: u32) {
+    while sem.stats().waiters != n {
+        thread::sleep(StdDuration::from_millis(1));
+    }
+}
+
+// Without the counters, give the thread ample time to block.
+#[cfg(not(all(target_os = "linux",
+              feature = "stats",
+              not(feature = "spin-fallback"))))]
+fn wait_for_waiters(_: &Semaphore, _: u32) {
+    thread::sleep(StdDuration::from_millis(50));
+}
+
+#[cfg(all(target_os = "linux",
+          feature = "stats",
+          not(feature = "spin-fallback")))]
+fn no_waiters(sem: &Semaphore) -> bool {
+    sem.stats().waiters == 0
+}
+
+#[cfg(not(all(target_os = "linux",
+              feature = "stats",
+              not(feature = "spin-fallback"))))]
+fn no_waiters(_: &Semaphore) -> bool {
+    true
+}
+
+// The semaphore works in the child, which doesn't have the parent's waiter.
+fn usable_in_child(sem: &Semaphore) -> bool {
+    no_waiters(sem)
+        && sem.try_wait().map_err(|e| e.kind()) == Err(ErrorKind::WouldBlock)
+        && { sem.post(); sem.wait_timeout(Duration::seconds(5)).is_ok() }
+        && { sem.post(); sem.try_wait().is_ok() }
+        && no_waiters(sem)
+}
+
+#[test]
+fn reinit_in_child_forgets_blocked_waiter() {
+    let sem = Arc::new(Semaphore::new(0));
+    let waiter = blocked_waiter(&sem);
+    assert!(in_child(|| {
+        unsafe {
+            sem.reinit_in_child();
+        }
+        usable_in_child(&sem)
+    }));
+    // The parent's waiter is still there.
+    sem.post();
+    waiter.join().unwrap();
+    assert!(sem.try_wait().is_err());
+}
+
+#[test]
+fn reinit_in_child_keeps_permits() {
+    let sem = Semaphore::new(2);
+    assert!(in_child(|| {
+        unsafe {
+            sem.reinit_in_child();
+        }
+        sem.try_wait().is_ok() && sem.try_wait().is_ok() && sem.try_wait().is_err()
+    }));
+    sem.try_wait().unwrap();
+    sem.try_wait().unwrap();
+}
+
+#[cfg(feature = "atfork")]
+#[test]
+fn reinit_on_fork_resets_registered_semaphore() {
+    let sem: &'static Arc<Semaphore> = Box::leak(Box::new(Arc::new(Semaphore::new(0))));
+    sem.reinit_on_fork().unwrap();
+    let waiter = blocked_waiter(sem);
+    // Reinitialized by the fork handler, without a call to `reinit_in_child()`.
+    assert!(in_child(|| usable_in_child(sem)));
+    sem.post();
+    waiter.join().unwrap();
+}
@@ -0,0 +1,85 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate libc;
+extern crate sema;
+extern crate time;
+
+use std::mem;
+use std::ptr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use sema::{
+    FutexMode,
+    Semaphore,
+};
+use time::Duration;
+
+// Maps an anonymous shared region large enough for a semaphore, inherited across `fork()`.
+fn shared_region() -> *mut Semaphore {
+    let ptr = unsafe {
+        libc::mmap(ptr::null_mut(), mem::size_of::<Semaphore>(), libc::PROT_READ | libc::PROT_WRITE,
+                   libc::MAP_SHARED | libc::MAP_ANONYMOUS, -1, 0)
+    };
+    assert!(ptr != libc::MAP_FAILED);
+    ptr as *mut Semaphore
+}
+
+#[test]
+fn default_modes() {
+    assert_eq!(Semaphore::new(0).futex_mode(), FutexMode::Private);
+    let sem = unsafe {
+        Semaphore::init_at(shared_region(), 0).unwrap()
+    };
+    assert_eq!(sem.futex_mode(), FutexMode::Shared);
+    for &mode in &[FutexMode::Private, FutexMode::Shared] {
+        assert_eq!(Semaphore::with_futex_mode(0, mode).futex_mode(), mode);
+    }
+}
+
+#[test]
+fn both_modes_wake_threads() {
+    for &mode in &[FutexMode::Private, FutexMode::Shared] {
+        let sem = Arc::new(Semaphore::with_futex_mode(0, mode));
+        let poster = sem.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(StdDuration::from_millis(50));
+            poster.post();
+        });
+        sem.wait_timeout(Duration::seconds(5)).unwrap();
+        handle.join().unwrap();
+    }
+}
+
+// A semaphore placed in shared memory by hand only needs `FutexMode::Shared` to wake a waiter in
+// another process.
+#[test]
+fn shared_mode_wakes_other_process() {
+    let ptr = shared_region();
+    unsafe {
+        ptr::write(ptr, Semaphore::with_futex_mode(0, FutexMode::Shared));
+    }
+    let sem = unsafe {
+        &*ptr
+    };
+    let pid = unsafe {
+        libc::fork()
+    };
+    assert!(pid != -1);
+    if pid == 0 {
+        let ok = sem.wait_timeout(Duration::seconds(5)).is_ok();
+        unsafe {
+            libc::_exit(if ok { 0 } else { 1 });
+        }
+    }
+    // Give the child time to block in the kernel, so that the post has to wake it.
+    thread::sleep(StdDuration::from_millis(50));
+    sem.post();
+    let mut status = 0;
+    unsafe {
+        libc::waitpid(pid, &mut status, 0);
+    }
+    assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+}
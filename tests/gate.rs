@@ -0,0 +1,35 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+
+use sema::Gate;
+use time::Duration;
+
+#[test]
+fn open_gate_lets_everyone_pass() {
+    let gate = Gate::new(true);
+    for _ in 0..10 {
+        gate.wait().unwrap();
+    }
+    gate.close();
+    assert!(gate.try_wait().is_err());
+}
+
+#[test]
+fn opening_releases_waiters() {
+    let gate = Arc::new(Gate::new(false));
+    let workers: Vec<_> = (0..4).map(|_| {
+        let gate = gate.clone();
+        thread::spawn(move || gate.wait_timeout(Duration::seconds(5)).is_ok())
+    }).collect();
+    thread::sleep(::std::time::Duration::from_millis(50));
+    gate.open();
+    for worker in workers {
+        assert!(worker.join().unwrap());
+    }
+}
@@ -0,0 +1,65 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+
+use sema::Semaphore;
+use time::Duration;
+
+fn handoff_semaphore(value: u32) -> Semaphore {
+    let mut sem = Semaphore::new(value);
+    sem.set_handoff(true);
+    sem
+}
+
+// A token posted while a thread is blocked can't be taken by anyone else.
+#[test]
+fn blocked_waiter_gets_token() {
+    let sem = Arc::new(handoff_semaphore(0));
+    let waiter = {
+        let sem = sem.clone();
+        thread::spawn(move || sem.wait_timeout(Duration::seconds(5)).is_ok())
+    };
+
+    // Give the waiter a chance to block.
+    thread::sleep(::std::time::Duration::from_millis(50));
+    sem.post();
+    assert!(sem.try_wait().is_err());
+
+    assert!(waiter.join().unwrap());
+    assert!(sem.try_wait().is_err());
+}
+
+// Waiters timing out must not strand tokens handed to them.
+#[test]
+fn no_tokens_lost() {
+    let sem = Arc::new(handoff_semaphore(0));
+    let waiters: Vec<_> = (0..4).map(|_| {
+        let sem = sem.clone();
+        thread::spawn(move || {
+            let mut taken = 0;
+            for _ in 0..200 {
+                if sem.wait_timeout(Duration::microseconds(100)).is_ok() {
+                    taken += 1;
+                }
+            }
+            taken
+        })
+    }).collect();
+
+    for _ in 0..100 {
+        sem.post();
+        thread::yield_now();
+    }
+
+    let taken: u32 = waiters.into_iter().map(|w| w.join().unwrap()).sum();
+    let mut left = 0;
+    while sem.try_wait().is_ok() {
+        left += 1;
+    }
+    assert_eq!(taken + left, 100);
+}
@@ -0,0 +1,49 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback"),
+           feature = "holders"))]
+
+extern crate sema;
+
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::thread;
+
+use sema::Semaphore;
+
+#[test]
+fn lists_holding_threads() {
+    let sem = Arc::new(Semaphore::new(2));
+    let mine = sem.take().unwrap();
+
+    let (taken, release) = (mpsc::channel(), mpsc::channel::<()>());
+    let worker = {
+        let sem = sem.clone();
+        let (taken, release) = (taken.0, release.1);
+        thread::Builder::new().name("worker".to_owned()).spawn(move || {
+            let _guard = sem.take().unwrap();
+            taken.send(thread::current().id()).unwrap();
+            release.recv().unwrap();
+        }).unwrap()
+    };
+    let worker_id = taken.1.recv().unwrap();
+
+    let holders = sem.holders();
+    assert_eq!(holders.len(), 2);
+    assert_eq!(holders[0].thread, thread::current().id());
+    assert_eq!(holders[1].thread, worker_id);
+    assert_eq!(holders[1].name.as_deref(), Some("worker"));
+
+    release.0.send(()).unwrap();
+    worker.join().unwrap();
+    assert_eq!(sem.holders().len(), 1);
+    drop(mine);
+    assert!(sem.holders().is_empty());
+}
+
+#[test]
+fn waits_have_no_holder() {
+    let sem = Semaphore::new(1);
+    sem.wait().unwrap();
+    assert!(sem.holders().is_empty());
+    sem.post();
+}
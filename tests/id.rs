@@ -0,0 +1,28 @@
+extern crate sema;
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::thread;
+
+use sema::Semaphore;
+
+#[test]
+fn ids_are_unique_and_stable() {
+    let sems: Vec<Semaphore> = (0..16).map(|_| Semaphore::new(1)).collect();
+    let ids: HashSet<u64> = sems.iter().map(|s| s.id()).collect();
+    assert_eq!(ids.len(), sems.len());
+
+    let sem = Arc::new(Semaphore::new(1));
+    let id = sem.id();
+    let seen = {
+        let sem = sem.clone();
+        thread::spawn(move || sem.id()).join().unwrap()
+    };
+    assert_eq!(seen, id);
+}
+
+#[test]
+fn debug_shows_the_id() {
+    let sem = Semaphore::new(1);
+    assert!(format!("{:?}", sem).contains(&format!("id: {}", sem.id())));
+}
@@ -0,0 +1,151 @@
+extern crate libc;
+extern crate sema;
+extern crate time;
+
+use sema::{
+    InterruptPolicy,
+    Semaphore,
+};
+
+#[test]
+fn surfaces_by_default() {
+    let mut sem = Semaphore::new(1);
+    assert_eq!(sem.interrupt_policy(), InterruptPolicy::Surface);
+    sem.set_interrupt_policy(InterruptPolicy::Retry);
+    assert_eq!(sem.interrupt_policy(), InterruptPolicy::Retry);
+    let sem = Semaphore::with_interrupt_policy(1, InterruptPolicy::Retry);
+    assert_eq!(sem.interrupt_policy(), InterruptPolicy::Retry);
+    sem.wait().unwrap();
+}
+
+// Interrupts a thread blocked in `wait()` with real signals.
+#[cfg(all(unix,
+          not(feature = "spin-fallback")))]
+mod signals {
+    use std::io::ErrorKind;
+    use std::mem;
+    use std::ptr;
+    use std::sync::Arc;
+    use std::sync::atomic::{
+        AtomicBool,
+        Ordering,
+    };
+    use std::thread;
+    use std::time::{
+        Duration as StdDuration,
+        Instant,
+    };
+
+    use libc;
+    use sema::{
+        InterruptPolicy,
+        Semaphore,
+    };
+    use time::Duration;
+
+    extern "C" fn ignore(_signum: libc::c_int) {}
+
+    // Installs a handler for SIGUSR1 without `SA_RESTART`, so that it interrupts blocking calls.
+    fn install() {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = ignore as *const () as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            assert_eq!(libc::sigaction(libc::SIGUSR1, &action, ptr::null_mut()), 0);
+        }
+    }
+
+    // Runs `wait` on a new thread, signalling it every few milliseconds for `signalling`, or
+    // until it returns.
+    fn interrupted<F>(signalling: StdDuration, wait: F) -> Result<(), ErrorKind>
+        where F: FnOnce() -> Result<(), ::std::io::Error> + Send + 'static
+    {
+        install();
+        let done = Arc::new(AtomicBool::new(false));
+        let waiter = {
+            let done = done.clone();
+            thread::spawn(move || {
+                let res = wait().map_err(|e| e.kind());
+                done.store(true, Ordering::SeqCst);
+                res
+            })
+        };
+        let tid = {
+            use std::os::unix::thread::JoinHandleExt;
+            waiter.as_pthread_t()
+        };
+        let mut left = signalling;
+        while !done.load(Ordering::SeqCst) && left > StdDuration::ZERO {
+            thread::sleep(StdDuration::from_millis(10));
+            left = left.saturating_sub(StdDuration::from_millis(10));
+            if !done.load(Ordering::SeqCst) {
+                unsafe {
+                    libc::pthread_kill(tid, libc::SIGUSR1);
+                }
+            }
+        }
+        waiter.join().unwrap()
+    }
+
+    #[test]
+    fn surface_fails_interrupted_wait() {
+        let sem = Arc::new(Semaphore::new(0));
+        let res = interrupted(StdDuration::from_secs(5), move || sem.wait());
+        assert_eq!(res, Err(ErrorKind::Interrupted));
+    }
+
+    #[test]
+    fn retry_keeps_waiting() {
+        let sem = Arc::new(Semaphore::with_interrupt_policy(0, InterruptPolicy::Retry));
+        let poster = sem.clone();
+        thread::spawn(move || {
+            thread::sleep(StdDuration::from_millis(200));
+            poster.post();
+        });
+        let res = interrupted(StdDuration::from_millis(150), move || sem.wait());
+        assert_eq!(res, Ok(()));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn infallible_wait_keeps_waiting() {
+        let sem = Arc::new(Semaphore::new(0));
+        let poster = sem.clone();
+        thread::spawn(move || {
+            thread::sleep(StdDuration::from_millis(200));
+            poster.post();
+        });
+        let res = interrupted(StdDuration::from_millis(150), move || {
+            sem.wait_infallible();
+            Ok(())
+        });
+        assert_eq!(res, Ok(()));
+    }
+
+    // An interrupted wait has used up only the time until the first signal.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn interrupted_wait_reports_remaining_time() {
+        let sem = Arc::new(Semaphore::new(0));
+        let res = interrupted(StdDuration::from_secs(5), move || {
+            let (res, remaining) = sem.wait_timeout_remaining(Duration::seconds(5));
+            assert!(remaining > Duration::seconds(3) && remaining < Duration::seconds(5),
+                    "{} remaining", remaining);
+            res
+        });
+        assert_eq!(res, Err(ErrorKind::Interrupted));
+    }
+
+    // Signals keep arriving well past the timeout. Were each retry to start the full timeout
+    // over, the wait would only end once they stop.
+    #[test]
+    fn retry_keeps_deadline() {
+        let sem = Arc::new(Semaphore::with_interrupt_policy(0, InterruptPolicy::Retry));
+        let start = Instant::now();
+        let res = interrupted(StdDuration::from_secs(5), move || {
+            sem.wait_timeout(Duration::milliseconds(300))
+        });
+        assert_eq!(res, Err(ErrorKind::TimedOut));
+        assert!(start.elapsed() < StdDuration::from_secs(3), "waited {:?}", start.elapsed());
+    }
+}
@@ -0,0 +1,209 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+// Randomized stress tests of the semaphore's counting invariants.
+//
+// Each case runs a few threads over random sequences of operations. Waiter threads take permits
+// for good with `wait()`, poster threads post them and pause in between, and both borrow permits
+// with `try_wait()` and short `wait_timeout()`s, giving back whatever they got. The case only
+// posts as many permits as the waits need, so every `wait()` must eventually succeed: one that
+// doesn't lost a wakeup. Afterwards the permits still available must be exactly those posted and
+// not taken.
+
+extern crate proptest;
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicBool,
+    AtomicU64,
+    Ordering,
+};
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use proptest::prelude::*;
+use sema::{
+    Semaphore,
+    WaitStrategy,
+};
+use time::Duration;
+
+// Long enough that a `wait()` only times out if its wakeup was lost.
+const PATIENCE: i64 = 5;
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    // Takes a permit for good.
+    Wait,
+    // Borrows a permit if one is available right away.
+    TryWait,
+    // Borrows a permit if one becomes available within the given milliseconds.
+    WaitTimeout(u8),
+    // Posts the given number of permits.
+    Post(u32),
+    // Sleeps for the given milliseconds, letting the other threads block.
+    Pause(u8),
+}
+
+#[derive(Default)]
+struct Counts {
+    acquired: AtomicU64,
+    released: AtomicU64,
+}
+
+fn borrow(sem: &Semaphore, counts: &Counts, res: Result<(), ::std::io::Error>) {
+    if res.is_ok() {
+        counts.acquired.fetch_add(1, Ordering::Relaxed);
+        thread::yield_now();
+        counts.released.fetch_add(1, Ordering::Relaxed);
+        sem.post();
+    }
+}
+
+fn run(sem: &Semaphore, counts: &Counts, ops: &[Op]) {
+    for op in ops {
+        match *op {
+            Op::Wait => {
+                let res = sem.wait_timeout(Duration::seconds(PATIENCE));
+                assert!(res.is_ok(), "lost wakeup: {:?}", res);
+                counts.acquired.fetch_add(1, Ordering::Relaxed);
+            }
+            Op::TryWait => borrow(sem, counts, sem.try_wait()),
+            Op::WaitTimeout(ms) => {
+                borrow(sem, counts, sem.wait_timeout(Duration::milliseconds(ms as i64)))
+            }
+            Op::Post(n) => {
+                counts.released.fetch_add(n as u64, Ordering::Relaxed);
+                if n == 1 {
+                    sem.post();
+                } else {
+                    sem.post_many(n);
+                }
+            }
+            Op::Pause(ms) => thread::sleep(StdDuration::from_millis(ms as u64)),
+        }
+    }
+}
+
+fn borrowing() -> BoxedStrategy<Op> {
+    prop_oneof![
+        Just(Op::TryWait),
+        (0u8..5).prop_map(Op::WaitTimeout),
+    ].boxed()
+}
+
+fn waiter() -> impl Strategy<Value = Vec<Op>> {
+    prop::collection::vec(prop_oneof![2 => Just(Op::Wait), 1 => borrowing()], 0..12)
+}
+
+fn poster() -> impl Strategy<Value = Vec<Op>> {
+    prop::collection::vec(prop_oneof![
+        3 => (1u32..4).prop_map(Op::Post),
+        1 => (0u8..3).prop_map(Op::Pause),
+        1 => borrowing(),
+    ], 0..12)
+}
+
+fn wait_strategy() -> impl Strategy<Value = WaitStrategy> {
+    prop_oneof![
+        Just(WaitStrategy::Adaptive),
+        Just(WaitStrategy::Block),
+        (0u32..50).prop_map(|spins| WaitStrategy::SpinThenBlock { spins }),
+        Just(WaitStrategy::YieldThenBlock),
+    ]
+}
+
+fn count(ops: &[Vec<Op>], f: fn(&Op) -> u64) -> u64 {
+    ops.iter().flatten().map(f).sum()
+}
+
+fn check(initial: u32, strategy: WaitStrategy, handoff: bool, waiters: Vec<Vec<Op>>,
+         mut posters: Vec<Vec<Op>>) {
+    let waits = count(&waiters, |op| matches!(*op, Op::Wait) as u64);
+    let posts = count(&posters, |op| match *op {
+        Op::Post(n) => n as u64,
+        _ => 0,
+    });
+    // Post what the waits still need, from a thread of its own.
+    let missing = waits.saturating_sub(initial as u64 + posts);
+    if missing > 0 {
+        posters.push(vec![Op::Pause(1), Op::Post(missing as u32)]);
+    }
+    let posted = initial as u64 + posts + missing;
+
+    let mut sem = Semaphore::with_wait_strategy(initial, strategy);
+    sem.set_handoff(handoff);
+    let sem = Arc::new(sem);
+    let counts = Arc::new(Counts::default());
+    let done = Arc::new(AtomicBool::new(false));
+    let nthreads = (waiters.len() + posters.len()) as u32;
+
+    let monitor = {
+        let sem = sem.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            while !done.load(Ordering::Relaxed) {
+                sample(&sem, nthreads, posted);
+                thread::yield_now();
+            }
+        })
+    };
+    let threads: Vec<_> = waiters.into_iter().chain(posters).map(|ops| {
+        let sem = sem.clone();
+        let counts = counts.clone();
+        thread::spawn(move || run(&sem, &counts, &ops))
+    }).collect();
+    let results: Vec<_> = threads.into_iter().map(|t| t.join()).collect();
+    done.store(true, Ordering::Relaxed);
+    monitor.join().unwrap();
+    for res in results {
+        res.unwrap();
+    }
+
+    let acquired = counts.acquired.load(Ordering::Relaxed);
+    let released = counts.released.load(Ordering::Relaxed);
+    assert_eq!(acquired - (released - posts - missing), waits);
+    let mut available = 0;
+    while sem.try_wait().is_ok() {
+        available += 1;
+    }
+    // Every permit was either taken for good or is still there.
+    assert_eq!(initial as u64 + released, acquired + available);
+    assert_eq!(available, posted - waits);
+    finished(&sem);
+}
+
+// Checks the counters that can be observed while threads are running.
+#[cfg(feature = "stats")]
+fn sample(sem: &Semaphore, nthreads: u32, posted: u64) {
+    let stats = sem.stats();
+    // A waiter count that went negative would wrap around.
+    assert!(stats.waiters <= nthreads, "{} waiters with {} threads", stats.waiters, nthreads);
+    assert!(stats.permits as u64 <= posted, "{} permits of {} posted", stats.permits, posted);
+}
+
+#[cfg(not(feature = "stats"))]
+fn sample(_sem: &Semaphore, _nthreads: u32, _posted: u64) {}
+
+#[cfg(feature = "stats")]
+fn finished(sem: &Semaphore) {
+    assert_eq!(sem.stats().waiters, 0);
+}
+
+#[cfg(not(feature = "stats"))]
+fn finished(_sem: &Semaphore) {}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn permits_are_conserved(initial in 0u32..4,
+                             strategy in wait_strategy(),
+                             handoff in any::<bool>(),
+                             waiters in prop::collection::vec(waiter(), 1..4),
+                             posters in prop::collection::vec(poster(), 1..3)) {
+        check(initial, strategy, handoff, waiters, posters);
+    }
+}
@@ -0,0 +1,145 @@
+#![cfg(unix)]
+
+extern crate libc;
+extern crate sema;
+extern crate time;
+
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Mutex;
+
+use sema::Jobserver;
+use time::Duration;
+
+// Serializes the tests which point `MAKEFLAGS` at a jobserver.
+static ENV: Mutex<()> = Mutex::new(());
+
+// Sets the variables `Jobserver::from_env()` looks at to `makeflags`, or removes them.
+fn set_makeflags(makeflags: Option<&str>) {
+    env::remove_var("CARGO_MAKEFLAGS");
+    env::remove_var("MFLAGS");
+    match makeflags {
+        Some(flags) => env::set_var("MAKEFLAGS", flags),
+        None => env::remove_var("MAKEFLAGS"),
+    }
+}
+
+// The descriptors in `--jobserver-auth=R,W`.
+fn pipe_fds(js: &Jobserver) -> (libc::c_int, libc::c_int) {
+    let flags = js.makeflags();
+    let fds = flags.trim_start_matches("--jobserver-auth=");
+    let mut fds = fds.split(',').map(|fd| fd.parse().unwrap());
+    (fds.next().unwrap(), fds.next().unwrap())
+}
+
+// A directory removed again when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(tag: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("sema-{}-{}", tag, process::id()));
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn tokens_are_counted() {
+    let js = Jobserver::new(2).unwrap();
+    js.wait().unwrap();
+    js.try_wait().unwrap();
+    assert_eq!(js.try_wait().unwrap_err().kind(), ErrorKind::WouldBlock);
+    js.post();
+    js.try_wait().unwrap();
+}
+
+// A jobserver for `make -j1` has no tokens at all: the process runs its one job in the implicit
+// slot, which is never written to the pipe.
+#[test]
+fn implicit_slot_is_not_a_token() {
+    let js = Jobserver::new(0).unwrap();
+    assert_eq!(js.try_wait().unwrap_err().kind(), ErrorKind::WouldBlock);
+    let err = js.wait_timeout(Duration::milliseconds(10)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+}
+
+#[test]
+fn guard_writes_back_the_byte_it_read() {
+    let js = Jobserver::new(0).unwrap();
+    let (read, write) = pipe_fds(&js);
+    let token = b'x';
+    assert_eq!(unsafe { libc::write(write, &token as *const u8 as *const libc::c_void, 1) }, 1);
+    {
+        let _guard = js.take().unwrap();
+        assert_eq!(js.try_wait().unwrap_err().kind(), ErrorKind::WouldBlock);
+    }
+    let mut buf = 0u8;
+    assert_eq!(unsafe { libc::read(read, &mut buf as *mut u8 as *mut libc::c_void, 1) }, 1);
+    assert_eq!(buf, b'x');
+}
+
+#[test]
+fn from_env_joins_pipe() {
+    let _env = ENV.lock().unwrap();
+    let js = Jobserver::new(1).unwrap();
+    let (read, write) = pipe_fds(&js);
+
+    set_makeflags(Some(&format!("-j2 --jobserver-auth={},{}", read, write)));
+    let joined = Jobserver::from_env().unwrap();
+    assert_eq!(joined.makeflags(), js.makeflags());
+    joined.try_wait().unwrap();
+    assert_eq!(js.try_wait().unwrap_err().kind(), ErrorKind::WouldBlock);
+    joined.post();
+    js.try_wait().unwrap();
+
+    // The spelling used before GNU Make 4.2, and the last occurrence winning.
+    set_makeflags(Some(&format!("--jobserver-fds={},{} -j", read, write)));
+    assert_eq!(Jobserver::from_env().unwrap().makeflags(), js.makeflags());
+    set_makeflags(Some(&format!("--jobserver-auth=-1,-1 --jobserver-auth={},{}", read, write)));
+    assert_eq!(Jobserver::from_env().unwrap().makeflags(), js.makeflags());
+    set_makeflags(None);
+}
+
+#[test]
+fn from_env_joins_fifo() {
+    let _env = ENV.lock().unwrap();
+    let dir = TempDir::new("jobserver");
+    let path = dir.0.join("fifo");
+    let c_path = CString::new(path.to_str().unwrap()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+
+    let flags = format!("--jobserver-auth=fifo:{}", path.display());
+    set_makeflags(Some(&format!("-j2 {}", flags)));
+    let a = Jobserver::from_env().unwrap();
+    let b = Jobserver::from_env().unwrap();
+    set_makeflags(None);
+    assert_eq!(a.makeflags(), flags);
+    assert_eq!(b.try_wait().unwrap_err().kind(), ErrorKind::WouldBlock);
+    a.post();
+    b.try_wait().unwrap();
+    assert_eq!(a.try_wait().unwrap_err().kind(), ErrorKind::WouldBlock);
+}
+
+#[test]
+fn from_env_without_jobserver() {
+    let _env = ENV.lock().unwrap();
+    for flags in [None, Some("-j4"), Some("--jobserver-auth=-1,-1")] {
+        set_makeflags(flags);
+        assert_eq!(Jobserver::from_env().err().map(|e| e.kind()), Some(ErrorKind::NotFound),
+                   "{:?}", flags);
+    }
+    // Descriptors which the recipe didn't inherit.
+    set_makeflags(Some("--jobserver-auth=1020,1021"));
+    assert_eq!(Jobserver::from_env().err().map(|e| e.kind()), Some(ErrorKind::NotFound));
+    set_makeflags(None);
+}
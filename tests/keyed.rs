@@ -0,0 +1,31 @@
+extern crate sema;
+extern crate time;
+
+use sema::KeyedSemaphore;
+use time::Duration;
+
+#[test]
+fn keys_are_limited_separately() {
+    let keyed = KeyedSemaphore::new(2);
+    let a1 = keyed.acquire("a").unwrap();
+    let _a2 = keyed.acquire("a").unwrap();
+    assert!(keyed.try_acquire("a").is_err());
+    assert!(keyed.acquire_timeout("a", Duration::milliseconds(10)).is_err());
+    let b = keyed.try_acquire("b").unwrap();
+    assert_eq!(*b.key(), "b");
+    assert_eq!(keyed.active_keys(), 2);
+    drop(a1);
+    assert!(keyed.try_acquire("a").is_ok());
+}
+
+#[test]
+fn idle_keys_are_removed() {
+    let keyed = KeyedSemaphore::new(1);
+    {
+        let _a = keyed.acquire(1).unwrap();
+        let _b = keyed.acquire(2).unwrap();
+        assert!(keyed.try_acquire(1).is_err());
+        assert_eq!(keyed.active_keys(), 2);
+    }
+    assert_eq!(keyed.active_keys(), 0);
+}
@@ -0,0 +1,46 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+
+use std::sync::Arc;
+
+use sema::Semaphore;
+use sema::labels;
+
+// The registry is process-wide, so every test uses labels of its own.
+fn find(label: &str) -> Vec<labels::LabelledSemaphore> {
+    labels::snapshot().into_iter().filter(|s| s.label == label).collect()
+}
+
+#[test]
+fn lists_labelled_semaphores() {
+    let sem = Semaphore::with_label("test-db-pool", 2);
+    sem.wait().unwrap();
+    assert_eq!(sem.label().as_deref(), Some("test-db-pool"));
+
+    let found = find("test-db-pool");
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].permits, 1);
+    assert_eq!(found[0].waiters, 0);
+    assert!(labels::dump().contains("test-db-pool: 1 permits, 0 waiters"));
+}
+
+#[test]
+fn forgets_dropped_semaphores() {
+    let sem = Semaphore::with_label("test-dropped", 1);
+    assert_eq!(find("test-dropped").len(), 1);
+    drop(sem);
+    assert!(find("test-dropped").is_empty());
+}
+
+#[test]
+fn registers_existing_semaphores() {
+    let sem = Arc::new(Semaphore::new(3));
+    assert_eq!(sem.label(), None);
+    labels::register("test-shard", &sem);
+    assert_eq!(sem.label().as_deref(), Some("test-shard"));
+    assert!(labels::unregister(&sem));
+    assert!(!labels::unregister(&sem));
+    assert_eq!(sem.label(), None);
+}
@@ -0,0 +1,34 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+
+use sema::CountdownLatch;
+use time::Duration;
+
+#[test]
+fn releases_after_count_events() {
+    let latch = Arc::new(CountdownLatch::new(3));
+    let waiters: Vec<_> = (0..2).map(|_| {
+        let latch = latch.clone();
+        thread::spawn(move || latch.wait_timeout(Duration::seconds(5)).is_ok())
+    }).collect();
+
+    for _ in 0..2 {
+        latch.count_down();
+    }
+    assert!(latch.wait_timeout(Duration::milliseconds(10)).is_err());
+    latch.count_down();
+    for waiter in waiters {
+        assert!(waiter.join().unwrap());
+    }
+
+    // Further count downs are ignored.
+    latch.count_down();
+    assert_eq!(latch.count(), 0);
+    assert!(latch.try_wait().is_ok());
+}
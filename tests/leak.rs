@@ -0,0 +1,40 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback"),
+           feature = "leak-check"))]
+
+extern crate sema;
+
+use std::mem;
+
+use sema::{
+    FutexMode,
+    Semaphore,
+};
+
+#[test]
+fn tracks_live_guards() {
+    let sem = Semaphore::new(2);
+    let a = sem.take().unwrap();
+    let b = sem.take().unwrap();
+    assert_eq!(sem.outstanding_guards(), 2);
+    drop(a);
+    assert_eq!(sem.outstanding_guards(), 1);
+    drop(b);
+    assert_eq!(sem.outstanding_guards(), 0);
+}
+
+#[test]
+fn remembers_forgotten_guards() {
+    let sem = Semaphore::new(1);
+    mem::forget(sem.take().unwrap());
+    assert_eq!(sem.outstanding_guards(), 1);
+    assert!(sem.try_wait().is_err());
+}
+
+#[test]
+fn ignores_shared_semaphores() {
+    let sem = Semaphore::with_futex_mode(1, FutexMode::Shared);
+    let guard = sem.take().unwrap();
+    assert_eq!(sem.outstanding_guards(), 0);
+    drop(guard);
+}
@@ -0,0 +1,58 @@
+extern crate sema;
+extern crate time;
+
+use std::mem;
+
+use sema::LeaseSemaphore;
+use time::Duration;
+
+#[test]
+fn release_returns_permit() {
+    let sem = LeaseSemaphore::new(1, Duration::seconds(60));
+    let lease = sem.acquire().unwrap();
+    assert!(!lease.is_expired());
+    assert!(sem.try_acquire().is_err());
+    drop(lease);
+    assert_eq!(sem.active(), 0);
+    assert!(sem.try_acquire().is_ok());
+}
+
+#[test]
+fn expired_lease_is_reclaimed() {
+    let sem = LeaseSemaphore::new(1, Duration::milliseconds(20));
+    let stale = sem.acquire().unwrap();
+    // A waiter gets the permit once the lease runs out.
+    let fresh = sem.acquire_timeout(Duration::seconds(5)).unwrap();
+    assert!(stale.is_expired());
+    // Dropping the stale lease does not hand out a second permit.
+    drop(stale);
+    assert!(sem.try_acquire().is_err());
+    mem::forget(fresh);
+}
+
+#[test]
+fn timeout_before_expiry() {
+    let sem = LeaseSemaphore::new(1, Duration::seconds(60));
+    let _lease = sem.acquire().unwrap();
+    assert!(sem.acquire_timeout(Duration::milliseconds(10)).is_err());
+    assert_eq!(sem.active(), 1);
+}
+
+#[test]
+fn renewal_extends_lease() {
+    let sem = LeaseSemaphore::new(1, Duration::milliseconds(50));
+    let mut lease = sem.acquire().unwrap();
+    lease.renew(Duration::seconds(60)).unwrap();
+    assert!(sem.acquire_timeout(Duration::milliseconds(100)).is_err());
+    assert!(!lease.is_expired());
+    assert_eq!(sem.active(), 1);
+}
+
+#[test]
+fn expired_lease_cannot_be_renewed() {
+    let sem = LeaseSemaphore::new(1, Duration::milliseconds(10));
+    let mut lease = sem.acquire().unwrap();
+    ::std::thread::sleep(::std::time::Duration::from_millis(20));
+    assert!(lease.renew(Duration::seconds(60)).is_err());
+    assert!(sem.try_acquire().is_ok());
+}
@@ -0,0 +1,78 @@
+extern crate sema;
+extern crate time;
+
+use std::panic;
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::thread;
+
+use sema::ConcurrencyLimiter;
+use time::Duration;
+
+#[test]
+fn limits_concurrency() {
+    let limiter = Arc::new(ConcurrencyLimiter::new(2));
+    let running = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..8).map(|_| {
+        let limiter = limiter.clone();
+        let running = running.clone();
+        thread::spawn(move || {
+            limiter.run(|| {
+                assert!(running.fetch_add(1, Ordering::SeqCst) < 2);
+                thread::sleep(::std::time::Duration::from_millis(5));
+                running.fetch_sub(1, Ordering::SeqCst);
+            }).unwrap();
+        })
+    }).collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert_eq!(limiter.in_flight(), 0);
+    assert_eq!(limiter.queued(), 0);
+}
+
+#[test]
+fn try_run_fails_at_limit() {
+    let limiter = ConcurrencyLimiter::new(1);
+    let res = limiter.run(|| {
+        assert_eq!(limiter.in_flight(), 1);
+        assert!(limiter.try_run(|| ()).is_err());
+        assert!(limiter.run_timeout(Duration::milliseconds(10), || ()).is_err());
+        7
+    });
+    assert_eq!(res.unwrap(), 7);
+    assert_eq!(limiter.try_run(|| 8).unwrap(), 8);
+}
+
+#[test]
+fn panic_releases_permit() {
+    let limiter = ConcurrencyLimiter::new(1);
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        limiter.run(|| panic!("boom")).unwrap();
+    }));
+    assert!(res.is_err());
+    assert_eq!(limiter.in_flight(), 0);
+    assert!(limiter.try_run(|| ()).is_ok());
+}
+
+#[test]
+fn limit_can_change() {
+    let limiter = ConcurrencyLimiter::new(1);
+    limiter.set_limit(3);
+    assert_eq!(limiter.limit(), 3);
+    limiter.run(|| {
+        limiter.run(|| {
+            // Two running, lowering to one leaves a permit owed.
+            limiter.set_limit(1);
+            assert!(limiter.try_run(|| ()).is_err());
+        }).unwrap();
+        // The inner closure paid off the owed permit.
+        assert!(limiter.try_run(|| ()).is_err());
+    }).unwrap();
+    assert!(limiter.try_run(|| ()).is_ok());
+    limiter.set_limit(2);
+    limiter.run(|| assert!(limiter.try_run(|| ()).is_ok())).unwrap();
+}
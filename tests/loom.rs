@@ -0,0 +1,109 @@
+// Model checks of the Linux backend. Run with:
+//
+//     RUSTFLAGS="--cfg loom" cargo test --test loom --release
+#![cfg(all(loom,
+           target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate loom;
+extern crate sema;
+extern crate time;
+
+use loom::sync::Arc;
+use loom::thread;
+
+use sema::{
+    Semaphore,
+    WaitStrategy,
+};
+use time::Duration;
+
+// Runs `f` under loom. Exhaustive runs take hours, while the races found so far need only a few
+// preemptions, so the bound defaults to 3. `LOOM_MAX_PREEMPTIONS` overrides it.
+fn model<F: Fn() + Sync + Send + 'static>(f: F) {
+    let mut builder = loom::model::Builder::new();
+    if builder.preemption_bound.is_none() {
+        builder.preemption_bound = Some(3);
+    }
+    builder.check(f);
+}
+
+// Spinning only multiplies the interleavings without adding any of interest.
+fn blocking(value: u32) -> Semaphore {
+    Semaphore::with_wait_strategy(value, WaitStrategy::Block)
+}
+
+// Takes every token left, returning how many there were.
+fn drain(sem: &Semaphore) -> u32 {
+    let mut n = 0;
+    while sem.try_wait().is_ok() {
+        n += 1;
+    }
+    n
+}
+
+#[test]
+fn post_wakes_blocked_waiter() {
+    model(|| {
+        let sem = Arc::new(blocking(0));
+        let poster = {
+            let sem = sem.clone();
+            thread::spawn(move || sem.post())
+        };
+        sem.wait().unwrap();
+        poster.join().unwrap();
+        assert_eq!(drain(&sem), 0);
+    });
+}
+
+#[test]
+fn post_many_wakes_every_waiter() {
+    model(|| {
+        let sem = Arc::new(blocking(0));
+        let waiters: Vec<_> = (0..2).map(|_| {
+            let sem = sem.clone();
+            thread::spawn(move || sem.wait().unwrap())
+        }).collect();
+        sem.post_many(2);
+        for waiter in waiters {
+            waiter.join().unwrap();
+        }
+        assert_eq!(drain(&sem), 0);
+    });
+}
+
+#[test]
+fn handoff_conserves_tokens() {
+    model(|| {
+        let mut sem = blocking(0);
+        sem.set_handoff(true);
+        let sem = Arc::new(sem);
+        let waiter = {
+            let sem = sem.clone();
+            thread::spawn(move || sem.wait().unwrap())
+        };
+        let barger = {
+            let sem = sem.clone();
+            thread::spawn(move || sem.try_wait().is_ok() as u32)
+        };
+        sem.post();
+        sem.post();
+        waiter.join().unwrap();
+        let barged = barger.join().unwrap();
+        assert_eq!(1 + barged + drain(&sem), 2);
+    });
+}
+
+#[test]
+fn timed_out_waiter_conserves_tokens() {
+    model(|| {
+        let sem = Arc::new(blocking(0));
+        let waiter = {
+            let sem = sem.clone();
+            thread::spawn(move || sem.wait_timeout(Duration::seconds(1)).is_ok() as u32)
+        };
+        sem.post();
+        let taken = waiter.join().unwrap();
+        assert_eq!(taken + drain(&sem), 1);
+    });
+}
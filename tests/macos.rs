@@ -0,0 +1,37 @@
+#![cfg(all(target_os = "macos",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::thread;
+use std::time::{
+    Duration as StdDuration,
+    Instant,
+};
+
+use sema::Semaphore;
+use time::Duration;
+
+#[test]
+fn local_wait_timeout_times_out() {
+    let sem = Semaphore::new(0);
+    let start = Instant::now();
+    let err = sem.wait_timeout(Duration::milliseconds(100)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(start.elapsed() >= StdDuration::from_millis(100));
+}
+
+#[test]
+fn local_wait_timeout_is_woken() {
+    let sem = Arc::new(Semaphore::new(0));
+    let poster = sem.clone();
+    thread::spawn(move || {
+        thread::sleep(StdDuration::from_millis(50));
+        poster.post_many(2);
+    });
+    sem.wait_timeout(Duration::seconds(5)).unwrap();
+    sem.wait_timeout(Duration::seconds(5)).unwrap();
+}
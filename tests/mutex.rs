@@ -0,0 +1,35 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+
+use std::sync::Arc;
+use std::thread;
+
+use sema::FutexMutex;
+
+#[test]
+fn guards_shared_data() {
+    let mutex = Arc::new(FutexMutex::new(0u64));
+    let threads: Vec<_> = (0..4).map(|_| {
+        let mutex = mutex.clone();
+        thread::spawn(move || {
+            for _ in 0..10000 {
+                *mutex.lock() += 1;
+            }
+        })
+    }).collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(*mutex.lock(), 40000);
+}
+
+#[test]
+fn try_lock_fails_while_locked() {
+    let mutex = FutexMutex::new(());
+    let guard = mutex.lock();
+    assert!(mutex.try_lock().is_none());
+    drop(guard);
+    assert!(mutex.try_lock().is_some());
+}
@@ -0,0 +1,96 @@
+#![cfg(unix)]
+
+extern crate libc;
+extern crate sema;
+extern crate time;
+
+use std::io::ErrorKind;
+use std::process;
+use std::time::{
+    Duration as StdDuration,
+    Instant,
+};
+
+use sema::{
+    NamedSemaphore,
+    NamedSemaphoreOptions,
+};
+use time::Duration;
+
+fn named(tag: &str, value: u32) -> NamedSemaphore {
+    let name = format!("/sema-named-{}-{}", process::id(), tag);
+    NamedSemaphoreOptions::new().unlink_on_drop(true).create(&name, value).unwrap()
+}
+
+#[test]
+fn wait_timeout_waits_for_the_timeout() {
+    let sem = named("timeout", 0);
+    let start = Instant::now();
+    let err = sem.wait_timeout(Duration::milliseconds(100)).unwrap_err();
+    let elapsed = start.elapsed();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(elapsed >= StdDuration::from_millis(100) && elapsed < StdDuration::from_secs(5),
+            "timed out after {:?}", elapsed);
+}
+
+#[test]
+fn negative_timeout_has_expired() {
+    let sem = named("negative", 0);
+    let err = sem.wait_timeout(Duration::seconds(-1)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    sem.post();
+    sem.wait_timeout(Duration::seconds(-1)).unwrap();
+}
+
+#[test]
+fn try_post() {
+    let sem = named("try-post", 0);
+    sem.try_post().unwrap();
+    sem.try_wait().unwrap();
+}
+
+// glibc's `SEM_VALUE_MAX` is `i32::MAX`.
+#[cfg(all(target_os = "linux",
+          target_env = "gnu"))]
+#[test]
+fn try_post_reports_overflow() {
+    let sem = named("overflow", i32::MAX as u32);
+    let err = sem.try_post().unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EOVERFLOW));
+    // Nothing was posted.
+    sem.try_wait().unwrap();
+    sem.try_post().unwrap();
+}
+
+#[test]
+fn create_unique() {
+    // Generated names are created exclusively whatever the options say.
+    let (name, sem) = NamedSemaphoreOptions::new().exclusive(false).unlink_on_drop(true)
+                                                  .create_unique(1).unwrap();
+    assert!(name.starts_with("/sema."), "{}", name);
+    assert!(NamedSemaphore::list_generated().unwrap().contains(&name));
+    sem.try_wait().unwrap();
+    let (other, _sem) = NamedSemaphoreOptions::new().unlink_on_drop(true).create_unique(0).unwrap();
+    assert!(other != name);
+}
+
+#[test]
+fn unlink_on_create() {
+    let name = format!("/sema-named-{}-unlinked", process::id());
+    let sem = NamedSemaphoreOptions::new().unlink_on_create(true).create(&name, 0).unwrap();
+    match NamedSemaphore::open(&name) {
+        Err(e) => assert_eq!(e.kind(), ErrorKind::NotFound),
+        Ok(_) => panic!("opened an unlinked name"),
+    }
+    sem.post();
+    sem.wait_timeout(Duration::seconds(5)).unwrap();
+    // The name is free again.
+    let _sem = NamedSemaphoreOptions::new().unlink_on_drop(true).create(&name, 0).unwrap();
+}
+
+#[test]
+fn unique_names_unlinked_on_create_are_not_listed() {
+    let (name, _sem) = NamedSemaphoreOptions::new().unlink_on_create(true)
+                                                   .create_unique(0).unwrap();
+    assert!(!NamedSemaphore::list_generated().unwrap().contains(&name));
+}
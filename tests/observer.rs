@@ -0,0 +1,94 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback"),
+           feature = "observer"))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicU32,
+    Ordering,
+};
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use sema::{
+    Semaphore,
+    SemaphoreObserver,
+    WaitStrategy,
+};
+use time::Duration;
+
+#[derive(Default)]
+struct Counts {
+    blocks: AtomicU32,
+    wakes: AtomicU32,
+    timeouts: AtomicU32,
+    posted: AtomicU32,
+}
+
+struct Counter(Arc<Counts>);
+
+impl SemaphoreObserver for Counter {
+    fn on_block(&self, _sem: &Semaphore) {
+        self.0.blocks.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_wake(&self, _sem: &Semaphore, _waited: StdDuration) {
+        self.0.wakes.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_timeout(&self, _sem: &Semaphore) {
+        self.0.timeouts.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_post(&self, _sem: &Semaphore, n: u32) {
+        self.0.posted.fetch_add(n, Ordering::SeqCst);
+    }
+}
+
+fn observed(value: u32) -> (Arc<Semaphore>, Arc<Counts>) {
+    let counts = Arc::new(Counts::default());
+    let mut sem = Semaphore::with_wait_strategy(value, WaitStrategy::Block);
+    sem.set_observer(Counter(counts.clone()));
+    (Arc::new(sem), counts)
+}
+
+#[test]
+fn reports_blocking_waits_and_posts() {
+    let (sem, counts) = observed(0);
+    let waiter = {
+        let sem = sem.clone();
+        thread::spawn(move || sem.wait().unwrap())
+    };
+    while counts.blocks.load(Ordering::SeqCst) == 0 {
+        thread::yield_now();
+    }
+    sem.post_many(2);
+    waiter.join().unwrap();
+    assert_eq!(counts.wakes.load(Ordering::SeqCst), 1);
+    assert_eq!(counts.posted.load(Ordering::SeqCst), 2);
+
+    // The remaining token is taken on the fast path, which isn't reported.
+    sem.wait().unwrap();
+    assert_eq!(counts.blocks.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn reports_timeouts() {
+    let (sem, counts) = observed(0);
+    assert!(sem.wait_timeout(Duration::milliseconds(10)).is_err());
+    assert_eq!(counts.blocks.load(Ordering::SeqCst), 1);
+    assert_eq!(counts.timeouts.load(Ordering::SeqCst), 1);
+    assert_eq!(counts.wakes.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn clearing_stops_reports() {
+    let (sem, counts) = observed(0);
+    let mut sem = Arc::try_unwrap(sem).ok().unwrap();
+    sem.clear_observer();
+    sem.post();
+    assert_eq!(counts.posted.load(Ordering::SeqCst), 0);
+}
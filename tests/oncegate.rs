@@ -0,0 +1,38 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+
+use sema::OnceGate;
+use time::Duration;
+
+#[test]
+fn opens_only_once() {
+    let gate = OnceGate::new();
+    assert!(!gate.is_open());
+    assert!(gate.try_wait().is_err());
+    assert!(gate.wait_timeout(Duration::milliseconds(10)).is_err());
+    assert!(gate.open());
+    assert!(!gate.open());
+    for _ in 0..10 {
+        gate.wait().unwrap();
+    }
+}
+
+#[test]
+fn opening_releases_waiters() {
+    let gate = Arc::new(OnceGate::new());
+    let workers: Vec<_> = (0..4).map(|_| {
+        let gate = gate.clone();
+        thread::spawn(move || gate.wait_timeout(Duration::seconds(5)).is_ok())
+    }).collect();
+    thread::sleep(::std::time::Duration::from_millis(50));
+    gate.open();
+    for worker in workers {
+        assert!(worker.join().unwrap());
+    }
+}
@@ -0,0 +1,80 @@
+extern crate sema;
+extern crate time;
+
+use std::error::Error as StdError;
+use std::io::{
+    Error,
+    ErrorKind,
+};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use sema::{
+    Semaphore,
+    TryWaitError,
+    WaitOutcome,
+};
+use time::Duration;
+
+#[test]
+fn try_acquire_would_block() {
+    let sem = Semaphore::new(0);
+    match sem.try_acquire() {
+        Err(TryWaitError::WouldBlock) => {}
+        res => panic!("unexpected {:?}", res),
+    }
+    sem.post();
+    sem.try_acquire().unwrap();
+}
+
+#[test]
+fn acquire_timeout_times_out() {
+    let sem = Semaphore::new(1);
+    let outcome = sem.acquire_timeout(Duration::milliseconds(10)).unwrap();
+    assert_eq!(outcome, WaitOutcome::Acquired);
+    assert!(!outcome.timed_out());
+    let outcome = sem.acquire_timeout(Duration::milliseconds(10)).unwrap();
+    assert_eq!(outcome, WaitOutcome::TimedOut);
+    assert!(outcome.timed_out());
+}
+
+#[test]
+fn acquire_timeout_is_woken() {
+    let sem = Arc::new(Semaphore::new(0));
+    let poster = sem.clone();
+    thread::spawn(move || {
+        thread::sleep(StdDuration::from_millis(50));
+        poster.post();
+    });
+    assert_eq!(sem.acquire_timeout(Duration::seconds(5)).unwrap(), WaitOutcome::Acquired);
+}
+
+#[test]
+fn try_wait_error_converts() {
+    let err: TryWaitError = Error::new(ErrorKind::WouldBlock, "wait would block").into();
+    assert!(matches!(err, TryWaitError::WouldBlock));
+    assert!(err.source().is_none());
+    assert_eq!(Error::from(err).kind(), ErrorKind::WouldBlock);
+
+    let err: TryWaitError = Error::new(ErrorKind::InvalidInput, "bad semaphore").into();
+    assert_eq!(err.to_string(), "bad semaphore");
+    assert!(err.source().is_some());
+    assert_eq!(Error::from(err).kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn remaining_after_acquiring() {
+    let sem = Semaphore::new(1);
+    let (res, remaining) = sem.wait_timeout_remaining(Duration::seconds(5));
+    res.unwrap();
+    assert!(remaining > Duration::seconds(4) && remaining <= Duration::seconds(5));
+}
+
+#[test]
+fn nothing_remains_after_timing_out() {
+    let sem = Semaphore::new(0);
+    let (res, remaining) = sem.wait_timeout_remaining(Duration::milliseconds(10));
+    assert_eq!(res.unwrap_err().kind(), ErrorKind::TimedOut);
+    assert_eq!(remaining, Duration::zero());
+}
@@ -0,0 +1,130 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use sema::{
+    OverflowPolicy,
+    Semaphore,
+};
+use time::Duration;
+
+// Takes every token, returning how many there were.
+fn drain(sem: &Semaphore) -> u64 {
+    let mut taken = 0;
+    loop {
+        match sem.try_wait_many(u32::MAX) {
+            0 => return taken,
+            n => taken += n as u64,
+        }
+    }
+}
+
+#[test]
+fn debug_panic_by_default() {
+    let mut sem = Semaphore::new(0);
+    assert_eq!(sem.overflow_policy(), OverflowPolicy::DebugPanic);
+    sem.set_overflow_policy(OverflowPolicy::Saturate);
+    assert_eq!(sem.overflow_policy(), OverflowPolicy::Saturate);
+}
+
+#[test]
+fn posts_up_to_the_limit() {
+    let sem = Semaphore::with_overflow_policy(u32::MAX - 3, OverflowPolicy::Panic);
+    sem.post_many(2);
+    sem.post();
+    assert_eq!(drain(&sem), u32::MAX as u64);
+}
+
+#[test]
+fn saturates() {
+    let sem = Semaphore::with_overflow_policy(u32::MAX - 1, OverflowPolicy::Saturate);
+    sem.post_many(5);
+    sem.post();
+    assert_eq!(drain(&sem), u32::MAX as u64);
+}
+
+#[test]
+#[should_panic(expected = "semaphore count overflow")]
+fn panics() {
+    let sem = Semaphore::with_overflow_policy(u32::MAX, OverflowPolicy::Panic);
+    sem.post();
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "semaphore count overflow")]
+fn debug_panic_panics_in_debug_builds() {
+    let sem = Semaphore::new(u32::MAX);
+    sem.post();
+}
+
+#[cfg(not(debug_assertions))]
+#[test]
+fn debug_panic_saturates_in_release_builds() {
+    let sem = Semaphore::new(u32::MAX);
+    sem.post();
+    assert_eq!(drain(&sem), u32::MAX as u64);
+}
+
+#[test]
+fn checked_post_fails_at_the_limit() {
+    let sem = Semaphore::with_overflow_policy(u32::MAX - 2, OverflowPolicy::Panic);
+    let err = sem.checked_post_many(3).unwrap_err();
+    assert_eq!(err.to_string(), "semaphore count overflow");
+    // Nothing was posted.
+    sem.checked_post_many(2).unwrap();
+    assert!(sem.checked_post().is_err());
+    assert_eq!(drain(&sem), u32::MAX as u64);
+}
+
+#[test]
+fn post_from_signal_saturates() {
+    let sem = Semaphore::with_overflow_policy(u32::MAX, OverflowPolicy::Panic);
+    sem.post_from_signal();
+    assert_eq!(drain(&sem), u32::MAX as u64);
+}
+
+// Tokens handed to a blocked thread count towards the limit.
+#[test]
+fn handed_tokens_count() {
+    let mut sem = Semaphore::with_overflow_policy(0, OverflowPolicy::Saturate);
+    sem.set_handoff(true);
+    let sem = Arc::new(sem);
+    let waiter = {
+        let sem = sem.clone();
+        thread::spawn(move || sem.wait_timeout(Duration::seconds(5)))
+    };
+    thread::sleep(StdDuration::from_millis(50));
+    sem.post_many(u32::MAX);
+    sem.post();
+    waiter.join().unwrap().unwrap();
+    assert_eq!(drain(&sem), u32::MAX as u64 - 1);
+}
+
+#[test]
+fn saturates_with_waiters() {
+    let sem = Arc::new(Semaphore::with_overflow_policy(0, OverflowPolicy::Saturate));
+    let waiter = {
+        let sem = sem.clone();
+        thread::spawn(move || sem.wait_timeout(Duration::seconds(5)))
+    };
+    thread::sleep(StdDuration::from_millis(50));
+    sem.post_many(u32::MAX - 1);
+    waiter.join().unwrap().unwrap();
+    sem.post_many(3);
+    assert_eq!(drain(&sem), u32::MAX as u64);
+}
+
+#[test]
+fn try_post_fails_at_the_limit() {
+    let sem = Semaphore::with_overflow_policy(u32::MAX - 1, OverflowPolicy::Panic);
+    sem.try_post().unwrap();
+    assert_eq!(sem.try_post().unwrap_err().to_string(), "semaphore count overflow");
+    assert_eq!(drain(&sem), u32::MAX as u64);
+}
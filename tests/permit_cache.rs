@@ -0,0 +1,50 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+
+use sema::Semaphore;
+use time::Duration;
+
+#[test]
+fn takes_tokens_in_batches() {
+    let sem = Semaphore::new(10);
+    {
+        let cache = sem.permit_cache(4);
+        cache.wait().unwrap();
+        assert_eq!(cache.cached(), 3);
+        assert_eq!(sem.try_wait_many(10), 6);
+        sem.post_many(6);
+
+        cache.post();
+        assert_eq!(cache.cached(), 4);
+        // More than a batch goes back to the semaphore.
+        cache.post();
+        assert_eq!(cache.cached(), 0);
+        assert!(sem.try_wait().is_ok());
+    }
+    // Dropping the cache returns its tokens.
+    assert_eq!(sem.try_wait_many(20), 10);
+}
+
+// A thread blocked on the semaphore gets the cached tokens on the next post.
+#[test]
+fn blocked_waiter_not_stranded() {
+    let sem = Arc::new(Semaphore::new(2));
+    let cache = sem.permit_cache(8);
+    cache.wait().unwrap();
+    assert_eq!(cache.cached(), 1);
+
+    let waiter = {
+        let sem = sem.clone();
+        thread::spawn(move || sem.wait_timeout(Duration::seconds(5)).is_ok())
+    };
+    thread::sleep(::std::time::Duration::from_millis(50));
+    cache.post();
+    assert_eq!(cache.cached(), 0);
+    assert!(waiter.join().unwrap());
+}
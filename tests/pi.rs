@@ -0,0 +1,55 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::thread;
+
+use sema::PiSemaphore;
+use time::Duration;
+
+#[test]
+fn mutual_exclusion() {
+    let sem = Arc::new(PiSemaphore::new());
+    let inside = Arc::new(AtomicUsize::new(0));
+    let threads: Vec<_> = (0..4).map(|_| {
+        let sem = sem.clone();
+        let inside = inside.clone();
+        thread::spawn(move || {
+            for _ in 0..1000 {
+                let _guard = sem.take().unwrap();
+                assert_eq!(inside.fetch_add(1, Ordering::SeqCst), 0);
+                inside.fetch_sub(1, Ordering::SeqCst);
+            }
+        })
+    }).collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+}
+
+#[test]
+fn only_holder_can_post() {
+    let sem = Arc::new(PiSemaphore::new());
+    sem.wait().unwrap();
+    assert_eq!(sem.wait().unwrap_err().kind(), ErrorKind::Deadlock);
+
+    let other = {
+        let sem = sem.clone();
+        thread::spawn(move || {
+            assert_eq!(sem.post().unwrap_err().kind(), ErrorKind::PermissionDenied);
+            assert_eq!(sem.wait_timeout(Duration::milliseconds(10)).unwrap_err().kind(),
+                       ErrorKind::TimedOut);
+        })
+    };
+    other.join().unwrap();
+    sem.post().unwrap();
+    assert!(sem.try_wait().is_ok());
+}
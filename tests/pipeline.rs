@@ -0,0 +1,32 @@
+extern crate sema;
+extern crate time;
+
+use std::io::ErrorKind;
+
+use sema::Pipeline;
+use time::Duration;
+
+#[test]
+fn advance_moves_between_stages() {
+    let pipeline = Pipeline::new(&[2, 1]);
+    let mut a = pipeline.enter(0).unwrap();
+    let mut b = pipeline.enter(0).unwrap();
+    assert!(pipeline.try_enter(0).is_err());
+    a.advance().unwrap();
+    assert_eq!(a.stage(), 1);
+    // Stage 1 is full, so `b` stays in stage 0.
+    assert!(b.advance_timeout(Duration::milliseconds(10)).is_err());
+    assert_eq!(b.stage(), 0);
+    assert!(pipeline.try_enter(0).is_ok());
+    drop(a);
+    b.try_advance().unwrap();
+    assert!(pipeline.try_enter(1).is_err());
+}
+
+#[test]
+fn last_stage_cannot_advance() {
+    let pipeline = Pipeline::new(&[1]);
+    let mut guard = pipeline.enter(0).unwrap();
+    assert_eq!(guard.advance().unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(pipeline.enter(1).err().unwrap().kind(), ErrorKind::InvalidInput);
+}
@@ -0,0 +1,40 @@
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+
+use sema::Pool;
+use time::Duration;
+
+#[test]
+fn checkout_and_return() {
+    let pool = Pool::new(vec![1, 2]);
+    let a = pool.get().unwrap();
+    let b = pool.try_get().unwrap();
+    assert_eq!(*a + *b, 3);
+    assert!(pool.try_get().is_err());
+    assert!(pool.get_timeout(Duration::milliseconds(10)).is_err());
+
+    drop(a);
+    assert_eq!(pool.available(), 1);
+    // A detached item doesn't come back.
+    assert_eq!(b.detach(), 1);
+    let c = pool.try_get().unwrap();
+    assert_eq!(*c, 2);
+    assert!(pool.try_get().is_err());
+}
+
+// A thread waiting for an item gets the one returned by another.
+#[test]
+fn waiter_gets_returned_item() {
+    let pool = Arc::new(Pool::new(vec![String::from("conn")]));
+    let item = pool.get().unwrap();
+    let waiter = {
+        let pool = pool.clone();
+        thread::spawn(move || pool.get_timeout(Duration::seconds(5)).map(|c| c.clone()).ok())
+    };
+    thread::sleep(::std::time::Duration::from_millis(50));
+    drop(item);
+    assert_eq!(waiter.join().unwrap(), Some(String::from("conn")));
+}
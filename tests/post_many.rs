@@ -0,0 +1,47 @@
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+
+use sema::Semaphore;
+use time::Duration;
+
+// Every blocked waiter must get one of the posted tokens, none may be left asleep.
+#[test]
+fn wakes_all_waiters() {
+    let sem = Arc::new(Semaphore::new(0));
+    let waiters: Vec<_> = (0..8).map(|_| {
+        let sem = sem.clone();
+        thread::spawn(move || sem.wait_timeout(Duration::seconds(5)).is_ok())
+    }).collect();
+
+    // Give the waiters a chance to block.
+    thread::sleep(::std::time::Duration::from_millis(50));
+    sem.post_many(8);
+
+    for waiter in waiters {
+        assert!(waiter.join().unwrap());
+    }
+    assert!(sem.try_wait().is_err());
+}
+
+// Surplus tokens stay available once every waiter has been woken.
+#[test]
+fn keeps_surplus_tokens() {
+    let sem = Arc::new(Semaphore::new(0));
+    let waiters: Vec<_> = (0..3).map(|_| {
+        let sem = sem.clone();
+        thread::spawn(move || sem.wait_timeout(Duration::seconds(5)).is_ok())
+    }).collect();
+
+    thread::sleep(::std::time::Duration::from_millis(50));
+    sem.post_many(5);
+
+    for waiter in waiters {
+        assert!(waiter.join().unwrap());
+    }
+    assert!(sem.try_wait().is_ok());
+    assert!(sem.try_wait().is_ok());
+    assert!(sem.try_wait().is_err());
+}
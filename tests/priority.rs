@@ -0,0 +1,61 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::thread;
+
+use sema::{
+    PrioritySemaphore,
+    PRIORITIES,
+};
+use time::Duration;
+
+#[test]
+fn highest_priority_goes_first() {
+    let sem = Arc::new(PrioritySemaphore::new(0));
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let workers: Vec<_> = [1, 7, 3].iter().map(|&priority| {
+        let sem = sem.clone();
+        let order = order.clone();
+        let worker = thread::spawn(move || {
+            sem.wait_timeout(priority, Duration::seconds(5)).unwrap();
+            order.lock().unwrap().push(priority);
+            sem.post();
+        });
+        thread::sleep(::std::time::Duration::from_millis(20));
+        worker
+    }).collect();
+    sem.post();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert_eq!(*order.lock().unwrap(), vec![7, 3, 1]);
+}
+
+#[test]
+fn lower_priority_waits_behind_higher() {
+    let sem = Arc::new(PrioritySemaphore::new(0));
+    let high = {
+        let sem = sem.clone();
+        thread::spawn(move || sem.wait_timeout(5, Duration::seconds(5)).is_ok())
+    };
+    thread::sleep(::std::time::Duration::from_millis(20));
+    sem.post();
+    assert!(high.join().unwrap());
+    assert!(sem.try_wait(0).is_err());
+    sem.post();
+    sem.try_wait(0).unwrap();
+}
+
+#[test]
+fn rejects_invalid_priority() {
+    let sem = PrioritySemaphore::new(1);
+    assert!(sem.try_wait(PRIORITIES).is_err());
+    assert!(sem.wait_timeout(0, Duration::milliseconds(10)).is_ok());
+}
@@ -0,0 +1,54 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback"),
+           feature = "prometheus"))]
+
+extern crate sema;
+
+use std::io::ErrorKind;
+use std::sync::Arc;
+
+use sema::{
+    PrometheusExporter,
+    Semaphore,
+};
+
+#[test]
+fn renders_registered_semaphores() {
+    let exporter = PrometheusExporter::new();
+    let db = Arc::new(Semaphore::new(3));
+    let api = Arc::new(Semaphore::new(0));
+    exporter.register("db", &db).unwrap();
+    exporter.register("api \"v2\"", &api).unwrap();
+    db.wait().unwrap();
+
+    let text = exporter.render();
+    assert!(text.contains("# TYPE sema_permits gauge\n"));
+    assert!(text.contains("sema_permits{semaphore=\"db\"} 2\n"));
+    assert!(text.contains("sema_permits{semaphore=\"api \\\"v2\\\"\"} 0\n"));
+    assert!(text.contains("sema_waiters{semaphore=\"db\"} 0\n"));
+    assert!(text.contains("sema_fast_acquisitions_total{semaphore=\"db\"} 1\n"));
+}
+
+#[test]
+fn rejects_duplicate_names() {
+    let exporter = PrometheusExporter::new();
+    let sem = Arc::new(Semaphore::new(1));
+    exporter.register("sem", &sem).unwrap();
+    let err = exporter.register("sem", &sem).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+    assert!(exporter.unregister("sem"));
+    assert!(!exporter.unregister("sem"));
+    exporter.register("sem", &sem).unwrap();
+}
+
+#[test]
+fn forgets_dropped_semaphores() {
+    let exporter = PrometheusExporter::new();
+    let sem = Arc::new(Semaphore::new(1));
+    exporter.register("gone", &sem).unwrap();
+    assert_eq!(exporter.names(), vec!["gone".to_owned()]);
+    drop(sem);
+    assert!(exporter.names().is_empty());
+    assert!(!exporter.render().contains("gone"));
+    exporter.register("gone", &Arc::new(Semaphore::new(1))).unwrap();
+}
@@ -0,0 +1,59 @@
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+
+use sema::BoundedQueue;
+use time::Duration;
+
+#[test]
+fn full_and_empty() {
+    let queue = BoundedQueue::new(2);
+    queue.try_push(1).unwrap();
+    queue.push(2);
+    assert_eq!(queue.try_push(3), Err(3));
+    assert_eq!(queue.push_timeout(3, Duration::milliseconds(10)), Err(3));
+    assert_eq!(queue.len(), 2);
+
+    assert_eq!(queue.pop(), 1);
+    assert_eq!(queue.try_pop(), Some(2));
+    assert_eq!(queue.try_pop(), None);
+    assert_eq!(queue.pop_timeout(Duration::milliseconds(10)), None);
+}
+
+// Every item pushed by the producers is popped exactly once.
+#[test]
+fn many_producers_and_consumers() {
+    let queue = Arc::new(BoundedQueue::new(4));
+    let producers: Vec<_> = (0..4).map(|p| {
+        let queue = queue.clone();
+        thread::spawn(move || {
+            for i in 0..1000 {
+                queue.push(p * 1000 + i);
+            }
+        })
+    }).collect();
+    let consumers: Vec<_> = (0..4).map(|_| {
+        let queue = queue.clone();
+        thread::spawn(move || (0..1000).map(|_| queue.pop()).collect::<Vec<u64>>())
+    }).collect();
+
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    let mut items: Vec<u64> = consumers.into_iter().flat_map(|c| c.join().unwrap()).collect();
+    items.sort();
+    assert_eq!(items, (0..4000).collect::<Vec<u64>>());
+}
+
+// Items left in the queue are dropped with it.
+#[test]
+fn drops_remaining_items() {
+    let item = Arc::new(());
+    let queue = BoundedQueue::new(4);
+    queue.push(item.clone());
+    queue.push(item.clone());
+    drop(queue);
+    assert_eq!(Arc::strong_count(&item), 1);
+}
@@ -0,0 +1,35 @@
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+
+use sema::QuotaSemaphore;
+use time::Duration;
+
+#[test]
+fn child_consumes_parent() {
+    let global = Arc::new(QuotaSemaphore::new(3));
+    let a = QuotaSemaphore::with_parent(2, global.clone());
+    let b = QuotaSemaphore::with_parent(2, global.clone());
+    let _a1 = a.acquire().unwrap();
+    let _a2 = a.acquire().unwrap();
+    assert!(a.try_acquire().is_err());
+    let b1 = b.acquire().unwrap();
+    // The global quota is exhausted although `b` has a permit left.
+    assert!(b.try_acquire().is_err());
+    assert!(global.try_acquire().is_err());
+    drop(b1);
+    assert!(global.try_acquire().is_ok());
+}
+
+#[test]
+fn failed_parent_rolls_back_child() {
+    let global = Arc::new(QuotaSemaphore::new(1));
+    let tenant = QuotaSemaphore::with_parent(1, global.clone());
+    let held = global.acquire().unwrap();
+    assert!(tenant.acquire_timeout(Duration::milliseconds(10)).is_err());
+    assert!(tenant.try_acquire().is_err());
+    drop(held);
+    // The tenant's permit was given back by the failed attempts.
+    let _t = tenant.try_acquire().unwrap();
+}
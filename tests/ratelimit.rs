@@ -0,0 +1,56 @@
+extern crate sema;
+extern crate time;
+
+use std::time::Instant;
+
+use sema::{
+    LeakyBucket,
+    RateLimit,
+    RateLimiter,
+};
+use time::Duration;
+
+#[test]
+fn allows_burst_then_limits() {
+    let limiter = RateLimiter::new(100.0, 5);
+    for _ in 0..5 {
+        limiter.try_acquire().unwrap();
+    }
+    assert!(limiter.try_acquire().is_err());
+    // The next permit is due in 10ms.
+    assert!(limiter.acquire_timeout(Duration::milliseconds(1)).is_err());
+    limiter.acquire_timeout(Duration::milliseconds(100)).unwrap();
+}
+
+#[test]
+fn acquire_follows_rate() {
+    let limiter = RateLimiter::new(200.0, 1);
+    let start = Instant::now();
+    for _ in 0..11 {
+        limiter.acquire();
+    }
+    // The first permit is immediate, the other ten take 5ms each.
+    assert!(start.elapsed() >= ::std::time::Duration::from_millis(45));
+}
+
+#[test]
+fn leaky_bucket_spaces_permits() {
+    let limiter = LeakyBucket::new(100.0);
+    limiter.try_acquire().unwrap();
+    // No burst, even after idling.
+    ::std::thread::sleep(::std::time::Duration::from_millis(30));
+    limiter.try_acquire().unwrap();
+    assert!(limiter.try_acquire().is_err());
+    limiter.acquire_timeout(Duration::milliseconds(100)).unwrap();
+}
+
+// Callers can be written against either policy.
+#[test]
+fn policies_are_interchangeable() {
+    fn take_two(limiter: &dyn RateLimit) {
+        limiter.acquire();
+        limiter.acquire();
+    }
+    take_two(&RateLimiter::new(1000.0, 1));
+    take_two(&LeakyBucket::new(1000.0));
+}
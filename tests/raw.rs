@@ -0,0 +1,78 @@
+#![cfg(not(any(target_os = "macos",
+               target_os = "linux",
+               target_os = "hermit",
+               feature = "spin-fallback")))]
+
+extern crate libc;
+extern crate sema;
+extern crate time;
+
+use std::mem;
+use std::thread;
+
+use sema::{
+    SemRef,
+    Semaphore,
+};
+use time::Duration;
+
+#[test]
+fn into_raw_hands_the_sem_t_over() {
+    let sem = Semaphore::new(1);
+    let raw = sem.into_raw();
+    assert_eq!(unsafe { libc::sem_trywait(raw) }, 0);
+    assert_eq!(unsafe { libc::sem_post(raw) }, 0);
+    let sem = unsafe { Semaphore::from_raw(raw) };
+    sem.try_wait().unwrap();
+    assert_eq!(sem.into_raw(), raw);
+    drop(unsafe { Semaphore::from_raw(raw) });
+}
+
+#[test]
+fn sem_ref_borrows_the_sem_t() {
+    let mut raw: libc::sem_t = unsafe { mem::zeroed() };
+    assert_eq!(unsafe { libc::sem_init(&mut raw, 0, 0) }, 0);
+    {
+        let sem = unsafe { SemRef::from_ptr(&mut raw) };
+        assert_eq!(sem.as_ptr(), &mut raw as *mut libc::sem_t);
+        sem.post();
+        sem.wait_timeout(Duration::seconds(5)).unwrap();
+        assert!(sem.try_wait().is_err());
+        sem.post();
+    }
+    // Still initialized, with the token posted through the `SemRef`.
+    assert_eq!(unsafe { libc::sem_trywait(&mut raw) }, 0);
+    assert_eq!(unsafe { libc::sem_destroy(&mut raw) }, 0);
+}
+
+// A `sem_t` the way a C library would hand one out, embedded in a struct of its own.
+#[repr(C)]
+struct Foreign {
+    id: libc::c_int,
+    sem: libc::sem_t,
+}
+
+#[test]
+fn sem_ref_adopts_a_sem_t_owned_by_c() {
+    let foreign: *mut Foreign = Box::into_raw(Box::new(unsafe { mem::zeroed() }));
+    unsafe {
+        (*foreign).id = 42;
+        assert_eq!(libc::sem_init(&mut (*foreign).sem, 0, 0), 0);
+    }
+    {
+        let sem = unsafe { SemRef::from_ptr(&mut (*foreign).sem) };
+        // A second borrow of the same `sem_t`, waited on in another thread.
+        let other = unsafe { SemRef::from_ptr(&mut (*foreign).sem) };
+        let waiter = thread::spawn(move || other.wait_timeout(Duration::seconds(5)).unwrap());
+        sem.post();
+        waiter.join().unwrap();
+    }
+    // The C side still owns a working `sem_t`, and the rest of its struct is untouched.
+    unsafe {
+        assert_eq!((*foreign).id, 42);
+        assert_eq!(libc::sem_post(&mut (*foreign).sem), 0);
+        assert_eq!(libc::sem_trywait(&mut (*foreign).sem), 0);
+        assert_eq!(libc::sem_destroy(&mut (*foreign).sem), 0);
+        drop(Box::from_raw(foreign));
+    }
+}
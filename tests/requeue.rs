@@ -0,0 +1,56 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate libc;
+extern crate sema;
+
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicU32,
+    AtomicUsize,
+    Ordering,
+};
+use std::thread;
+use std::time::Duration;
+
+use sema::Semaphore;
+
+const FUTEX_WAIT_PRIVATE: libc::c_int = 128;
+
+// Threads sleeping on a condition word are moved to the semaphore and proceed as tokens arrive.
+#[test]
+fn requeued_threads_take_tokens() {
+    let sem = Arc::new(Semaphore::new(0));
+    let cond = Arc::new(AtomicU32::new(0));
+    let sleeping = Arc::new(AtomicUsize::new(0));
+
+    let threads: Vec<_> = (0..4).map(|_| {
+        let sem = sem.clone();
+        let cond = cond.clone();
+        let sleeping = sleeping.clone();
+        thread::spawn(move || {
+            sleeping.fetch_add(1, Ordering::SeqCst);
+            unsafe {
+                libc::syscall(libc::SYS_futex, cond.as_ptr(), FUTEX_WAIT_PRIVATE, 0,
+                              ptr::null::<libc::timespec>());
+            }
+            sem.wait_requeued().unwrap();
+        })
+    }).collect();
+
+    while sleeping.load(Ordering::SeqCst) < 4 {
+        thread::yield_now();
+    }
+    thread::sleep(Duration::from_millis(100));
+
+    // A stale expected value moves nobody.
+    assert!(sem.requeue_from(&cond, 1, 0, 4).is_err());
+    assert_eq!(sem.requeue_from(&cond, 0, 0, 4).unwrap(), 4);
+
+    sem.post_many(4);
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert!(sem.try_wait().is_err());
+}
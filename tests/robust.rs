@@ -0,0 +1,37 @@
+#![cfg(unix)]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{
+    Duration as StdDuration,
+    Instant,
+};
+
+use sema::RobustSemaphore;
+use time::Duration;
+
+#[test]
+fn post_wakes_sleeping_waiters() {
+    let sem = Arc::new(RobustSemaphore::new(0));
+    let waiters: Vec<_> = (0..4).map(|_| {
+        let sem = sem.clone();
+        thread::spawn(move || {
+            sem.wait_timeout(Duration::seconds(5)).unwrap();
+            Instant::now()
+        })
+    }).collect();
+    // Long enough for the waiters to back off as far as they go.
+    thread::sleep(StdDuration::from_millis(100));
+    let posted = Instant::now();
+    for _ in 0..4 {
+        sem.post();
+    }
+    for waiter in waiters {
+        let woken = waiter.join().unwrap();
+        assert!(woken.duration_since(posted) < StdDuration::from_secs(1));
+    }
+    assert_eq!(sem.value(), 0);
+}
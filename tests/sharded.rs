@@ -0,0 +1,55 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+
+use sema::ShardedSemaphore;
+use time::Duration;
+
+// Tokens posted to any shard can be taken, and none are lost or duplicated.
+#[test]
+fn tokens_are_conserved() {
+    let sem = Arc::new(ShardedSemaphore::with_shards(10, 4));
+    assert_eq!(sem.value(), 10);
+
+    let threads: Vec<_> = (0..4).map(|_| {
+        let sem = sem.clone();
+        thread::spawn(move || {
+            for _ in 0..1000 {
+                let _guard = sem.take().unwrap();
+            }
+        })
+    }).collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(sem.value(), 10);
+
+    for _ in 0..10 {
+        sem.try_wait().unwrap();
+    }
+    assert!(sem.try_wait().is_err());
+}
+
+// A blocked waiter is woken by a post, whichever shard it lands in.
+#[test]
+fn wakes_blocked_waiters() {
+    let sem = Arc::new(ShardedSemaphore::with_shards(0, 8));
+    let waiters: Vec<_> = (0..4).map(|_| {
+        let sem = sem.clone();
+        thread::spawn(move || sem.wait_timeout(Duration::seconds(5)).is_ok())
+    }).collect();
+
+    thread::sleep(::std::time::Duration::from_millis(50));
+    for _ in 0..4 {
+        sem.post();
+    }
+    for waiter in waiters {
+        assert!(waiter.join().unwrap());
+    }
+    assert!(sem.wait_timeout(Duration::milliseconds(10)).is_err());
+}
@@ -0,0 +1,87 @@
+#![cfg(target_os = "linux")]
+
+extern crate libc;
+extern crate sema;
+extern crate time;
+
+use std::mem;
+use std::ptr;
+use std::time::Instant;
+use std::io::ErrorKind;
+
+use sema::Semaphore;
+use time::Duration;
+
+// Maps an anonymous shared region large enough for a semaphore, inherited across `fork()`.
+fn shared_region() -> *mut Semaphore {
+    let ptr = unsafe {
+        libc::mmap(ptr::null_mut(), mem::size_of::<Semaphore>(), libc::PROT_READ | libc::PROT_WRITE,
+                   libc::MAP_SHARED | libc::MAP_ANONYMOUS, -1, 0)
+    };
+    assert!(ptr != libc::MAP_FAILED);
+    ptr as *mut Semaphore
+}
+
+// Runs `f` in a forked child and returns whether it succeeded.
+fn in_child<F: FnOnce() -> bool>(f: F) -> bool {
+    let pid = unsafe {
+        libc::fork()
+    };
+    assert!(pid != -1);
+    if pid == 0 {
+        let ok = f();
+        unsafe {
+            libc::_exit(if ok { 0 } else { 1 });
+        }
+    }
+    let mut status = 0;
+    unsafe {
+        libc::waitpid(pid, &mut status, 0);
+    }
+    libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0
+}
+
+#[test]
+fn child_timeout_fires() {
+    let sem = unsafe {
+        Semaphore::init_at(shared_region(), 0).unwrap()
+    };
+    assert!(in_child(|| {
+        let start = Instant::now();
+        let res = sem.wait_timeout(Duration::milliseconds(100));
+        let elapsed = start.elapsed();
+        res.map_err(|e| e.kind()) == Err(ErrorKind::TimedOut)
+            && elapsed >= ::std::time::Duration::from_millis(100)
+            && elapsed < ::std::time::Duration::from_secs(5)
+    }));
+}
+
+#[test]
+fn child_wakes_on_parent_post() {
+    let sem = unsafe {
+        Semaphore::init_at(shared_region(), 0).unwrap()
+    };
+    let done = unsafe {
+        Semaphore::init_at(shared_region(), 0).unwrap()
+    };
+    let pid = unsafe {
+        libc::fork()
+    };
+    assert!(pid != -1);
+    if pid == 0 {
+        let ok = sem.wait_timeout(Duration::seconds(5)).is_ok();
+        if ok {
+            done.post();
+        }
+        unsafe {
+            libc::_exit(if ok { 0 } else { 1 });
+        }
+    }
+    sem.post();
+    done.wait_timeout(Duration::seconds(5)).unwrap();
+    let mut status = 0;
+    unsafe {
+        libc::waitpid(pid, &mut status, 0);
+    }
+    assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+}
@@ -0,0 +1,64 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate libc;
+extern crate sema;
+extern crate time;
+
+use std::os::unix::io::AsRawFd;
+
+use sema::signal::{
+    SignalFdSemaphore,
+    SignalSemaphore,
+};
+use time::Duration;
+
+static SIGNALS: SignalSemaphore = SignalSemaphore::new();
+
+extern "C" fn handler(signum: libc::c_int) {
+    SIGNALS.notify_from_handler(signum);
+}
+
+#[test]
+fn handler_notifies_waiter() {
+    unsafe {
+        libc::signal(libc::SIGUSR1, handler as *const () as libc::sighandler_t);
+        libc::signal(libc::SIGUSR2, handler as *const () as libc::sighandler_t);
+        libc::raise(libc::SIGUSR2);
+        libc::raise(libc::SIGUSR1);
+    }
+    let mut received = Vec::new();
+    while received.len() < 2 {
+        let pending = SIGNALS.wait_timeout(Duration::seconds(5)).unwrap();
+        received.extend(pending);
+    }
+    assert_eq!(received, vec![libc::SIGUSR1, libc::SIGUSR2]);
+
+    unsafe {
+        libc::raise(libc::SIGUSR1);
+    }
+    assert_eq!(SIGNALS.iter().next(), Some(libc::SIGUSR1));
+}
+
+#[test]
+fn nothing_pending() {
+    let sem = SignalSemaphore::new();
+    assert!(sem.try_wait().is_err());
+    assert!(sem.wait_timeout(Duration::milliseconds(10)).is_err());
+    sem.notify_from_handler(libc::SIGHUP);
+    let pending = sem.try_wait().unwrap();
+    assert!(pending.contains(libc::SIGHUP));
+    assert!(!pending.contains(libc::SIGINT));
+}
+
+#[test]
+fn signalfd_reports_signal_number() {
+    let sem = SignalFdSemaphore::new(&[libc::SIGWINCH, libc::SIGURG]).unwrap();
+    assert!(sem.try_wait().is_err());
+    unsafe {
+        libc::raise(libc::SIGURG);
+    }
+    assert_eq!(sem.wait_timeout(Duration::seconds(5)).unwrap(), libc::SIGURG);
+    assert!(sem.wait_timeout(Duration::milliseconds(10)).is_err());
+    assert!(sem.as_raw_fd() >= 0);
+}
@@ -0,0 +1,22 @@
+#![cfg(any(feature = "spin-fallback",
+           target_os = "hermit"))]
+
+extern crate sema;
+
+use sema::Semaphore;
+
+#[test]
+fn try_post_fails_at_max() {
+    let sem = Semaphore::new(u32::MAX - 1);
+    sem.try_post().unwrap();
+    assert!(sem.try_post().is_err());
+    sem.try_wait().unwrap();
+    sem.try_post().unwrap();
+}
+
+#[test]
+#[should_panic(expected = "semaphore count overflow")]
+fn post_many_panics_past_max() {
+    let sem = Semaphore::new(u32::MAX - 1);
+    sem.post_many(2);
+}
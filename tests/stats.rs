@@ -0,0 +1,69 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback"),
+           feature = "stats"))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+
+use sema::{
+    Semaphore,
+    WaitStrategy,
+};
+use time::Duration;
+
+#[test]
+fn counts_fast_acquisitions_and_timeouts() {
+    let sem = Semaphore::new(2);
+    sem.wait().unwrap();
+    sem.try_wait().unwrap();
+    assert!(sem.wait_timeout(Duration::milliseconds(10)).is_err());
+    let stats = sem.stats();
+    assert_eq!(stats.fast_acquisitions, 2);
+    assert_eq!(stats.slow_acquisitions, 0);
+    assert_eq!(stats.timeouts, 1);
+    assert_eq!(stats.waiters, 0);
+    assert_eq!(stats.peak_waiters, 1);
+}
+
+#[test]
+fn counts_blocked_acquisitions() {
+    let sem = Arc::new(Semaphore::with_wait_strategy(0, WaitStrategy::Block));
+    let waiter = {
+        let sem = sem.clone();
+        thread::spawn(move || sem.wait_timeout(Duration::seconds(5)).unwrap())
+    };
+    while sem.stats().waiters == 0 {
+        thread::yield_now();
+    }
+    sem.post();
+    waiter.join().unwrap();
+    let stats = sem.stats();
+    assert_eq!(stats.slow_acquisitions, 1);
+    assert!(stats.futex_syscalls >= 2);
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn records_wait_latency() {
+    let sem = Arc::new(Semaphore::with_wait_strategy(0, WaitStrategy::Block));
+    assert_eq!(sem.stats().wait_latency.percentile(99.0), None);
+    let waiter = {
+        let sem = sem.clone();
+        thread::spawn(move || sem.wait_timeout(Duration::seconds(5)).unwrap())
+    };
+    while sem.stats().waiters == 0 {
+        thread::yield_now();
+    }
+    thread::sleep(::std::time::Duration::from_millis(20));
+    sem.post();
+    waiter.join().unwrap();
+    let latency = sem.stats().wait_latency;
+    assert_eq!(latency.count(), 1);
+    let p99 = latency.percentile(99.0).unwrap();
+    assert!(p99 >= Duration::milliseconds(20));
+    assert!(p99 < Duration::seconds(5));
+    assert_eq!(latency.max(), Some(p99));
+}
@@ -0,0 +1,31 @@
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+
+use sema::{
+    Semaphore,
+    TimedGuard,
+};
+use time::Duration;
+
+#[test]
+fn drop_returns_permit() {
+    let sem = Arc::new(Semaphore::new(1));
+    let guard = TimedGuard::acquire(sem.clone(), Duration::seconds(60)).unwrap();
+    assert!(sem.try_wait().is_err());
+    assert!(!guard.is_expired());
+    drop(guard);
+    sem.try_wait().unwrap();
+    assert!(sem.try_wait().is_err());
+}
+
+#[test]
+fn deadline_returns_permit_once() {
+    let sem = Arc::new(Semaphore::new(1));
+    let guard = TimedGuard::acquire(sem.clone(), Duration::milliseconds(10)).unwrap();
+    sem.wait_timeout(Duration::seconds(5)).unwrap();
+    assert!(guard.is_expired());
+    drop(guard);
+    assert!(sem.try_wait().is_err());
+}
@@ -0,0 +1,295 @@
+// The timeout semantics every semaphore's `wait_timeout()` shares, whatever the OS primitive
+// underneath takes: the timeout is a duration relative to the call, a negative one has already
+// expired, a zero one takes only a token that is already there, and one too long to ever pass
+// simply waits for a post.
+
+extern crate sema;
+extern crate time;
+
+use std::io::{
+    Error,
+    ErrorKind,
+};
+use std::sync::Arc;
+use std::thread;
+use std::time::{
+    Duration as StdDuration,
+    Instant,
+};
+
+use time::Duration;
+
+trait Timed: Send + Sync + 'static {
+    fn make(value: u32) -> Self;
+    fn wait_timeout(&self, timeout: Duration) -> Result<(), Error>;
+    fn post(&self);
+}
+
+fn expires_after_the_timeout<S: Timed>() {
+    let sem = S::make(0);
+    let start = Instant::now();
+    let err = sem.wait_timeout(Duration::milliseconds(100)).unwrap_err();
+    let elapsed = start.elapsed();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(elapsed >= StdDuration::from_millis(100) && elapsed < StdDuration::from_secs(5),
+            "timed out after {:?}", elapsed);
+}
+
+fn negative_timeout_has_expired<S: Timed>() {
+    let sem = S::make(0);
+    let start = Instant::now();
+    let err = sem.wait_timeout(Duration::seconds(-1)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(start.elapsed() < StdDuration::from_secs(1));
+    sem.post();
+    sem.wait_timeout(Duration::seconds(-1)).unwrap();
+}
+
+fn zero_timeout_is_a_try_wait<S: Timed>() {
+    let sem = S::make(1);
+    sem.wait_timeout(Duration::zero()).unwrap();
+    let start = Instant::now();
+    let err = sem.wait_timeout(Duration::zero()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(start.elapsed() < StdDuration::from_secs(1));
+}
+
+fn most_negative_timeout_has_expired<S: Timed>() {
+    let sem = S::make(1);
+    sem.wait_timeout(Duration::min_value()).unwrap();
+    let err = sem.wait_timeout(Duration::min_value()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+}
+
+fn endless_timeout_waits_for_a_post<S: Timed>() {
+    let sem = Arc::new(S::make(0));
+    let poster = sem.clone();
+    thread::spawn(move || {
+        thread::sleep(StdDuration::from_millis(50));
+        poster.post();
+    });
+    sem.wait_timeout(Duration::max_value()).unwrap();
+}
+
+// Timeouts past what a single OS call can take, such as Windows' 32-bit milliseconds, or so far
+// off that the deadline lies beyond the clock's range.
+fn long_timeouts_wait_for_a_post<S: Timed>() {
+    let timeouts = [
+        Duration::milliseconds(u32::MAX as i64 - 1),
+        Duration::milliseconds(u32::MAX as i64),
+        Duration::days(365 * 1_000_000),
+        Duration::seconds(i64::MAX / 1000),
+    ];
+    for &timeout in &timeouts {
+        let sem = Arc::new(S::make(0));
+        let poster = sem.clone();
+        thread::spawn(move || {
+            thread::sleep(StdDuration::from_millis(10));
+            poster.post();
+        });
+        sem.wait_timeout(timeout).unwrap();
+    }
+}
+
+macro_rules! timeout_tests {
+    ($name:ident, $ty:ty) => {
+        mod $name {
+            #[test]
+            fn expires_after_the_timeout() {
+                ::expires_after_the_timeout::<$ty>();
+            }
+
+            #[test]
+            fn negative_timeout_has_expired() {
+                ::negative_timeout_has_expired::<$ty>();
+            }
+
+            #[test]
+            fn zero_timeout_is_a_try_wait() {
+                ::zero_timeout_is_a_try_wait::<$ty>();
+            }
+
+            #[test]
+            fn most_negative_timeout_has_expired() {
+                ::most_negative_timeout_has_expired::<$ty>();
+            }
+
+            #[test]
+            fn endless_timeout_waits_for_a_post() {
+                ::endless_timeout_waits_for_a_post::<$ty>();
+            }
+
+            #[test]
+            fn long_timeouts_wait_for_a_post() {
+                ::long_timeouts_wait_for_a_post::<$ty>();
+            }
+        }
+    };
+}
+
+impl Timed for sema::Semaphore {
+    fn make(value: u32) -> Self {
+        sema::Semaphore::new(value as _)
+    }
+
+    fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        sema::Semaphore::wait_timeout(self, timeout)
+    }
+
+    fn post(&self) {
+        sema::Semaphore::post(self);
+    }
+}
+
+timeout_tests!(semaphore, sema::Semaphore);
+
+#[cfg(unix)]
+mod unix {
+    use std::io::Error;
+    use std::process;
+    use std::sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    };
+
+    use sema::{
+        NamedSemaphore,
+        NamedSemaphoreOptions,
+        SysVSemaphore,
+    };
+    use time::Duration;
+
+    use super::Timed;
+
+    impl Timed for NamedSemaphore {
+        fn make(value: u32) -> Self {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let name = format!("/sema-timeouts-{}-{}", process::id(),
+                               NEXT.fetch_add(1, Ordering::Relaxed));
+            NamedSemaphoreOptions::new().unlink_on_drop(true).create(&name, value).unwrap()
+        }
+
+        fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+            NamedSemaphore::wait_timeout(self, timeout)
+        }
+
+        fn post(&self) {
+            NamedSemaphore::post(self);
+        }
+    }
+
+    timeout_tests!(named, ::sema::NamedSemaphore);
+
+    // Removes the semaphore set when dropped.
+    pub struct SysV(Option<SysVSemaphore>);
+
+    impl Drop for SysV {
+        fn drop(&mut self) {
+            self.0.take().unwrap().remove().unwrap();
+        }
+    }
+
+    impl Timed for SysV {
+        fn make(value: u32) -> Self {
+            SysV(Some(SysVSemaphore::private(value).unwrap()))
+        }
+
+        fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+            self.0.as_ref().unwrap().wait_timeout(timeout)
+        }
+
+        fn post(&self) {
+            self.0.as_ref().unwrap().post();
+        }
+    }
+
+    timeout_tests!(sysv, ::unix::SysV);
+}
+
+#[cfg(all(target_os = "linux",
+          not(feature = "spin-fallback")))]
+mod linux {
+    use std::io::Error;
+
+    use sema::{
+        EventFdSemaphore,
+        FairSemaphore,
+        RobustSemaphore,
+    };
+    use time::Duration;
+
+    use super::Timed;
+
+    impl Timed for FairSemaphore {
+        fn make(value: u32) -> Self {
+            FairSemaphore::new(value)
+        }
+
+        fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+            FairSemaphore::wait_timeout(self, timeout)
+        }
+
+        fn post(&self) {
+            FairSemaphore::post(self);
+        }
+    }
+
+    timeout_tests!(fair, ::sema::FairSemaphore);
+
+    impl Timed for RobustSemaphore {
+        fn make(value: u32) -> Self {
+            RobustSemaphore::new(value)
+        }
+
+        fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+            RobustSemaphore::wait_timeout(self, timeout)
+        }
+
+        fn post(&self) {
+            RobustSemaphore::post(self);
+        }
+    }
+
+    timeout_tests!(robust, ::sema::RobustSemaphore);
+
+    impl Timed for EventFdSemaphore {
+        fn make(value: u32) -> Self {
+            EventFdSemaphore::new(value).unwrap()
+        }
+
+        fn wait_timeout(&self, timeout: Duration) -> Result<(), Error> {
+            EventFdSemaphore::wait_timeout(self, timeout)
+        }
+
+        fn post(&self) {
+            EventFdSemaphore::post(self);
+        }
+    }
+
+    timeout_tests!(eventfd, ::sema::EventFdSemaphore);
+
+    // Deadlines at either end of the range of a `Duration`.
+    #[test]
+    fn deadline_bounds() {
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Duration as StdDuration;
+
+        use sema::{
+            Clock,
+            Semaphore,
+        };
+
+        let sem = Arc::new(Semaphore::new(0));
+        for &clock in &[Clock::Monotonic, Clock::Realtime] {
+            let err = sem.wait_deadline(clock, Duration::min_value()).unwrap_err();
+            assert_eq!(err.kind(), ::std::io::ErrorKind::TimedOut);
+            let poster = sem.clone();
+            thread::spawn(move || {
+                thread::sleep(StdDuration::from_millis(50));
+                poster.post();
+            });
+            sem.wait_deadline(clock, Duration::max_value()).unwrap();
+        }
+    }
+}
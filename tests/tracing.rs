@@ -0,0 +1,67 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback"),
+           feature = "tracing"))]
+
+extern crate sema;
+extern crate time;
+extern crate tracing;
+
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+
+use sema::Semaphore;
+use time::Duration;
+use tracing::span;
+use tracing::{
+    Event,
+    Metadata,
+    Subscriber,
+};
+
+#[derive(Default)]
+struct Counts {
+    spans: AtomicUsize,
+    events: AtomicUsize,
+}
+
+// Counts the spans and events it sees.
+struct Counter(Arc<Counts>);
+
+impl Subscriber for Counter {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn new_span(&self, _: &span::Attributes) -> span::Id {
+        span::Id::from_u64(self.0.spans.fetch_add(1, Ordering::SeqCst) as u64 + 1)
+    }
+
+    fn record(&self, _: &span::Id, _: &span::Record) {}
+
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    fn event(&self, _: &Event) {
+        self.0.events.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn enter(&self, _: &span::Id) {}
+
+    fn exit(&self, _: &span::Id) {}
+}
+
+#[test]
+fn blocked_wait_is_traced() {
+    let counter = Arc::new(Counts::default());
+    let sem = Semaphore::new(1);
+    tracing::subscriber::with_default(Counter(counter.clone()), || {
+        // The fast path stays quiet.
+        sem.wait().unwrap();
+        assert_eq!(counter.events.load(Ordering::SeqCst), 0);
+        assert!(sem.wait_timeout(Duration::milliseconds(10)).is_err());
+    });
+    assert_eq!(counter.spans.load(Ordering::SeqCst), 1);
+    assert_eq!(counter.events.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,47 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::thread;
+
+use sema::Turnstile;
+use time::Duration;
+
+#[test]
+fn unlocked_turnstile_lets_threads_pass() {
+    let turnstile = Turnstile::new(false);
+    for _ in 0..10 {
+        turnstile.pass().unwrap();
+    }
+    turnstile.lock().unwrap();
+    assert!(turnstile.try_pass().is_err());
+    assert!(turnstile.pass_timeout(Duration::milliseconds(10)).is_err());
+}
+
+#[test]
+fn unlocking_releases_queued_threads() {
+    let turnstile = Arc::new(Turnstile::new(true));
+    let passed = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..4).map(|_| {
+        let turnstile = turnstile.clone();
+        let passed = passed.clone();
+        thread::spawn(move || {
+            turnstile.pass_timeout(Duration::seconds(5)).unwrap();
+            passed.fetch_add(1, Ordering::SeqCst);
+        })
+    }).collect();
+    thread::sleep(::std::time::Duration::from_millis(50));
+    assert_eq!(passed.load(Ordering::SeqCst), 0);
+    turnstile.unlock();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert_eq!(passed.load(Ordering::SeqCst), 4);
+}
@@ -0,0 +1,65 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback")))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::thread;
+
+use sema::WaitGroup;
+use time::Duration;
+
+#[test]
+fn waits_for_all_tasks() {
+    let wg = Arc::new(WaitGroup::new());
+    let finished = Arc::new(AtomicUsize::new(0));
+    wg.add(4);
+    for _ in 0..4 {
+        let wg = wg.clone();
+        let finished = finished.clone();
+        thread::spawn(move || {
+            thread::sleep(::std::time::Duration::from_millis(10));
+            finished.fetch_add(1, Ordering::SeqCst);
+            wg.done();
+        });
+    }
+    wg.wait().unwrap();
+    assert_eq!(finished.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn empty_group_does_not_block() {
+    let wg = WaitGroup::new();
+    wg.wait().unwrap();
+}
+
+// Waiters of a finished round are released even if the next round starts right away.
+#[test]
+fn reuse_releases_previous_waiters() {
+    let wg = Arc::new(WaitGroup::new());
+    wg.add(1);
+    let waiter = {
+        let wg = wg.clone();
+        thread::spawn(move || wg.wait_timeout(Duration::seconds(5)).is_ok())
+    };
+    thread::sleep(::std::time::Duration::from_millis(50));
+    wg.done();
+    wg.add(1);
+    assert!(waiter.join().unwrap());
+
+    assert!(wg.wait_timeout(Duration::milliseconds(10)).is_err());
+    wg.done();
+    wg.wait().unwrap();
+}
+
+#[test]
+#[should_panic(expected = "negative WaitGroup counter")]
+fn negative_counter_panics() {
+    let wg = WaitGroup::new();
+    wg.done();
+}
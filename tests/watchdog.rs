@@ -0,0 +1,66 @@
+#![cfg(all(target_os = "linux",
+           not(feature = "spin-fallback"),
+           feature = "watchdog"))]
+
+extern crate sema;
+extern crate time;
+
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use sema::{
+    LongWait,
+    Semaphore,
+    WaitStrategy,
+};
+use time::Duration;
+
+fn watched(value: u32, threshold: Duration) -> (Arc<Semaphore>, Arc<Mutex<Vec<LongWait>>>) {
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let mut sem = Semaphore::with_wait_strategy(value, WaitStrategy::Block);
+    {
+        let reports = reports.clone();
+        sem.set_watchdog(threshold, move |_, wait| reports.lock().unwrap().push(*wait));
+    }
+    (Arc::new(sem), reports)
+}
+
+#[test]
+fn reports_a_stuck_wait_once() {
+    let (sem, reports) = watched(0, Duration::milliseconds(20));
+    let waiter = {
+        let sem = sem.clone();
+        thread::spawn(move || sem.wait().unwrap())
+    };
+    while reports.lock().unwrap().is_empty() {
+        thread::sleep(StdDuration::from_millis(5));
+    }
+    thread::sleep(StdDuration::from_millis(50));
+    sem.post();
+    waiter.join().unwrap();
+
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports.len(), 1);
+    assert!(reports[0].waited >= StdDuration::from_millis(20));
+    assert_eq!(reports[0].permits, 0);
+    assert_eq!(reports[0].waiters, 1);
+}
+
+#[test]
+fn short_timeouts_are_not_reported() {
+    let (sem, reports) = watched(0, Duration::seconds(5));
+    assert!(sem.wait_timeout(Duration::milliseconds(10)).is_err());
+    assert!(reports.lock().unwrap().is_empty());
+}
+
+#[test]
+fn keeps_waiting_after_the_report() {
+    let (sem, reports) = watched(0, Duration::milliseconds(10));
+    let err = sem.wait_timeout(Duration::milliseconds(50)).unwrap_err();
+    assert_eq!(err.kind(), ::std::io::ErrorKind::TimedOut);
+    assert_eq!(reports.lock().unwrap().len(), 1);
+}
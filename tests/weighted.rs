@@ -0,0 +1,38 @@
+extern crate sema;
+extern crate time;
+
+use std::sync::Arc;
+use std::thread;
+
+use sema::WeightedSemaphore;
+use time::Duration;
+
+#[test]
+fn acquires_by_weight() {
+    let sem = WeightedSemaphore::new(10);
+    sem.acquire(6).unwrap();
+    assert!(sem.try_acquire(5).is_err());
+    sem.try_acquire(4).unwrap();
+    assert_eq!(sem.available(), 0);
+    sem.release(10);
+    assert!(sem.acquire(11).is_err());
+}
+
+// A queued heavy request blocks lighter ones arriving after it.
+#[test]
+fn heavy_request_not_starved() {
+    let sem = Arc::new(WeightedSemaphore::new(10));
+    let guard = sem.take(5).unwrap();
+    let heavy = {
+        let sem = sem.clone();
+        thread::spawn(move || sem.acquire_timeout(10, Duration::seconds(5)).is_ok())
+    };
+    thread::sleep(::std::time::Duration::from_millis(50));
+    // Would fit, but the heavy request is first in line.
+    assert!(sem.try_acquire(1).is_err());
+    assert!(sem.acquire_timeout(1, Duration::milliseconds(10)).is_err());
+
+    drop(guard);
+    assert!(heavy.join().unwrap());
+    assert_eq!(sem.available(), 0);
+}